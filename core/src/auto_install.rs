@@ -0,0 +1,567 @@
+//! Library of unattended install templates for Ventoy's `auto_install` plugin.
+//!
+//! Templates are plain kickstart/preseed/autounattend/cloud-init files with
+//! `{{variable}}` placeholders, which are filled in before being written to the drive.
+//! Values are escaped for the template's format: XML entities for autounattend, YAML
+//! scalars for cloud-init, and kickstart/preseed values can't span lines.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail};
+
+use super::{drive::VentoyDrive, ventoy_json::VentoyJson};
+
+/// Variables every built-in template uses, shown first in the form.
+pub const COMMON_VARIABLES: &[&str] = &["hostname", "username", "timezone", "ssh_key"];
+
+/// Whether a variable holds a password or the like, which shouldn't be saved.
+pub fn is_secret(name: &str) -> bool {
+    let name = name.to_lowercase();
+    ["password", "passwd", "secret", "token"]
+        .iter()
+        .any(|secret| name.contains(secret))
+}
+
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    (
+        "ubuntu-autoinstall.yaml",
+        include_str!("../assets/templates/ubuntu-autoinstall.yaml"),
+    ),
+    (
+        "fedora-kickstart.ks",
+        include_str!("../assets/templates/fedora-kickstart.ks"),
+    ),
+    (
+        "debian-preseed.preseed",
+        include_str!("../assets/templates/debian-preseed.preseed"),
+    ),
+    (
+        "windows-autounattend.xml",
+        include_str!("../assets/templates/windows-autounattend.xml"),
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateKind {
+    Kickstart,
+    Preseed,
+    Autounattend,
+    CloudInit,
+}
+
+impl TemplateKind {
+    /// Guesses the kind from the file extension, falling back to the content
+    /// for generic ones like `.cfg`.
+    pub fn detect(file_name: &str, content: &str) -> Self {
+        let ext = file_name.rsplit('.').next().unwrap_or_default();
+        match ext.to_lowercase().as_str() {
+            "ks" => Self::Kickstart,
+            "preseed" => Self::Preseed,
+            "xml" => Self::Autounattend,
+            "yaml" | "yml" => Self::CloudInit,
+            _ if content.contains("d-i ") => Self::Preseed,
+            _ if content.contains("<unattend") => Self::Autounattend,
+            _ if content.starts_with("#cloud-config") || content.contains("autoinstall:") => {
+                Self::CloudInit
+            }
+            _ => Self::Kickstart,
+        }
+    }
+
+    pub const fn extension(&self) -> &'static str {
+        match self {
+            Self::Kickstart => "ks",
+            Self::Preseed => "preseed",
+            Self::Autounattend => "xml",
+            Self::CloudInit => "yaml",
+        }
+    }
+}
+
+impl fmt::Display for TemplateKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Kickstart => "kickstart",
+            Self::Preseed => "preseed",
+            Self::Autounattend => "autounattend",
+            Self::CloudInit => "cloud-init",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pub name: String,
+    pub kind: TemplateKind,
+    pub content: String,
+    /// Built-in templates are bundled with the app and can't be removed.
+    pub builtin: bool,
+    /// File it was read from, `None` for built-in and not yet saved templates
+    pub path: Option<PathBuf>,
+}
+
+impl Template {
+    fn new(file_name: &str, content: String, builtin: bool) -> Self {
+        let name = file_name
+            .rsplit_once('.')
+            .map_or(file_name, |(name, _)| name)
+            .to_string();
+        Self {
+            name,
+            kind: TemplateKind::detect(file_name, &content),
+            content,
+            builtin,
+            path: None,
+        }
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("{}.{}", self.name, self.kind.extension())
+    }
+
+    pub fn variables(&self) -> Vec<&str> {
+        variables(&self.content)
+    }
+
+    pub fn render(&self, vars: &BTreeMap<String, String>) -> anyhow::Result<String> {
+        render(&self.content, self.kind, vars)
+    }
+}
+
+/// User templates are stored as files in a directory, next to the built-in ones.
+///
+/// A template is stored as `<name>.<extension of its kind>`, so removing goes by the
+/// file it was listed from rather than its name.
+pub struct TemplateLibrary {
+    dir: PathBuf,
+}
+
+impl TemplateLibrary {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn templates(&self) -> anyhow::Result<Vec<Template>> {
+        let mut templates = BUILTIN_TEMPLATES
+            .iter()
+            .map(|(file_name, content)| Template::new(file_name, content.to_string(), true))
+            .collect::<Vec<_>>();
+        if !self.dir.is_dir() {
+            return Ok(templates);
+        }
+
+        let mut user_templates = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if let Some(file_name) = path.file_name().and_then(|name| name.to_str()) {
+                if path.is_file() {
+                    user_templates.push(Template {
+                        path: Some(path.clone()),
+                        ..Template::new(file_name, fs::read_to_string(&path)?, false)
+                    });
+                }
+            }
+        }
+        user_templates.sort_by(|a, b| a.name.cmp(&b.name));
+        templates.extend(user_templates);
+        Ok(templates)
+    }
+
+    /// Copies a template file into the library.
+    pub fn import<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<Template> {
+        let path = path.as_ref();
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("invalid template file name {:?}", path))?;
+        let template = Template::new(file_name, fs::read_to_string(path)?, false);
+        let path = self.save(&template)?;
+        Ok(Template {
+            path: Some(path),
+            ..template
+        })
+    }
+
+    /// Writes the template into the library, returning where it was written to.
+    pub fn save(&self, template: &Template) -> anyhow::Result<PathBuf> {
+        if self.is_builtin(&template.name) {
+            bail!("`{}` is the name of a built-in template", template.name);
+        }
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(template.file_name());
+        fs::write(&path, &template.content)?;
+        Ok(path)
+    }
+
+    pub fn remove(&self, template: &Template) -> anyhow::Result<()> {
+        if template.builtin {
+            bail!("built-in templates can't be removed");
+        }
+        let path = template
+            .path
+            .as_ref()
+            .filter(|path| path.parent() == Some(self.dir.as_path()))
+            .ok_or_else(|| anyhow!("`{}` isn't stored in the library", template.name))?;
+        Ok(fs::remove_file(path)?)
+    }
+
+    fn is_builtin(&self, name: &str) -> bool {
+        BUILTIN_TEMPLATES
+            .iter()
+            .any(|(file_name, _)| file_name.starts_with(&format!("{name}.")))
+    }
+}
+
+/// Names of the `{{variable}}` placeholders in order of appearance, without duplicates.
+pub fn variables(content: &str) -> Vec<&str> {
+    let mut vars = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + 2 + len].trim();
+        if !name.is_empty() && !vars.contains(&name) {
+            vars.push(name);
+        }
+        rest = &rest[start + 2 + len + 2..];
+    }
+    vars
+}
+
+/// Replaces all placeholders with their values escaped for `kind`, failing with the list
+/// of variables left empty.
+pub fn render(
+    content: &str,
+    kind: TemplateKind,
+    vars: &BTreeMap<String, String>,
+) -> anyhow::Result<String> {
+    let missing = variables(content)
+        .into_iter()
+        .filter(|name| !matches!(vars.get(*name), Some(value) if !value.is_empty()))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        bail!("missing values for: {}", missing.join(", "));
+    }
+
+    let mut rendered = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + 2 + len].trim();
+        rendered.push_str(&rest[..start]);
+        match vars.get(name) {
+            Some(value) => {
+                let end = start + 2 + len + 2;
+                let quote = match (rest[..start].chars().last(), rest[end..].chars().next()) {
+                    (Some(open), Some(close)) if open == close && matches!(open, '"' | '\'') => {
+                        Some(open)
+                    }
+                    _ => None,
+                };
+                let escaped = escape(kind, name, value, quote)
+                    .map_err(|e| anyhow!("invalid value for {}: {}", name, e))?;
+                rendered.push_str(&escaped);
+            }
+            // Empty braces aren't placeholders
+            None => rendered.push_str(&rest[start..start + 2 + len + 2]),
+        }
+        rest = &rest[start + 2 + len + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Escapes the value of variable `name` for a template of `kind`, where it's placed
+/// between `quote`s if any.
+fn escape(
+    kind: TemplateKind,
+    name: &str,
+    value: &str,
+    quote: Option<char>,
+) -> anyhow::Result<String> {
+    match kind {
+        TemplateKind::Autounattend => Ok(value
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .replace('\'', "&apos;")),
+        TemplateKind::CloudInit => {
+            if quote == Some('\'') {
+                if value.chars().any(char::is_control) {
+                    bail!("control characters can't be single quoted");
+                }
+                return Ok(value.replace('\'', "''"));
+            }
+            // A JSON string is also a double quoted YAML scalar
+            let quoted = serde_json::to_string(value)?;
+            Ok(match quote {
+                Some(_) => quoted[1..quoted.len() - 1].to_string(),
+                None => quoted,
+            })
+        }
+        TemplateKind::Kickstart | TemplateKind::Preseed => {
+            if value.chars().any(char::is_control) {
+                bail!("it can't contain line breaks or other control characters");
+            }
+            if let Some(quote) = quote.filter(|quote| value.contains(*quote)) {
+                bail!("it can't contain {} as it's quoted with it", quote);
+            }
+            if kind == TemplateKind::Preseed {
+                // Preseed values also end up in the shell of `preseed/late_command`
+                if name == "username" && !is_unix_username(value) {
+                    bail!("it must be lowercase letters, digits, _ and -, not starting with a digit or -");
+                }
+                if quote == Some('"') && value.contains(['$', '`', '\\']) {
+                    bail!("it can't contain $, ` or \\ as the shell expands them");
+                }
+            }
+            Ok(value.to_string())
+        }
+    }
+}
+
+/// Whether `name` matches `[a-z_][a-z0-9_-]*`, what Debian's `adduser` accepts.
+fn is_unix_username(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-'))
+}
+
+/// Writes the rendered template to `/ventoy/script` on the drive and links it to
+/// `iso` in the `auto_install` plugin of `ventoy.json`.
+pub fn install<P: AsRef<Path>>(
+    drive: &VentoyDrive,
+    iso: P,
+    template: &Template,
+    vars: &BTreeMap<String, String>,
+) -> anyhow::Result<PathBuf> {
+    let iso = iso.as_ref();
+    let image = drive
+        .image_path(iso)
+        .ok_or_else(|| anyhow!("{:?} is not on the ventoy drive", iso))?;
+    let stem = iso
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| anyhow!("invalid image name {:?}", iso))?;
+    let rendered = template.render(vars)?;
+
+    let script_dir = drive.ventoy_dir().join("script");
+    fs::create_dir_all(&script_dir)?;
+    let script_path = script_dir.join(format!(
+        "{}_{}.{}",
+        stem,
+        template.name,
+        template.kind.extension()
+    ));
+    fs::write(&script_path, rendered)?;
+    let script = drive
+        .image_path(&script_path)
+        .expect("script is written to the drive");
+
+    let mut ventoy_json = VentoyJson::load(drive.ventoy_json_path())?;
+    ventoy_json.append("auto_install", "image", &image, "template", &script)?;
+    ventoy_json.save(drive.ventoy_json_path())?;
+    Ok(script_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use tempdir::TempDir;
+
+    fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn template_variables() {
+        assert_eq!(
+            variables("host={{hostname}} user={{ username }} home=/home/{{username}} {{}}"),
+            vec!["hostname", "username"]
+        );
+    }
+
+    #[test]
+    fn render_template() {
+        let content = "network --hostname={{hostname}}\nuser --name={{ username }}\n";
+        assert_eq!(
+            render(
+                content,
+                TemplateKind::Kickstart,
+                &vars(&[("hostname", "lab-01"), ("username", "ops")])
+            )
+            .unwrap(),
+            "network --hostname=lab-01\nuser --name=ops\n"
+        );
+        let err = render(
+            content,
+            TemplateKind::Kickstart,
+            &vars(&[("hostname", "lab-01")]),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "missing values for: username");
+    }
+
+    #[test]
+    fn escape_values() {
+        let xml = "<ComputerName>{{hostname}}</ComputerName>";
+        assert_eq!(
+            render(
+                xml,
+                TemplateKind::Autounattend,
+                &vars(&[("hostname", "a&b</ComputerName><x>")])
+            )
+            .unwrap(),
+            "<ComputerName>a&amp;b&lt;/ComputerName&gt;&lt;x&gt;</ComputerName>"
+        );
+
+        let yaml = "hostname: {{hostname}}\npassword: \"{{password_hash}}\"\nkey: '{{ssh_key}}'";
+        let vars = vars(&[
+            ("hostname", "lab\nusers: [root]"),
+            ("password_hash", "$6$\"x\\"),
+            ("ssh_key", "it's"),
+        ]);
+        assert_eq!(
+            render(yaml, TemplateKind::CloudInit, &vars).unwrap(),
+            "hostname: \"lab\\nusers: [root]\"\npassword: \"$6$\\\"x\\\\\"\nkey: 'it''s'"
+        );
+
+        let err = render(
+            "network --hostname={{hostname}}",
+            TemplateKind::Kickstart,
+            &vars,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("line breaks"), "{}", err);
+        let err = render(
+            "sshkey --username=ops \"{{password_hash}}\"",
+            TemplateKind::Kickstart,
+            &vars,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("quoted"), "{}", err);
+
+        assert!(is_secret("password"));
+        assert!(is_secret("root_Password_hash"));
+        assert!(!is_secret("ssh_key"));
+    }
+
+    #[test]
+    fn preseed_shell_values() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let preseed = TemplateLibrary::new(tmpdir.path())
+            .templates()
+            .unwrap()
+            .into_iter()
+            .find(|template| template.kind == TemplateKind::Preseed)
+            .unwrap();
+        let with = |username: &str, ssh_key: &str| {
+            preseed.render(&vars(&[
+                ("hostname", "lab-01"),
+                ("username", username),
+                ("password_hash", "$6$salt$hash"),
+                ("timezone", "UTC"),
+                ("ssh_key", ssh_key),
+            ]))
+        };
+
+        let rendered = with("ops", "ssh-ed25519 AAAA ops@lab").unwrap();
+        assert!(rendered.contains("echo 'ssh-ed25519 AAAA ops@lab' > /target/home/ops/.ssh/"));
+        for username in [
+            "ops;reboot",
+            "$(reboot)",
+            "Ops",
+            "-ops",
+            "1ops",
+            "ops/../root",
+        ] {
+            let err = with(username, "ssh-ed25519 AAAA").unwrap_err();
+            assert!(err.to_string().contains("username"), "{}", err);
+        }
+        let err = with("ops", "x'; reboot; echo '").unwrap_err();
+        assert!(err.to_string().contains("ssh_key"), "{}", err);
+        let err = render(
+            "d-i preseed/late_command string echo \"{{ssh_key}}\"",
+            TemplateKind::Preseed,
+            &vars(&[("ssh_key", "$(reboot)")]),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("shell"), "{}", err);
+    }
+
+    #[test]
+    fn builtin_templates_use_common_variables() {
+        let tmpdir = TempDir::new("test").unwrap();
+        for template in TemplateLibrary::new(tmpdir.path()).templates().unwrap() {
+            let vars = template.variables();
+            for common in COMMON_VARIABLES {
+                // autounattend has no ssh
+                if template.kind != TemplateKind::Autounattend || *common != "ssh_key" {
+                    assert!(vars.contains(common), "{} lacks {}", template.name, common);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn library_import_and_remove() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let library = TemplateLibrary::new(tmpdir.path().join("templates"));
+        let src = tmpdir.path().join("lab.cfg");
+        fs::write(&src, "d-i netcfg/get_hostname string {{hostname}}\n").unwrap();
+
+        let template = library.import(&src).unwrap();
+        assert_eq!(template.kind, TemplateKind::Preseed);
+        let templates = library.templates().unwrap();
+        assert_eq!(templates.len(), BUILTIN_TEMPLATES.len() + 1);
+        assert!(templates.iter().any(|t| t.name == "lab" && !t.builtin));
+
+        // Dropped in by hand with an extension other than its kind's
+        let yml = tmpdir.path().join("templates/ops.yml");
+        fs::write(&yml, "#cloud-config\nhostname: {{hostname}}\n").unwrap();
+        let templates = library.templates().unwrap();
+        let ops = templates.iter().find(|t| t.name == "ops").unwrap();
+        assert_eq!(ops.file_name(), "ops.yaml");
+        library.remove(ops).unwrap();
+        assert!(!yml.exists());
+
+        library.remove(&template).unwrap();
+        assert!(library.remove(&templates[0]).is_err());
+        assert_eq!(library.templates().unwrap().len(), BUILTIN_TEMPLATES.len());
+    }
+
+    #[test]
+    fn install_on_drive() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let drive = VentoyDrive::new(tmpdir.path());
+        fs::create_dir_all(tmpdir.path().join("ISO")).unwrap();
+        let iso = tmpdir.path().join("ISO/fedora.iso");
+        fs::File::create(&iso).unwrap();
+        let template = Template::new("lab.ks", "network --hostname={{hostname}}\n".into(), false);
+
+        let script = install(&drive, &iso, &template, &vars(&[("hostname", "lab-01")])).unwrap();
+        assert_eq!(
+            fs::read_to_string(script).unwrap(),
+            "network --hostname=lab-01\n"
+        );
+        let ventoy_json = VentoyJson::load(drive.ventoy_json_path()).unwrap();
+        assert_eq!(
+            ventoy_json.entries("auto_install"),
+            &[json!({ "image": "/ISO/fedora.iso", "template": "/ventoy/script/fedora_lab.ks" })]
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! What a frontend keeps between runs that can be fetched or downloaded again.
+
+use serde::{Deserialize, Serialize};
+
+use super::{downloads::DownloadRequest, mirror::IsoSource, update::CachedPkg, utils::FeedsItem};
+
+#[derive(Default, Serialize, Deserialize, Clone, Debug)]
+pub struct AppCache {
+    pub release_feeds: Vec<FeedsItem>,
+    pub ventoy_update_pkg: Option<CachedPkg>,
+    /// Unfinished downloads, queued again on the next start
+    #[serde(default)]
+    pub downloads: Vec<DownloadRequest>,
+    /// Unfinished mirror downloads, started again with their verification
+    #[serde(default)]
+    pub iso_downloads: Vec<IsoSource>,
+}
+
+impl AppCache {
+    /// Forgets the cached package if it was removed since.
+    pub fn drop_missing_pkg(&mut self) {
+        if let Some(pkg) = self.ventoy_update_pkg.take() {
+            if pkg.path.is_file() {
+                self.ventoy_update_pkg = Some(pkg);
+            }
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Hints on how well distros boot with Ventoy, from a database bundled with the app that
+//! a local file can update.
+//!
+//! Entries are keyed by distro, as in [`distro_key`], and optionally by version prefixes.
+//! Every bundled entry names its source, e.g. Ventoy's list of tested images.
+//!
+//! The bundled database only covers images on that tested list, so it never says that
+//! an image needs the GRUB2 or memdisk mode or has a known issue. Those come from a
+//! local database, and distros in neither have no hint at all.
+
+use std::{fmt, fs, path::Path};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use super::{iso::IsoInfo, utils::FeedsItem};
+
+const BUNDLED: &str = include_str!("../assets/compat.json");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompatStatus {
+    /// Boots in the normal mode
+    Tested,
+    /// Needs Ventoy's GRUB2 mode
+    Grub2,
+    /// Needs Ventoy's memdisk mode
+    Memdisk,
+    KnownIssue,
+}
+
+impl CompatStatus {
+    /// What to do about it in Ventoy's boot menu.
+    pub fn hint(self) -> &'static str {
+        match self {
+            Self::Tested => "Boots with Ventoy as is.",
+            Self::Grub2 => "Press Ctrl+R on it in Ventoy's boot menu to boot in GRUB2 mode.",
+            Self::Memdisk => "Press Ctrl+D on it in Ventoy's boot menu to boot in memdisk mode.",
+            Self::KnownIssue => "It's known not to boot properly with Ventoy.",
+        }
+    }
+}
+
+impl fmt::Display for CompatStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Tested => "tested",
+            Self::Grub2 => "needs grub2 mode",
+            Self::Memdisk => "needs memdisk mode",
+            Self::KnownIssue => "known issue",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompatEntry {
+    /// Key of the distro, see [`distro_key`]
+    pub distro: String,
+    /// Version prefixes it applies to, e.g. `22.04` for `22.04.1`, all versions if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub versions: Vec<String>,
+    pub status: CompatStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Where the status comes from, e.g. a page of Ventoy's documentation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+impl CompatEntry {
+    fn matches_version(&self, version: Option<&str>) -> bool {
+        self.versions.is_empty()
+            || version.is_some_and(|version| {
+                self.versions.iter().any(|prefix| {
+                    version
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '-']))
+                })
+            })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompatDb {
+    /// When the entries were last reviewed, e.g. `2023-01-15`
+    #[serde(default)]
+    pub updated: Option<String>,
+    pub entries: Vec<CompatEntry>,
+}
+
+impl CompatDb {
+    /// The database bundled with the app, only tested images.
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED).expect("the bundled compatibility database is valid")
+    }
+
+    pub fn parse(json: &str) -> anyhow::Result<Self> {
+        let mut db: Self = serde_json::from_str(json)?;
+        for entry in &mut db.entries {
+            entry.distro = distro_key(&entry.distro);
+        }
+        Ok(db)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path).with_context(|| format!("failed to read {path:?}"))?;
+        Self::parse(&json).with_context(|| format!("failed to parse {path:?}"))
+    }
+
+    /// The bundled database with the distros in the one at `path` replaced by its entries.
+    pub fn load_over_bundled<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let local = Self::load(path)?;
+        let mut db = Self::bundled();
+        db.entries.retain(|entry| {
+            !local
+                .entries
+                .iter()
+                .any(|local| local.distro == entry.distro)
+        });
+        db.entries.extend(local.entries);
+        db.updated = local.updated.or(db.updated);
+        Ok(db)
+    }
+
+    /// The entry of `distro`, preferring the ones listing `version`.
+    pub fn lookup(&self, distro: &str, version: Option<&str>) -> Option<&CompatEntry> {
+        let key = distro_key(distro);
+        self.entries
+            .iter()
+            .filter(|entry| entry.distro == key && entry.matches_version(version))
+            .min_by_key(|entry| entry.versions.is_empty())
+    }
+
+    pub fn for_feed(&self, item: &FeedsItem) -> Option<&CompatEntry> {
+        self.lookup(&item.group, item.version())
+    }
+
+    pub fn for_iso(&self, info: &IsoInfo) -> Option<&CompatEntry> {
+        let distro = info.distro.as_ref()?;
+        self.lookup(&distro.name, distro.version.as_deref())
+    }
+}
+
+/// Lowercase alphanumeric key of a distro name or feeds group, e.g. `linuxmint` for both
+/// `Linux Mint` and `mint`, and `debian` for `Debian GNU/Linux`.
+pub fn distro_key(name: &str) -> String {
+    let key: String = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+        .replace("gnulinux", "");
+    match key.as_str() {
+        "arch" => "archlinux",
+        "mint" => "linuxmint",
+        "kali" => "kalilinux",
+        "rocky" => "rockylinux",
+        "alma" => "almalinux",
+        "qubesos" => "qubes",
+        "redhat" | "rhel" => "rhel",
+        key if key.starts_with("opensuse") => "opensuse",
+        key if key.starts_with("memtest86") => "memtest86",
+        _ => return key,
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::iso::{self, Distro};
+    use tempdir::TempDir;
+
+    #[test]
+    fn bundled_lookups() {
+        let db = CompatDb::bundled();
+        // As the module docs say
+        assert!(db
+            .entries
+            .iter()
+            .all(|entry| entry.status == CompatStatus::Tested));
+        let item = FeedsItem {
+            group: "mint".to_string(),
+            name: "linuxmint-21.1-cinnamon-64bit.iso.torrent".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(db.for_feed(&item).unwrap().status, CompatStatus::Tested);
+
+        let info = |name: &str, version: Option<&str>| IsoInfo {
+            distro: Some(Distro {
+                name: name.to_string(),
+                version: version.map(String::from),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            db.for_iso(&info("Arch Linux", Some("2023.01.01")))
+                .unwrap()
+                .distro,
+            "archlinux"
+        );
+        assert_eq!(
+            db.for_iso(&info("openSUSE Leap", Some("15.4")))
+                .unwrap()
+                .distro,
+            "opensuse"
+        );
+        // Windows images are detected without a version, nothing to go by
+        assert_eq!(db.for_iso(&info("Windows", None)), None);
+        assert_eq!(db.for_iso(&info("Haiku", Some("r1beta4"))), None);
+        assert_eq!(db.for_iso(&IsoInfo::default()), None);
+        assert_eq!(CompatStatus::Grub2.to_string(), "needs grub2 mode");
+        assert!(db.entries.iter().all(|entry| entry.source.is_some()));
+    }
+
+    #[test]
+    fn disk_info_lookups() {
+        let db = CompatDb::bundled();
+        for (disk_info, distro) in [
+            (
+                "Debian GNU/Linux 11.6.0 \"Bullseye\" - Official amd64 DVD Binary-1 20221217-10:40",
+                "debian",
+            ),
+            (
+                "Kali GNU/Linux 2022.4 \"Kali-last-snapshot\" - Official amd64 LIVE Binary 20221205-09:14",
+                "kalilinux",
+            ),
+            (
+                "Linux Mint 21.1 \"Vera\" - Release amd64 20221217",
+                "linuxmint",
+            ),
+            (
+                "Ubuntu 22.10 \"Kinetic Kudu\" - Release amd64 (20221020)",
+                "ubuntu",
+            ),
+        ] {
+            let detected = iso::distro_from_disk_info(disk_info).unwrap();
+            let entry = db.lookup(&detected.name, detected.version.as_deref());
+            assert_eq!(entry.map(|entry| entry.distro.as_str()), Some(distro));
+        }
+    }
+
+    #[test]
+    fn update_from_local_file() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let path = tmpdir.path().join("compat.json");
+        std::fs::write(
+            &path,
+            r#"{
+                "updated": "2023-02-01",
+                "entries": [
+                    { "distro": "Ubuntu", "versions": ["23.04"], "status": "known_issue", "note": "Beta" },
+                    { "distro": "NixOS", "status": "grub2" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let db = CompatDb::load_over_bundled(&path).unwrap();
+        assert_eq!(db.updated.as_deref(), Some("2023-02-01"));
+        let entry = db.lookup("ubuntu", Some("23.04")).unwrap();
+        assert_eq!(
+            (entry.status, entry.note.as_deref()),
+            (CompatStatus::KnownIssue, Some("Beta"))
+        );
+        // The bundled entries of ubuntu are replaced
+        assert_eq!(db.lookup("ubuntu", Some("22.10")), None);
+        // Not `23.04` as in `23.040`
+        assert_eq!(db.lookup("ubuntu", Some("23.040")), None);
+        assert_eq!(
+            db.lookup("nixos", None).unwrap().status,
+            CompatStatus::Grub2
+        );
+        assert_eq!(
+            db.lookup("debian", Some("11")).unwrap().status,
+            CompatStatus::Tested
+        );
+
+        std::fs::write(
+            &path,
+            r#"{ "entries": [{ "distro": "nixos", "status": "works" }] }"#,
+        )
+        .unwrap();
+        assert!(CompatDb::load_over_bundled(&path).is_err());
+    }
+}
@@ -0,0 +1,582 @@
+//! A queue of downloads, running a few at a time under a shared bandwidth cap and
+//! retrying transient failures with an exponential backoff.
+//!
+//! Every running download is listed in the [`TaskManager`] it was created with, so it can
+//! be cancelled from there too. Unfinished downloads can be persisted with
+//! [`DownloadManager::pending`] and queued again on the next start.
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::{Error, Result},
+    task::{CancelToken, Task, TaskManager},
+    update,
+};
+
+/// How long blocking waits sleep at most before checking for a cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadConfig {
+    /// Downloads running at once, the rest wait in the queue
+    pub max_concurrent: usize,
+    /// Bytes per second shared by all downloads, unlimited if `None`
+    pub bandwidth_limit: Option<u64>,
+    /// Retries of a download failing with a retryable error
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for every further one
+    pub retry_delay: Duration,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 2,
+            bandwidth_limit: None,
+            max_retries: 3,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DownloadKind {
+    /// A Ventoy package, installed once downloaded
+    Package,
+    Torrent,
+    Iso,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadRequest {
+    pub url: String,
+    /// Written atomically, see [`update::download`]
+    pub dest: PathBuf,
+    pub kind: DownloadKind,
+}
+
+impl DownloadRequest {
+    /// File name of `dest`, falling back to the url.
+    pub fn name(&self) -> String {
+        self.dest
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.url.clone())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum DownloadStatus {
+    Queued,
+    Running {
+        done: u64,
+        total: Option<u64>,
+    },
+    /// Waiting to try again after `error`
+    Retrying {
+        attempt: u32,
+        error: Error,
+    },
+    Done,
+    Failed(Error),
+    Cancelled,
+}
+
+impl DownloadStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Done | Self::Failed(_) | Self::Cancelled)
+    }
+
+    fn is_active(&self) -> bool {
+        matches!(self, Self::Running { .. } | Self::Retrying { .. })
+    }
+}
+
+pub type DownloadId = u64;
+
+#[derive(Debug, Clone)]
+pub struct DownloadInfo {
+    pub id: DownloadId,
+    pub request: DownloadRequest,
+    pub status: DownloadStatus,
+}
+
+#[derive(Debug)]
+struct Entry {
+    info: DownloadInfo,
+    /// Token of the task running it
+    token: Option<CancelToken>,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    config: DownloadConfig,
+    next_id: DownloadId,
+    entries: Vec<Entry>,
+}
+
+impl Queue {
+    fn entry_mut(&mut self, id: DownloadId) -> Option<&mut Entry> {
+        self.entries.iter_mut().find(|entry| entry.info.id == id)
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    queue: Mutex<Queue>,
+    /// Notified whenever a download finishes
+    finished: Condvar,
+    limiter: RateLimiter,
+    tasks: TaskManager,
+}
+
+/// Shared handle to the queue, clones refer to the same queue.
+#[derive(Debug, Clone)]
+pub struct DownloadManager {
+    inner: Arc<Inner>,
+}
+
+impl Default for DownloadManager {
+    fn default() -> Self {
+        Self::new(Default::default(), Default::default())
+    }
+}
+
+impl DownloadManager {
+    pub fn new(config: DownloadConfig, tasks: TaskManager) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                limiter: RateLimiter::new(config.bandwidth_limit),
+                queue: Mutex::new(Queue {
+                    config,
+                    ..Default::default()
+                }),
+                finished: Condvar::new(),
+                tasks,
+            }),
+        }
+    }
+
+    pub fn config(&self) -> DownloadConfig {
+        self.inner.queue.lock().unwrap().config.clone()
+    }
+
+    /// Applies to the running downloads too, apart from their retries.
+    pub fn set_config(&self, config: DownloadConfig) {
+        self.inner.limiter.set_rate(config.bandwidth_limit);
+        self.inner.queue.lock().unwrap().config = config;
+        start_queued(&self.inner);
+    }
+
+    pub fn enqueue(&self, request: DownloadRequest) -> DownloadId {
+        let id = {
+            let mut queue = self.inner.queue.lock().unwrap();
+            // Two downloads into the same file would race to replace it
+            if let Some(entry) = queue.entries.iter().find(|entry| {
+                !entry.info.status.is_finished() && entry.info.request.dest == request.dest
+            }) {
+                tracing::info!(
+                    "Already downloading {:?} as {}",
+                    request.dest,
+                    entry.info.id
+                );
+                return entry.info.id;
+            }
+            let id = queue.next_id;
+            queue.next_id += 1;
+            tracing::info!("Queued download {}: {}", id, request.url);
+            queue.entries.push(Entry {
+                info: DownloadInfo {
+                    id,
+                    request,
+                    status: DownloadStatus::Queued,
+                },
+                token: None,
+            });
+            id
+        };
+        start_queued(&self.inner);
+        id
+    }
+
+    /// Every download since the last [`DownloadManager::clear_finished`], in queued order.
+    pub fn downloads(&self) -> Vec<DownloadInfo> {
+        let queue = self.inner.queue.lock().unwrap();
+        queue
+            .entries
+            .iter()
+            .map(|entry| entry.info.clone())
+            .collect()
+    }
+
+    /// Requests of the downloads that didn't finish yet.
+    pub fn pending(&self) -> Vec<DownloadRequest> {
+        let queue = self.inner.queue.lock().unwrap();
+        queue
+            .entries
+            .iter()
+            .filter(|entry| !entry.info.status.is_finished())
+            .map(|entry| entry.info.request.clone())
+            .collect()
+    }
+
+    /// Queued downloads are cancelled right away, running ones once they stop.
+    pub fn cancel(&self, id: DownloadId) {
+        let mut queue = self.inner.queue.lock().unwrap();
+        if let Some(entry) = queue.entry_mut(id) {
+            match (&entry.info.status, &entry.token) {
+                (DownloadStatus::Queued, _) => {
+                    entry.info.status = DownloadStatus::Cancelled;
+                    self.inner.finished.notify_all();
+                }
+                (status, Some(token)) if !status.is_finished() => token.cancel(),
+                _ => (),
+            }
+        }
+    }
+
+    pub fn clear_finished(&self) {
+        let mut queue = self.inner.queue.lock().unwrap();
+        queue
+            .entries
+            .retain(|entry| !entry.info.status.is_finished());
+    }
+
+    /// Blocks until the download finished, cancelling it once `cancel` is cancelled.
+    pub fn wait(&self, id: DownloadId, cancel: &CancelToken) -> Result<()> {
+        let mut queue = self.inner.queue.lock().unwrap();
+        loop {
+            let entry = match queue.entry_mut(id) {
+                Some(entry) => entry,
+                None => return Err(Error::Cancelled),
+            };
+            match &entry.info.status {
+                DownloadStatus::Done => return Ok(()),
+                DownloadStatus::Failed(err) => return Err(err.clone()),
+                DownloadStatus::Cancelled => return Err(Error::Cancelled),
+                _ => (),
+            }
+            if cancel.is_cancelled() {
+                // Still waited for, so that it's cleaned up after on return
+                drop(queue);
+                self.cancel(id);
+                queue = self.inner.queue.lock().unwrap();
+            }
+            queue = self
+                .inner
+                .finished
+                .wait_timeout(queue, POLL_INTERVAL)
+                .unwrap()
+                .0;
+        }
+    }
+}
+
+/// Starts queued downloads until `max_concurrent` of them are running.
+fn start_queued(inner: &Arc<Inner>) {
+    let mut queue = inner.queue.lock().unwrap();
+    let max_concurrent = queue.config.max_concurrent.max(1);
+    loop {
+        let running = queue
+            .entries
+            .iter()
+            .filter(|entry| entry.info.status.is_active())
+            .count();
+        if running >= max_concurrent {
+            break;
+        }
+        let entry = match queue
+            .entries
+            .iter_mut()
+            .find(|entry| matches!(entry.info.status, DownloadStatus::Queued))
+        {
+            Some(entry) => entry,
+            None => break,
+        };
+        let task = inner
+            .tasks
+            .start(format!("Downloading {}", entry.info.request.name()));
+        entry.token = Some(task.token().clone());
+        entry.info.status = DownloadStatus::Running {
+            done: 0,
+            total: None,
+        };
+        let (id, request) = (entry.info.id, entry.info.request.clone());
+        let inner = Arc::clone(inner);
+        thread::Builder::new()
+            .name(format!("download-{id}"))
+            .spawn(move || run(inner, id, request, task))
+            .expect("failed to spawn a download thread");
+    }
+}
+
+fn run(inner: Arc<Inner>, id: DownloadId, request: DownloadRequest, task: Task) {
+    let set_status = |status| {
+        if let Some(entry) = inner.queue.lock().unwrap().entry_mut(id) {
+            entry.info.status = status;
+        }
+    };
+    let config = inner.queue.lock().unwrap().config.clone();
+    let mut attempt = 0;
+    let downloaded = loop {
+        let mut last = 0;
+        let downloaded = request
+            .dest
+            .parent()
+            .map_or(Ok(()), |dir| {
+                fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))
+            })
+            .and_then(|_| {
+                update::download_with(&request.url, &request.dest, task.token(), |done, total| {
+                    inner.limiter.take(done - last, task.token());
+                    last = done;
+                    set_status(DownloadStatus::Running { done, total });
+                })
+            });
+        match downloaded {
+            Err(err)
+                if err.is_retryable()
+                    && !matches!(err, Error::Cancelled)
+                    && attempt < config.max_retries =>
+            {
+                let delay = config.retry_delay * 2u32.pow(attempt);
+                attempt += 1;
+                tracing::warn!(
+                    "Download of {} failed, retrying in {:?}: {}",
+                    request.url,
+                    delay,
+                    err
+                );
+                set_status(DownloadStatus::Retrying {
+                    attempt,
+                    error: err,
+                });
+                if !sleep_unless_cancelled(delay, task.token()) {
+                    break Err(Error::Cancelled);
+                }
+            }
+            downloaded => break downloaded,
+        }
+    };
+    let status = match downloaded {
+        Ok(()) => {
+            tracing::info!("Downloaded {} to {:?}", request.url, request.dest);
+            DownloadStatus::Done
+        }
+        Err(Error::Cancelled) => {
+            tracing::info!("Cancelled the download of {}", request.url);
+            DownloadStatus::Cancelled
+        }
+        Err(err) => {
+            tracing::error!("Failed to download {}: {}", request.url, err);
+            DownloadStatus::Failed(err)
+        }
+    };
+    set_status(status);
+    drop(task);
+    inner.finished.notify_all();
+    start_queued(&inner);
+}
+
+/// Returns `false` if `cancel` was cancelled before `duration` passed.
+fn sleep_unless_cancelled(duration: Duration, cancel: &CancelToken) -> bool {
+    let until = Instant::now() + duration;
+    loop {
+        if cancel.is_cancelled() {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= until {
+            return true;
+        }
+        thread::sleep((until - now).min(POLL_INTERVAL));
+    }
+}
+
+/// A token bucket holding up to a second worth of bytes, going into debt for chunks
+/// larger than what's left.
+#[derive(Debug)]
+struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate: Option<u64>,
+    allowance: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    fn new(rate: Option<u64>) -> Self {
+        Self {
+            bucket: Mutex::new(Bucket {
+                rate,
+                allowance: 0.,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    fn set_rate(&self, rate: Option<u64>) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.rate = rate;
+        bucket.allowance = bucket.allowance.min(rate.unwrap_or(0) as f64);
+    }
+
+    /// Blocks until `bytes` fit in the bandwidth limit.
+    fn take(&self, bytes: u64, cancel: &CancelToken) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let rate = match bucket.rate {
+                Some(rate) if rate > 0 => rate as f64,
+                _ => return,
+            };
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.refilled).as_secs_f64() * rate;
+            bucket.allowance = (bucket.allowance + refill).min(rate) - bytes as f64;
+            bucket.refilled = now;
+            if bucket.allowance >= 0. {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.allowance / rate)
+        };
+        sleep_unless_cancelled(wait, cancel);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{MockResponse, MockServer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tempdir::TempDir;
+
+    fn request(server: &MockServer, dir: &TempDir, name: &str) -> DownloadRequest {
+        DownloadRequest {
+            url: format!("{}/{name}", server.url),
+            dest: dir.path().join(name),
+            kind: DownloadKind::Iso,
+        }
+    }
+
+    #[test]
+    fn throttle_downloads() {
+        let server = MockServer::start(|_| MockResponse::new(200, vec![1; 200 * 1024]));
+        let tmpdir = TempDir::new("test").unwrap();
+        let downloads = DownloadManager::default();
+        downloads.set_config(DownloadConfig {
+            bandwidth_limit: Some(400 * 1024),
+            ..Default::default()
+        });
+
+        let started = Instant::now();
+        let id = downloads.enqueue(request(&server, &tmpdir, "alpine.iso"));
+        downloads.wait(id, &CancelToken::new()).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(400));
+        assert_eq!(
+            fs::read(tmpdir.path().join("alpine.iso")).unwrap(),
+            vec![1; 200 * 1024]
+        );
+        assert!(matches!(
+            downloads.downloads()[0].status,
+            DownloadStatus::Done
+        ));
+    }
+
+    #[test]
+    fn queue_and_retry_downloads() {
+        // Fails the first request of every file
+        let requests = AtomicUsize::new(0);
+        let server = MockServer::start(move |req| {
+            if requests.fetch_add(1, Ordering::SeqCst) % 2 == 0 {
+                MockResponse::new(503, "Service Unavailable")
+            } else {
+                MockResponse::new(200, req.path.clone())
+            }
+        });
+        let tmpdir = TempDir::new("test").unwrap();
+        let tasks = TaskManager::default();
+        let downloads = DownloadManager::new(
+            DownloadConfig {
+                max_concurrent: 1,
+                retry_delay: Duration::from_millis(10),
+                ..Default::default()
+            },
+            tasks.clone(),
+        );
+
+        let first = downloads.enqueue(request(&server, &tmpdir, "debian.iso"));
+        let second = downloads.enqueue(request(&server, &tmpdir, "fedora.iso"));
+        let statuses = downloads.downloads();
+        assert!(statuses[0].status.is_active());
+        assert!(matches!(statuses[1].status, DownloadStatus::Queued));
+        assert_eq!(downloads.pending().len(), 2);
+        assert_eq!(tasks.tasks().len(), 1);
+        // Queued again into the same file, e.g. restored and then started by hand
+        let again = downloads.enqueue(request(&server, &tmpdir, "fedora.iso"));
+        assert_eq!(again, second);
+        assert_eq!(downloads.downloads().len(), 2);
+
+        let cancel = CancelToken::new();
+        downloads.wait(first, &cancel).unwrap();
+        downloads.wait(second, &cancel).unwrap();
+        assert_eq!(
+            fs::read_to_string(tmpdir.path().join("fedora.iso")).unwrap(),
+            "/fedora.iso"
+        );
+        assert_eq!(server.requests().len(), 4);
+        assert!(downloads.pending().is_empty());
+
+        // Not found isn't retried
+        let server = MockServer::start(|_| MockResponse::new(404, "Not Found"));
+        let missing = downloads.enqueue(request(&server, &tmpdir, "missing.iso"));
+        let err = downloads.wait(missing, &cancel).unwrap_err();
+        assert!(matches!(err, Error::HttpStatus { status: 404, .. }));
+        assert_eq!(server.requests().len(), 1);
+
+        downloads.clear_finished();
+        assert!(downloads.downloads().is_empty());
+    }
+
+    #[test]
+    fn cancel_queued_download() {
+        let server = MockServer::start(|_| MockResponse::new(200, vec![1; 64 * 1024]));
+        let tmpdir = TempDir::new("test").unwrap();
+        let downloads = DownloadManager::new(
+            DownloadConfig {
+                max_concurrent: 1,
+                bandwidth_limit: Some(16 * 1024),
+                ..Default::default()
+            },
+            TaskManager::default(),
+        );
+
+        let running = downloads.enqueue(request(&server, &tmpdir, "arch.iso"));
+        let queued = downloads.enqueue(request(&server, &tmpdir, "void.iso"));
+        downloads.cancel(queued);
+        let cancel = CancelToken::new();
+        assert!(matches!(
+            downloads.wait(queued, &cancel),
+            Err(Error::Cancelled)
+        ));
+
+        cancel.cancel();
+        assert!(matches!(
+            downloads.wait(running, &cancel),
+            Err(Error::Cancelled)
+        ));
+        // The partial download is removed
+        assert_eq!(fs::read_dir(tmpdir.path()).unwrap().count(), 0);
+        assert!(downloads.pending().is_empty());
+    }
+}
@@ -0,0 +1,754 @@
+//! Inspection of a mounted Ventoy drive.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::Context;
+
+use super::update;
+
+/// File types that Ventoy lists in its boot menu.
+pub const IMAGE_EXTENSIONS: &[&str] = &["iso", "wim", "img", "vhd", "vhdx", "efi", "vtoy"];
+
+#[derive(Debug, Clone)]
+pub struct VentoyDrive {
+    root: PathBuf,
+}
+
+impl VentoyDrive {
+    /// `root` is the mount point of the Ventoy (data) partition.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn ventoy_dir(&self) -> PathBuf {
+        self.root.join("ventoy")
+    }
+
+    pub fn ventoy_json_path(&self) -> PathBuf {
+        self.ventoy_dir().join("ventoy.json")
+    }
+
+    /// Lists all bootable images on the drive, skipping the `ventoy` config directory.
+    pub fn images(&self) -> io::Result<Vec<PathBuf>> {
+        fn walk(dir: &Path, skip: &Path, images: &mut Vec<PathBuf>) -> io::Result<()> {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    if path != skip {
+                        walk(&path, skip, images)?;
+                    }
+                } else if is_image(&path) {
+                    images.push(path);
+                }
+            }
+            Ok(())
+        }
+
+        let mut images = Vec::new();
+        walk(&self.root, &self.ventoy_dir(), &mut images)?;
+        images.sort();
+        Ok(images)
+    }
+
+    /// Path of an image the way Ventoy plugins refer to it, e.g. `/ISO/ubuntu.iso`.
+    pub fn image_path<P: AsRef<Path>>(&self, image: P) -> Option<String> {
+        let relative = image.as_ref().strip_prefix(&self.root).ok()?;
+        let mut path = String::new();
+        for component in relative.components() {
+            path.push('/');
+            path.push_str(component.as_os_str().to_str()?);
+        }
+        Some(path)
+    }
+}
+
+/// Version of Ventoy installed on a drive, e.g. `1.0.86`, read from `grub/grub.cfg` on
+/// the mount point of its `VTOYEFI` partition.
+pub fn installed_version<P: AsRef<Path>>(efi_root: P) -> Option<String> {
+    let grub_cfg = fs::read_to_string(efi_root.as_ref().join("grub").join("grub.cfg")).ok()?;
+    grub_cfg.lines().find_map(|line| {
+        let version = line.trim().strip_prefix("set VENTOY_VERSION=")?;
+        Some(version.trim_matches('"').to_string())
+    })
+}
+
+/// Mount point of the `VTOYEFI` partition of the drive whose Ventoy partition is mounted
+/// at `root`, from `/proc/self/mounts`.
+pub fn efi_partition<P: AsRef<Path>>(root: P) -> Option<PathBuf> {
+    efi_partition_in(root, &read_mounts("/proc/self/mounts").ok()?)
+}
+
+/// Ventoy puts the `VTOYEFI` partition right after the Ventoy one on the same disk, e.g.
+/// `/dev/sdb2` for `/dev/sdb1` or `/dev/nvme0n1p2` for `/dev/nvme0n1p1`.
+pub fn efi_partition_in<P: AsRef<Path>>(root: P, mounts: &[Mount]) -> Option<PathBuf> {
+    let root = root.as_ref();
+    let source = &mounts
+        .iter()
+        .find(|mount| Path::new(&mount.mount_point) == root)?
+        .source;
+    let disk = source.trim_end_matches(|c: char| c.is_ascii_digit());
+    let number: u32 = source[disk.len()..].parse().ok()?;
+    let efi = format!("{disk}{}", number + 1);
+    mounts
+        .iter()
+        .find(|mount| mount.source == efi)
+        .map(|mount| PathBuf::from(&mount.mount_point))
+}
+
+/// A whole disk, as listed in `/sys/block` on Linux.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockDevice {
+    /// e.g. `sdb`
+    pub name: String,
+    /// e.g. `/dev/sdb`
+    pub path: PathBuf,
+    /// Vendor and model, e.g. `SanDisk Cruzer Blade`
+    pub model: String,
+    /// Size in bytes
+    pub size: u64,
+    pub removable: bool,
+}
+
+impl BlockDevice {
+    /// e.g. `SanDisk Cruzer Blade (/dev/sdb, 14.9 GiB)`, used wherever a device is
+    /// about to be overwritten.
+    pub fn description(&self) -> String {
+        let model = if self.model.is_empty() {
+            "Unknown device"
+        } else {
+            &self.model
+        };
+        format!(
+            "{} ({}, {})",
+            model,
+            self.path.display(),
+            human_size(self.size)
+        )
+    }
+}
+
+/// Lists the block devices of the system, skipping virtual ones like loop devices.
+pub fn block_devices() -> io::Result<Vec<BlockDevice>> {
+    if cfg!(target_os = "linux") {
+        block_devices_in("/sys/block", "/dev")
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// [`block_devices`] from a sysfs `block` directory, with device nodes in `dev_dir`.
+pub fn block_devices_in<P: AsRef<Path>, Q: AsRef<Path>>(
+    sys_block: P,
+    dev_dir: Q,
+) -> io::Result<Vec<BlockDevice>> {
+    let read = |path: PathBuf| {
+        fs::read_to_string(path)
+            .map(|content| content.trim().to_string())
+            .unwrap_or_default()
+    };
+    let mut devices = Vec::new();
+    for entry in fs::read_dir(sys_block)? {
+        let sys_path = entry?.path();
+        let Some(name) = sys_path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if ["loop", "ram", "zram", "dm-", "sr"]
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+        {
+            continue;
+        }
+        // `size` is always in 512 byte sectors
+        let size = read(sys_path.join("size"))
+            .parse::<u64>()
+            .unwrap_or_default()
+            * 512;
+        if size == 0 {
+            continue;
+        }
+        let model = [
+            read(sys_path.join("device/vendor")),
+            read(sys_path.join("device/model")),
+        ]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+        devices.push(BlockDevice {
+            path: dev_dir.as_ref().join(name),
+            name: name.to_string(),
+            model,
+            size,
+            removable: read(sys_path.join("removable")) == "1",
+        });
+    }
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(devices)
+}
+
+/// Formats a byte count with binary units, e.g. `14.9 GiB`.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024. && unit < UNITS.len() - 1 {
+        size /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Mount points that make a disk a system disk.
+const SYSTEM_MOUNTS: &[&str] = &["/", "/boot", "/boot/efi", "/efi", "/home", "/usr", "/var"];
+/// Removable drives larger than this are most likely external hard disks with data on them.
+pub const LARGE_DRIVE_SIZE: u64 = 512 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mount {
+    pub source: String,
+    pub mount_point: String,
+    /// Filesystem type, e.g. `vfat` or `exfat`
+    pub fstype: String,
+}
+
+/// Parses the source, mount point and filesystem type of every entry of
+/// `/proc/self/mounts`.
+pub fn read_mounts<P: AsRef<Path>>(path: P) -> io::Result<Vec<Mount>> {
+    // Spaces and such are octal escaped, e.g. `\040`
+    fn unescape(field: &str) -> String {
+        let mut unescaped = String::new();
+        let mut rest = field;
+        while let Some(idx) = rest.find('\\') {
+            unescaped.push_str(&rest[..idx]);
+            match rest
+                .get(idx + 1..idx + 4)
+                .and_then(|oct| u8::from_str_radix(oct, 8).ok())
+            {
+                Some(byte) => {
+                    unescaped.push(byte as char);
+                    rest = &rest[idx + 4..];
+                }
+                None => {
+                    unescaped.push('\\');
+                    rest = &rest[idx + 1..];
+                }
+            }
+        }
+        unescaped.push_str(rest);
+        unescaped
+    }
+
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some(Mount {
+                source: unescape(fields.next()?),
+                mount_point: unescape(fields.next()?),
+                fstype: fields.next().unwrap_or_default().to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Parses `/proc/swaps`, with `[swap]` as the mount point of every entry.
+pub fn read_swaps<P: AsRef<Path>>(path: P) -> io::Result<Vec<Mount>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            Some(Mount {
+                source: line.split_whitespace().next()?.to_string(),
+                mount_point: "[swap]".to_string(),
+                fstype: "swap".to_string(),
+            })
+        })
+        .collect())
+}
+
+/// How risky it is to overwrite a device.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DeviceSafety {
+    /// Why the device is a system disk, overwriting it needs the expert override
+    pub system: Vec<String>,
+    /// Things worth a second look, that don't block overwriting
+    pub warnings: Vec<String>,
+    /// Partitions mounted outside the system, unmounted before overwriting, see
+    /// [`release_device`]
+    pub mounted: Vec<Mount>,
+}
+
+impl DeviceSafety {
+    pub fn is_system(&self) -> bool {
+        !self.system.is_empty()
+    }
+}
+
+/// Kernel names of the partitions of `disk` and of whatever is stacked on top of
+/// them, like LUKS or LVM volumes, with their `/dev/mapper` names.
+fn stacked_names(sys_block: &Path, disk: &str) -> Vec<String> {
+    fn holders(sys_block: &Path, dir: &Path, names: &mut Vec<String>, depth: usize) {
+        let Ok(entries) = fs::read_dir(dir.join("holders")) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if depth > 8 || names.contains(&name) {
+                continue;
+            }
+            if let Ok(mapper_name) = fs::read_to_string(sys_block.join(&name).join("dm/name")) {
+                names.push(format!("mapper/{}", mapper_name.trim()));
+            }
+            names.push(name.clone());
+            holders(sys_block, &sys_block.join(&name), names, depth + 1);
+        }
+    }
+
+    let disk_dir = sys_block.join(disk);
+    let mut names = vec![disk.to_string()];
+    holders(sys_block, &disk_dir, &mut names, 0);
+    if let Ok(entries) = fs::read_dir(&disk_dir) {
+        for entry in entries.flatten() {
+            if entry.path().join("partition").is_file() {
+                names.push(entry.file_name().to_string_lossy().to_string());
+                holders(sys_block, &entry.path(), &mut names, 0);
+            }
+        }
+    }
+    names
+}
+
+/// Classifies `device` by what's mounted from it, whether it's removable and its size.
+pub fn assess_device(device: &BlockDevice) -> io::Result<DeviceSafety> {
+    let mut mounts = read_mounts("/proc/self/mounts")?;
+    mounts.extend(read_swaps("/proc/swaps").unwrap_or_default());
+    Ok(assess_device_in(device, "/sys/block", &mounts))
+}
+
+/// [`assess_device`] with a sysfs `block` directory and mounts to check against.
+pub fn assess_device_in<P: AsRef<Path>>(
+    device: &BlockDevice,
+    sys_block: P,
+    mounts: &[Mount],
+) -> DeviceSafety {
+    let names = stacked_names(sys_block.as_ref(), &device.name);
+    let mut safety = DeviceSafety::default();
+    for mount in mounts {
+        // `/dev/disk/by-uuid/..` and such are symlinks to the kernel names
+        let source = fs::canonicalize(&mount.source)
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_else(|_| mount.source.clone());
+        let Some(name) = source.strip_prefix("/dev/") else {
+            continue;
+        };
+        if !names.iter().any(|n| n == name) {
+            continue;
+        }
+        if mount.mount_point == "[swap]" {
+            safety
+                .system
+                .push(format!("{} is in use as swap", mount.source));
+        } else if SYSTEM_MOUNTS.contains(&mount.mount_point.as_str()) {
+            safety.system.push(format!(
+                "{} is mounted from {}",
+                mount.mount_point, mount.source
+            ));
+        } else {
+            safety.warnings.push(format!(
+                "{} is mounted at {}, it's unmounted before writing",
+                mount.source, mount.mount_point
+            ));
+            safety.mounted.push(mount.clone());
+        }
+    }
+    if !device.removable {
+        safety
+            .warnings
+            .push("it's not reported as a removable drive".to_string());
+    }
+    if device.size > LARGE_DRIVE_SIZE {
+        safety.warnings.push(format!(
+            "it's larger than {}, unusual for a USB stick",
+            human_size(LARGE_DRIVE_SIZE)
+        ));
+    }
+    safety
+}
+
+/// Allows overwriting `device` only once its name was typed in, e.g. `sdb`, and if it's
+/// a system disk only with the expert override.
+pub fn check_overwrite(
+    device: &BlockDevice,
+    safety: &DeviceSafety,
+    typed_name: &str,
+    expert_override: bool,
+) -> anyhow::Result<()> {
+    if safety.is_system() && !expert_override {
+        anyhow::bail!(
+            "refusing to overwrite the system disk {}: {}",
+            device.name,
+            safety.system.join(", ")
+        );
+    }
+    if typed_name.trim() != device.name {
+        anyhow::bail!("type `{}` to confirm overwriting it", device.name);
+    }
+    Ok(())
+}
+
+/// Unmounts what's mounted from `device` right before it's overwritten, failing if
+/// anything outside the system mounts is left mounted.
+pub fn release_device(device: &BlockDevice) -> anyhow::Result<()> {
+    release_device_in(
+        device,
+        "/sys/block",
+        || read_mounts("/proc/self/mounts"),
+        unmount,
+    )
+}
+
+/// [`release_device`] with a sysfs `block` directory, the mounts to check against and
+/// how they're unmounted.
+pub fn release_device_in<P, F, U>(
+    device: &BlockDevice,
+    sys_block: P,
+    mut read_mounts: F,
+    mut unmount: U,
+) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+    F: FnMut() -> io::Result<Vec<Mount>>,
+    U: FnMut(&Mount) -> io::Result<()>,
+{
+    let mounted = |mounts: &[Mount]| assess_device_in(device, sys_block.as_ref(), mounts).mounted;
+    for mount in mounted(&read_mounts()?) {
+        tracing::info!("Unmounting {} from {}", mount.source, mount.mount_point);
+        unmount(&mount).with_context(|| format!("failed to unmount {}", mount.mount_point))?;
+    }
+    // Something may have been mounted again in the meantime, e.g. by the desktop
+    if let Some(mount) = mounted(&read_mounts()?).first() {
+        anyhow::bail!(
+            "{} is still mounted at {}, unmount it first",
+            mount.source,
+            mount.mount_point
+        );
+    }
+    Ok(())
+}
+
+/// Unmounts `mount` through udisks as desktops mount drives with it, which doesn't need
+/// root, and with `umount` otherwise.
+pub fn unmount(mount: &Mount) -> io::Result<()> {
+    let udisks = Command::new("udisksctl")
+        .args(["unmount", "--no-user-interaction", "-b", &mount.source])
+        .output();
+    if matches!(&udisks, Ok(output) if output.status.success()) {
+        return Ok(());
+    }
+    let output = Command::new("umount").arg(&mount.mount_point).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether `path` is a device node, which has to be picked as a drive to go through
+/// the safety checks instead of being written to as a file.
+pub fn is_device_node<P: AsRef<Path>>(path: P) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        matches!(
+            fs::metadata(path.as_ref()),
+            Ok(meta) if meta.file_type().is_block_device() || meta.file_type().is_char_device()
+        ) || path.as_ref().starts_with("/dev")
+    }
+    #[cfg(not(unix))]
+    {
+        path.as_ref().to_string_lossy().starts_with(r"\\.\")
+    }
+}
+
+/// Whether `path` has an image extension, staging files of unfinished copies aside.
+pub fn is_image<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref();
+    !update::is_staging(path)
+        && matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some(ext) if IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fs::File;
+    use std::cell::RefCell;
+    use tempdir::TempDir;
+
+    #[test]
+    fn read_installed_version() {
+        let tmpdir = TempDir::new("test").unwrap();
+        assert_eq!(installed_version(tmpdir.path()), None);
+        fs::create_dir(tmpdir.path().join("grub")).unwrap();
+        fs::write(
+            tmpdir.path().join("grub/grub.cfg"),
+            "insmod part_msdos\nset VENTOY_VERSION=\"1.0.86\"\nset VTOY_EFI_ARCH=x64\n",
+        )
+        .unwrap();
+        assert_eq!(installed_version(tmpdir.path()).as_deref(), Some("1.0.86"));
+    }
+
+    #[test]
+    fn list_images() {
+        let tmpdir = TempDir::new("test").unwrap();
+        fs::create_dir_all(tmpdir.path().join("ISO")).unwrap();
+        fs::create_dir_all(tmpdir.path().join("ventoy")).unwrap();
+        File::create(tmpdir.path().join("ISO/debian.iso")).unwrap();
+        File::create(tmpdir.path().join("win.WIM")).unwrap();
+        File::create(tmpdir.path().join("notes.txt")).unwrap();
+        File::create(tmpdir.path().join("ventoy/theme.img")).unwrap();
+
+        let drive = VentoyDrive::new(tmpdir.path());
+        let images = drive.images().unwrap();
+        assert_eq!(
+            images
+                .iter()
+                .map(|image| drive.image_path(image).unwrap())
+                .collect::<Vec<_>>(),
+            vec!["/ISO/debian.iso", "/win.WIM"]
+        );
+    }
+
+    /// Creates a fake `/sys/block` entry.
+    fn sys_block_device(sys_block: &Path, name: &str, sectors: u64, removable: bool, model: &str) {
+        let dir = sys_block.join(name);
+        fs::create_dir_all(dir.join("device")).unwrap();
+        fs::write(dir.join("size"), format!("{sectors}\n")).unwrap();
+        fs::write(dir.join("removable"), if removable { "1\n" } else { "0\n" }).unwrap();
+        if let Some((vendor, model)) = model.split_once(' ') {
+            fs::write(dir.join("device/vendor"), format!("{vendor:<8}\n")).unwrap();
+            fs::write(dir.join("device/model"), format!("{model:<16}\n")).unwrap();
+        }
+    }
+
+    #[test]
+    fn list_block_devices() {
+        let tmpdir = TempDir::new("test").unwrap();
+        sys_block_device(
+            tmpdir.path(),
+            "sdb",
+            31_116_288,
+            true,
+            "SanDisk Cruzer Blade",
+        );
+        sys_block_device(tmpdir.path(), "nvme0n1", 1_000_215_216, false, "");
+        sys_block_device(tmpdir.path(), "loop0", 2048, false, "");
+        sys_block_device(tmpdir.path(), "sdc", 0, true, "Generic Card Reader");
+
+        let devices = block_devices_in(tmpdir.path(), "/dev").unwrap();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].name, "nvme0n1");
+        assert!(!devices[0].removable);
+        assert_eq!(
+            devices[1].description(),
+            "SanDisk Cruzer Blade (/dev/sdb, 14.8 GiB)"
+        );
+        assert_eq!(human_size(512), "512 B");
+    }
+
+    fn block_device(name: &str, size: u64, removable: bool) -> BlockDevice {
+        BlockDevice {
+            name: name.to_string(),
+            path: PathBuf::from("/dev").join(name),
+            model: String::new(),
+            size,
+            removable,
+        }
+    }
+
+    fn mount(source: &str, mount_point: &str) -> Mount {
+        Mount {
+            source: source.to_string(),
+            mount_point: mount_point.to_string(),
+            fstype: String::new(),
+        }
+    }
+
+    /// A laptop with LVM on LUKS on `nvme0n1p2` and a USB stick on `sdb`.
+    fn sys_block_fixture(sys_block: &Path) {
+        sys_block_device(sys_block, "nvme0n1", 1_000_215_216, false, "");
+        sys_block_device(sys_block, "sdb", 31_116_288, true, "SanDisk Cruzer Blade");
+        for part in ["nvme0n1/nvme0n1p1", "nvme0n1/nvme0n1p2", "sdb/sdb1"] {
+            fs::create_dir_all(sys_block.join(part).join("holders")).unwrap();
+            fs::write(sys_block.join(part).join("partition"), "1\n").unwrap();
+        }
+        fs::create_dir_all(sys_block.join("nvme0n1/nvme0n1p2/holders/dm-0")).unwrap();
+        fs::create_dir_all(sys_block.join("dm-0/holders/dm-1")).unwrap();
+        fs::create_dir_all(sys_block.join("dm-0/dm")).unwrap();
+        fs::write(sys_block.join("dm-0/dm/name"), "luks-root\n").unwrap();
+        fs::create_dir_all(sys_block.join("dm-1/dm")).unwrap();
+        fs::write(sys_block.join("dm-1/dm/name"), "vg-root\n").unwrap();
+    }
+
+    #[test]
+    fn assess_system_disk() {
+        let tmpdir = TempDir::new("test").unwrap();
+        sys_block_fixture(tmpdir.path());
+        let mounts = [
+            mount("/dev/mapper/vg-root", "/"),
+            mount("/dev/nvme0n1p1", "/boot/efi"),
+            mount("/dev/sdb1", "/media/user/Ventoy"),
+            mount("tmpfs", "/tmp"),
+        ];
+
+        let nvme = block_device("nvme0n1", 512 * 1_000_215_216, false);
+        let safety = assess_device_in(&nvme, tmpdir.path(), &mounts);
+        assert_eq!(
+            safety.system,
+            vec![
+                "/ is mounted from /dev/mapper/vg-root",
+                "/boot/efi is mounted from /dev/nvme0n1p1"
+            ]
+        );
+        assert_eq!(
+            safety.warnings,
+            vec!["it's not reported as a removable drive"]
+        );
+        assert!(check_overwrite(&nvme, &safety, "nvme0n1", false).is_err());
+        assert!(check_overwrite(&nvme, &safety, "nvme0n1", true).is_ok());
+
+        let usb = block_device("sdb", 512 * 31_116_288, true);
+        let safety = assess_device_in(&usb, tmpdir.path(), &mounts);
+        assert!(!safety.is_system());
+        assert_eq!(
+            safety.warnings,
+            vec!["/dev/sdb1 is mounted at /media/user/Ventoy, it's unmounted before writing"]
+        );
+        assert_eq!(
+            safety.mounted,
+            vec![mount("/dev/sdb1", "/media/user/Ventoy")]
+        );
+        let err = check_overwrite(&usb, &safety, "sda", false).unwrap_err();
+        assert_eq!(err.to_string(), "type `sdb` to confirm overwriting it");
+        assert!(check_overwrite(&usb, &safety, " sdb ", false).is_ok());
+    }
+
+    #[test]
+    fn release_mounted_partitions() {
+        let tmpdir = TempDir::new("test").unwrap();
+        sys_block_fixture(tmpdir.path());
+        let usb = block_device("sdb", 512 * 31_116_288, true);
+        let mounts = RefCell::new(vec![
+            mount("/dev/mapper/vg-root", "/"),
+            mount("/dev/sdb1", "/media/user/Ventoy"),
+        ]);
+        let read = || Ok(mounts.borrow().clone());
+
+        release_device_in(&usb, tmpdir.path(), read, |unmounted| {
+            mounts.borrow_mut().retain(|mount| mount != unmounted);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(*mounts.borrow(), vec![mount("/dev/mapper/vg-root", "/")]);
+
+        // Mounted again by the desktop right away
+        mounts
+            .borrow_mut()
+            .push(mount("/dev/sdb1", "/media/user/Ventoy"));
+        let err = release_device_in(&usb, tmpdir.path(), read, |_| Ok(())).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "/dev/sdb1 is still mounted at /media/user/Ventoy, unmount it first"
+        );
+        let err = release_device_in(&usb, tmpdir.path(), read, |_| {
+            Err(io::Error::other("target is busy"))
+        })
+        .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "failed to unmount /media/user/Ventoy: target is busy"
+        );
+    }
+
+    #[test]
+    fn assess_swap_and_size() {
+        let tmpdir = TempDir::new("test").unwrap();
+        sys_block_fixture(tmpdir.path());
+        let swaps = tmpdir.path().join("swaps");
+        fs::write(
+            &swaps,
+            "Filename\tType\tSize\tUsed\tPriority\n/dev/sdb1 partition 8388604 0 -2\n",
+        )
+        .unwrap();
+        let usb = block_device("sdb", 2 * LARGE_DRIVE_SIZE, true);
+        let safety = assess_device_in(&usb, tmpdir.path(), &read_swaps(&swaps).unwrap());
+        assert_eq!(safety.system, vec!["/dev/sdb1 is in use as swap"]);
+        assert_eq!(
+            safety.warnings,
+            vec!["it's larger than 512.0 GiB, unusual for a USB stick"]
+        );
+    }
+
+    #[test]
+    fn find_efi_partition() {
+        let mounts = [
+            mount("/dev/sdb1", "/media/user/Ventoy"),
+            mount("/dev/sdc1", "/media/user/Ventoy1"),
+            mount("/dev/sdc2", "/media/user/VTOYEFI"),
+            mount("/dev/nvme0n1p1", "/mnt/ventoy"),
+            mount("/dev/nvme0n1p2", "/mnt/efi"),
+        ];
+        // The VTOYEFI next to it belongs to another drive
+        assert_eq!(efi_partition_in("/media/user/Ventoy", &mounts), None);
+        assert_eq!(
+            efi_partition_in("/media/user/Ventoy1", &mounts),
+            Some(PathBuf::from("/media/user/VTOYEFI"))
+        );
+        assert_eq!(
+            efi_partition_in("/mnt/ventoy", &mounts),
+            Some(PathBuf::from("/mnt/efi"))
+        );
+        assert_eq!(efi_partition_in("/media/user/Other", &mounts), None);
+    }
+
+    #[test]
+    fn parse_mounts() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let path = tmpdir.path().join("mounts");
+        fs::write(
+            &path,
+            "/dev/sdb1 /media/user/My\\040Drive exfat rw 0 0\nproc /proc proc rw 0 0\n",
+        )
+        .unwrap();
+        assert_eq!(
+            read_mounts(&path).unwrap(),
+            vec![
+                Mount {
+                    fstype: "exfat".to_string(),
+                    ..mount("/dev/sdb1", "/media/user/My Drive")
+                },
+                Mount {
+                    fstype: "proc".to_string(),
+                    ..mount("proc", "/proc")
+                },
+            ]
+        );
+        assert!(is_device_node("/dev/sdb"));
+        assert!(!is_device_node(&path));
+    }
+}
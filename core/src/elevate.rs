@@ -0,0 +1,379 @@
+//! Running privileged sub-operations on Linux through polkit's `pkexec`, or through
+//! `sudo -A` with a graphical askpass helper where polkit isn't around.
+//!
+//! Only the operation that needs root is elevated, never the app itself.
+
+use std::{
+    env, fmt, fs, io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+/// Environment the Ventoy GUI needs to show up on the user's display, as both
+/// `pkexec` and `sudo` reset it.
+pub const FORWARDED_ENV: &[&str] = &[
+    "DISPLAY",
+    "WAYLAND_DISPLAY",
+    "XAUTHORITY",
+    "XDG_RUNTIME_DIR",
+    "LANG",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElevatorKind {
+    Pkexec,
+    /// `sudo -A`, asking for the password through `SUDO_ASKPASS`
+    Sudo,
+}
+
+#[derive(Debug)]
+pub enum ElevateError {
+    /// Neither `pkexec` nor `sudo` with an askpass helper are available
+    Unavailable(String),
+    /// The authentication was dismissed, failed or the user isn't allowed to
+    Denied(String),
+    /// The elevated command ran but failed
+    Failed {
+        code: Option<i32>,
+        stderr: String,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for ElevateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unavailable(reason) => write!(f, "can't get root privileges, {reason}"),
+            Self::Denied(reason) if reason.is_empty() => f.write_str("root privileges denied"),
+            Self::Denied(reason) => write!(f, "root privileges denied: {reason}"),
+            Self::Failed { code, stderr } => {
+                match code {
+                    Some(code) => write!(f, "command failed with exit code {code}")?,
+                    None => f.write_str("command was killed")?,
+                }
+                if !stderr.is_empty() {
+                    write!(f, "\n{stderr}")?;
+                }
+                Ok(())
+            }
+            Self::Io(err) => write!(f, "failed to run the command: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ElevateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ElevateError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Elevator {
+    pub kind: ElevatorKind,
+    /// Path of `pkexec` or `sudo`, can be a stub
+    pub program: PathBuf,
+    /// Variables set for the elevated command, [`FORWARDED_ENV`] by default
+    pub env: Vec<(String, String)>,
+}
+
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+impl Elevator {
+    pub fn new<P: Into<PathBuf>>(kind: ElevatorKind, program: P) -> Self {
+        let mut env = FORWARDED_ENV
+            .iter()
+            .filter_map(|key| Some((key.to_string(), env::var(key).ok()?)))
+            .collect::<Vec<_>>();
+        // X falls back to root's own `~/.Xauthority` otherwise
+        if !env.iter().any(|(key, _)| key == "XAUTHORITY") {
+            if let Some(xauthority) = dirs::home_dir()
+                .map(|home| home.join(".Xauthority"))
+                .filter(|path| path.is_file())
+            {
+                env.push((
+                    "XAUTHORITY".to_string(),
+                    xauthority.to_string_lossy().to_string(),
+                ));
+            }
+        }
+        Self {
+            kind,
+            program: program.into(),
+            env,
+        }
+    }
+
+    /// Prefers `pkexec`, falling back to `sudo -A` if an askpass helper is set up.
+    pub fn detect() -> Result<Self, ElevateError> {
+        if let Some(pkexec) = find_in_path("pkexec") {
+            return Ok(Self::new(ElevatorKind::Pkexec, pkexec));
+        }
+        match find_in_path("sudo") {
+            Some(sudo) if env::var_os("SUDO_ASKPASS").is_some() => {
+                Ok(Self::new(ElevatorKind::Sudo, sudo))
+            }
+            Some(_) => Err(ElevateError::Unavailable(
+                "pkexec isn't installed and SUDO_ASKPASS isn't set for sudo".to_string(),
+            )),
+            None => Err(ElevateError::Unavailable(
+                "neither pkexec nor sudo are installed".to_string(),
+            )),
+        }
+    }
+
+    /// `program` with `args` run as root, with [`Elevator::env`] passed through `env`.
+    pub fn command<S: AsRef<std::ffi::OsStr>>(&self, program: &Path, args: &[S]) -> Command {
+        let mut cmd = Command::new(&self.program);
+        if self.kind == ElevatorKind::Sudo {
+            cmd.arg("-A");
+        }
+        cmd.arg("/usr/bin/env");
+        for (key, value) in &self.env {
+            cmd.arg(format!("{key}={value}"));
+        }
+        cmd.arg(program).args(args);
+        if let Some(dir) = program.parent().filter(|dir| dir.is_dir()) {
+            cmd.current_dir(dir);
+        }
+        cmd
+    }
+
+    /// Runs `program` as root and waits for it, telling a denied authentication apart
+    /// from the command failing.
+    pub fn run<S: AsRef<std::ffi::OsStr>>(
+        &self,
+        program: &Path,
+        args: &[S],
+    ) -> Result<Output, ElevateError> {
+        let output = self.command(program, args).output()?;
+        if output.status.success() {
+            return Ok(output);
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        Err(self.classify(output.status.code(), stderr))
+    }
+
+    fn classify(&self, code: Option<i32>, stderr: String) -> ElevateError {
+        match self.kind {
+            // Dismissed authentication dialog
+            ElevatorKind::Pkexec if code == Some(126) => ElevateError::Denied(stderr),
+            // Also used when pkexec fails itself, e.g. without a polkit agent
+            ElevatorKind::Pkexec
+                if code == Some(127) && stderr.to_lowercase().contains("not authorized") =>
+            {
+                ElevateError::Denied(stderr)
+            }
+            ElevatorKind::Sudo => {
+                let sudo_errors = stderr
+                    .lines()
+                    .filter(|line| line.starts_with("sudo:"))
+                    .collect::<Vec<_>>();
+                let matches = |patterns: &[&str]| {
+                    sudo_errors
+                        .iter()
+                        .any(|line| patterns.iter().any(|pattern| line.contains(pattern)))
+                };
+                if matches(&["no askpass program", "unable to run"]) {
+                    ElevateError::Unavailable(stderr)
+                } else if matches(&[
+                    "no password was provided",
+                    "incorrect password",
+                    "a password is required",
+                    "not in the sudoers file",
+                    "not allowed to execute",
+                ]) {
+                    ElevateError::Denied(stderr)
+                } else {
+                    ElevateError::Failed { code, stderr }
+                }
+            }
+            _ => ElevateError::Failed { code, stderr },
+        }
+    }
+
+    /// Gives the current user read and write access to the device node at `path` with an
+    /// ACL, until the returned [`DeviceAccess`] is revoked or dropped.
+    pub fn grant_access<P: AsRef<Path>>(&self, path: P) -> Result<DeviceAccess, ElevateError> {
+        let uid = fs::metadata("/proc/self")?.uid();
+        let setfacl = find_in_path("setfacl").ok_or_else(|| {
+            ElevateError::Unavailable("setfacl isn't installed, it's part of acl".to_string())
+        })?;
+        let path = path.as_ref().to_path_buf();
+        self.run(
+            &setfacl,
+            &[
+                "-m".as_ref(),
+                format!("u:{uid}:rw").as_ref(),
+                path.as_os_str(),
+            ],
+        )?;
+        Ok(DeviceAccess {
+            elevator: self.clone(),
+            setfacl,
+            uid,
+            path,
+            revoked: false,
+        })
+    }
+}
+
+/// Access to a device node given by [`Elevator::grant_access`]. It's revoked when
+/// dropped, which may ask for authentication again.
+#[derive(Debug)]
+#[must_use = "the access is revoked as soon as it's dropped"]
+pub struct DeviceAccess {
+    elevator: Elevator,
+    setfacl: PathBuf,
+    uid: u32,
+    path: PathBuf,
+    revoked: bool,
+}
+
+impl DeviceAccess {
+    /// Removes the ACL entry, dropping it does the same but only logs a failure.
+    pub fn revoke(mut self) -> Result<(), ElevateError> {
+        self.remove_acl()
+    }
+
+    fn remove_acl(&mut self) -> Result<(), ElevateError> {
+        self.revoked = true;
+        self.elevator
+            .run(
+                &self.setfacl,
+                &[
+                    "-x".as_ref(),
+                    format!("u:{}", self.uid).as_ref(),
+                    self.path.as_os_str(),
+                ],
+            )
+            .map(|_| ())
+    }
+}
+
+impl Drop for DeviceAccess {
+    fn drop(&mut self) {
+        if self.revoked {
+            return;
+        }
+        if let Err(err) = self.remove_acl() {
+            tracing::warn!("Failed to revoke the access to {:?}: {}", self.path, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempdir::TempDir;
+
+    fn stub(dir: &Path, name: &str, script: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    fn stub_elevator(dir: &Path, kind: ElevatorKind, script: &str) -> Elevator {
+        Elevator {
+            kind,
+            program: stub(dir, "elevate", script),
+            env: vec![("DISPLAY".to_string(), ":7".to_string())],
+        }
+    }
+
+    #[test]
+    fn run_elevated() {
+        let tmpdir = TempDir::new("test").unwrap();
+        for (kind, script) in [
+            (ElevatorKind::Pkexec, r#"exec "$@""#),
+            (
+                ElevatorKind::Sudo,
+                r#"[ "$1" = -A ] || exit 99; shift; exec "$@""#,
+            ),
+        ] {
+            let elevator = stub_elevator(tmpdir.path(), kind, script);
+            let output = elevator
+                .run(Path::new("/bin/sh"), &["-c", "echo $DISPLAY; pwd -P"])
+                .unwrap();
+            // Run from the program's directory
+            let bin_dir = fs::canonicalize("/bin").unwrap();
+            assert_eq!(
+                String::from_utf8_lossy(&output.stdout),
+                format!(":7\n{}\n", bin_dir.display())
+            );
+        }
+    }
+
+    #[test]
+    fn denied_or_failed() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let sh = Path::new("/bin/sh");
+        let elevator = stub_elevator(tmpdir.path(), ElevatorKind::Pkexec, "exit 126");
+        let err = elevator.run(sh, &["-c", "true"]).unwrap_err();
+        assert!(matches!(err, ElevateError::Denied(_)), "{err:?}");
+
+        let elevator = stub_elevator(
+            tmpdir.path(),
+            ElevatorKind::Sudo,
+            "echo 'sudo: no password was provided' >&2; exit 1",
+        );
+        let err = elevator.run(sh, &["-c", "true"]).unwrap_err();
+        assert!(matches!(err, ElevateError::Denied(_)), "{err:?}");
+
+        for (kind, script) in [
+            (ElevatorKind::Pkexec, r#"exec "$@""#),
+            (ElevatorKind::Sudo, r#"shift; exec "$@""#),
+        ] {
+            let elevator = stub_elevator(tmpdir.path(), kind, script);
+            let err = elevator
+                .run(sh, &["-c", "echo 'sudo: not a real sudo' >&2; exit 3"])
+                .unwrap_err();
+            match err {
+                ElevateError::Failed { code, stderr } => {
+                    assert_eq!(code, Some(3));
+                    assert_eq!(stderr, "sudo: not a real sudo");
+                }
+                err => panic!("{err:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn revoke_device_access() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let log = tmpdir.path().join("setfacl.log");
+        let setfacl = stub(
+            tmpdir.path(),
+            "setfacl",
+            &format!(r#"echo "$@" >> {}"#, log.display()),
+        );
+        let access = |path: &str| DeviceAccess {
+            elevator: stub_elevator(tmpdir.path(), ElevatorKind::Pkexec, r#"exec "$@""#),
+            setfacl: setfacl.clone(),
+            uid: 1000,
+            path: PathBuf::from(path),
+            revoked: false,
+        };
+        access("/dev/sdy").revoke().unwrap();
+        drop(access("/dev/sdz"));
+        assert_eq!(
+            fs::read_to_string(&log).unwrap(),
+            "-x u:1000 /dev/sdy\n-x u:1000 /dev/sdz\n"
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! Errors of the downloads and installs the UI waits on, telling apart what can be
+//! retried and what the user has to do something about.
+
+use std::{io, path::PathBuf, sync::Arc};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Sources are behind an `Arc` so that errors can be cloned out of promises.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Error {
+    #[error("network error: {0}")]
+    Network(String),
+    #[error("{status} {status_text} for {url}")]
+    HttpStatus {
+        status: u16,
+        status_text: String,
+        url: String,
+    },
+    #[error("failed to parse the {what} from {url}: {source}")]
+    Json {
+        what: &'static str,
+        url: String,
+        #[source]
+        source: Arc<serde_json::Error>,
+    },
+    #[error("failed to write {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: Arc<io::Error>,
+    },
+    #[error("failed to extract {archive:?}: {source}")]
+    Extract {
+        archive: PathBuf,
+        #[source]
+        source: Arc<io::Error>,
+    },
+    #[error("checksum mismatch for {name}, expected {expected} but got {actual}")]
+    Checksum {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("no {target} package among the release assets: {}", assets.join(", "))]
+    NoMatchingAsset { target: String, assets: Vec<String> },
+    #[error("{name} isn't listed in {url}")]
+    MissingChecksum { name: String, url: String },
+    #[error("the signature of {name} couldn't be verified: {reason}")]
+    Signature { name: String, reason: String },
+    /// Stopped through its [`crate::task::CancelToken`]
+    #[error("cancelled")]
+    Cancelled,
+}
+
+impl Error {
+    /// An error status of `resp`.
+    pub fn http(resp: &ureq::Response) -> Self {
+        Self::HttpStatus {
+            status: resp.status(),
+            status_text: resp.status_text().to_string(),
+            url: resp.get_url().to_string(),
+        }
+    }
+
+    pub fn io<P: Into<PathBuf>>(path: P, source: io::Error) -> Self {
+        Self::Io {
+            path: path.into(),
+            source: Arc::new(source),
+        }
+    }
+
+    pub fn extract<P: Into<PathBuf>>(archive: P, source: io::Error) -> Self {
+        Self::Extract {
+            archive: archive.into(),
+            source: Arc::new(source),
+        }
+    }
+
+    /// Whether trying again, downloading again that is, may get past the error.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Network(_) | Self::Checksum { .. } | Self::Extract { .. } | Self::Cancelled => {
+                true
+            }
+            Self::HttpStatus { status, .. } => matches!(status, 403 | 408 | 429 | 500..=599),
+            Self::Json { .. }
+            | Self::Io { .. }
+            | Self::NoMatchingAsset { .. }
+            | Self::MissingChecksum { .. }
+            | Self::Signature { .. } => false,
+        }
+    }
+
+    /// What the user can do about it.
+    pub fn suggestion(&self) -> String {
+        match self {
+            Self::Network(_) => "Check your internet connection and try again.".to_string(),
+            Self::HttpStatus {
+                status: 403 | 429, ..
+            } => "GitHub's API rate limit may be exceeded, try again in a while.".to_string(),
+            Self::HttpStatus { status: 404, .. } => {
+                "The file was not found, it may have been removed from the release.".to_string()
+            }
+            Self::HttpStatus { status, .. } if *status >= 500 => {
+                "The server is having issues, try again in a while.".to_string()
+            }
+            Self::HttpStatus { .. } => "The request was refused by the server.".to_string(),
+            Self::Json { .. } => {
+                "The response wasn't understood, a newer version of the app may be needed."
+                    .to_string()
+            }
+            Self::Io { path, .. } => format!(
+                "Check that there's enough free space and that {:?} is writable.",
+                path.parent().unwrap_or(path)
+            ),
+            Self::Extract { .. } => {
+                "The download may be corrupt, try downloading it again.".to_string()
+            }
+            Self::Checksum { .. } => {
+                "The download is corrupt, try downloading it again.".to_string()
+            }
+            Self::NoMatchingAsset { .. } => {
+                "Pick another package to download, or wait for a release with this one.".to_string()
+            }
+            Self::MissingChecksum { .. } => {
+                "Check the name of the mirror's checksums file in Settings.".to_string()
+            }
+            Self::Signature { .. } => {
+                "Import the distro's signing key in Settings, or turn off signature verification."
+                    .to_string()
+            }
+            Self::Cancelled => "It was cancelled, try again to start over.".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn retryable_kinds() {
+        let status = |status| Error::HttpStatus {
+            status,
+            status_text: String::new(),
+            url: "https://api.github.com/repos/ventoy/Ventoy/releases/latest".to_string(),
+        };
+        assert!(Error::Network("connection reset".to_string()).is_retryable());
+        assert!(status(503).is_retryable());
+        assert!(status(429).is_retryable());
+        assert!(!status(404).is_retryable());
+        assert!(status(403).suggestion().contains("rate limit"));
+
+        let err = Error::io(
+            "/home/user/.cache/ventoy-livecd.iso",
+            io::Error::other("No space left on device"),
+        );
+        assert!(!err.is_retryable());
+        assert!(err.suggestion().contains("\"/home/user/.cache\""));
+        assert!(std::error::Error::source(&err).is_some());
+
+        let err = Error::NoMatchingAsset {
+            target: "linux-aarch64".to_string(),
+            assets: vec![
+                "sha256.txt".to_string(),
+                "ventoy-1.0.86-windows.zip".to_string(),
+            ],
+        };
+        assert_eq!(
+            err.to_string(),
+            "no linux-aarch64 package among the release assets: sha256.txt, ventoy-1.0.86-windows.zip"
+        );
+        assert!(!err.is_retryable());
+    }
+}
@@ -0,0 +1,565 @@
+//! Extraction of downloaded archives that can't write outside of the destination.
+//!
+//! Entries with absolute paths or `..` components, symlinks resolving outside of the
+//! destination and archives blowing past the [`Limits`] are rejected, naming the
+//! offending entry in the error. Failures to write the destination, e.g. when it's full,
+//! are told apart with [`split_write_error`].
+
+use std::{
+    fmt, fs,
+    io::{self, Read, Seek, Write},
+    path::{Component, Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Total size of the extracted files in bytes
+    pub max_size: u64,
+    pub max_entries: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_size: 4 * 1024 * 1024 * 1024,
+            max_entries: 100_000,
+        }
+    }
+}
+
+pub fn extract_targz<P, Q>(archive_path: P, dest_dir: Q, limits: Limits) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    unpack_targz(fs::File::open(archive_path)?, dest_dir, limits)
+}
+
+/// [`extract_targz`] out of a reader, e.g. a [`crate::task::Cancellable`] one.
+pub fn unpack_targz<R, Q>(reader: R, dest_dir: Q, limits: Limits) -> io::Result<()>
+where
+    R: Read,
+    Q: AsRef<Path>,
+{
+    use flate2::read::GzDecoder;
+    let mut archive = tar::Archive::new(GzDecoder::new(reader));
+    let mut extractor = Extractor::new(dest_dir.as_ref(), limits)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().to_string();
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => extractor.dir(&name)?,
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                let mode = entry.header().mode().ok();
+                extractor.file(&name, &mut entry, mode)?
+            }
+            tar::EntryType::Link => {
+                let source = entry
+                    .link_name()?
+                    .ok_or_else(|| entry_error(&name, "hard link without a target"))?;
+                extractor.hard_link(&name, &source.to_string_lossy())?
+            }
+            tar::EntryType::Symlink => {
+                let target = entry
+                    .link_name()?
+                    .ok_or_else(|| entry_error(&name, "symlink without a target"))?;
+                extractor.symlink(&name, &target)?
+            }
+            // Metadata like pax headers, device files and fifos aren't needed
+            _ => (),
+        }
+    }
+    extractor.finish()
+}
+
+pub fn extract_zip<P, Q>(archive_path: P, dest_dir: Q, limits: Limits) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    unpack_zip(fs::File::open(archive_path)?, dest_dir, limits)
+}
+
+/// [`extract_zip`] out of a reader, e.g. a [`crate::task::Cancellable`] one.
+pub fn unpack_zip<R, Q>(reader: R, dest_dir: Q, limits: Limits) -> io::Result<()>
+where
+    R: Read + Seek,
+    Q: AsRef<Path>,
+{
+    const S_IFMT: u32 = 0o170000;
+    const S_IFLNK: u32 = 0o120000;
+
+    let mut archive = zip::ZipArchive::new(reader)?;
+    let mut extractor = Extractor::new(dest_dir.as_ref(), limits)?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = file.name().to_string();
+        let mode = file.unix_mode();
+        if file.is_dir() {
+            extractor.dir(&name)?;
+        } else if matches!(mode, Some(mode) if mode & S_IFMT == S_IFLNK) {
+            let mut target = String::new();
+            (&mut file)
+                .take(4096)
+                .read_to_string(&mut target)
+                .map_err(|e| entry_error(&name, e))?;
+            extractor.symlink(&name, Path::new(&target))?;
+        } else {
+            extractor.file(&name, &mut file, mode)?;
+        }
+    }
+    extractor.finish()
+}
+
+fn entry_error<E: std::fmt::Display>(entry: &str, err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("`{entry}`: {err}"))
+}
+
+/// Failure to write `path`, as opposed to a problem with the archive.
+#[derive(Debug)]
+struct WriteError {
+    path: PathBuf,
+    source: io::Error,
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.path, self.source)
+    }
+}
+
+impl std::error::Error for WriteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Marks `err` as a failure to write `path`, see [`split_write_error`].
+pub(crate) fn write_error<P: Into<PathBuf>>(path: P, err: io::Error) -> io::Error {
+    io::Error::new(
+        err.kind(),
+        WriteError {
+            path: path.into(),
+            source: err,
+        },
+    )
+}
+
+/// Splits a failure to write the destination into the path and its error, giving back
+/// any other error, e.g. of a corrupt archive.
+pub fn split_write_error(err: io::Error) -> Result<(PathBuf, io::Error), io::Error> {
+    if !err.get_ref().is_some_and(|inner| inner.is::<WriteError>()) {
+        return Err(err);
+    }
+    let inner = err
+        .into_inner()
+        .and_then(|inner| inner.downcast::<WriteError>().ok())
+        .expect("checked to be a WriteError");
+    Ok((inner.path, inner.source))
+}
+
+/// Entry path relative to the destination, `None` for entries like `./`.
+fn sanitize(entry: &str) -> io::Result<Option<PathBuf>> {
+    relative_path(entry).map_err(|reason| entry_error(entry, reason))
+}
+
+/// [`sanitize`], with only the reason `path` is rejected.
+fn relative_path(path: &str) -> Result<Option<PathBuf>, &'static str> {
+    let mut relative = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(name) => relative.push(name),
+            Component::CurDir => (),
+            Component::ParentDir => return Err("path contains `..`"),
+            Component::RootDir | Component::Prefix(_) => return Err("path is absolute"),
+        }
+    }
+    Ok((!relative.as_os_str().is_empty()).then_some(relative))
+}
+
+fn is_symlink(path: &Path) -> bool {
+    matches!(path.symlink_metadata(), Ok(meta) if meta.file_type().is_symlink())
+}
+
+struct Symlink {
+    entry: String,
+    #[cfg_attr(not(unix), allow(dead_code))]
+    path: PathBuf,
+    target: PathBuf,
+}
+
+struct Extractor {
+    dest: PathBuf,
+    limits: Limits,
+    entries: usize,
+    size: u64,
+    symlinks: Vec<Symlink>,
+}
+
+impl Extractor {
+    fn new(dest: &Path, limits: Limits) -> io::Result<Self> {
+        fs::create_dir_all(dest).map_err(|e| write_error(dest, e))?;
+        Ok(Self {
+            dest: dest.canonicalize().map_err(|e| write_error(dest, e))?,
+            limits,
+            entries: 0,
+            size: 0,
+            symlinks: Vec::new(),
+        })
+    }
+
+    /// Checks the entry against the limits and resolves where it goes.
+    fn target(&mut self, entry: &str) -> io::Result<Option<PathBuf>> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(entry_error(
+                entry,
+                format!("archive has more than {} entries", self.limits.max_entries),
+            ));
+        }
+        let Some(relative) = sanitize(entry)? else {
+            return Ok(None);
+        };
+        // Never write through a symlink that was already on disk
+        let mut path = self.dest.clone();
+        for component in relative.iter() {
+            path.push(component);
+            if is_symlink(&path) {
+                return Err(entry_error(entry, "path goes through a symlink"));
+            }
+        }
+        Ok(Some(path))
+    }
+
+    fn reserve(&mut self, entry: &str, size: u64) -> io::Result<()> {
+        if size > self.limits.max_size - self.size {
+            return Err(entry_error(
+                entry,
+                format!(
+                    "archive extracts to more than {} bytes",
+                    self.limits.max_size
+                ),
+            ));
+        }
+        self.size += size;
+        Ok(())
+    }
+
+    fn dir(&mut self, entry: &str) -> io::Result<()> {
+        if let Some(path) = self.target(entry)? {
+            fs::create_dir_all(&path).map_err(|e| write_error(&path, e))?;
+        }
+        Ok(())
+    }
+
+    fn file<R: Read>(&mut self, entry: &str, reader: R, mode: Option<u32>) -> io::Result<()> {
+        let Some(path) = self.target(entry)? else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| write_error(parent, e))?;
+        }
+        let mut file = fs::File::create(&path).map_err(|e| write_error(&path, e))?;
+        // Sizes in headers can lie, so count what's actually written
+        let remaining = self.limits.max_size - self.size;
+        let mut reader = reader.take(remaining + 1);
+        let mut buf = vec![0; 64 * 1024];
+        let mut written = 0;
+        loop {
+            let read = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(entry_error(entry, err)),
+            };
+            file.write_all(&buf[..read])
+                .map_err(|e| write_error(&path, e))?;
+            written += read as u64;
+        }
+        if let Err(err) = self.reserve(entry, written) {
+            drop(file);
+            let _ = fs::remove_file(&path);
+            return Err(err);
+        }
+
+        #[cfg(unix)]
+        if let Some(mode) = mode {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(mode & 0o777))
+                .map_err(|e| write_error(&path, e))?;
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+        Ok(())
+    }
+
+    /// Hard links are extracted as copies of a file extracted earlier.
+    fn hard_link(&mut self, entry: &str, source: &str) -> io::Result<()> {
+        let relative = relative_path(source)
+            .map_err(|reason| entry_error(entry, format!("link to `{source}`: {reason}")))?;
+        let source = match relative {
+            Some(source) if !is_symlink(&self.dest.join(&source)) => self.dest.join(source),
+            _ => {
+                return Err(entry_error(
+                    entry,
+                    format!("link to `{source}` is not a file"),
+                ))
+            }
+        };
+        if !source.is_file() {
+            return Err(entry_error(
+                entry,
+                format!("link to {source:?} is not a file"),
+            ));
+        }
+        let Some(path) = self.target(entry)? else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| write_error(parent, e))?;
+        }
+        self.reserve(entry, source.metadata()?.len())?;
+        fs::copy(&source, &path).map_err(|e| write_error(&path, e))?;
+        Ok(())
+    }
+
+    /// Symlinks are only created in [`Extractor::finish`], after all other entries.
+    fn symlink(&mut self, entry: &str, target: &Path) -> io::Result<()> {
+        let Some(path) = self.target(entry)? else {
+            return Ok(());
+        };
+        if target.has_root() {
+            return Err(entry_error(entry, "symlink target is absolute"));
+        }
+        let mut resolved = path.parent().unwrap_or(&self.dest).to_path_buf();
+        for component in target.components() {
+            match component {
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                Component::Normal(name) => resolved.push(name),
+                _ => (),
+            }
+        }
+        if !resolved.starts_with(&self.dest) {
+            return Err(entry_error(
+                entry,
+                "symlink points outside of the destination",
+            ));
+        }
+        self.symlinks.push(Symlink {
+            entry: entry.to_string(),
+            path,
+            target: target.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    /// Creates the symlinks, then verifies where they resolve to on disk, as links
+    /// through other links can still escape.
+    fn finish(self) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            for link in &self.symlinks {
+                if let Some(parent) = link.path.parent() {
+                    fs::create_dir_all(parent).map_err(|e| write_error(parent, e))?;
+                }
+                if link.path.symlink_metadata().is_ok() {
+                    return Err(entry_error(&link.entry, "path already exists"));
+                }
+                std::os::unix::fs::symlink(&link.target, &link.path)
+                    .map_err(|e| write_error(&link.path, e))?;
+            }
+            for link in &self.symlinks {
+                let escapes = match link.path.canonicalize() {
+                    Ok(resolved) => !resolved.starts_with(&self.dest),
+                    // dangling
+                    Err(_) => true,
+                };
+                if escapes {
+                    for link in &self.symlinks {
+                        let _ = fs::remove_file(&link.path);
+                    }
+                    return Err(entry_error(
+                        &link.entry,
+                        "symlink doesn't resolve to a path inside of the destination",
+                    ));
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        for link in &self.symlinks {
+            tracing::warn!(
+                "Skipped symlink `{}` to {:?}, symlinks aren't extracted on this platform",
+                link.entry,
+                link.target
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    enum Entry<'a> {
+        File(&'a str, &'a [u8], u32),
+        Symlink(&'a str, &'a str),
+        HardLink(&'a str, &'a str),
+    }
+
+    /// Builds the archive by hand, as `tar::Builder` refuses to write malicious paths.
+    fn targz(path: &Path, entries: &[Entry]) {
+        let enc = flate2::write::GzEncoder::new(
+            fs::File::create(path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut tar = tar::Builder::new(enc);
+        for entry in entries {
+            let mut header = tar::Header::new_old();
+            let (name, data, entry_type, link) = match entry {
+                Entry::File(name, data, mode) => {
+                    header.set_mode(*mode);
+                    (*name, *data, tar::EntryType::Regular, None)
+                }
+                Entry::Symlink(name, target) => {
+                    (*name, &[][..], tar::EntryType::Symlink, Some(*target))
+                }
+                Entry::HardLink(name, source) => {
+                    (*name, &[][..], tar::EntryType::Link, Some(*source))
+                }
+            };
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            if let Some(link) = link {
+                header.as_old_mut().linkname[..link.len()].copy_from_slice(link.as_bytes());
+            }
+            header.set_entry_type(entry_type);
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            tar.append(&header, data).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn extract_err(entries: &[Entry], limits: Limits) -> String {
+        let tmpdir = TempDir::new("test").unwrap();
+        let archive = tmpdir.path().join("pkg.tar.gz");
+        targz(&archive, entries);
+        let err = extract_targz(&archive, tmpdir.path().join("dest"), limits).unwrap_err();
+        assert!(!tmpdir.path().join("evil").exists());
+        err.to_string()
+    }
+
+    #[test]
+    fn extract_regular_archive() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let archive = tmpdir.path().join("pkg.tar.gz");
+        targz(
+            &archive,
+            &[
+                Entry::File("./ventoy/VentoyGUI.x86_64", b"elf", 0o755),
+                Entry::File("ventoy/README", b"readme", 0o644),
+                Entry::HardLink("ventoy/README.copy", "ventoy/README"),
+                Entry::Symlink("ventoy/gui", "VentoyGUI.x86_64"),
+            ],
+        );
+        let dest = tmpdir.path().join("dest");
+        extract_targz(&archive, &dest, Limits::default()).unwrap();
+        assert_eq!(fs::read(dest.join("ventoy/gui")).unwrap(), b"elf");
+        assert_eq!(
+            fs::read(dest.join("ventoy/README.copy")).unwrap(),
+            b"readme"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |p: &str| dest.join(p).metadata().unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode("ventoy/VentoyGUI.x86_64"), 0o755);
+            assert_eq!(mode("ventoy/README"), 0o644);
+        }
+    }
+
+    #[test]
+    fn reject_path_traversal() {
+        let err = extract_err(&[Entry::File("../evil", b"", 0o644)], Limits::default());
+        assert_eq!(err, "`../evil`: path contains `..`");
+        let err = extract_err(
+            &[Entry::File("a/../../evil", b"", 0o644)],
+            Limits::default(),
+        );
+        assert!(err.starts_with("`a/../../evil`"), "{}", err);
+        let err = extract_err(&[Entry::File("/tmp/evil", b"", 0o644)], Limits::default());
+        assert_eq!(err, "`/tmp/evil`: path is absolute");
+        let err = extract_err(&[Entry::HardLink("a", "../evil")], Limits::default());
+        assert_eq!(err, "`a`: link to `../evil`: path contains `..`");
+    }
+
+    #[test]
+    fn reject_symlink_escapes() {
+        let err = extract_err(&[Entry::Symlink("link", "../evil")], Limits::default());
+        assert_eq!(err, "`link`: symlink points outside of the destination");
+        let err = extract_err(&[Entry::Symlink("link", "/etc")], Limits::default());
+        assert_eq!(err, "`link`: symlink target is absolute");
+
+        // Each link looks fine on its own, but `b/..` resolves above `dest` once `b` is `.`
+        let err = extract_err(
+            &[Entry::Symlink("a", "b/.."), Entry::Symlink("b", ".")],
+            Limits::default(),
+        );
+        assert!(err.starts_with("`a`"), "{}", err);
+
+        // A file written through an earlier symlink
+        let tmpdir = TempDir::new("test").unwrap();
+        let dest = tmpdir.path().join("dest");
+        fs::create_dir_all(&dest).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(tmpdir.path(), dest.join("link")).unwrap();
+        let archive = tmpdir.path().join("pkg.tar.gz");
+        targz(&archive, &[Entry::File("link/evil", b"", 0o644)]);
+        let err = extract_targz(&archive, &dest, Limits::default()).unwrap_err();
+        assert_eq!(err.to_string(), "`link/evil`: path goes through a symlink");
+    }
+
+    #[test]
+    fn enforce_limits() {
+        let limits = Limits {
+            max_size: 8,
+            max_entries: 2,
+        };
+        let err = extract_err(&[Entry::File("big", &[0; 9], 0o644)], limits);
+        assert_eq!(err, "`big`: archive extracts to more than 8 bytes");
+        let err = extract_err(
+            &[
+                Entry::File("a", b"", 0o644),
+                Entry::File("b", b"", 0o644),
+                Entry::File("c", b"", 0o644),
+            ],
+            limits,
+        );
+        assert_eq!(err, "`c`: archive has more than 2 entries");
+    }
+
+    #[test]
+    fn extract_zip_archive() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let archive = tmpdir.path().join("pkg.zip");
+        {
+            let mut zip = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+            let options = zip::write::FileOptions::default().unix_permissions(0o755);
+            zip.start_file("ventoy/Ventoy2Disk.exe", options).unwrap();
+            zip.write_all(b"exe").unwrap();
+            zip.start_file("../evil", options).unwrap();
+            zip.finish().unwrap();
+        }
+        let dest = tmpdir.path().join("dest");
+        let err = extract_zip(&archive, &dest, Limits::default()).unwrap_err();
+        assert_eq!(err.to_string(), "`../evil`: path contains `..`");
+        assert_eq!(
+            fs::read(dest.join("ventoy/Ventoy2Disk.exe")).unwrap(),
+            b"exe"
+        );
+    }
+}
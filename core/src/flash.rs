@@ -0,0 +1,446 @@
+//! Writing images raw to a block device, or to a file standing in for one.
+//!
+//! Compressed images are decompressed while streaming. Everything written is hashed
+//! on the way, then read back from the target and compared once it's synced. The
+//! target's cached pages are dropped before reading it back, so the comparison sees
+//! what's on the device rather than what's still in memory.
+
+use std::{
+    cell::Cell,
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use anyhow::{bail, Context};
+use sha2::{Digest, Sha256};
+
+const BUF_SIZE: usize = 4 * 1024 * 1024;
+
+/// Images that can be written raw, compressed ones included.
+pub const IMAGE_EXTENSIONS: &[&str] = &[
+    ".iso", ".img", ".raw", ".bin", ".img.gz", ".img.xz", ".iso.gz", ".iso.xz", ".raw.xz",
+    ".bin.xz", ".bin.zip", ".img.zip",
+];
+
+pub fn is_flashable<P: AsRef<Path>>(path: P) -> bool {
+    let name = path
+        .as_ref()
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    IMAGE_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Xz,
+    /// Holding the image as its only flashable entry, like ChromeOS Flex
+    Zip,
+}
+
+impl Compression {
+    /// Detects the compression from the magic bytes, regardless of the extension.
+    pub fn detect<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut magic = [0; 6];
+        let mut file = fs::File::open(path)?;
+        let mut len = 0;
+        while len < magic.len() {
+            match file.read(&mut magic[len..])? {
+                0 => break,
+                read => len += read,
+            }
+        }
+        Ok(match &magic[..len] {
+            [0x1f, 0x8b, ..] => Self::Gzip,
+            [0xfd, b'7', b'z', b'X', b'Z', 0x00] => Self::Xz,
+            [b'P', b'K', 0x03, 0x04, ..] => Self::Zip,
+            _ => Self::None,
+        })
+    }
+}
+
+/// Counts the bytes read through it, for the progress of compressed images.
+struct CountingReader<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.set(self.count.get() + read as u64);
+        Ok(read)
+    }
+}
+
+/// What the target path is expected to be, so a mistyped device path fails instead of
+/// quietly becoming a new file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    /// An existing block device, written in place
+    Device,
+    /// An image file, created or truncated
+    File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Stage {
+    #[default]
+    Writing,
+    Verifying,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Progress {
+    pub stage: Stage,
+    pub done: u64,
+    /// Bytes to write, `None` while unknown
+    pub total: Option<u64>,
+    /// Compressed bytes read out of the image's size, as the decompressed size of
+    /// compressed images isn't known upfront
+    pub compressed: Option<(u64, u64)>,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> Option<f32> {
+        let (done, total) = match (self.total, self.compressed) {
+            (Some(total), _) => (self.done, total),
+            (None, Some(compressed)) => compressed,
+            (None, None) => return None,
+        };
+        (total > 0).then(|| done as f32 / total as f32)
+    }
+}
+
+/// Writes `image` to `target`, decompressing `.gz`, `.xz` and `.zip` images on the fly,
+/// see [`write_stream`].
+pub fn write_image<P, Q, F>(
+    image: P,
+    target: Q,
+    kind: TargetKind,
+    mut progress: F,
+) -> anyhow::Result<u64>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    F: FnMut(Progress),
+{
+    let image = image.as_ref();
+    let file = fs::File::open(image).with_context(|| format!("failed to open {image:?}"))?;
+    let size = file.metadata()?.len();
+    let compression = Compression::detect(image)?;
+    match compression {
+        Compression::None => return write_stream(file, Some(size), target, kind, progress),
+        Compression::Zip => return write_zip_entry(file, image, target.as_ref(), kind, progress),
+        _ => (),
+    }
+
+    let count = Rc::new(Cell::new(0));
+    let counted = CountingReader {
+        inner: io::BufReader::new(file),
+        count: Rc::clone(&count),
+    };
+    let src: Box<dyn Read> = match compression {
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(counted)),
+        _ => Box::new(xz2::read::XzDecoder::new_multi_decoder(counted)),
+    };
+    write_stream(src, None, target, kind, |p| {
+        progress(Progress {
+            compressed: (p.stage == Stage::Writing).then(|| (count.get(), size)),
+            ..p
+        })
+    })
+    .with_context(|| format!("failed to write {compression:?} compressed {image:?}"))
+}
+
+/// Writes the flashable entry of the zip archive `image`, zips know its size upfront.
+fn write_zip_entry<F>(
+    file: fs::File,
+    image: &Path,
+    target: &Path,
+    kind: TargetKind,
+    progress: F,
+) -> anyhow::Result<u64>
+where
+    F: FnMut(Progress),
+{
+    let mut archive = zip::ZipArchive::new(io::BufReader::new(file))
+        .with_context(|| format!("failed to read the zip archive {image:?}"))?;
+    let name = match archive.file_names().find(|name| is_flashable(name)) {
+        Some(name) => name.to_string(),
+        None => bail!("no image to write in {:?}", image),
+    };
+    let entry = archive
+        .by_name(&name)
+        .with_context(|| format!("failed to read {name} in {image:?}"))?;
+    let size = entry.size();
+    write_stream(entry, Some(size), target, kind, progress)
+        .with_context(|| format!("failed to write {name} of {image:?}"))
+}
+
+/// Writes everything from `src` to the start of `target` and verifies it by reading it
+/// back, returning the number of bytes written.
+///
+/// Devices are written in place and have to exist already, image files are created or
+/// truncated first.
+pub fn write_stream<R, P, F>(
+    mut src: R,
+    total: Option<u64>,
+    target: P,
+    kind: TargetKind,
+    mut progress: F,
+) -> anyhow::Result<u64>
+where
+    R: Read,
+    P: AsRef<Path>,
+    F: FnMut(Progress),
+{
+    let target = target.as_ref();
+    let is_file = kind == TargetKind::File;
+    if is_file && matches!(fs::metadata(target), Ok(meta) if !meta.is_file()) {
+        bail!("{:?} exists and isn't a regular file", target);
+    }
+    let mut dest = fs::OpenOptions::new()
+        .write(true)
+        .create(is_file)
+        .truncate(is_file)
+        .open(target)
+        .with_context(|| format!("failed to open {target:?} for writing"))?;
+
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; BUF_SIZE];
+    let mut written = 0;
+    loop {
+        let len = match src.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err).context("failed to read the image"),
+        };
+        dest.write_all(&buf[..len])
+            .with_context(|| format!("failed to write to {target:?} at byte {written}"))?;
+        hasher.update(&buf[..len]);
+        written += len as u64;
+        progress(Progress {
+            stage: Stage::Writing,
+            done: written,
+            total,
+            compressed: None,
+        });
+    }
+    dest.flush()?;
+    dest.sync_all()
+        .with_context(|| format!("failed to sync {target:?}"))?;
+    drop_cache(&dest).with_context(|| format!("failed to drop the cached pages of {target:?}"))?;
+    drop(dest);
+
+    let expected = hasher.finalize();
+    let actual = hash_prefix(target, written, |done| {
+        progress(Progress {
+            stage: Stage::Verifying,
+            done,
+            total: Some(written),
+            compressed: None,
+        })
+    })?;
+    if actual != expected.as_slice() {
+        bail!(
+            "verification failed, {:?} doesn't read back what was written",
+            target
+        );
+    }
+    Ok(written)
+}
+
+/// SHA-256 of the first `len` bytes of `path`.
+fn hash_prefix<P, F>(path: P, len: u64, mut progress: F) -> anyhow::Result<Vec<u8>>
+where
+    P: AsRef<Path>,
+    F: FnMut(u64),
+{
+    let path = path.as_ref();
+    let src = fs::File::open(path)
+        .with_context(|| format!("failed to open {path:?} for verification"))?;
+    drop_cache(&src).with_context(|| format!("failed to drop the cached pages of {path:?}"))?;
+    let mut src = src.take(len);
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; BUF_SIZE];
+    let mut done = 0;
+    loop {
+        let read = src.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+        done += read as u64;
+        progress(done);
+    }
+    if done < len {
+        bail!(
+            "{:?} is smaller than the image, only {} of {} bytes",
+            path,
+            done,
+            len
+        );
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+/// `_IO(0x12, 97)` from `linux/fs.h`, which the libc crate doesn't export.
+#[cfg(target_os = "linux")]
+const BLKFLSBUF: u32 = 0x1261;
+
+/// Evicts the page cache of an already synced `file`, so the next read comes from the
+/// device. Block devices also get their buffer cache flushed with `BLKFLSBUF`.
+#[cfg(target_os = "linux")]
+fn drop_cache(file: &fs::File) -> io::Result<()> {
+    use std::os::unix::{fs::FileTypeExt, io::AsRawFd};
+
+    let fd = file.as_raw_fd();
+    if file.metadata()?.file_type().is_block_device() {
+        // SAFETY: `fd` is open for the lifetime of `file` and BLKFLSBUF takes no argument
+        if unsafe { libc::ioctl(fd, BLKFLSBUF as _, 0) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    // SAFETY: as above, `posix_fadvise` only takes the descriptor and a range
+    match unsafe { libc::posix_fadvise(fd, 0, 0, libc::POSIX_FADV_DONTNEED) } {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+/// There's no portable way to evict the cache elsewhere, the read-back only catches
+/// errors the OS reports there.
+#[cfg(not(target_os = "linux"))]
+fn drop_cache(_file: &fs::File) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn write_and_verify() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let image = tmpdir.path().join("ventoy-livecd.iso");
+        let data = (0..BUF_SIZE * 2 + 123)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        fs::write(&image, &data).unwrap();
+        // A regular file standing in for the device
+        let device = tmpdir.path().join("sdz");
+
+        let mut last = Progress::default();
+        let written = write_image(&image, &device, TargetKind::File, |progress| {
+            last = progress
+        })
+        .unwrap();
+        assert_eq!(written, data.len() as u64);
+        assert_eq!(fs::read(&device).unwrap(), data);
+        assert_eq!(last.stage, Stage::Verifying);
+        assert_eq!(last.fraction(), Some(1.));
+    }
+
+    #[test]
+    fn write_compressed_images() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let data = (0..BUF_SIZE + 4567)
+            .map(|i| (i / 1000 % 251) as u8)
+            .collect::<Vec<_>>();
+
+        let gz = tmpdir.path().join("raspios.img.gz");
+        let mut enc = flate2::write::GzEncoder::new(
+            fs::File::create(&gz).unwrap(),
+            flate2::Compression::default(),
+        );
+        enc.write_all(&data).unwrap();
+        enc.finish().unwrap();
+        let xz = tmpdir.path().join("chromeos.bin.xz");
+        let mut enc = xz2::write::XzEncoder::new(fs::File::create(&xz).unwrap(), 6);
+        enc.write_all(&data).unwrap();
+        enc.finish().unwrap();
+
+        let zip = tmpdir.path().join("chromeos.bin.zip");
+        let mut enc = zip::ZipWriter::new(fs::File::create(&zip).unwrap());
+        enc.start_file("README.txt", Default::default()).unwrap();
+        enc.write_all(b"not the image").unwrap();
+        enc.start_file("chromeos_15183.69.0_reven_recovery.bin", Default::default())
+            .unwrap();
+        enc.write_all(&data).unwrap();
+        enc.finish().unwrap();
+
+        for (image, compression) in [
+            (gz, Compression::Gzip),
+            (xz, Compression::Xz),
+            (zip, Compression::Zip),
+        ] {
+            assert_eq!(Compression::detect(&image).unwrap(), compression);
+            let device = tmpdir.path().join("sdz");
+            let mut fractions = Vec::new();
+            let written = write_image(&image, &device, TargetKind::File, |progress| {
+                fractions.push(progress.fraction().unwrap())
+            })
+            .unwrap();
+            assert_eq!(written, data.len() as u64);
+            assert_eq!(fs::read(&device).unwrap(), data);
+            assert!(fractions.iter().all(|f| (0. ..=1.).contains(f)));
+        }
+        assert!(is_flashable("2022-09-22-raspios-bullseye-arm64.IMG.XZ"));
+        assert!(is_flashable(
+            "chromeos_15183.69.0_reven_recovery_stable-channel.bin.zip"
+        ));
+        assert!(!is_flashable("notes.txt.gz"));
+    }
+
+    #[test]
+    fn reject_corrupt_image() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let image = tmpdir.path().join("broken.img.gz");
+        fs::write(&image, [0x1f, 0x8b, 0x08, 0x00, 0xde, 0xad]).unwrap();
+        let err =
+            write_image(&image, tmpdir.path().join("sdz"), TargetKind::File, |_| ()).unwrap_err();
+        assert!(format!("{err:#}").contains("Gzip compressed"), "{:#}", err);
+    }
+
+    #[test]
+    fn never_create_devices() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let image = tmpdir.path().join("ventoy-livecd.iso");
+        fs::write(&image, b"ventoy").unwrap();
+
+        // A mistyped device path
+        let device = tmpdir.path().join("sdz");
+        let err = write_image(&image, &device, TargetKind::Device, |_| ()).unwrap_err();
+        assert!(err.to_string().contains("for writing"), "{}", err);
+        assert!(!device.exists());
+
+        // Nor write an image file over a directory
+        let err = write_image(&image, tmpdir.path(), TargetKind::File, |_| ()).unwrap_err();
+        assert!(err.to_string().contains("isn't a regular file"), "{}", err);
+
+        // Devices are written in place, without truncating what's past the image
+        fs::write(&device, b"0123456789").unwrap();
+        let written = write_image(&image, &device, TargetKind::Device, |_| ()).unwrap();
+        assert_eq!(written, 6);
+        assert_eq!(fs::read(&device).unwrap(), b"ventoy6789");
+    }
+
+    #[test]
+    fn detect_short_target() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let err = hash_prefix(tmpdir.path().join("missing"), 1, |_| ()).unwrap_err();
+        assert!(err.to_string().contains("for verification"), "{}", err);
+
+        let short = tmpdir.path().join("short");
+        fs::write(&short, b"abc").unwrap();
+        let err = hash_prefix(&short, 4, |_| ()).unwrap_err();
+        assert!(err.to_string().contains("only 3 of 4 bytes"), "{}", err);
+    }
+}
@@ -0,0 +1,494 @@
+//! The Ventoy update flow and the release feeds browsing as state machines, events in,
+//! state and effects out.
+//!
+//! Frontends draw the state, run the effects however they like, e.g. on a thread, and
+//! feed their results back in as events. Events that don't apply to the current state,
+//! like a download finishing after it was abandoned, are ignored.
+
+use std::path::PathBuf;
+
+use super::{
+    error::{Error, Result},
+    package::PkgTarget,
+    update::{CachedPkg, Release},
+    utils::FeedsItem,
+};
+
+#[derive(Debug, Clone, Default)]
+pub enum UpdateState {
+    /// Nothing was checked yet
+    #[default]
+    Idle,
+    CheckingRelease,
+    ReleaseFailed(Error),
+    FoundRelease(Release),
+    Downloading {
+        release: Release,
+        target: PkgTarget,
+    },
+    Done {
+        release: Release,
+        /// Where the package was extracted to
+        pkg_dir: PathBuf,
+        target: PkgTarget,
+    },
+    Failed {
+        release: Release,
+        /// `None` if there's no package for the running system
+        target: Option<PkgTarget>,
+        error: Error,
+    },
+}
+
+impl UpdateState {
+    /// The latest release, once it was found.
+    pub fn release(&self) -> Option<&Release> {
+        match self {
+            UpdateState::Idle | UpdateState::CheckingRelease | UpdateState::ReleaseFailed(_) => {
+                None
+            }
+            UpdateState::FoundRelease(release)
+            | UpdateState::Downloading { release, .. }
+            | UpdateState::Done { release, .. }
+            | UpdateState::Failed { release, .. } => Some(release),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum UpdateEvent {
+    /// Check for the latest release, also refreshes a failed check
+    Check,
+    ReleaseFetched(Result<Release>),
+    /// Download the package for the target, `None` if there's none for the running system
+    Download(Option<PkgTarget>),
+    Installed(Result<(PathBuf, CachedPkg)>),
+    /// Try the failed step again, only if its error is retryable
+    Retry,
+    /// Go back from a failed download to picking the package
+    Back,
+    /// Stop checking for the release or downloading the package, the state changes once
+    /// the stopped step reports [`Error::Cancelled`]
+    Cancel,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateEffect {
+    FetchRelease,
+    /// Cancel the running [`UpdateEffect::FetchRelease`]
+    CancelFetch,
+    /// Download and extract the package, see [`super::update::install_pkg`]
+    InstallPkg {
+        release: Release,
+        target: PkgTarget,
+    },
+    /// Remember the installed package, to be reused on the next start
+    CachePkg(CachedPkg),
+    /// Cancel the running [`UpdateEffect::InstallPkg`]
+    CancelInstall,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UpdateFlow {
+    state: UpdateState,
+}
+
+impl UpdateFlow {
+    pub fn state(&self) -> &UpdateState {
+        &self.state
+    }
+
+    pub fn handle(&mut self, event: UpdateEvent) -> Option<UpdateEffect> {
+        use UpdateState::*;
+
+        let (state, effect) = match (&self.state, event) {
+            (Idle | ReleaseFailed(_) | FoundRelease(_), UpdateEvent::Check) => {
+                (CheckingRelease, Some(UpdateEffect::FetchRelease))
+            }
+            (ReleaseFailed(error), UpdateEvent::Retry) if error.is_retryable() => {
+                (CheckingRelease, Some(UpdateEffect::FetchRelease))
+            }
+            (CheckingRelease, UpdateEvent::ReleaseFetched(fetched)) => match fetched {
+                Ok(release) => (FoundRelease(release), None),
+                Err(error) => (ReleaseFailed(error), None),
+            },
+            (FoundRelease(release), UpdateEvent::Download(target)) => match target {
+                Some(target) => Self::install(release.clone(), target),
+                None => (
+                    Failed {
+                        release: release.clone(),
+                        target: None,
+                        error: Error::NoMatchingAsset {
+                            target: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+                            assets: release.assets.iter().map(|a| a.name.clone()).collect(),
+                        },
+                    },
+                    None,
+                ),
+            },
+            (Downloading { release, target }, UpdateEvent::Installed(installed)) => match installed
+            {
+                Ok((pkg_dir, pkg)) => (
+                    Done {
+                        release: release.clone(),
+                        pkg_dir,
+                        target: *target,
+                    },
+                    Some(UpdateEffect::CachePkg(pkg)),
+                ),
+                Err(error) => (
+                    Failed {
+                        release: release.clone(),
+                        target: Some(*target),
+                        error,
+                    },
+                    None,
+                ),
+            },
+            (
+                Failed {
+                    release,
+                    target: Some(target),
+                    error,
+                },
+                UpdateEvent::Retry,
+            ) if error.is_retryable() => Self::install(release.clone(), *target),
+            (Failed { release, .. }, UpdateEvent::Back) => (FoundRelease(release.clone()), None),
+            (CheckingRelease, UpdateEvent::Cancel) => return Some(UpdateEffect::CancelFetch),
+            (Downloading { .. }, UpdateEvent::Cancel) => return Some(UpdateEffect::CancelInstall),
+            (_, event) => {
+                tracing::debug!("Ignored {:?} in {:?}", event, self.state);
+                return None;
+            }
+        };
+        tracing::info!("Update flow: {:?}", StateName(&state));
+        self.state = state;
+        effect
+    }
+
+    fn install(release: Release, target: PkgTarget) -> (UpdateState, Option<UpdateEffect>) {
+        (
+            UpdateState::Downloading {
+                release: release.clone(),
+                target,
+            },
+            Some(UpdateEffect::InstallPkg { release, target }),
+        )
+    }
+}
+
+/// Logs just the name of the state, without the whole release.
+struct StateName<'a>(&'a UpdateState);
+
+impl std::fmt::Debug for StateName<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self.0 {
+            UpdateState::Idle => "Idle",
+            UpdateState::CheckingRelease => "CheckingRelease",
+            UpdateState::ReleaseFailed(_) => "ReleaseFailed",
+            UpdateState::FoundRelease(_) => "FoundRelease",
+            UpdateState::Downloading { .. } => "Downloading",
+            UpdateState::Done { .. } => "Done",
+            UpdateState::Failed { .. } => "Failed",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum FeedsState {
+    #[default]
+    Idle,
+    Loading,
+    Loaded {
+        feeds: Vec<FeedsItem>,
+        /// Distinct groups of the feeds in order, after `"all"`
+        groups: Vec<String>,
+    },
+    Failed(Error),
+}
+
+#[derive(Debug, Clone)]
+pub enum FeedsEvent {
+    /// Show the cached feeds, fetching them if there are none
+    Load(Vec<FeedsItem>),
+    /// Fetch the feeds again, ignoring the cache
+    Refresh,
+    Fetched(Result<Vec<FeedsItem>>),
+    /// Fetch again after a retryable error
+    Retry,
+    /// Stop fetching, the state changes once the fetch reports [`Error::Cancelled`] and
+    /// goes back to the feeds loaded before, if there were any
+    Cancel,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedsEffect {
+    FetchFeeds,
+    /// Remember the fetched feeds, to be used on the next start
+    CacheFeeds,
+    /// Cancel the running [`FeedsEffect::FetchFeeds`]
+    CancelFetch,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FeedsFlow {
+    state: FeedsState,
+    /// The last loaded feeds, shown again when a refresh is cancelled
+    last_loaded: Vec<FeedsItem>,
+}
+
+fn loaded(feeds: Vec<FeedsItem>) -> FeedsState {
+    let mut groups = vec!["all".to_string()];
+    for item in &feeds {
+        if !groups.contains(&item.group) {
+            groups.push(item.group.clone());
+        }
+    }
+    FeedsState::Loaded { feeds, groups }
+}
+
+impl FeedsFlow {
+    pub fn state(&self) -> &FeedsState {
+        &self.state
+    }
+
+    pub fn feeds(&self) -> &[FeedsItem] {
+        match &self.state {
+            FeedsState::Loaded { feeds, .. } => feeds,
+            _ => &[],
+        }
+    }
+
+    pub fn handle(&mut self, event: FeedsEvent) -> Option<FeedsEffect> {
+        use FeedsState::*;
+
+        let (state, effect) = match (&self.state, event) {
+            (Idle, FeedsEvent::Load(cached)) if !cached.is_empty() => {
+                self.last_loaded = cached.clone();
+                (loaded(cached), None)
+            }
+            (Idle, FeedsEvent::Load(_))
+            | (Idle | Loaded { .. } | Failed(_), FeedsEvent::Refresh) => {
+                (Loading, Some(FeedsEffect::FetchFeeds))
+            }
+            (Failed(error), FeedsEvent::Retry) if error.is_retryable() => {
+                (Loading, Some(FeedsEffect::FetchFeeds))
+            }
+            (Loading, FeedsEvent::Cancel) => return Some(FeedsEffect::CancelFetch),
+            (Loading, FeedsEvent::Fetched(fetched)) => match fetched {
+                Ok(feeds) => {
+                    self.last_loaded = feeds.clone();
+                    (loaded(feeds), Some(FeedsEffect::CacheFeeds))
+                }
+                Err(Error::Cancelled) if !self.last_loaded.is_empty() => {
+                    (loaded(self.last_loaded.clone()), None)
+                }
+                Err(error) => (Failed(error), None),
+            },
+            (_, event) => {
+                tracing::debug!("Ignored {:?} while feeds are {:?}", event, self.state);
+                return None;
+            }
+        };
+        self.state = state;
+        effect
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        package::{Arch, PkgKind},
+        testing::feeds_item,
+        update::ReleaseAsset,
+    };
+
+    fn release() -> Release {
+        Release {
+            tag_name: "v1.0.86".to_string(),
+            assets: vec![ReleaseAsset {
+                name: "ventoy-1.0.86-linux.tar.gz".to_string(),
+                download_url: "https://example.com/ventoy-1.0.86-linux.tar.gz".to_string(),
+            }],
+        }
+    }
+
+    const TARGET: PkgTarget = PkgTarget {
+        kind: PkgKind::Linux,
+        arch: Arch::X86_64,
+    };
+
+    fn installed() -> (PathBuf, CachedPkg) {
+        let pkg = CachedPkg {
+            version: "v1.0.86".to_string(),
+            path: "/cache/ventoy-1.0.86-linux.tar.gz".into(),
+        };
+        ("/cache/ventoy-v1.0.86-linux-x86_64".into(), pkg)
+    }
+
+    #[test]
+    fn update_retry_after_failed_download() {
+        let mut flow = UpdateFlow::default();
+        assert_eq!(
+            flow.handle(UpdateEvent::Check),
+            Some(UpdateEffect::FetchRelease)
+        );
+        assert!(matches!(flow.state(), UpdateState::CheckingRelease));
+        assert_eq!(
+            flow.handle(UpdateEvent::ReleaseFetched(Ok(release()))),
+            None
+        );
+        assert!(matches!(flow.state(), UpdateState::FoundRelease(_)));
+
+        let install = Some(UpdateEffect::InstallPkg {
+            release: release(),
+            target: TARGET,
+        });
+        assert_eq!(flow.handle(UpdateEvent::Download(Some(TARGET))), install);
+        let reset = Error::Network("connection reset".to_string());
+        assert_eq!(flow.handle(UpdateEvent::Installed(Err(reset))), None);
+        assert!(matches!(flow.state(), UpdateState::Failed { .. }));
+
+        // Results of downloads that are no longer waited on don't count
+        assert_eq!(flow.handle(UpdateEvent::Installed(Ok(installed()))), None);
+        assert!(matches!(flow.state(), UpdateState::Failed { .. }));
+
+        assert_eq!(flow.handle(UpdateEvent::Retry), install);
+        assert!(matches!(flow.state(), UpdateState::Downloading { .. }));
+        assert_eq!(
+            flow.handle(UpdateEvent::Installed(Ok(installed()))),
+            Some(UpdateEffect::CachePkg(installed().1))
+        );
+        match flow.state() {
+            UpdateState::Done {
+                pkg_dir, target, ..
+            } => {
+                assert_eq!(pkg_dir, &installed().0);
+                assert_eq!(target, &TARGET);
+            }
+            state => panic!("{state:?}"),
+        }
+    }
+
+    #[test]
+    fn update_cancel_download() {
+        let mut flow = UpdateFlow::default();
+        flow.handle(UpdateEvent::Check);
+        flow.handle(UpdateEvent::ReleaseFetched(Ok(release())));
+        flow.handle(UpdateEvent::Download(Some(TARGET)));
+        assert_eq!(
+            flow.handle(UpdateEvent::Cancel),
+            Some(UpdateEffect::CancelInstall)
+        );
+        // Still downloading until the download stops
+        assert!(matches!(flow.state(), UpdateState::Downloading { .. }));
+        flow.handle(UpdateEvent::Installed(Err(Error::Cancelled)));
+        assert!(matches!(
+            flow.state(),
+            UpdateState::Failed {
+                error: Error::Cancelled,
+                ..
+            }
+        ));
+        assert_eq!(flow.handle(UpdateEvent::Cancel), None);
+        assert!(matches!(
+            flow.handle(UpdateEvent::Retry),
+            Some(UpdateEffect::InstallPkg { .. })
+        ));
+    }
+
+    #[test]
+    fn update_errors_not_retryable() {
+        let mut flow = UpdateFlow::default();
+        flow.handle(UpdateEvent::Check);
+        let not_found = Error::HttpStatus {
+            status: 404,
+            status_text: "Not Found".to_string(),
+            url: crate::update::LATEST_RELEASE_URL.to_string(),
+        };
+        flow.handle(UpdateEvent::ReleaseFetched(Err(not_found)));
+        assert_eq!(flow.handle(UpdateEvent::Retry), None);
+        assert!(matches!(flow.state(), UpdateState::ReleaseFailed(_)));
+        assert_eq!(
+            flow.handle(UpdateEvent::Check),
+            Some(UpdateEffect::FetchRelease)
+        );
+        flow.handle(UpdateEvent::ReleaseFetched(Ok(release())));
+
+        // No package for the running system
+        assert_eq!(flow.handle(UpdateEvent::Download(None)), None);
+        match flow.state() {
+            UpdateState::Failed { target, error, .. } => {
+                assert_eq!(target, &None);
+                assert!(matches!(error, Error::NoMatchingAsset { .. }));
+            }
+            state => panic!("{state:?}"),
+        }
+        assert_eq!(flow.handle(UpdateEvent::Retry), None);
+        assert_eq!(flow.handle(UpdateEvent::Back), None);
+        assert!(matches!(flow.state(), UpdateState::FoundRelease(_)));
+    }
+
+    #[test]
+    fn feeds_cache_refresh_and_retry() {
+        let cached = vec![
+            feeds_item("ubuntu", "ubuntu-22.10-desktop-amd64.iso.torrent"),
+            feeds_item(
+                "fedora",
+                "Fedora-Workstation-Live-x86_64-37-1.7.iso.torrent",
+            ),
+            feeds_item("ubuntu", "ubuntu-22.04.1-desktop-amd64.iso.torrent"),
+        ];
+        let mut flow = FeedsFlow::default();
+        assert_eq!(flow.handle(FeedsEvent::Load(cached.clone())), None);
+        match flow.state() {
+            FeedsState::Loaded { feeds, groups } => {
+                assert_eq!(feeds.len(), 3);
+                assert_eq!(groups, &["all", "ubuntu", "fedora"]);
+            }
+            state => panic!("{state:?}"),
+        }
+
+        assert_eq!(
+            flow.handle(FeedsEvent::Refresh),
+            Some(FeedsEffect::FetchFeeds)
+        );
+        assert!(flow.feeds().is_empty());
+        flow.handle(FeedsEvent::Fetched(Err(Error::Network(
+            "timed out".to_string(),
+        ))));
+        assert!(matches!(flow.state(), FeedsState::Failed(_)));
+        assert_eq!(
+            flow.handle(FeedsEvent::Retry),
+            Some(FeedsEffect::FetchFeeds)
+        );
+        assert_eq!(
+            flow.handle(FeedsEvent::Fetched(Ok(cached))),
+            Some(FeedsEffect::CacheFeeds)
+        );
+        assert_eq!(flow.feeds().len(), 3);
+
+        // A cancelled refresh goes back to the feeds from before
+        flow.handle(FeedsEvent::Refresh);
+        assert_eq!(
+            flow.handle(FeedsEvent::Cancel),
+            Some(FeedsEffect::CancelFetch)
+        );
+        assert_eq!(
+            flow.handle(FeedsEvent::Fetched(Err(Error::Cancelled))),
+            None
+        );
+        assert_eq!(flow.feeds().len(), 3);
+
+        let mut flow = FeedsFlow::default();
+        assert_eq!(
+            flow.handle(FeedsEvent::Load(Vec::new())),
+            Some(FeedsEffect::FetchFeeds)
+        );
+        assert_eq!(flow.handle(FeedsEvent::Retry), None);
+        // Nothing to go back to
+        flow.handle(FeedsEvent::Fetched(Err(Error::Cancelled)));
+        assert!(matches!(flow.state(), FeedsState::Failed(Error::Cancelled)));
+    }
+}
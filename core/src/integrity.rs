@@ -0,0 +1,597 @@
+//! Checking images already downloaded, or copied to a drive, against their checksums.
+//!
+//! The reference checksum comes from a pasted value, a sibling checksum file or the
+//! checksums file of the image's feed, and picks the hash to compute. Computed hashes are
+//! kept in a [`VerifyCache`] so that unchanged files aren't hashed again.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::digest::DynDigest;
+
+use super::{
+    error::{Error, Result},
+    task::CancelToken,
+    update,
+};
+
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+/// Chunks read ahead of the hashing.
+const CHUNKS_IN_FLIGHT: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum HashAlgo {
+    Sha256,
+    Sha1,
+    Md5,
+}
+
+impl HashAlgo {
+    pub const ALL: [Self; 3] = [Self::Sha256, Self::Sha1, Self::Md5];
+
+    /// The algorithm of a hex encoded hash, going by its length.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Self::ALL
+            .into_iter()
+            .find(|algo| algo.hex_len() == hex.len())
+    }
+
+    pub fn hex_len(self) -> usize {
+        match self {
+            Self::Sha256 => 64,
+            Self::Sha1 => 40,
+            Self::Md5 => 32,
+        }
+    }
+
+    /// Extension of the checksum files made with it, e.g. `sha256` for `<image>.sha256`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha1 => "sha1",
+            Self::Md5 => "md5",
+        }
+    }
+
+    fn hasher(self) -> Box<dyn DynDigest + Send> {
+        match self {
+            Self::Sha256 => Box::<sha2::Sha256>::default(),
+            Self::Sha1 => Box::<sha1::Sha1>::default(),
+            Self::Md5 => Box::<md5::Md5>::default(),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Sha256 => "SHA-256",
+            Self::Sha1 => "SHA-1",
+            Self::Md5 => "MD5",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChecksumSource {
+    /// The checksums file of the image's feed, at this url
+    Feed(String),
+    /// A checksum file next to the image
+    File(PathBuf),
+    Pasted,
+}
+
+impl fmt::Display for ChecksumSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Feed(url) => write!(f, "{url}"),
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Pasted => f.write_str("the pasted checksum"),
+        }
+    }
+}
+
+/// The checksum an image is expected to have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expected {
+    pub algo: HashAlgo,
+    /// Lowercase hex
+    pub hash: String,
+    pub source: ChecksumSource,
+}
+
+impl Expected {
+    /// A pasted hash, possibly followed by the file name as `sha256sum` prints it.
+    pub fn pasted(text: &str) -> Option<Self> {
+        let hash = text.split_whitespace().next()?;
+        Some(Self {
+            algo: HashAlgo::from_hex(hash)?,
+            hash: hash.to_ascii_lowercase(),
+            source: ChecksumSource::Pasted,
+        })
+    }
+
+    /// The checksum of `name` in the checksums file `text`, or its only hash if the file
+    /// holds a bare hash, like some `<image>.sha256` files do.
+    pub fn from_checksums(text: &str, name: &str, source: ChecksumSource) -> Option<Self> {
+        let (algo, hash) = match parse_checksums(text).remove(name) {
+            Some(entry) => entry,
+            None => {
+                let mut tokens = text.split_whitespace();
+                let hash = tokens.next().filter(|_| tokens.next().is_none())?;
+                (HashAlgo::from_hex(hash)?, hash.to_ascii_lowercase())
+            }
+        };
+        Some(Self { algo, hash, source })
+    }
+}
+
+/// Parses `sha256sum`, `sha1sum` and `md5sum` output, `<hash>  <name>` or `<hash> *<name>`
+/// lines, and BSD style `SHA256 (<name>) = <hash>` lines into names and their lowercase
+/// hashes. Anything else, like the armor of a clearsigned file, is skipped.
+pub fn parse_checksums(text: &str) -> BTreeMap<String, (HashAlgo, String)> {
+    let mut sums = BTreeMap::new();
+    for line in text.lines().map(str::trim) {
+        let bsd = ["SHA256 (", "SHA1 (", "MD5 ("]
+            .into_iter()
+            .find_map(|tag| line.strip_prefix(tag));
+        let parsed = match bsd {
+            Some(rest) => rest.rsplit_once(") = "),
+            None => line
+                .split_once(char::is_whitespace)
+                .map(|(hash, name)| (name.trim_start().trim_start_matches('*'), hash)),
+        };
+        if let Some((name, hash)) = parsed {
+            if let Some(algo) = HashAlgo::from_hex(hash) {
+                let name = name.trim_start_matches("./");
+                sums.insert(name.to_string(), (algo, hash.to_ascii_lowercase()));
+            }
+        }
+    }
+    sums
+}
+
+/// A checksum file next to the image at `path`, `<image>.sha256`, `.sha1` or `.md5`, or a
+/// `SHA256SUMS` in its directory listing it.
+pub fn sibling_checksum<P: AsRef<Path>>(path: P) -> Option<Expected> {
+    let path = path.as_ref();
+    let name = path.file_name()?.to_str()?;
+    let dir = path.parent()?;
+    let mut candidates: Vec<_> = HashAlgo::ALL
+        .into_iter()
+        .flat_map(|algo| {
+            let ext = algo.extension();
+            [format!("{name}.{ext}"), format!("{name}.{ext}sum")]
+        })
+        .collect();
+    candidates.extend(["SHA256SUMS", "SHA1SUMS", "MD5SUMS"].map(String::from));
+    candidates.into_iter().find_map(|file| {
+        let file = dir.join(file);
+        let text = fs::read_to_string(&file).ok()?;
+        Expected::from_checksums(&text, name, ChecksumSource::File(file))
+    })
+}
+
+/// The checksum of `name` in the checksums file at `sums_url`.
+pub fn fetch_checksum(sums_url: &str, name: &str) -> Result<Expected> {
+    let text = update::get(sums_url)?
+        .into_string()
+        .map_err(|e| Error::Network(e.to_string()))?;
+    Expected::from_checksums(&text, name, ChecksumSource::Feed(sums_url.to_string())).ok_or_else(
+        || Error::MissingChecksum {
+            name: name.to_string(),
+            url: sums_url.to_string(),
+        },
+    )
+}
+
+/// Hashes the file at `path`, calling `progress` with the bytes hashed so far and the
+/// file size. Chunks are read on another thread while the previous ones are hashed.
+pub fn hash_file<P, F>(
+    path: P,
+    algo: HashAlgo,
+    cancel: &CancelToken,
+    mut progress: F,
+) -> Result<String>
+where
+    P: AsRef<Path>,
+    F: FnMut(u64, u64),
+{
+    let path = path.as_ref();
+    let mut file = fs::File::open(path).map_err(|e| Error::io(path, e))?;
+    let total = file.metadata().map_err(|e| Error::io(path, e))?.len();
+    let mut hasher = algo.hasher();
+
+    thread::scope(|scope| {
+        let (chunk_tx, chunk_rx) = mpsc::sync_channel::<io::Result<Vec<u8>>>(CHUNKS_IN_FLIGHT);
+        // Hashed chunks are sent back to be read into again
+        let (free_tx, free_rx) = mpsc::channel::<Vec<u8>>();
+        scope.spawn(move || loop {
+            let mut buf = free_rx.try_recv().unwrap_or_else(|_| vec![0; CHUNK_SIZE]);
+            buf.resize(CHUNK_SIZE, 0);
+            let read = read_chunk(&mut file, &mut buf).map(|read| {
+                buf.truncate(read);
+                buf
+            });
+            let done = !matches!(&read, Ok(buf) if !buf.is_empty());
+            // The hashing side hung up, it failed or was cancelled
+            if chunk_tx.send(read).is_err() || done {
+                break;
+            }
+        });
+
+        let mut done = 0;
+        for chunk in chunk_rx {
+            cancel.check()?;
+            let chunk = chunk.map_err(|e| Error::io(path, e))?;
+            if chunk.is_empty() {
+                break;
+            }
+            hasher.update(&chunk);
+            done += chunk.len() as u64;
+            progress(done, total);
+            let _ = free_tx.send(chunk);
+        }
+        Ok(())
+    })?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Fills `buf` as far as the file goes.
+fn read_chunk(file: &mut fs::File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// A hash computed earlier, valid as long as the file keeps its size and mtime.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyRecord {
+    pub size: u64,
+    /// Seconds since the epoch
+    pub modified: u64,
+    pub hashes: BTreeMap<HashAlgo, String>,
+    /// Whether it matched its checksum the last time it was verified
+    #[serde(default)]
+    pub verified: Option<bool>,
+}
+
+/// Computed hashes of files, by path. Clones share the records.
+#[derive(Debug, Clone, Default)]
+pub struct VerifyCache {
+    records: Arc<Mutex<BTreeMap<PathBuf, VerifyRecord>>>,
+}
+
+impl VerifyCache {
+    /// Loads the records saved at `path`, empty if there are none.
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let records = fs::read(path.as_ref())
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        Self {
+            records: Arc::new(Mutex::new(records)),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let bytes = serde_json::to_vec_pretty(&*self.records.lock().unwrap())?;
+        update::write_atomic(path, &bytes)
+    }
+
+    /// The record of the file at `path`, if it's unchanged since.
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<VerifyRecord> {
+        let path = path.as_ref();
+        let (size, modified) = file_stamp(path).ok()?;
+        self.records
+            .lock()
+            .unwrap()
+            .get(path)
+            .filter(|record| record.size == size && record.modified == modified)
+            .cloned()
+    }
+
+    /// Hash of the file at `path`, from the cache if it's unchanged since it was hashed.
+    pub fn hash<P, F>(
+        &self,
+        path: P,
+        algo: HashAlgo,
+        cancel: &CancelToken,
+        progress: F,
+    ) -> Result<String>
+    where
+        P: AsRef<Path>,
+        F: FnMut(u64, u64),
+    {
+        let path = path.as_ref();
+        if let Some(hash) = self
+            .get(path)
+            .and_then(|record| record.hashes.get(&algo).cloned())
+        {
+            return Ok(hash);
+        }
+        self.rehash(path, algo, cancel, progress)
+    }
+
+    /// Hashes the file at `path` even if it's cached, for changes that kept its size and
+    /// modification time, and records the new hash.
+    pub fn rehash<P, F>(
+        &self,
+        path: P,
+        algo: HashAlgo,
+        cancel: &CancelToken,
+        progress: F,
+    ) -> Result<String>
+    where
+        P: AsRef<Path>,
+        F: FnMut(u64, u64),
+    {
+        let path = path.as_ref();
+        let hash = hash_file(path, algo, cancel, progress)?;
+        self.record(path, algo, &hash, None);
+        Ok(hash)
+    }
+
+    /// Records a hash of the file at `path`, and whether it matched what was expected.
+    fn record<P: AsRef<Path>>(&self, path: P, algo: HashAlgo, hash: &str, verified: Option<bool>) {
+        let path = path.as_ref();
+        let Ok((size, modified)) = file_stamp(path) else {
+            return;
+        };
+        let mut records = self.records.lock().unwrap();
+        let record = records
+            .entry(path.to_path_buf())
+            .or_insert_with(|| VerifyRecord {
+                size,
+                modified,
+                hashes: BTreeMap::new(),
+                verified: None,
+            });
+        if (record.size, record.modified) != (size, modified) {
+            record.hashes.clear();
+            record.verified = None;
+            (record.size, record.modified) = (size, modified);
+        }
+        record.hashes.insert(algo, hash.to_string());
+        if verified.is_some() {
+            record.verified = verified;
+        }
+    }
+}
+
+fn file_stamp(path: &Path) -> io::Result<(u64, u64)> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    Ok((metadata.len(), modified))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verification {
+    pub expected: Expected,
+    pub actual: String,
+    /// Whether the hash came from the cache instead of hashing the file
+    pub cached: bool,
+}
+
+impl Verification {
+    pub fn is_match(&self) -> bool {
+        self.actual == self.expected.hash
+    }
+}
+
+/// Hashes the file at `path` with the algorithm of `expected`, unless it's in `cache`,
+/// and compares the two, recording the outcome in `cache`.
+pub fn verify<P, F>(
+    path: P,
+    expected: Expected,
+    cache: &VerifyCache,
+    cancel: &CancelToken,
+    progress: F,
+) -> Result<Verification>
+where
+    P: AsRef<Path>,
+    F: FnMut(u64, u64),
+{
+    let path = path.as_ref();
+    let cached = cache
+        .get(path)
+        .and_then(|record| record.hashes.get(&expected.algo).cloned());
+    let (actual, cached) = match cached {
+        Some(hash) => (hash, true),
+        None => (hash_file(path, expected.algo, cancel, progress)?, false),
+    };
+    let verification = Verification {
+        expected,
+        actual,
+        cached,
+    };
+    cache.record(
+        path,
+        verification.expected.algo,
+        &verification.actual,
+        Some(verification.is_match()),
+    );
+    tracing::info!(
+        "Verified {:?} against {}, matches: {}",
+        path,
+        verification.expected.source,
+        verification.is_match()
+    );
+    Ok(verification)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{MockResponse, MockServer};
+    use tempdir::TempDir;
+
+    const SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+    const SHA1: &str = "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d";
+    const MD5: &str = "5d41402abc4b2a76b9719d911017c592";
+
+    #[test]
+    fn hash_files() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let iso = tmpdir.path().join("hello.iso");
+        fs::write(&iso, "hello").unwrap();
+        let cancel = CancelToken::new();
+        for (algo, hash) in [
+            (HashAlgo::Sha256, SHA256),
+            (HashAlgo::Sha1, SHA1),
+            (HashAlgo::Md5, MD5),
+        ] {
+            assert_eq!(HashAlgo::from_hex(hash), Some(algo));
+            assert_eq!(hash_file(&iso, algo, &cancel, |_, _| ()).unwrap(), hash);
+        }
+
+        // Spans a few chunks
+        let big = tmpdir.path().join("big.iso");
+        let bytes: Vec<u8> = (0..CHUNK_SIZE * 2 + 123).map(|i| i as u8).collect();
+        fs::write(&big, &bytes).unwrap();
+        let mut last = (0, 0);
+        let hash = hash_file(&big, HashAlgo::Sha256, &cancel, |done, total| {
+            last = (done, total)
+        })
+        .unwrap();
+        assert_eq!(hash, hex(&<sha2::Sha256 as sha2::Digest>::digest(&bytes)));
+        assert_eq!(last, (bytes.len() as u64, bytes.len() as u64));
+
+        cancel.cancel();
+        let err = hash_file(&big, HashAlgo::Sha256, &cancel, |_, _| ()).unwrap_err();
+        assert!(matches!(err, Error::Cancelled), "{err:?}");
+    }
+
+    #[test]
+    fn find_expected_checksums() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let iso = tmpdir.path().join("hello.iso");
+        fs::write(&iso, "hello").unwrap();
+        assert_eq!(sibling_checksum(&iso), None);
+
+        fs::write(tmpdir.path().join("MD5SUMS"), format!("{MD5} *hello.iso\n")).unwrap();
+        assert_eq!(sibling_checksum(&iso).unwrap().algo, HashAlgo::Md5);
+        // A bare hash in `<image>.sha256` wins
+        let file = tmpdir.path().join("hello.iso.sha256");
+        fs::write(&file, format!("{}\n", SHA256.to_uppercase())).unwrap();
+        assert_eq!(
+            sibling_checksum(&iso),
+            Some(Expected {
+                algo: HashAlgo::Sha256,
+                hash: SHA256.to_string(),
+                source: ChecksumSource::File(file),
+            })
+        );
+
+        assert_eq!(
+            Expected::pasted(&format!("  {SHA1}  hello.iso"))
+                .unwrap()
+                .algo,
+            HashAlgo::Sha1
+        );
+        assert_eq!(Expected::pasted("not a hash"), None);
+
+        let sums = format!("SHA256 (hello.iso) = {SHA256}\n{SHA1}  ./other.iso\n");
+        let server = MockServer::start(move |req| match req.path.as_str() {
+            "/SHA256SUMS" => MockResponse::new(200, sums.clone()),
+            _ => MockResponse::new(404, "Not Found"),
+        });
+        let url = format!("{}/SHA256SUMS", server.url);
+        assert_eq!(fetch_checksum(&url, "hello.iso").unwrap().hash, SHA256);
+        assert_eq!(
+            fetch_checksum(&url, "other.iso").unwrap().algo,
+            HashAlgo::Sha1
+        );
+        let err = fetch_checksum(&url, "missing.iso").unwrap_err();
+        assert!(matches!(err, Error::MissingChecksum { .. }), "{err:?}");
+    }
+
+    #[test]
+    fn verify_and_cache() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let iso = tmpdir.path().join("hello.iso");
+        fs::write(&iso, "hello").unwrap();
+        let cache = VerifyCache::default();
+        let cancel = CancelToken::new();
+
+        let verification = verify(
+            &iso,
+            Expected::pasted(SHA256).unwrap(),
+            &cache,
+            &cancel,
+            |_, _| (),
+        )
+        .unwrap();
+        assert!(verification.is_match());
+        assert!(!verification.cached);
+        assert_eq!(cache.get(&iso).unwrap().verified, Some(true));
+
+        // Not hashed again, not even to find out it's corrupt now
+        let cancelled = CancelToken::new();
+        cancelled.cancel();
+        let verification = verify(
+            &iso,
+            Expected::pasted(SHA256).unwrap(),
+            &cache,
+            &cancelled,
+            |_, _| (),
+        )
+        .unwrap();
+        assert!(verification.cached);
+        let verification = verify(
+            &iso,
+            Expected::pasted(MD5).unwrap(),
+            &cache,
+            &cancel,
+            |_, _| (),
+        )
+        .unwrap();
+        assert!(verification.is_match() && !verification.cached);
+
+        let saved = tmpdir.path().join("verified.json");
+        cache.save(&saved).unwrap();
+        let cache = VerifyCache::load(&saved);
+        assert_eq!(cache.get(&iso).unwrap().hashes.len(), 2);
+
+        // Changing the file throws away its hashes
+        fs::write(&iso, "hello, corrupted").unwrap();
+        assert_eq!(cache.get(&iso), None);
+        let verification = verify(
+            &iso,
+            Expected::pasted(SHA256).unwrap(),
+            &cache,
+            &cancel,
+            |_, _| (),
+        )
+        .unwrap();
+        assert!(!verification.is_match() && !verification.cached);
+        let record = cache.get(&iso).unwrap();
+        assert_eq!(record.verified, Some(false));
+        assert_eq!(record.hashes.len(), 1);
+    }
+}
@@ -0,0 +1,908 @@
+//! Inspection of ISO images: their volume label, creation date, El Torito boot entries
+//! and the distro they're of.
+//!
+//! Only the volume descriptors, the boot catalog and a few well-known files are read, so
+//! inspecting an image is cheap enough to do for every image on a drive.
+
+use std::{
+    fmt, fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+const SECTOR_SIZE: u64 = 2048;
+/// Volume descriptors start after the system area.
+const FIRST_DESCRIPTOR: u64 = 16;
+/// Where UDF keeps its anchor volume descriptor pointer.
+const UDF_ANCHOR: u64 = 256;
+/// Bounds what's read of directories and files, against corrupt images.
+const MAX_DIR_SIZE: u64 = 1024 * 1024;
+const MAX_FILE_SIZE: u64 = 64 * 1024;
+
+const PLATFORM_X86: u8 = 0;
+const PLATFORM_EFI: u8 = 0xef;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filesystem {
+    Iso9660,
+    Joliet,
+    Udf,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BootModes {
+    /// Has a BIOS (legacy) El Torito boot entry
+    pub bios: bool,
+    /// Has a UEFI El Torito boot entry
+    pub uefi: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsoDate {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl fmt::Display for IsoDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Distro {
+    /// e.g. `Ubuntu`
+    pub name: String,
+    /// e.g. `22.10`
+    pub version: Option<String>,
+}
+
+impl fmt::Display for Distro {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{} {}", self.name, version),
+            None => f.write_str(&self.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IsoInfo {
+    pub label: Option<String>,
+    pub created: Option<IsoDate>,
+    pub filesystems: Vec<Filesystem>,
+    pub boot: BootModes,
+    pub distro: Option<Distro>,
+}
+
+impl IsoInfo {
+    /// The distro if it was detected, the volume label otherwise.
+    pub fn name(&self) -> Option<String> {
+        self.distro
+            .as_ref()
+            .map(|distro| distro.to_string())
+            .or_else(|| self.label.clone())
+    }
+
+    /// What may keep the image from booting.
+    pub fn warnings(&self) -> Vec<&'static str> {
+        match (self.boot.bios, self.boot.uefi) {
+            (false, false) => vec!["This ISO has no El Torito boot entries, it may not boot"],
+            (true, false) => vec!["This ISO is not UEFI-bootable"],
+            (false, true) => vec!["This ISO is not BIOS-bootable"],
+            (true, true) => vec![],
+        }
+    }
+}
+
+/// Inspects the ISO image at `path`.
+pub fn inspect<P: AsRef<Path>>(path: P) -> io::Result<IsoInfo> {
+    inspect_reader(fs::File::open(path)?)
+}
+
+/// Inspects an ISO image, failing with [`io::ErrorKind::InvalidData`] if it's neither an
+/// ISO9660 nor a UDF image.
+pub fn inspect_reader<R: Read + Seek>(reader: R) -> io::Result<IsoInfo> {
+    let mut image = Image { reader };
+    let mut info = IsoInfo::default();
+    let mut primary = None;
+    let mut joliet = None;
+    let mut udf = false;
+
+    for sector in FIRST_DESCRIPTOR..FIRST_DESCRIPTOR + 64 {
+        let Some(desc) = image.read_sector(sector)? else {
+            break;
+        };
+        match &desc[1..6] {
+            b"CD001" => match desc[0] {
+                0 if desc[7..].starts_with(b"EL TORITO SPECIFICATION") => {
+                    let catalog = u32_le(&desc[71..75]) as u64;
+                    if let Some(catalog) = image.read_sector(catalog)? {
+                        info.boot = boot_modes(&catalog);
+                    }
+                }
+                1 if primary.is_none() => primary = Some(desc),
+                2 if matches!(&desc[88..91], b"%/@" | b"%/C" | b"%/E") => joliet = Some(desc),
+                _ => (),
+            },
+            b"BEA01" | b"TEA01" | b"CDW02" => (),
+            b"NSR02" | b"NSR03" => udf = true,
+            _ => break,
+        }
+    }
+
+    if let Some(desc) = &primary {
+        info.filesystems.push(Filesystem::Iso9660);
+        info.label = non_empty(String::from_utf8_lossy(&desc[40..72]).trim_end());
+        info.created = parse_dec_date(&desc[813..830]);
+    }
+    if let Some(desc) = &joliet {
+        info.filesystems.push(Filesystem::Joliet);
+        // Not limited to uppercase like the primary label
+        info.label = non_empty(decode_ucs2(&desc[40..72]).trim_end()).or(info.label);
+    }
+    if udf {
+        info.filesystems.push(Filesystem::Udf);
+        if let Some((label, created)) = udf_volume(&mut image)? {
+            info.label = info.label.or(label);
+            info.created = info.created.or(created);
+        }
+    }
+    if info.filesystems.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "neither an ISO9660 nor a UDF image",
+        ));
+    }
+
+    let tree = joliet
+        .map(|desc| (desc, true))
+        .or_else(|| primary.map(|desc| (desc, false)));
+    if let Some((desc, joliet)) = tree {
+        let root = DirEntry::parse(&desc[156..190], false)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid root directory"))?;
+        let mut fs = Tree {
+            image: &mut image,
+            root,
+            joliet,
+        };
+        info.distro = detect_distro(&mut fs)?;
+    }
+    if info.distro.is_none() {
+        info.distro = info.label.as_deref().and_then(distro_from_label);
+    }
+    Ok(info)
+}
+
+struct Image<R> {
+    reader: R,
+}
+
+impl<R: Read + Seek> Image<R> {
+    /// `None` past the end of the image.
+    fn read_sector(&mut self, sector: u64) -> io::Result<Option<Vec<u8>>> {
+        let mut buf = vec![0; SECTOR_SIZE as usize];
+        self.reader.seek(SeekFrom::Start(sector * SECTOR_SIZE))?;
+        match self.reader.read_exact(&mut buf) {
+            Ok(()) => Ok(Some(buf)),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn read_extent(&mut self, sector: u64, size: u64) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.reader.seek(SeekFrom::Start(sector * SECTOR_SIZE))?;
+        (&mut self.reader).take(size).read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// The boot modes of the entries in an El Torito boot catalog.
+fn boot_modes(catalog: &[u8]) -> BootModes {
+    let mut modes = BootModes::default();
+    // Validation entry
+    if catalog[0] != 1 || catalog[30..32] != [0x55, 0xaa] {
+        return modes;
+    }
+    let mut add = |platform: u8, entry: &[u8]| {
+        if entry[0] == 0x88 {
+            match platform {
+                PLATFORM_X86 => modes.bios = true,
+                PLATFORM_EFI => modes.uefi = true,
+                _ => (),
+            }
+        }
+    };
+    // Initial entry
+    add(catalog[1], &catalog[32..64]);
+
+    let mut entries = catalog[64..].chunks_exact(32);
+    while let Some(header) = entries.next() {
+        if !matches!(header[0], 0x90 | 0x91) {
+            break;
+        }
+        let platform = header[1];
+        let mut count = u16::from_le_bytes([header[2], header[3]]);
+        while count > 0 {
+            let Some(entry) = entries.next() else {
+                break;
+            };
+            // Extension entries continue the previous entry
+            if entry[0] != 0x44 {
+                add(platform, entry);
+                count -= 1;
+            }
+        }
+        if header[0] == 0x91 {
+            break;
+        }
+    }
+    modes
+}
+
+/// Label and recording date of the primary volume descriptor of an UDF image.
+fn udf_volume<R: Read + Seek>(
+    image: &mut Image<R>,
+) -> io::Result<Option<(Option<String>, Option<IsoDate>)>> {
+    let Some(anchor) = image.read_sector(UDF_ANCHOR)? else {
+        return Ok(None);
+    };
+    if u16_le(&anchor[0..2]) != 2 {
+        return Ok(None);
+    }
+    let length = u32_le(&anchor[16..20]) as u64;
+    let location = u32_le(&anchor[20..24]) as u64;
+    for sector in location..location + (length / SECTOR_SIZE).min(64) {
+        let Some(desc) = image.read_sector(sector)? else {
+            break;
+        };
+        match u16_le(&desc[0..2]) {
+            1 => {
+                let label = non_empty(decode_dstring(&desc[24..56]).trim_end());
+                let ts = &desc[376..388];
+                let created = (u16_le(&ts[2..4]) > 0).then(|| IsoDate {
+                    year: u16_le(&ts[2..4]),
+                    month: ts[4],
+                    day: ts[5],
+                    hour: ts[6],
+                    minute: ts[7],
+                    second: ts[8],
+                });
+                return Ok(Some((label, created)));
+            }
+            // Terminating descriptor
+            8 => break,
+            _ => (),
+        }
+    }
+    Ok(None)
+}
+
+#[derive(Debug, Clone)]
+struct DirEntry {
+    name: String,
+    sector: u64,
+    size: u64,
+    is_dir: bool,
+}
+
+impl DirEntry {
+    /// Parses a directory record, preferring its Rock Ridge name if not `joliet`.
+    fn parse(record: &[u8], joliet: bool) -> Option<Self> {
+        let len = *record.first()? as usize;
+        let name_len = *record.get(32)? as usize;
+        let record = record.get(..len)?;
+        let raw_name = record.get(33..33 + name_len)?;
+        let name = if joliet {
+            decode_ucs2(raw_name)
+        } else {
+            // The system use area follows the name, padded to an even length
+            let system_use = record
+                .get(33 + name_len + (name_len + 1) % 2..)
+                .unwrap_or(&[]);
+            rock_ridge_name(system_use).unwrap_or_else(|| {
+                let name = String::from_utf8_lossy(raw_name);
+                let name = name.split(';').next().unwrap_or_default();
+                name.strip_suffix('.').unwrap_or(name).to_string()
+            })
+        };
+        Some(Self {
+            name,
+            sector: u32_le(&record[2..6]) as u64,
+            size: u32_le(&record[10..14]) as u64,
+            is_dir: record[25] & 2 != 0,
+        })
+    }
+
+    /// Whether it's `name`, ignoring case and what plain ISO9660 names can't hold.
+    fn is(&self, name: &str) -> bool {
+        let plain = |name: &str| {
+            name.trim_start_matches(['.', '_'])
+                .replace(['.', '-'], "_")
+                .to_lowercase()
+        };
+        self.name.eq_ignore_ascii_case(name) || plain(&self.name) == plain(name)
+    }
+}
+
+fn rock_ridge_name(mut system_use: &[u8]) -> Option<String> {
+    let mut name = Vec::new();
+    while system_use.len() >= 4 {
+        let len = system_use[2] as usize;
+        if len < 4 || len > system_use.len() {
+            break;
+        }
+        // The name may be split over several entries, flagged as continued
+        if &system_use[..2] == b"NM" && len >= 5 {
+            name.extend_from_slice(&system_use[5..len]);
+            if system_use[4] & 1 == 0 {
+                return Some(String::from_utf8_lossy(&name).to_string());
+            }
+        }
+        system_use = &system_use[len..];
+    }
+    None
+}
+
+struct Tree<'a, R> {
+    image: &'a mut Image<R>,
+    root: DirEntry,
+    joliet: bool,
+}
+
+impl<R: Read + Seek> Tree<'_, R> {
+    fn read_dir(&mut self, dir: &DirEntry) -> io::Result<Vec<DirEntry>> {
+        let data = self
+            .image
+            .read_extent(dir.sector, dir.size.min(MAX_DIR_SIZE))?;
+        let mut entries = Vec::new();
+        // Records don't cross sectors, the rest of a sector is zeroed
+        for sector in data.chunks(SECTOR_SIZE as usize) {
+            let mut offset = 0;
+            while offset < sector.len() && sector[offset] != 0 {
+                let record = &sector[offset..];
+                offset += record[0] as usize;
+                // Skip `.` and `..`
+                if matches!(record.get(32..34), Some([1, 0 | 1])) {
+                    continue;
+                }
+                entries.extend(DirEntry::parse(record, self.joliet));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn find(&mut self, path: &str) -> io::Result<Option<DirEntry>> {
+        let mut entry = self.root.clone();
+        for component in path.split('/') {
+            if !entry.is_dir {
+                return Ok(None);
+            }
+            match self
+                .read_dir(&entry)?
+                .into_iter()
+                .find(|child| child.is(component))
+            {
+                Some(child) => entry = child,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(entry))
+    }
+
+    fn read_file(&mut self, path: &str) -> io::Result<Option<String>> {
+        match self.find(path)? {
+            Some(file) if !file.is_dir => {
+                let data = self
+                    .image
+                    .read_extent(file.sector, file.size.min(MAX_FILE_SIZE))?;
+                Ok(Some(String::from_utf8_lossy(&data).to_string()))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Guesses the distro from files that identify it.
+fn detect_distro<R: Read + Seek>(fs: &mut Tree<R>) -> io::Result<Option<Distro>> {
+    if let Some(distro) = fs
+        .read_file(".disk/info")?
+        .and_then(|info| distro_from_disk_info(&info))
+    {
+        return Ok(Some(distro));
+    }
+    // Fedora, RHEL and its rebuilds
+    if let Some(treeinfo) = fs.read_file(".treeinfo")? {
+        let mut section = "";
+        let mut found = (None, None);
+        for line in treeinfo.lines().map(str::trim) {
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                section = name;
+            } else if let Some((key, value)) = line.split_once('=') {
+                let value = value.trim().to_string();
+                match (section, key.trim()) {
+                    ("release" | "product" | "general", "name" | "family") if found.0.is_none() => {
+                        found.0 = Some(value)
+                    }
+                    ("release" | "product" | "general", "version") if found.1.is_none() => {
+                        found.1 = Some(value)
+                    }
+                    _ => (),
+                }
+            }
+        }
+        if let (Some(name), version) = found {
+            return Ok(Some(Distro { name, version }));
+        }
+    }
+    // openSUSE, e.g. `/ openSUSE-Leap 15.4-0`
+    if let Some(products) = fs.read_file("media.1/products")? {
+        let mut words = products.split_whitespace().skip(1);
+        if let Some(name) = words.next() {
+            return Ok(Some(Distro {
+                name: name.replace('-', " "),
+                version: words
+                    .next()
+                    .map(|version| version.strip_suffix("-0").unwrap_or(version).to_string()),
+            }));
+        }
+    }
+    if let Some(version) = fs.read_file("arch/version")? {
+        return Ok(Some(Distro {
+            name: "Arch Linux".to_string(),
+            version: non_empty(version.trim()),
+        }));
+    }
+    if fs.find("sources/install.wim")?.is_some() || fs.find("sources/install.esd")?.is_some() {
+        return Ok(Some(Distro {
+            name: "Windows".to_string(),
+            version: None,
+        }));
+    }
+    Ok(None)
+}
+
+/// Parses `.disk/info` of Debian, Ubuntu and their derivatives, e.g.
+/// `Ubuntu 22.10 "Kinetic Kudu" - Release amd64 (20221020)`.
+pub(crate) fn distro_from_disk_info(info: &str) -> Option<Distro> {
+    let line = info.lines().next().unwrap_or_default();
+    let name: Vec<_> = line
+        .split_whitespace()
+        .take_while(|word| !word.starts_with(|c: char| c.is_ascii_digit()))
+        .collect();
+    let version = line
+        .split_whitespace()
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))
+        .map(String::from);
+    (!name.is_empty()).then(|| Distro {
+        name: name.join(" "),
+        version,
+    })
+}
+
+/// Guesses the distro from a volume label, e.g. `Fedora-WS-Live-37-1-7`.
+fn distro_from_label(label: &str) -> Option<Distro> {
+    const KNOWN: &[(&str, &str)] = &[
+        ("ubuntu", "Ubuntu"),
+        ("kubuntu", "Kubuntu"),
+        ("xubuntu", "Xubuntu"),
+        ("lubuntu", "Lubuntu"),
+        ("debian", "Debian"),
+        ("fedora", "Fedora"),
+        ("arch", "Arch Linux"),
+        ("manjaro", "Manjaro"),
+        ("endeavouros", "EndeavourOS"),
+        ("linux mint", "Linux Mint"),
+        ("opensuse", "openSUSE"),
+        ("centos", "CentOS"),
+        ("rocky", "Rocky Linux"),
+        ("alma", "AlmaLinux"),
+        ("kali", "Kali Linux"),
+        ("pop_os", "Pop!_OS"),
+        ("tails", "Tails"),
+    ];
+    let lower = label.to_lowercase();
+    // Windows labels look like `CCCOMA_X64FRE_EN-US_DV9`
+    if ["_x64fre", "_x86fre", "_a64fre"]
+        .iter()
+        .any(|tag| lower.contains(tag))
+    {
+        return Some(Distro {
+            name: "Windows".to_string(),
+            version: None,
+        });
+    }
+    let (_, name) = KNOWN
+        .iter()
+        .filter(|(prefix, _)| lower.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())?;
+    let version = label
+        .split(['-', '_', ' '])
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))
+        .map(String::from);
+    Some(Distro {
+        name: name.to_string(),
+        version,
+    })
+}
+
+/// A `dec-datetime` of a volume descriptor, `YYYYMMDDHHMMSScc` and a timezone byte.
+fn parse_dec_date(raw: &[u8]) -> Option<IsoDate> {
+    let text = std::str::from_utf8(&raw[..16]).ok()?;
+    let field = |range: std::ops::Range<usize>| text.get(range)?.parse::<u16>().ok();
+    let date = IsoDate {
+        year: field(0..4)?,
+        month: field(4..6)? as u8,
+        day: field(6..8)? as u8,
+        hour: field(8..10)? as u8,
+        minute: field(10..12)? as u8,
+        second: field(12..14)? as u8,
+    };
+    // Unset dates are all zeros
+    (date.year > 0).then_some(date)
+}
+
+fn decode_ucs2(raw: &[u8]) -> String {
+    let units: Vec<u16> = raw
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .take_while(|unit| *unit != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// An UDF `dstring`, its last byte is the length used.
+fn decode_dstring(raw: &[u8]) -> String {
+    let len = (raw[raw.len() - 1] as usize).min(raw.len() - 1);
+    match raw[..len] {
+        [8, ref chars @ ..] => String::from_utf8_lossy(chars).to_string(),
+        [16, ref chars @ ..] => decode_ucs2(chars),
+        _ => String::new(),
+    }
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn u16_le(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{collections::BTreeSet, io::Cursor};
+
+    const SECTOR: usize = SECTOR_SIZE as usize;
+
+    /// Builds tiny ISO images, with everything in one sector each.
+    #[derive(Default)]
+    struct Fixture {
+        label: &'static str,
+        joliet: bool,
+        rock_ridge: bool,
+        udf_label: Option<&'static str>,
+        /// Platforms of the boot entries, the first is the initial entry
+        boot: Vec<u8>,
+        files: Vec<(&'static str, &'static str)>,
+    }
+
+    impl Fixture {
+        fn build(&self) -> Vec<u8> {
+            let mut dirs = BTreeSet::from([String::new()]);
+            for (path, _) in &self.files {
+                let mut dir = String::new();
+                for component in path.split('/').collect::<Vec<_>>().split_last().unwrap().1 {
+                    dir = if dir.is_empty() {
+                        component.to_string()
+                    } else {
+                        format!("{dir}/{component}")
+                    };
+                    dirs.insert(dir.clone());
+                }
+            }
+            let dirs: Vec<_> = dirs.into_iter().collect();
+
+            // Descriptors, boot catalog, then both trees and the files
+            let catalog = 24;
+            let primary_tree = catalog + 1;
+            let joliet_tree = primary_tree + dirs.len();
+            let files = joliet_tree + dirs.len();
+            let udf_vds = (files + self.files.len()).max(UDF_ANCHOR as usize + 1);
+            let mut image = vec![0; (udf_vds + 2) * SECTOR];
+            let sector = |n: usize| n * SECTOR..(n + 1) * SECTOR;
+
+            let mut descriptors = vec![];
+            descriptors.push(self.volume_descriptor(1, primary_tree, false));
+            if !self.boot.is_empty() {
+                let mut desc = vec![0; SECTOR];
+                desc[1..7].copy_from_slice(b"CD001\x01");
+                desc[7..30].copy_from_slice(b"EL TORITO SPECIFICATION");
+                desc[71..75].copy_from_slice(&(catalog as u32).to_le_bytes());
+                descriptors.push(desc);
+                image[sector(catalog)].copy_from_slice(&self.boot_catalog());
+            }
+            if self.joliet {
+                descriptors.push(self.volume_descriptor(2, joliet_tree, true));
+            }
+            let mut terminator = vec![0; SECTOR];
+            terminator[..7].copy_from_slice(b"\xffCD001\x01");
+            descriptors.push(terminator);
+            if self.udf_label.is_some() {
+                for id in [b"BEA01", b"NSR03", b"TEA01"] {
+                    let mut desc = vec![0; SECTOR];
+                    desc[1..6].copy_from_slice(id);
+                    descriptors.push(desc);
+                }
+            }
+            for (i, desc) in descriptors.into_iter().enumerate() {
+                image[sector(16 + i)].copy_from_slice(&desc);
+            }
+
+            for (i, dir) in dirs.iter().enumerate() {
+                for (tree, joliet) in [(primary_tree, false), (joliet_tree, true)] {
+                    let parent = dir.rsplit_once('/').map_or("", |(parent, _)| parent);
+                    let parent = dirs.iter().position(|d| d == parent).unwrap();
+                    let mut records = vec![
+                        record(&[0], tree + i, SECTOR, true),
+                        record(&[1], tree + parent, SECTOR, true),
+                    ];
+                    let children = |path: &str| {
+                        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+                        (parent == dir).then(|| name.to_string())
+                    };
+                    for (j, child) in dirs.iter().enumerate().skip(1) {
+                        if let Some(name) = children(child) {
+                            records.push(self.named_record(&name, tree + j, SECTOR, true, joliet));
+                        }
+                    }
+                    for (j, (path, content)) in self.files.iter().enumerate() {
+                        if let Some(name) = children(path) {
+                            records.push(self.named_record(
+                                &name,
+                                files + j,
+                                content.len(),
+                                false,
+                                joliet,
+                            ));
+                        }
+                    }
+                    let records = records.concat();
+                    image[(tree + i) * SECTOR..][..records.len()].copy_from_slice(&records);
+                }
+            }
+            for (j, (_, content)) in self.files.iter().enumerate() {
+                image[(files + j) * SECTOR..][..content.len()].copy_from_slice(content.as_bytes());
+            }
+
+            if let Some(label) = self.udf_label {
+                let anchor = &mut image[sector(UDF_ANCHOR as usize)];
+                anchor[0..2].copy_from_slice(&2u16.to_le_bytes());
+                anchor[16..20].copy_from_slice(&(2 * SECTOR as u32).to_le_bytes());
+                anchor[20..24].copy_from_slice(&(udf_vds as u32).to_le_bytes());
+                let pvd = &mut image[sector(udf_vds)];
+                pvd[0..2].copy_from_slice(&1u16.to_le_bytes());
+                pvd[24] = 8;
+                pvd[25..25 + label.len()].copy_from_slice(label.as_bytes());
+                pvd[55] = label.len() as u8 + 1;
+                pvd[378..380].copy_from_slice(&2022u16.to_le_bytes());
+                pvd[380..385].copy_from_slice(&[9, 7, 1, 2, 3]);
+                image[sector(udf_vds + 1)][0..2].copy_from_slice(&8u16.to_le_bytes());
+            }
+            image
+        }
+
+        fn volume_descriptor(&self, kind: u8, root: usize, joliet: bool) -> Vec<u8> {
+            let mut desc = vec![0; SECTOR];
+            desc[0] = kind;
+            desc[1..7].copy_from_slice(b"CD001\x01");
+            desc[40..72].fill(if joliet { 0 } else { b' ' });
+            if joliet {
+                desc[88..91].copy_from_slice(b"%/E");
+                let label = ucs2(self.label);
+                desc[40..40 + label.len()].copy_from_slice(&label);
+            } else {
+                let label = self.label.to_uppercase();
+                desc[40..40 + label.len()].copy_from_slice(label.as_bytes());
+            }
+            desc[156..190].copy_from_slice(&record(&[0], root, SECTOR, true));
+            desc[813..830].copy_from_slice(b"2022102014020700\0");
+            desc
+        }
+
+        fn boot_catalog(&self) -> Vec<u8> {
+            let mut catalog = vec![0; SECTOR];
+            catalog[0] = 1;
+            catalog[1] = self.boot[0];
+            catalog[30..32].copy_from_slice(&[0x55, 0xaa]);
+            catalog[32] = 0x88;
+            let sections = &self.boot[1..];
+            for (i, platform) in sections.iter().enumerate() {
+                let header = &mut catalog[64 + i * 64..];
+                header[0] = if i + 1 == sections.len() { 0x91 } else { 0x90 };
+                header[1] = *platform;
+                header[2] = 1;
+                header[32] = 0x88;
+            }
+            catalog
+        }
+
+        fn named_record(
+            &self,
+            name: &str,
+            sector: usize,
+            size: usize,
+            is_dir: bool,
+            joliet: bool,
+        ) -> Vec<u8> {
+            if joliet {
+                return record(&ucs2(name), sector, size, is_dir);
+            }
+            let mut plain = name
+                .trim_start_matches('.')
+                .replace(['.', '-'], "_")
+                .to_uppercase();
+            if !is_dir {
+                plain.push_str(";1");
+            }
+            let mut record = record(plain.as_bytes(), sector, size, is_dir);
+            if self.rock_ridge {
+                let mut nm = vec![b'N', b'M', 5 + name.len() as u8, 1, 0];
+                nm.extend_from_slice(name.as_bytes());
+                record.extend(nm);
+                record[0] = record.len() as u8;
+            }
+            record
+        }
+    }
+
+    fn record(name: &[u8], sector: usize, size: usize, is_dir: bool) -> Vec<u8> {
+        let mut record = vec![0; 33 + name.len() + (name.len() + 1) % 2];
+        record[0] = record.len() as u8;
+        record[2..6].copy_from_slice(&(sector as u32).to_le_bytes());
+        record[10..14].copy_from_slice(&(size as u32).to_le_bytes());
+        record[25] = if is_dir { 2 } else { 0 };
+        record[32] = name.len() as u8;
+        record[33..33 + name.len()].copy_from_slice(name);
+        record
+    }
+
+    fn ucs2(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    fn inspect_fixture(fixture: Fixture) -> IsoInfo {
+        inspect_reader(Cursor::new(fixture.build())).unwrap()
+    }
+
+    #[test]
+    fn inspect_ubuntu() {
+        let info = inspect_fixture(Fixture {
+            label: "Ubuntu 22.10",
+            joliet: true,
+            boot: vec![PLATFORM_X86, PLATFORM_EFI],
+            files: vec![
+                (
+                    ".disk/info",
+                    "Ubuntu 22.10 \"Kinetic Kudu\" - Release amd64 (20221020)\n",
+                ),
+                ("casper/vmlinuz", "kernel"),
+            ],
+            ..Default::default()
+        });
+        assert_eq!(
+            info,
+            IsoInfo {
+                label: Some("Ubuntu 22.10".to_string()),
+                created: Some(IsoDate {
+                    year: 2022,
+                    month: 10,
+                    day: 20,
+                    hour: 14,
+                    minute: 2,
+                    second: 7
+                }),
+                filesystems: vec![Filesystem::Iso9660, Filesystem::Joliet],
+                boot: BootModes {
+                    bios: true,
+                    uefi: true
+                },
+                distro: Some(Distro {
+                    name: "Ubuntu".to_string(),
+                    version: Some("22.10".to_string())
+                }),
+            }
+        );
+        assert_eq!(info.name().unwrap(), "Ubuntu 22.10");
+        assert!(info.warnings().is_empty());
+        assert_eq!(info.created.unwrap().to_string(), "2022-10-20 14:02:07");
+    }
+
+    #[test]
+    fn detect_distros() {
+        // Rock Ridge names only
+        let info = inspect_fixture(Fixture {
+            label: "Fedora-WS-Live-37-1-7",
+            rock_ridge: true,
+            boot: vec![PLATFORM_EFI],
+            files: vec![(
+                ".treeinfo",
+                "[header]\nversion = 1.2\n[release]\nname = Fedora\nversion = 37\n",
+            )],
+            ..Default::default()
+        });
+        assert_eq!(info.distro.as_ref().unwrap().to_string(), "Fedora 37");
+        assert_eq!(info.warnings(), ["This ISO is not BIOS-bootable"]);
+
+        // Plain ISO9660 names
+        let info = inspect_fixture(Fixture {
+            label: "ARCH_202301",
+            boot: vec![PLATFORM_X86],
+            files: vec![("arch/version", "2023.01.01\n")],
+            ..Default::default()
+        });
+        assert_eq!(
+            info.distro.as_ref().unwrap().to_string(),
+            "Arch Linux 2023.01.01"
+        );
+        assert_eq!(info.warnings(), ["This ISO is not UEFI-bootable"]);
+
+        let info = inspect_fixture(Fixture {
+            label: "openSUSE-Leap-15.4-DVD-x86_64",
+            joliet: true,
+            files: vec![("media.1/products", "/ openSUSE-Leap 15.4-0\n")],
+            ..Default::default()
+        });
+        assert_eq!(
+            info.distro.as_ref().unwrap().to_string(),
+            "openSUSE Leap 15.4"
+        );
+        assert_eq!(info.boot, BootModes::default());
+        assert_eq!(info.warnings().len(), 1);
+
+        // Nothing but the label
+        let info = inspect_fixture(Fixture {
+            label: "debian 11.6.0 amd64 n",
+            ..Default::default()
+        });
+        assert_eq!(info.distro.as_ref().unwrap().to_string(), "Debian 11.6.0");
+    }
+
+    #[test]
+    fn inspect_udf() {
+        // Windows images keep their files in UDF, with a stub ISO9660 volume
+        let info = inspect_fixture(Fixture {
+            label: "",
+            udf_label: Some("CCCOMA_X64FRE_EN-US_DV9"),
+            boot: vec![PLATFORM_X86, PLATFORM_EFI],
+            files: vec![("README.TXT", "This disc contains a UDF file system")],
+            ..Default::default()
+        });
+        assert_eq!(info.label.as_deref(), Some("CCCOMA_X64FRE_EN-US_DV9"));
+        assert_eq!(info.filesystems, [Filesystem::Iso9660, Filesystem::Udf]);
+        assert_eq!(info.name().unwrap(), "Windows");
+        assert!(info.warnings().is_empty());
+
+        let err = inspect_reader(Cursor::new(vec![0; 40 * SECTOR])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = inspect_reader(Cursor::new(vec![0; 100])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,54 @@
+//! The logic behind Ventoy Toybox, usable without its GUI.
+//!
+//! - [`update`]: the latest Ventoy release, downloading and installing its packages,
+//!   with [`package`] matching them to a system
+//! - [`utils::FeedsItem`]: the linux distro release feeds, and [`mirror`] downloading
+//!   their images over HTTP, verified against their checksums
+//! - [`integrity`]: checking images already downloaded against their checksums, and
+//!   [`iso`] reading their labels, boot modes and distros
+//! - [`compat`]: hints on how well distros boot with Ventoy
+//! - [`drive`]: Ventoy drives and their images, and block devices to write to with
+//!   [`flash`]
+//! - [`ventoy_json`], [`theme`], [`persistence`], [`auto_install`] and [`secure_boot`]:
+//!   configuring a Ventoy drive
+//! - [`sync`]: mirroring a directory of images onto a drive
+//! - [`provision`]: profiles of the drive to build, planned against a drive and applied
+//! - [`torrent`]: sending releases to torrent clients
+//! - [`flow`]: the update flow and feeds browsing as frontend agnostic state machines
+//! - [`task`]: cancelling downloads and extractions, and listing the running ones
+//! - [`downloads`]: the queue of throttled and retried downloads
+//! - [`cache`]: what's kept between runs, like the feeds and the downloaded package
+//!
+//! Nothing here needs an async runtime, functions that download block until they're
+//! done so that callers can run them however they like. Only the [`downloads`] queue
+//! runs its downloads on threads of its own.
+
+pub mod auto_install;
+pub mod cache;
+pub mod compat;
+pub mod downloads;
+pub mod drive;
+#[cfg(target_os = "linux")]
+pub mod elevate;
+pub mod error;
+pub mod extract;
+pub mod flash;
+pub mod flow;
+pub mod integrity;
+pub mod iso;
+pub mod logs;
+pub mod mirror;
+pub mod package;
+pub mod persistence;
+pub mod provision;
+pub mod secure_boot;
+pub mod sync;
+pub mod task;
+pub mod theme;
+pub mod torrent;
+pub mod update;
+pub mod utils;
+pub mod ventoy_json;
+
+#[cfg(test)]
+pub(crate) mod testing;
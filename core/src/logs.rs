@@ -0,0 +1,519 @@
+//! Capturing log events for the Logs page and rotating log files, as stdout is
+//! disabled in windows release builds, and bundling them up for bug reports.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context as _;
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, Layer};
+
+use super::auto_install;
+
+pub const LOG_FILE: &str = "ventoy-toybox.log";
+/// Size after which the log file is rotated.
+pub const MAX_LOG_SIZE: u64 = 1024 * 1024;
+/// Rotated log files kept around, as `ventoy-toybox.log.1` and so on.
+pub const KEPT_LOG_FILES: usize = 3;
+const DEFAULT_CAPACITY: usize = 2000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    pub time: SystemTime,
+    pub level: Level,
+    pub target: String,
+    /// The message followed by the event's fields, like `path=/tmp/x`
+    pub message: String,
+}
+
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:>5} {}: {}",
+            format_timestamp(self.time),
+            self.level,
+            self.target,
+            self.message
+        )
+    }
+}
+
+/// Formats `time` in UTC, like `2022-11-20 14:03:05.123Z`.
+pub fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = ((secs / 86400) as i64, secs % 86400);
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}.{:03}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// The most recent log records, shared between the tracing layer and the UI.
+#[derive(Debug, Clone)]
+pub struct LogBuffer {
+    records: Arc<Mutex<VecDeque<LogRecord>>>,
+    capacity: usize,
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: Default::default(),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, record: LogRecord) {
+        let mut records = self.records.lock().unwrap();
+        if records.len() >= self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Records at `level` or more severe, oldest first.
+    pub fn records(&self, level: Level) -> Vec<LogRecord> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| record.level <= level)
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.records.lock().unwrap().clear();
+    }
+}
+
+/// Log file in `dir` that's moved aside once it grows past `max_size`.
+#[derive(Debug)]
+pub struct RotatingFile {
+    dir: PathBuf,
+    max_size: u64,
+    keep: usize,
+    file: Option<File>,
+    size: u64,
+}
+
+impl RotatingFile {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self::with_limits(dir, MAX_LOG_SIZE, KEPT_LOG_FILES)
+    }
+
+    pub fn with_limits<P: Into<PathBuf>>(dir: P, max_size: u64, keep: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            max_size,
+            keep,
+            file: None,
+            size: 0,
+        }
+    }
+
+    fn path(&self, idx: usize) -> PathBuf {
+        match idx {
+            0 => self.dir.join(LOG_FILE),
+            idx => self.dir.join(format!("{LOG_FILE}.{idx}")),
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        for idx in (0..self.keep).rev() {
+            let from = self.path(idx);
+            if from.exists() {
+                fs::rename(from, self.path(idx + 1))?;
+            }
+        }
+        if self.keep == 0 {
+            fs::remove_file(self.path(0))?;
+        }
+        Ok(())
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.file.is_some() && self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                fs::create_dir_all(&self.dir)?;
+                let file = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(self.path(0))?;
+                self.size = file.metadata()?.len();
+                self.file.insert(file)
+            }
+        };
+        writeln!(file, "{line}")?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: Vec<String>,
+}
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = value.to_string(),
+            name => self.fields.push(format!("{name}={value}")),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{value:?}"),
+            name => self.fields.push(format!("{name}={value:?}")),
+        }
+    }
+}
+
+/// Tracing layer that keeps events in a [`LogBuffer`] and optionally a [`RotatingFile`].
+pub struct LogLayer {
+    buffer: LogBuffer,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+impl LogLayer {
+    pub fn new(buffer: LogBuffer, file: Option<RotatingFile>) -> Self {
+        Self {
+            buffer,
+            file: file.map(Mutex::new),
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let mut message = visitor.message;
+        for field in visitor.fields {
+            if !message.is_empty() {
+                message.push(' ');
+            }
+            message.push_str(&field);
+        }
+        let record = LogRecord {
+            time: SystemTime::now(),
+            level: *event.metadata().level(),
+            target: event.metadata().target().to_string(),
+            message,
+        };
+        if let Some(file) = &self.file {
+            // Nowhere to report it to, the record still makes it to the Logs page
+            _ = file.lock().unwrap().write_line(&record.to_string());
+        }
+        self.buffer.push(record);
+    }
+}
+
+/// Where the diagnostics bundle is collected from.
+#[derive(Debug, Clone, Default)]
+pub struct DiagnosticsSources {
+    /// Version of the app the bundle is created by
+    pub app_version: String,
+    pub log_dir: Option<PathBuf>,
+    pub config_path: Option<PathBuf>,
+    pub cache_path: Option<PathBuf>,
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// Blanks out the values of RON fields and map entries that look like credentials,
+/// however deeply they're nested, and strips query strings and user info from URLs.
+///
+/// The text is parsed and written out again, so its formatting isn't kept, and neither
+/// are the names of enum variants without fields, which come out as `()`. Text that
+/// isn't valid RON is left out rather than risk leaking it.
+pub fn redact_ron(text: &str) -> String {
+    let mut value = match ron::from_str::<ron::Value>(text) {
+        Ok(value) => value,
+        Err(err) => return format!("// left out, not valid RON: {err}\n"),
+    };
+    redact_value(&mut value);
+    ron::ser::to_string_pretty(&value, Default::default())
+        .unwrap_or_else(|err| format!("// left out, failed to write it back: {err}\n"))
+}
+
+fn redact_value(value: &mut ron::Value) {
+    match value {
+        ron::Value::Map(map) => {
+            for (key, value) in map.iter_mut() {
+                match key {
+                    ron::Value::String(key) if auto_install::is_secret(key) => {
+                        *value = ron::Value::String("<redacted>".to_string())
+                    }
+                    _ => redact_value(value),
+                }
+            }
+        }
+        ron::Value::Seq(values) => values.iter_mut().for_each(redact_value),
+        ron::Value::Option(Some(value)) => redact_value(value),
+        ron::Value::String(text) => {
+            if let Some(url) = redact_url(text) {
+                *text = url;
+            }
+        }
+        _ => (),
+    }
+}
+
+/// `text` without its query string and `user:pass@`, if it's a URL with either.
+fn redact_url(text: &str) -> Option<String> {
+    let (scheme, rest) = text.split_once("://")?;
+    let (rest, query) = match rest.split_once('?') {
+        Some((rest, _)) => (rest, "?<redacted>"),
+        None => (rest, ""),
+    };
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let host = match authority.rsplit_once('@') {
+        Some((_, host)) => host,
+        None if query.is_empty() => return None,
+        None => authority,
+    };
+    Some(format!("{scheme}://{host}{path}{query}"))
+}
+
+/// Listing of `dir` with the size of every file, a couple of levels deep.
+fn list_dir(dir: &Path, depth: usize, listing: &mut String) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.flatten().collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let meta = entry.metadata()?;
+        let indent = "  ".repeat(depth);
+        let name = entry.file_name().to_string_lossy().to_string();
+        if meta.is_dir() {
+            listing.push_str(&format!("{indent}{name}/\n"));
+            if depth < 2 {
+                list_dir(&entry.path(), depth + 1, listing)?;
+            }
+        } else {
+            listing.push_str(&format!("{indent}{name} ({} bytes)\n", meta.len()));
+        }
+    }
+    Ok(())
+}
+
+/// Zips the logs, the settings with credentials redacted and what's in the cache into
+/// `dest`, to be attached to bug reports.
+pub fn create_diagnostics_bundle<P: AsRef<Path>>(
+    dest: P,
+    sources: &DiagnosticsSources,
+    records: &[LogRecord],
+) -> anyhow::Result<()> {
+    let dest = dest.as_ref();
+    let file = File::create(dest).with_context(|| format!("failed to create {dest:?}"))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::FileOptions::default();
+
+    zip.start_file("system.txt", options)?;
+    writeln!(
+        zip,
+        "version: {}\ncore version: {}\nos: {} ({})\narch: {}",
+        sources.app_version,
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::FAMILY,
+        std::env::consts::ARCH
+    )?;
+
+    zip.start_file("recent.log", options)?;
+    for record in records {
+        writeln!(zip, "{record}")?;
+    }
+
+    if let Some(log_dir) = &sources.log_dir {
+        if let Ok(entries) = fs::read_dir(log_dir) {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if entry.path().is_file() && name.starts_with(LOG_FILE) {
+                    zip.start_file(format!("logs/{name}"), options)?;
+                    io::copy(&mut File::open(entry.path())?, &mut zip)?;
+                }
+            }
+        }
+    }
+    if let Some(config) = sources
+        .config_path
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok())
+    {
+        zip.start_file("config.ron", options)?;
+        zip.write_all(redact_ron(&config).as_bytes())?;
+    }
+    if let Some(cache) = sources
+        .cache_path
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok())
+    {
+        zip.start_file("cache.ron", options)?;
+        zip.write_all(redact_ron(&cache).as_bytes())?;
+    }
+    if let Some(cache_dir) = &sources.cache_dir {
+        let mut listing = String::new();
+        if let Err(err) = list_dir(cache_dir, 0, &mut listing) {
+            listing.push_str(&format!("failed to list {cache_dir:?}: {err}\n"));
+        }
+        zip.start_file("cache-dir.txt", options)?;
+        zip.write_all(listing.as_bytes())?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+    use tempdir::TempDir;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn capture_events() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let buffer = LogBuffer::new(2);
+        let layer = LogLayer::new(buffer.clone(), Some(RotatingFile::new(tmpdir.path())));
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+            tracing::debug!("dropped from the buffer");
+            tracing::info!(path = "/tmp/x", "extracted {} files", 3);
+            tracing::error!("failed");
+        });
+
+        let records = buffer.records(Level::TRACE);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].message, "extracted 3 files path=/tmp/x");
+        assert_eq!(records[0].level, Level::INFO);
+        assert_eq!(buffer.records(Level::WARN).len(), 1);
+        let log = fs::read_to_string(tmpdir.path().join(LOG_FILE)).unwrap();
+        assert_eq!(log.lines().count(), 3);
+        let last = log.lines().last().unwrap();
+        assert!(
+            last.contains("ERROR ") && last.ends_with(": failed"),
+            "{last}"
+        );
+
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_millis(1_668_953_185_123)),
+            "2022-11-20 14:06:25.123Z"
+        );
+    }
+
+    #[test]
+    fn rotate_log_files() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let mut file = RotatingFile::with_limits(tmpdir.path(), 10, 2);
+        for line in ["first", "second", "third", "fourth"] {
+            file.write_line(line).unwrap();
+        }
+        let read = |name: &str| fs::read_to_string(tmpdir.path().join(name)).unwrap();
+        assert_eq!(read(LOG_FILE), "fourth\n");
+        assert_eq!(read(&format!("{LOG_FILE}.1")), "third\n");
+        assert_eq!(read(&format!("{LOG_FILE}.2")), "second\n");
+        assert!(!tmpdir.path().join(format!("{LOG_FILE}.3")).exists());
+    }
+
+    #[test]
+    fn diagnostics_bundle() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let log_dir = tmpdir.path().join("logs");
+        RotatingFile::new(&log_dir).write_line("hello").unwrap();
+        let config_path = tmpdir.path().join("config.ron");
+        fs::write(
+            &config_path,
+            "(\n    torrent_client: (\n        kind: Deluge,\n        username: \"admin\",\n        password: \"hunter2\",\n    ),\n    auto_install_vars: {\n        \"hostname\": \"box\",\n        \"root_password\":\n            \"hunter3\",\n    },\n)",
+        )
+        .unwrap();
+        let cache_path = tmpdir.path().join("cache.ron");
+        fs::write(
+            &cache_path,
+            "(downloads: [(url: \"https://x/?token=abc\"), (url: \"ftp://me:pw@y/a.iso\")])",
+        )
+        .unwrap();
+        let cache_dir = tmpdir.path().join("cache");
+        fs::create_dir_all(cache_dir.join("ventoy-v1.0.86-linux-x86_64")).unwrap();
+        fs::write(cache_dir.join("ventoy-livecd.iso"), [0; 5]).unwrap();
+
+        let dest = tmpdir.path().join("diagnostics.zip");
+        let sources = DiagnosticsSources {
+            app_version: "0.5.0".to_string(),
+            log_dir: Some(log_dir),
+            config_path: Some(config_path),
+            cache_path: Some(cache_path),
+            cache_dir: Some(cache_dir),
+        };
+        create_diagnostics_bundle(&dest, &sources, &[]).unwrap();
+
+        let mut zip = zip::ZipArchive::new(File::open(&dest).unwrap()).unwrap();
+        let mut names = zip.file_names().map(str::to_string).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "cache-dir.txt",
+                "cache.ron",
+                "config.ron",
+                "logs/ventoy-toybox.log",
+                "recent.log",
+                "system.txt"
+            ]
+        );
+        let read = |zip: &mut zip::ZipArchive<File>, name: &str| {
+            io::read_to_string(zip.by_name(name).unwrap()).unwrap()
+        };
+        let config = read(&mut zip, "config.ron");
+        assert!(config.contains("\"username\": \"admin\","), "{config}");
+        assert!(config.contains("\"password\": \"<redacted>\","), "{config}");
+        assert!(config.contains("\"hostname\": \"box\","), "{config}");
+        assert!(!config.contains("hunter"), "{config}");
+        let cache = read(&mut zip, "cache.ron");
+        assert!(cache.contains("\"https://x/?<redacted>\""), "{cache}");
+        assert!(cache.contains("\"ftp://y/a.iso\""), "{cache}");
+        assert!(!cache.contains("abc") && !cache.contains("pw"), "{cache}");
+        assert!(redact_ron("(password: \"hunter2\"").starts_with("// left out"));
+        assert_eq!(
+            read(&mut zip, "cache-dir.txt"),
+            "ventoy-livecd.iso (5 bytes)\nventoy-v1.0.86-linux-x86_64/\n"
+        );
+    }
+}
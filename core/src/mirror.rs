@@ -0,0 +1,538 @@
+//! Downloading distro images over HTTP from their mirrors, verified against the
+//! distro's SHA256SUMS and optionally its GPG signature.
+//!
+//! Signatures are checked with `gpgv` against the keys of a [`Keyring`], the distro's
+//! signing keys have to be imported into it first.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    downloads::{DownloadKind, DownloadManager, DownloadRequest},
+    error::{Error, Result},
+    integrity::{self, HashAlgo},
+    task::CancelToken,
+    utils::FeedsItem,
+};
+
+/// Name of the checksums file most distros publish next to their images.
+pub const SHA256SUMS: &str = "SHA256SUMS";
+const CLEARSIGNED_HEADER: &str = "-----BEGIN PGP SIGNED MESSAGE-----";
+
+/// Where the images of a feeds group are mirrored, for feeds without an `iso_url`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GroupMirror {
+    /// Directory of the images, `{version}` is replaced by the release version,
+    /// e.g. `https://releases.ubuntu.com/{version}`
+    pub url: String,
+    /// Checksums file in the directory, none if empty
+    pub sums_file: String,
+    /// Detached signature of `sums_file` in the directory, e.g. `SHA256SUMS.gpg`,
+    /// none if empty
+    pub signature_file: String,
+}
+
+impl Default for GroupMirror {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            sums_file: SHA256SUMS.to_string(),
+            signature_file: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IsoSource {
+    /// File name of the image
+    pub name: String,
+    pub url: String,
+    pub sums_url: Option<String>,
+    pub signature_url: Option<String>,
+}
+
+/// Where to download the image of `item` from, preferring the urls in the feed over the
+/// mirror of its group. `None` if neither is known.
+pub fn resolve(item: &FeedsItem, mirrors: &BTreeMap<String, GroupMirror>) -> Option<IsoSource> {
+    let name = item.image_name().to_string();
+    if let Some(url) = &item.iso_url {
+        return Some(IsoSource {
+            name,
+            url: url.clone(),
+            sums_url: item.sha256sums_url.clone(),
+            signature_url: item.signature_url.clone(),
+        });
+    }
+
+    let mirror = mirrors
+        .get(&item.group)
+        .filter(|mirror| !mirror.url.is_empty())?;
+    let dir = if mirror.url.contains("{version}") {
+        mirror.url.replace("{version}", item.version()?)
+    } else {
+        mirror.url.clone()
+    };
+    let dir = dir.trim_end_matches('/');
+    let file_url = |file: &str| (!file.is_empty()).then(|| format!("{dir}/{file}"));
+    Some(IsoSource {
+        url: format!("{dir}/{name}"),
+        sums_url: file_url(&mirror.sums_file),
+        signature_url: file_url(&mirror.signature_file),
+        name,
+    })
+}
+
+/// The SHA-256 checksums among `text`, by name, see [`integrity::parse_checksums`].
+pub fn parse_sha256sums(text: &str) -> BTreeMap<String, String> {
+    integrity::parse_checksums(text)
+        .into_iter()
+        .filter(|(_, (algo, _))| *algo == HashAlgo::Sha256)
+        .map(|(name, (_, hash))| (name, hash))
+        .collect()
+}
+
+/// Hashes the file at `path`, checking `cancel` between chunks.
+pub fn sha256_file<P: AsRef<Path>>(path: P, cancel: &CancelToken) -> Result<String> {
+    integrity::hash_file(path, HashAlgo::Sha256, cancel, |_, _| ())
+}
+
+/// Checks the file at `path` against its entry for `name` in the `sums` fetched from
+/// `sums_url`.
+pub fn verify_checksum<P: AsRef<Path>>(
+    path: P,
+    name: &str,
+    sums: &str,
+    sums_url: &str,
+    cancel: &CancelToken,
+) -> Result<()> {
+    let expected = parse_sha256sums(sums)
+        .remove(name)
+        .ok_or_else(|| Error::MissingChecksum {
+            name: name.to_string(),
+            url: sums_url.to_string(),
+        })?;
+    let actual = sha256_file(path, cancel)?;
+    if actual != expected {
+        return Err(Error::Checksum {
+            name: name.to_string(),
+            expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Directories of OpenPGP keys to verify signatures with, e.g. the one keys are imported
+/// into. No keys ship with the app.
+#[derive(Debug, Clone, Default)]
+pub struct Keyring {
+    pub dirs: Vec<PathBuf>,
+}
+
+impl Keyring {
+    /// Binary key files in the dirs, `gpgv` doesn't read armored ones.
+    pub fn keys(&self) -> Vec<PathBuf> {
+        let mut keys: Vec<_> = self
+            .dirs
+            .iter()
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| {
+                path.is_file()
+                    && matches!(
+                        path.extension().and_then(|ext| ext.to_str()),
+                        Some("gpg" | "pgp" | "kbx")
+                    )
+            })
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// Copies the key file at `key` into `dir`, dearmoring `.asc` keys with `gpg`.
+    pub fn import<P: AsRef<Path>, Q: AsRef<Path>>(dir: P, key: Q) -> anyhow::Result<PathBuf> {
+        let (dir, key) = (dir.as_ref(), key.as_ref());
+        let stem = key
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| anyhow!("invalid key path {:?}", key))?;
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {dir:?}"))?;
+        let dest = dir.join(format!("{stem}.gpg"));
+        if key.extension().and_then(|ext| ext.to_str()) == Some("asc") {
+            let output = Command::new("gpg")
+                .args(["--batch", "--yes", "--dearmor", "--output"])
+                .arg(&dest)
+                .arg(key)
+                .output()
+                .context("failed to run gpg, is it installed?")?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "gpg failed to dearmor {:?}: {}",
+                    key,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+        } else {
+            fs::copy(key, &dest).with_context(|| format!("failed to copy {key:?}"))?;
+        }
+        tracing::info!("Imported the key {:?} into {:?}", key, dest);
+        Ok(dest)
+    }
+
+    /// Verifies `data` against its detached `signature`, or verifies `data` itself if
+    /// it's clearsigned, and returns the signed text. Anything around the signed block of
+    /// a clearsigned file isn't part of it.
+    pub fn verify<P: AsRef<Path>>(&self, signature: Option<&Path>, data: P) -> Result<String> {
+        let data = data.as_ref();
+        let name = data
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let failed = |reason: String| Error::Signature {
+            name: name.clone(),
+            reason,
+        };
+        let keys = self.keys();
+        if keys.is_empty() {
+            return Err(failed("there are no keys in the keyring".to_string()));
+        }
+
+        let mut cmd = Command::new("gpgv");
+        for key in &keys {
+            cmd.arg("--keyring").arg(key);
+        }
+        // Writes out the signed text, which is all of `data` with a detached signature
+        cmd.args(["--output", "-"]).args(signature).arg(data);
+        let output = cmd.output().map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => failed("gpgv isn't installed".to_string()),
+            _ => failed(format!("failed to run gpgv: {e}")),
+        })?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr
+                .lines()
+                .rev()
+                .find(|line| !line.trim().is_empty())
+                .unwrap_or("gpgv failed")
+                .trim_start_matches("gpgv: ");
+            return Err(failed(reason.to_string()));
+        }
+        String::from_utf8(output.stdout)
+            .map_err(|_| failed("the signed text isn't valid UTF-8".to_string()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedIso {
+    pub path: PathBuf,
+    /// Whether it was checked against a checksums file
+    pub checksum: bool,
+    /// Whether the checksums file was signed with a key of the keyring
+    pub signature: bool,
+}
+
+/// Downloads the image of `source` into `dest_dir` through `downloads` and verifies it
+/// against the checksums and their signature, if there's a `keyring` to verify with.
+///
+/// An image already in `dest_dir` is verified instead of downloaded again, and an image
+/// not matching its checksum is removed. The checksums and signature are kept next to
+/// the image.
+pub fn download_iso<P: AsRef<Path>>(
+    source: &IsoSource,
+    dest_dir: P,
+    downloads: &DownloadManager,
+    keyring: Option<&Keyring>,
+    cancel: &CancelToken,
+) -> Result<VerifiedIso> {
+    let dest_dir = dest_dir.as_ref();
+    let iso = dest_dir.join(&source.name);
+    let sums = source
+        .sums_url
+        .as_ref()
+        .map(|url| (url, dest_dir.join(format!("{}.sha256sums", source.name))));
+    let signature = source
+        .signature_url
+        .as_ref()
+        .filter(|_| keyring.is_some() && sums.is_some())
+        .map(|url| {
+            (
+                url,
+                dest_dir.join(format!("{}.sha256sums.sig", source.name)),
+            )
+        });
+
+    let mut queued = Vec::new();
+    let mut enqueue = |url: &str, dest: &Path| {
+        queued.push(downloads.enqueue(DownloadRequest {
+            url: url.to_string(),
+            dest: dest.to_path_buf(),
+            kind: DownloadKind::Iso,
+        }))
+    };
+    for (url, dest) in sums.iter().chain(&signature) {
+        enqueue(url, dest);
+    }
+    // Downloads are written atomically, an existing image is complete
+    if !iso.is_file() {
+        enqueue(&source.url, &iso);
+    }
+    for id in queued {
+        downloads.wait(id, cancel)?;
+    }
+
+    let (sums_url, sums_path) = match sums {
+        Some(sums) => sums,
+        None => {
+            tracing::warn!("No checksums to verify {} with", source.name);
+            return Ok(VerifiedIso {
+                path: iso,
+                checksum: false,
+                signature: false,
+            });
+        }
+    };
+    let mut sums_text = fs::read_to_string(&sums_path).map_err(|e| Error::io(&sums_path, e))?;
+    let mut signed = false;
+    if let Some(keyring) = keyring {
+        // Only what's signed is trusted, not lines added around a clearsigned block
+        sums_text = match &signature {
+            Some((_, signature)) => keyring.verify(Some(signature), &sums_path)?,
+            None if sums_text.starts_with(CLEARSIGNED_HEADER) => {
+                keyring.verify(None, &sums_path)?
+            }
+            None => {
+                return Err(Error::Signature {
+                    name: source.name.clone(),
+                    reason: "the mirror has no signature to verify".to_string(),
+                })
+            }
+        };
+        signed = true;
+    }
+    if let Err(err) = verify_checksum(&iso, &source.name, &sums_text, sums_url, cancel) {
+        if matches!(err, Error::Checksum { .. }) {
+            let _ = fs::remove_file(&iso);
+        }
+        return Err(err);
+    }
+    tracing::info!("Verified {:?}, signed: {}", iso, signed);
+    Ok(VerifiedIso {
+        path: iso,
+        checksum: true,
+        signature: signed,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        task::TaskManager,
+        testing::{MockResponse, MockServer},
+    };
+    use sha2::Digest;
+    use tempdir::TempDir;
+
+    const ISO: &[u8] = b"not really an iso";
+
+    fn sha256(bytes: &[u8]) -> String {
+        format!("{:x}", sha2::Sha256::digest(bytes))
+    }
+
+    #[test]
+    fn parse_checksums() {
+        let hash = sha256(ISO);
+        let sums = parse_sha256sums(&format!(
+            "{CLEARSIGNED_HEADER}\nHash: SHA256\n\n\
+            {hash} *ubuntu-22.10-desktop-amd64.iso\n\
+            {}  ./ubuntu-22.10-live-server-amd64.iso\n\
+            # Fedora-Workstation-Live-x86_64-37-1.7.iso: 2037372928 bytes\n\
+            SHA256 (Fedora-Workstation-Live-x86_64-37-1.7.iso) = {}\n\
+            not a hash  ubuntu-22.10-netboot-amd64.tar.gz\n",
+            hash.to_uppercase(),
+            hash
+        ));
+        assert_eq!(sums.len(), 3, "{sums:?}");
+        assert_eq!(sums["ubuntu-22.10-desktop-amd64.iso"], hash);
+        assert_eq!(sums["ubuntu-22.10-live-server-amd64.iso"], hash);
+        assert_eq!(sums["Fedora-Workstation-Live-x86_64-37-1.7.iso"], hash);
+    }
+
+    #[test]
+    fn resolve_mirrors() {
+        let item = FeedsItem {
+            group: "ubuntu".to_string(),
+            name: "ubuntu-22.10-desktop-amd64.iso.torrent".to_string(),
+            ..Default::default()
+        };
+        let mut mirrors = BTreeMap::new();
+        assert_eq!(resolve(&item, &mirrors), None);
+
+        mirrors.insert(
+            "ubuntu".to_string(),
+            GroupMirror {
+                url: "https://releases.ubuntu.com/{version}/".to_string(),
+                signature_file: "SHA256SUMS.gpg".to_string(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            resolve(&item, &mirrors),
+            Some(IsoSource {
+                name: "ubuntu-22.10-desktop-amd64.iso".to_string(),
+                url: "https://releases.ubuntu.com/22.10/ubuntu-22.10-desktop-amd64.iso".to_string(),
+                sums_url: Some("https://releases.ubuntu.com/22.10/SHA256SUMS".to_string()),
+                signature_url: Some("https://releases.ubuntu.com/22.10/SHA256SUMS.gpg".to_string()),
+            })
+        );
+
+        // Urls in the feed win
+        let item = FeedsItem {
+            iso_url: Some("https://mirror.example.com/ubuntu.iso".to_string()),
+            ..item
+        };
+        let source = resolve(&item, &mirrors).unwrap();
+        assert_eq!(source.url, "https://mirror.example.com/ubuntu.iso");
+        assert_eq!(source.sums_url, None);
+    }
+
+    #[test]
+    fn download_and_verify_iso() {
+        let good = format!("{}  debian-11.6.0-amd64-netinst.iso\n", sha256(ISO));
+        let server = MockServer::start(move |req| match req.path.as_str() {
+            "/debian-11.6.0-amd64-netinst.iso" => MockResponse::new(200, ISO),
+            "/SHA256SUMS" => MockResponse::new(200, good.clone()),
+            "/BADSUMS" => MockResponse::new(
+                200,
+                format!("{}  debian-11.6.0-amd64-netinst.iso", sha256(b"other")),
+            ),
+            _ => MockResponse::new(404, "Not Found"),
+        });
+        let tmpdir = TempDir::new("test").unwrap();
+        let downloads = DownloadManager::new(Default::default(), TaskManager::default());
+        let cancel = CancelToken::new();
+        let source = |sums: &str| IsoSource {
+            name: "debian-11.6.0-amd64-netinst.iso".to_string(),
+            url: format!("{}/debian-11.6.0-amd64-netinst.iso", server.url),
+            sums_url: Some(format!("{}/{sums}", server.url)),
+            signature_url: None,
+        };
+
+        let verified = download_iso(
+            &source("SHA256SUMS"),
+            tmpdir.path(),
+            &downloads,
+            None,
+            &cancel,
+        )
+        .unwrap();
+        assert_eq!(
+            verified,
+            VerifiedIso {
+                path: tmpdir.path().join("debian-11.6.0-amd64-netinst.iso"),
+                checksum: true,
+                signature: false,
+            }
+        );
+        assert_eq!(fs::read(&verified.path).unwrap(), ISO);
+
+        // A corrupt image is removed, to be downloaded again
+        let err =
+            download_iso(&source("BADSUMS"), tmpdir.path(), &downloads, None, &cancel).unwrap_err();
+        assert!(matches!(err, Error::Checksum { .. }), "{err:?}");
+        assert!(err.is_retryable());
+        assert!(!verified.path.exists());
+
+        // Signatures are required with a keyring
+        let keyring = Keyring::default();
+        let err = download_iso(
+            &source("SHA256SUMS"),
+            tmpdir.path(),
+            &downloads,
+            Some(&keyring),
+            &cancel,
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::Signature { .. }), "{err:?}");
+    }
+
+    #[test]
+    #[ignore = "needs gpg and gpgv installed"]
+    fn verify_gpg_signature() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let home = tmpdir.path().join("gnupg");
+        fs::create_dir(&home).unwrap();
+        let gpg = |args: &[&str]| {
+            let output = Command::new("gpg")
+                .env("GNUPGHOME", &home)
+                .args(["--batch", "--yes", "--passphrase", ""])
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        };
+        gpg(&[
+            "--quick-gen-key",
+            "Distro Signing <signing@example.com>",
+            "ed25519",
+            "sign",
+            "never",
+        ]);
+        let armored = tmpdir.path().join("distro.asc");
+        gpg(&["--armor", "--output", armored.to_str().unwrap(), "--export"]);
+
+        let sums = tmpdir.path().join("SHA256SUMS");
+        fs::write(&sums, format!("{}  distro.iso\n", sha256(ISO))).unwrap();
+        let signature = tmpdir.path().join("SHA256SUMS.gpg");
+        gpg(&[
+            "--output",
+            signature.to_str().unwrap(),
+            "--detach-sign",
+            sums.to_str().unwrap(),
+        ]);
+
+        let keys = tmpdir.path().join("keyring");
+        let keyring = Keyring {
+            dirs: vec![keys.clone()],
+        };
+        assert!(keyring.verify(Some(&signature), &sums).is_err());
+        assert_eq!(
+            Keyring::import(&keys, &armored).unwrap(),
+            keys.join("distro.gpg")
+        );
+        assert_eq!(keyring.keys(), [keys.join("distro.gpg")]);
+        keyring.verify(Some(&signature), &sums).unwrap();
+
+        fs::write(&sums, format!("{}  distro.iso\n", sha256(b"tampered"))).unwrap();
+        let err = keyring.verify(Some(&signature), &sums).unwrap_err();
+        assert!(matches!(err, Error::Signature { .. }), "{err:?}");
+
+        // A forged line after the signature of a clearsigned file is left out
+        fs::write(&sums, format!("{}  distro.iso\n", sha256(ISO))).unwrap();
+        let clearsigned = tmpdir.path().join("SHA256SUMS.asc");
+        gpg(&[
+            "--output",
+            clearsigned.to_str().unwrap(),
+            "--clearsign",
+            sums.to_str().unwrap(),
+        ]);
+        let mut forged = fs::read_to_string(&clearsigned).unwrap();
+        forged.push_str(&format!("{}  distro.iso\n", sha256(b"forged")));
+        fs::write(&clearsigned, forged).unwrap();
+        let signed = keyring.verify(None, &clearsigned).unwrap();
+        assert_eq!(parse_sha256sums(&signed)["distro.iso"], sha256(ISO));
+    }
+}
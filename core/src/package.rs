@@ -0,0 +1,295 @@
+//! Matching of Ventoy release assets, named like `ventoy-1.0.86-linux.tar.gz`,
+//! `ventoy-1.0.86-windows.zip` and `ventoy-1.0.86-livecd.iso`, to the running system.
+//!
+//! The linux package bundles the binaries of every architecture, the architecture only
+//! picks which one to launch.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::{Error, Result},
+    update::ReleaseAsset,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PkgKind {
+    Linux,
+    Windows,
+    LiveCd,
+}
+
+impl PkgKind {
+    pub fn native() -> Option<Self> {
+        match std::env::consts::OS {
+            "linux" => Some(Self::Linux),
+            "windows" => Some(Self::Windows),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PkgKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Linux => "linux",
+            Self::Windows => "windows",
+            Self::LiveCd => "livecd",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Arch {
+    X86_64,
+    I386,
+    Aarch64,
+    Mips64el,
+}
+
+impl Arch {
+    pub const ALL: [Self; 4] = [Self::X86_64, Self::I386, Self::Aarch64, Self::Mips64el];
+
+    pub fn native() -> Option<Self> {
+        match std::env::consts::ARCH {
+            "x86_64" => Some(Self::X86_64),
+            "x86" => Some(Self::I386),
+            "aarch64" => Some(Self::Aarch64),
+            "mips64" if cfg!(target_endian = "little") => Some(Self::Mips64el),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Arch {
+    /// Suffix of the `VentoyGUI.<arch>` binaries in the linux package
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::X86_64 => "x86_64",
+            Self::I386 => "i386",
+            Self::Aarch64 => "aarch64",
+            Self::Mips64el => "mips64el",
+        })
+    }
+}
+
+/// Which package to download, defaults to the running system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PkgTarget {
+    pub kind: PkgKind,
+    pub arch: Arch,
+}
+
+impl PkgTarget {
+    pub fn native() -> Option<Self> {
+        Some(Self {
+            kind: PkgKind::native()?,
+            arch: Arch::native()?,
+        })
+    }
+
+    /// Whether the binary of the package runs on this system, x86_64 runs i386 ones too.
+    pub fn runs_here(&self) -> bool {
+        let arch_runs = match Arch::native() {
+            Some(Arch::X86_64) => matches!(self.arch, Arch::X86_64 | Arch::I386),
+            native => native == Some(self.arch),
+        };
+        PkgKind::native() == Some(self.kind) && arch_runs
+    }
+
+    /// Binary to launch from the extracted package.
+    pub fn bin_name(&self) -> String {
+        match self.kind {
+            PkgKind::Windows => "Ventoy2Disk.exe".to_string(),
+            _ => format!("VentoyGUI.{}", self.arch),
+        }
+    }
+}
+
+impl fmt::Display for PkgTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.kind, self.arch)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PkgAsset {
+    pub version: String,
+    pub kind: PkgKind,
+}
+
+/// Parses an asset name, `None` for anything that isn't a package, like `sha256.txt`.
+pub fn parse_asset_name(name: &str) -> Option<PkgAsset> {
+    let name = name.to_lowercase();
+    let stem = name.strip_prefix("ventoy-")?;
+    let (stem, ext_kind) = if let Some(stem) = stem.strip_suffix(".tar.gz") {
+        (stem, PkgKind::Linux)
+    } else if let Some(stem) = stem.strip_suffix(".zip") {
+        (stem, PkgKind::Windows)
+    } else if let Some(stem) = stem.strip_suffix(".iso") {
+        (stem, PkgKind::LiveCd)
+    } else {
+        return None;
+    };
+
+    let (version, kind) = stem.split_once('-')?;
+    if !version.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let kind = match kind {
+        "linux" => PkgKind::Linux,
+        "windows" => PkgKind::Windows,
+        "livecd" => PkgKind::LiveCd,
+        _ => return None,
+    };
+    // The extension has to agree, e.g. no `-linux.zip`
+    if kind != ext_kind {
+        return None;
+    }
+    Some(PkgAsset {
+        version: version.to_string(),
+        kind,
+    })
+}
+
+/// Picks the package for `target`.
+pub fn select_asset(assets: &[ReleaseAsset], target: PkgTarget) -> Result<&ReleaseAsset> {
+    let selected = assets
+        .iter()
+        .find(|asset| parse_asset_name(&asset.name).is_some_and(|pkg| pkg.kind == target.kind));
+    match selected {
+        Some(asset) => Ok(asset),
+        None => Err(Error::NoMatchingAsset {
+            target: target.to_string(),
+            assets: assets.iter().map(|asset| asset.name.clone()).collect(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assets(names: &[&str]) -> Vec<ReleaseAsset> {
+        names
+            .iter()
+            .map(|name| ReleaseAsset {
+                name: name.to_string(),
+                download_url: format!("https://example.com/{name}"),
+            })
+            .collect()
+    }
+
+    const V1_0_86: &[&str] = &[
+        "sha256.txt",
+        "ventoy-1.0.86-linux.tar.gz",
+        "ventoy-1.0.86-livecd.iso",
+        "ventoy-1.0.86-windows.zip",
+    ];
+    const V1_0_20: &[&str] = &["ventoy-1.0.20-linux.tar.gz", "ventoy-1.0.20-windows.zip"];
+
+    #[test]
+    fn parse_asset_names() {
+        let table: &[(&str, Option<(&str, PkgKind)>)] = &[
+            (
+                "ventoy-1.0.86-linux.tar.gz",
+                Some(("1.0.86", PkgKind::Linux)),
+            ),
+            (
+                "ventoy-1.0.86-windows.zip",
+                Some(("1.0.86", PkgKind::Windows)),
+            ),
+            (
+                "ventoy-1.0.86-livecd.iso",
+                Some(("1.0.86", PkgKind::LiveCd)),
+            ),
+            (
+                "Ventoy-1.0.20-Linux.tar.gz",
+                Some(("1.0.20", PkgKind::Linux)),
+            ),
+            ("ventoy-1.0.86-linux.zip", None),
+            ("ventoy-1.0.86-linux.tar.gz.sig", None),
+            ("ventoy-latest-linux.tar.gz", None),
+            ("sha256.txt", None),
+        ];
+        for (name, expected) in table {
+            let expected = expected.map(|(version, kind)| PkgAsset {
+                version: version.to_string(),
+                kind,
+            });
+            assert_eq!(parse_asset_name(name), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn select_for_target() {
+        let table: &[(&[&str], PkgKind, Arch, Option<&str>)] = &[
+            (
+                V1_0_86,
+                PkgKind::Linux,
+                Arch::X86_64,
+                Some("ventoy-1.0.86-linux.tar.gz"),
+            ),
+            (
+                V1_0_86,
+                PkgKind::Linux,
+                Arch::Aarch64,
+                Some("ventoy-1.0.86-linux.tar.gz"),
+            ),
+            (
+                V1_0_86,
+                PkgKind::Windows,
+                Arch::X86_64,
+                Some("ventoy-1.0.86-windows.zip"),
+            ),
+            (
+                V1_0_86,
+                PkgKind::LiveCd,
+                Arch::X86_64,
+                Some("ventoy-1.0.86-livecd.iso"),
+            ),
+            (
+                V1_0_20,
+                PkgKind::Linux,
+                Arch::I386,
+                Some("ventoy-1.0.20-linux.tar.gz"),
+            ),
+            (V1_0_20, PkgKind::LiveCd, Arch::X86_64, None),
+        ];
+        for (names, kind, arch, expected) in table {
+            let assets = assets(names);
+            let target = PkgTarget {
+                kind: *kind,
+                arch: *arch,
+            };
+            let selected = select_asset(&assets, target).ok().map(|a| a.name.as_str());
+            assert_eq!(selected, *expected, "{} in {:?}", target, names);
+        }
+    }
+
+    #[test]
+    fn bin_names() {
+        let target = |kind, arch| PkgTarget { kind, arch };
+        assert_eq!(
+            target(PkgKind::Linux, Arch::Aarch64).bin_name(),
+            "VentoyGUI.aarch64"
+        );
+        assert_eq!(
+            target(PkgKind::Windows, Arch::X86_64).bin_name(),
+            "Ventoy2Disk.exe"
+        );
+    }
+
+    #[test]
+    fn host_targets() {
+        if let Some(native) = PkgTarget::native() {
+            assert!(native.runs_here());
+        }
+        assert!(!PkgTarget {
+            kind: PkgKind::LiveCd,
+            arch: Arch::X86_64
+        }
+        .runs_here());
+    }
+}
@@ -0,0 +1,525 @@
+//! Native replacement for Ventoy's `CreatePersistentImg.sh`.
+//!
+//! Backing files are sparse, so only the filesystem metadata takes up space on the
+//! drive. The filesystem is laid out as ext2 revision 1 (no journal, no extents),
+//! which the ext4 driver used by casper and live-boot mounts as is.
+
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    fs::{self, File},
+    hash::{BuildHasher, Hasher},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use super::{drive::VentoyDrive, ventoy_json::VentoyJson};
+
+const BLOCK_SIZE: u64 = 4096;
+const BLOCKS_PER_GROUP: u64 = BLOCK_SIZE * 8;
+const INODE_SIZE: u64 = 256;
+const INODES_PER_BLOCK: u64 = BLOCK_SIZE / INODE_SIZE;
+/// Bytes of data per inode, same default as `mke2fs`.
+const INODE_RATIO: u64 = 16384;
+const GROUP_DESC_SIZE: u64 = 32;
+
+const ROOT_INO: u32 = 2;
+const LOST_FOUND_INO: u32 = 11;
+const CONF_INO: u32 = 12;
+
+pub const MIN_SIZE: u64 = 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PersistenceLabel {
+    /// Used by Ubuntu and derivatives (casper)
+    #[default]
+    CasperRw,
+    /// Used by Debian and derivatives (live-boot)
+    Persistence,
+}
+
+impl PersistenceLabel {
+    pub const ALL: [Self; 2] = [Self::CasperRw, Self::Persistence];
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::CasperRw => "casper-rw",
+            Self::Persistence => "persistence",
+        }
+    }
+}
+
+impl fmt::Display for PersistenceLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Creates a sparse persistence image of at least `size` bytes, rounded up to the
+/// block size. Nothing is left behind if it fails.
+///
+/// live-boot only uses a `persistence` volume if it contains a `persistence.conf`,
+/// so one mapping `/` as a union mount is written for that label.
+pub fn create_image<P: AsRef<Path>>(path: P, size: u64, label: PersistenceLabel) -> io::Result<()> {
+    if size < MIN_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("persistence image must be at least {MIN_SIZE} bytes"),
+        ));
+    }
+    let conf: Option<&[u8]> = match label {
+        PersistenceLabel::CasperRw => None,
+        PersistenceLabel::Persistence => Some(b"/ union\n"),
+    };
+    let layout = Layout::new(size.div_ceil(BLOCK_SIZE));
+
+    let path = path.as_ref();
+    let mut file = File::create(path)?;
+    let created = file
+        .set_len(layout.blocks_count * BLOCK_SIZE)
+        .and_then(|_| layout.write(&mut file, label.as_str(), conf))
+        .and_then(|_| file.sync_all());
+    if created.is_err() {
+        drop(file);
+        let _ = fs::remove_file(path);
+    }
+    created
+}
+
+/// Reads the volume label from the superblock of an ext2/3/4 image.
+pub fn read_label<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut sb = [0; 1024];
+    file.seek(SeekFrom::Start(1024))?;
+    file.read_exact(&mut sb)?;
+    if sb[56..58] != 0xef53u16.to_le_bytes() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an ext filesystem",
+        ));
+    }
+    let label = &sb[120..136];
+    let len = label.iter().position(|&b| b == 0).unwrap_or(label.len());
+    Ok(String::from_utf8_lossy(&label[..len]).to_string())
+}
+
+/// Creates a persistence image under `/persistence` on the drive and registers it
+/// for `iso` in the `persistence` plugin of `ventoy.json`.
+pub fn create_for_image<P: AsRef<Path>>(
+    drive: &VentoyDrive,
+    iso: P,
+    size: u64,
+    label: PersistenceLabel,
+) -> anyhow::Result<PathBuf> {
+    let iso = iso.as_ref();
+    let image = drive
+        .image_path(iso)
+        .ok_or_else(|| anyhow!("{:?} is not on the ventoy drive", iso))?;
+    let stem = iso
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| anyhow!("invalid image name {:?}", iso))?;
+
+    let dir = drive.root().join("persistence");
+    fs::create_dir_all(&dir)?;
+    let backend_path = dir.join(format!("{stem}_{label}.dat"));
+    if backend_path.exists() {
+        bail!("{:?} already exists", backend_path);
+    }
+    create_image(&backend_path, size, label)?;
+    let backend = drive
+        .image_path(&backend_path)
+        .expect("backend is created on the drive");
+
+    let mut ventoy_json = VentoyJson::load(drive.ventoy_json_path())?;
+    register(&mut ventoy_json, &image, &backend)?;
+    ventoy_json.save(drive.ventoy_json_path())?;
+    Ok(backend_path)
+}
+
+/// Adds `backend` to the persistence entry of `image`, Ventoy shows a selection
+/// menu on boot when an image has more than one backend.
+pub fn register(ventoy_json: &mut VentoyJson, image: &str, backend: &str) -> anyhow::Result<()> {
+    ventoy_json.append("persistence", "image", image, "backend", backend)
+}
+
+struct Layout {
+    blocks_count: u64,
+    groups: u64,
+    inodes_per_group: u64,
+    gdt_blocks: u64,
+}
+
+impl Layout {
+    fn new(blocks_count: u64) -> Self {
+        let inodes_per_group = |blocks: u64| {
+            let inodes =
+                (blocks.min(BLOCKS_PER_GROUP) * BLOCK_SIZE / INODE_RATIO).max(INODES_PER_BLOCK);
+            // Fill up whole inode table blocks
+            inodes.div_ceil(INODES_PER_BLOCK) * INODES_PER_BLOCK
+        };
+        let mut layout = Self {
+            blocks_count,
+            groups: 0,
+            inodes_per_group: inodes_per_group(blocks_count),
+            gdt_blocks: 0,
+        };
+        layout.groups = blocks_count.div_ceil(BLOCKS_PER_GROUP);
+        layout.gdt_blocks = (layout.groups * GROUP_DESC_SIZE).div_ceil(BLOCK_SIZE);
+        // mke2fs drops a trailing group too small to hold its own metadata, it's grown
+        // instead so that the image isn't smaller than asked for
+        let last_group_blocks = blocks_count - (layout.groups - 1) * BLOCKS_PER_GROUP;
+        let min_group_blocks = layout.overhead() + 50;
+        if layout.groups > 1 && last_group_blocks < min_group_blocks {
+            layout.blocks_count += min_group_blocks - last_group_blocks;
+        }
+        layout
+    }
+
+    fn inode_table_blocks(&self) -> u64 {
+        self.inodes_per_group / INODES_PER_BLOCK
+    }
+
+    /// Blocks used by metadata at the start of every group.
+    fn overhead(&self) -> u64 {
+        1 + self.gdt_blocks + 2 + self.inode_table_blocks()
+    }
+
+    fn group_start(&self, group: u64) -> u64 {
+        group * BLOCKS_PER_GROUP
+    }
+
+    fn group_blocks(&self, group: u64) -> u64 {
+        (self.blocks_count - self.group_start(group)).min(BLOCKS_PER_GROUP)
+    }
+
+    fn block_bitmap(&self, group: u64) -> u64 {
+        self.group_start(group) + 1 + self.gdt_blocks
+    }
+
+    fn inode_bitmap(&self, group: u64) -> u64 {
+        self.block_bitmap(group) + 1
+    }
+
+    fn inode_table(&self, group: u64) -> u64 {
+        self.inode_bitmap(group) + 1
+    }
+
+    fn write(&self, file: &mut File, label: &str, conf: Option<&[u8]>) -> io::Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as u32);
+
+        // Data blocks of the root directory, lost+found and persistence.conf
+        let first_data = self.group_start(0) + self.overhead();
+        let root_block = first_data;
+        let lost_found_block = first_data + 1;
+        let conf_block = first_data + 2;
+        let data_blocks = if conf.is_some() { 3 } else { 2 };
+        let used_inodes = if conf.is_some() {
+            CONF_INO
+        } else {
+            LOST_FOUND_INO
+        } as u64;
+
+        let mut free_blocks = Vec::new();
+        for group in 0..self.groups {
+            let used = self.overhead() + if group == 0 { data_blocks } else { 0 };
+            free_blocks.push(self.group_blocks(group) - used);
+        }
+        let total_free_blocks: u64 = free_blocks.iter().sum();
+        let total_inodes = self.inodes_per_group * self.groups;
+
+        let mut gdt = vec![0; (self.gdt_blocks * BLOCK_SIZE) as usize];
+        for group in 0..self.groups {
+            let desc = &mut gdt[(group * GROUP_DESC_SIZE) as usize..];
+            let free_inodes = if group == 0 {
+                self.inodes_per_group - used_inodes
+            } else {
+                self.inodes_per_group
+            };
+            put_u32(desc, 0, self.block_bitmap(group) as u32);
+            put_u32(desc, 4, self.inode_bitmap(group) as u32);
+            put_u32(desc, 8, self.inode_table(group) as u32);
+            put_u16(desc, 12, free_blocks[group as usize] as u16);
+            put_u16(desc, 14, free_inodes as u16);
+            put_u16(desc, 16, if group == 0 { 2 } else { 0 });
+        }
+
+        let mut sb = vec![0; 1024];
+        put_u32(&mut sb, 0, total_inodes as u32);
+        put_u32(&mut sb, 4, self.blocks_count as u32);
+        put_u32(&mut sb, 12, total_free_blocks as u32);
+        put_u32(&mut sb, 16, (total_inodes - used_inodes) as u32);
+        put_u32(&mut sb, 20, 0); // first data block
+        put_u32(&mut sb, 24, 2); // log2(block size) - 10
+        put_u32(&mut sb, 28, 2); // fragment size
+        put_u32(&mut sb, 32, BLOCKS_PER_GROUP as u32);
+        put_u32(&mut sb, 36, BLOCKS_PER_GROUP as u32);
+        put_u32(&mut sb, 40, self.inodes_per_group as u32);
+        put_u32(&mut sb, 48, now); // write time
+        put_u16(&mut sb, 54, u16::MAX); // max mount count, disables checks
+        put_u16(&mut sb, 56, 0xef53); // magic
+        put_u16(&mut sb, 58, 1); // state: clean
+        put_u16(&mut sb, 60, 1); // errors: continue
+        put_u32(&mut sb, 64, now); // last check
+        put_u32(&mut sb, 76, 1); // revision
+        put_u32(&mut sb, 84, LOST_FOUND_INO); // first non-reserved inode
+        put_u16(&mut sb, 88, INODE_SIZE as u16);
+        put_u32(&mut sb, 96, 0x2); // incompat: filetype
+        put_u32(&mut sb, 100, 0x2); // ro_compat: large_file
+        sb[104..120].copy_from_slice(&random_uuid());
+        let label = label.as_bytes();
+        sb[120..120 + label.len().min(16)].copy_from_slice(&label[..label.len().min(16)]);
+
+        for group in 0..self.groups {
+            // Backup copies of the superblock and descriptors live in every group
+            put_u16(&mut sb, 90, group as u16);
+            let start = self.group_start(group) * BLOCK_SIZE;
+            file.seek(SeekFrom::Start(if group == 0 { 1024 } else { start }))?;
+            file.write_all(&sb)?;
+            file.seek(SeekFrom::Start(start + BLOCK_SIZE))?;
+            file.write_all(&gdt)?;
+
+            let mut block_bitmap = vec![0; BLOCK_SIZE as usize];
+            let used = self.overhead() + if group == 0 { data_blocks } else { 0 };
+            set_bits(&mut block_bitmap, 0..used);
+            // Blocks past the end of a short last group are marked as in use
+            set_bits(
+                &mut block_bitmap,
+                self.group_blocks(group)..BLOCKS_PER_GROUP,
+            );
+            write_block(file, self.block_bitmap(group), &block_bitmap)?;
+
+            let mut inode_bitmap = vec![0; BLOCK_SIZE as usize];
+            if group == 0 {
+                set_bits(&mut inode_bitmap, 0..used_inodes);
+            }
+            set_bits(&mut inode_bitmap, self.inodes_per_group..BLOCK_SIZE * 8);
+            write_block(file, self.inode_bitmap(group), &inode_bitmap)?;
+        }
+
+        let mut root = DirBlock::default();
+        root.push(ROOT_INO, ".", FT_DIR);
+        root.push(ROOT_INO, "..", FT_DIR);
+        root.push(LOST_FOUND_INO, "lost+found", FT_DIR);
+        if conf.is_some() {
+            root.push(CONF_INO, "persistence.conf", FT_REG);
+        }
+        write_block(file, root_block, &root.finish())?;
+        write_inode(
+            file, self, ROOT_INO, 0o040755, 3, BLOCK_SIZE, root_block, now,
+        )?;
+
+        let mut lost_found = DirBlock::default();
+        lost_found.push(LOST_FOUND_INO, ".", FT_DIR);
+        lost_found.push(ROOT_INO, "..", FT_DIR);
+        write_block(file, lost_found_block, &lost_found.finish())?;
+        write_inode(
+            file,
+            self,
+            LOST_FOUND_INO,
+            0o040700,
+            2,
+            BLOCK_SIZE,
+            lost_found_block,
+            now,
+        )?;
+
+        if let Some(conf) = conf {
+            write_block(file, conf_block, conf)?;
+            write_inode(
+                file,
+                self,
+                CONF_INO,
+                0o100644,
+                1,
+                conf.len() as u64,
+                conf_block,
+                now,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+const FT_REG: u8 = 1;
+const FT_DIR: u8 = 2;
+
+#[derive(Default)]
+struct DirBlock {
+    data: Vec<u8>,
+    last_entry: usize,
+}
+
+impl DirBlock {
+    fn push(&mut self, ino: u32, name: &str, file_type: u8) {
+        let rec_len = (8 + name.len()).div_ceil(4) * 4;
+        self.last_entry = self.data.len();
+        self.data.extend_from_slice(&ino.to_le_bytes());
+        self.data.extend_from_slice(&(rec_len as u16).to_le_bytes());
+        self.data.push(name.len() as u8);
+        self.data.push(file_type);
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.resize(self.last_entry + rec_len, 0);
+    }
+
+    /// The last entry spans the rest of the block.
+    fn finish(mut self) -> Vec<u8> {
+        let rec_len = BLOCK_SIZE as usize - self.last_entry;
+        put_u16(&mut self.data, self.last_entry + 4, rec_len as u16);
+        self.data.resize(BLOCK_SIZE as usize, 0);
+        self.data
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_inode(
+    file: &mut File,
+    layout: &Layout,
+    ino: u32,
+    mode: u16,
+    links: u16,
+    size: u64,
+    block: u64,
+    time: u32,
+) -> io::Result<()> {
+    let mut inode = vec![0; INODE_SIZE as usize];
+    put_u16(&mut inode, 0, mode);
+    put_u32(&mut inode, 4, size as u32);
+    put_u32(&mut inode, 8, time); // atime
+    put_u32(&mut inode, 12, time); // ctime
+    put_u32(&mut inode, 16, time); // mtime
+    put_u16(&mut inode, 26, links);
+    put_u32(&mut inode, 28, (BLOCK_SIZE / 512) as u32); // 512-byte sectors in use
+    put_u32(&mut inode, 40, block as u32); // first direct block
+    put_u16(&mut inode, 128, 32); // extra inode size
+
+    // All reserved inodes live in the first group
+    let offset = layout.inode_table(0) * BLOCK_SIZE + (ino as u64 - 1) * INODE_SIZE;
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(&inode)
+}
+
+fn write_block(file: &mut File, block: u64, data: &[u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(block * BLOCK_SIZE))?;
+    file.write_all(data)
+}
+
+fn set_bits(bitmap: &mut [u8], bits: std::ops::Range<u64>) {
+    for bit in bits {
+        bitmap[(bit / 8) as usize] |= 1 << (bit % 8);
+    }
+}
+
+fn put_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Version 4 UUID seeded from the randomly keyed std hasher.
+fn random_uuid() -> [u8; 16] {
+    let mut uuid = [0; 16];
+    for chunk in uuid.chunks_mut(8) {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos()),
+        );
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    uuid[6] = (uuid[6] & 0x0f) | 0x40;
+    uuid[8] = (uuid[8] & 0x3f) | 0x80;
+    uuid
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use tempdir::TempDir;
+
+    const MIB: u64 = 1024 * 1024;
+
+    #[test]
+    fn create_casper_image() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let path = tmpdir.path().join("casper.dat");
+        create_image(&path, 300 * MIB, PersistenceLabel::CasperRw).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 300 * MIB);
+        assert_eq!(read_label(&path).unwrap(), "casper-rw");
+    }
+
+    #[test]
+    fn create_persistence_image() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let path = tmpdir.path().join("persistence.dat");
+        create_image(&path, 8 * MIB, PersistenceLabel::Persistence).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 8 * MIB);
+        assert_eq!(read_label(&path).unwrap(), "persistence");
+        let content = fs::read(&path).unwrap();
+        assert!(content.windows(8).any(|w| w == b"/ union\n"));
+
+        // Never smaller than asked for
+        create_image(&path, 8 * MIB + 1, PersistenceLabel::Persistence).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 8 * MIB + 4096);
+    }
+
+    #[test]
+    fn grow_short_last_group() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let path = tmpdir.path().join("casper.dat");
+        // One block into the second group of 128 MiB
+        let size = 128 * MIB + 4096;
+        create_image(&path, size, PersistenceLabel::CasperRw).unwrap();
+        assert!(fs::metadata(&path).unwrap().len() >= size);
+        assert_eq!(read_label(&path).unwrap(), "casper-rw");
+    }
+
+    #[test]
+    fn reject_tiny_image() {
+        let tmpdir = TempDir::new("test").unwrap();
+        assert!(create_image(
+            tmpdir.path().join("a.dat"),
+            4096,
+            PersistenceLabel::CasperRw
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn create_and_register() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let drive = VentoyDrive::new(tmpdir.path());
+        fs::create_dir_all(tmpdir.path().join("ISO")).unwrap();
+        let iso = tmpdir.path().join("ISO/ubuntu.iso");
+        File::create(&iso).unwrap();
+
+        let backend = create_for_image(&drive, &iso, 4 * MIB, PersistenceLabel::CasperRw).unwrap();
+        assert_eq!(
+            backend,
+            tmpdir.path().join("persistence/ubuntu_casper-rw.dat")
+        );
+        create_for_image(&drive, &iso, 4 * MIB, PersistenceLabel::Persistence).unwrap();
+
+        let ventoy_json = VentoyJson::load(drive.ventoy_json_path()).unwrap();
+        assert_eq!(
+            ventoy_json.entries("persistence"),
+            &[json!({
+                "image": "/ISO/ubuntu.iso",
+                "backend": [
+                    "/persistence/ubuntu_casper-rw.dat",
+                    "/persistence/ubuntu_persistence.dat"
+                ]
+            })]
+        );
+    }
+}
@@ -0,0 +1,675 @@
+//! Profiles describing the state a Ventoy drive should be in, for building the same drive
+//! over and over.
+//!
+//! [`plan`] compares a [`Profile`] with a drive and lists the [`Step`]s getting it there,
+//! to be shown before [`execute`] carries them out. Updating Ventoy itself is left to
+//! Ventoy2Disk. Partitioning is out of scope: profiles apply to drives that already have
+//! Ventoy, and updating keeps their partitions.
+
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    convert::Infallible,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    downloads::DownloadManager,
+    drive::VentoyDrive,
+    mirror::{self, GroupMirror, IsoSource, Keyring},
+    sync,
+    task::CancelToken,
+    theme::{self, ThemeSelection},
+    utils::FeedsItem,
+    ventoy_json::VentoyJson,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    /// Ventoy release the drive should run, e.g. `1.0.86`, the latest if empty
+    pub ventoy_version: String,
+    /// Update Ventoy with Secure Boot support
+    pub secure_boot: bool,
+    /// Directory on the drive the images are copied into, e.g. `ISO`, the root if empty
+    pub image_dir: String,
+    pub images: Vec<ImageSource>,
+    /// Delete the images on the drive that aren't in `images`
+    pub prune: bool,
+    /// `ventoy.json` plugins, replacing the ones of the same name on the drive
+    pub plugins: BTreeMap<String, Value>,
+    /// Theme archive to install and select
+    pub theme: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ImageSource {
+    /// The newest release of `group` in the feeds whose image name matches `pattern`,
+    /// where `*` matches anything
+    Feed {
+        group: String,
+        pattern: String,
+    },
+    Local(PathBuf),
+}
+
+impl fmt::Display for ImageSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Feed { group, pattern } if pattern.is_empty() => f.write_str(group),
+            Self::Feed { group, pattern } => write!(f, "{group}: {pattern}"),
+            Self::Local(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl FromStr for ImageSource {
+    type Err = Infallible;
+
+    /// Parses `group: pattern` or `group` into a feed source, anything else is a path.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (group, pattern) = s.split_once(':').unwrap_or((s, ""));
+        let group = group.trim();
+        // Not `C:\ISO\tools.img`
+        let is_group = group.len() > 1
+            && group
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        Ok(if is_group {
+            Self::Feed {
+                group: group.to_string(),
+                pattern: pattern.trim().to_string(),
+            }
+        } else {
+            Self::Local(s.into())
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// Update Ventoy with Ventoy2Disk, done by hand
+    UpdateVentoy {
+        /// `None` if it couldn't be read from the drive
+        installed: Option<String>,
+        version: String,
+        secure_boot: bool,
+    },
+    /// Download an image into `dir`, then copy it to the drive
+    Download {
+        source: IsoSource,
+        dir: PathBuf,
+        to: PathBuf,
+    },
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Delete(PathBuf),
+    InstallTheme(PathBuf),
+    /// Set `plugins` in `ventoy.json` and select the installed theme named `theme`
+    Configure {
+        plugins: BTreeMap<String, Value>,
+        theme: Option<String>,
+    },
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UpdateVentoy {
+                installed,
+                version,
+                secure_boot,
+            } => {
+                match installed {
+                    Some(installed) => write!(f, "Update Ventoy {installed} to {version}")?,
+                    None => write!(f, "Update Ventoy to {version}")?,
+                }
+                // Never `-i`, that would wipe the drive
+                let args = if *secure_boot { "-u -s" } else { "-u" };
+                write!(f, " with `Ventoy2Disk.sh {args}`")
+            }
+            Self::Download { source, to, .. } => {
+                write!(
+                    f,
+                    "Download {} and copy it to {}",
+                    source.name,
+                    to.display()
+                )
+            }
+            Self::Copy { from, to } => write!(f, "Copy {} to {}", from.display(), to.display()),
+            Self::Delete(path) => write!(f, "Delete {}", path.display()),
+            Self::InstallTheme(archive) => write!(f, "Install theme {}", archive.display()),
+            Self::Configure { plugins, theme } => {
+                let mut changes: Vec<_> = plugins.keys().map(|name| name.as_str()).collect();
+                if let Some(theme) = theme {
+                    changes.push(theme);
+                }
+                write!(f, "Configure {}", changes.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Plan {
+    pub steps: Vec<Step>,
+    /// Parts of the profile that can't be carried out, e.g. images not in the feeds
+    pub warnings: Vec<String>,
+}
+
+/// What a plan is made against, besides the drive.
+#[derive(Debug, Clone, Copy)]
+pub struct PlanContext<'a> {
+    pub feeds: &'a [FeedsItem],
+    pub mirrors: &'a BTreeMap<String, GroupMirror>,
+    /// Where images are downloaded to, the ones already there are copied from it
+    pub download_dir: &'a Path,
+    /// Version of the latest Ventoy release, for profiles not pinning one
+    pub latest_version: Option<&'a str>,
+    /// Version of Ventoy on the drive, see [`crate::drive::installed_version`], `None` if
+    /// it couldn't be read
+    pub installed_version: Option<&'a str>,
+}
+
+/// The steps bringing `drive` to the state of `profile`, nothing if it's already there.
+pub fn plan(profile: &Profile, drive: &VentoyDrive, ctx: &PlanContext) -> anyhow::Result<Plan> {
+    let mut plan = Plan::default();
+
+    let version = match profile.ventoy_version.trim() {
+        "" => ctx.latest_version,
+        version => Some(version),
+    }
+    .map(|version| version.trim_start_matches('v'));
+    match version {
+        Some(version) if ctx.installed_version != Some(version) => {
+            if ctx.installed_version.is_none() {
+                plan.warnings.push(
+                    "The Ventoy version of the drive couldn't be read, mount its VTOYEFI \
+                    partition to check it"
+                        .to_string(),
+                );
+            }
+            plan.steps.push(Step::UpdateVentoy {
+                installed: ctx.installed_version.map(String::from),
+                version: version.to_string(),
+                secure_boot: profile.secure_boot,
+            })
+        }
+        Some(_) => (),
+        None => plan
+            .warnings
+            .push("The latest Ventoy release isn't known yet".to_string()),
+    }
+
+    let image_dir = drive.root().join(&profile.image_dir);
+    let mut wanted = Vec::new();
+    // Patterns of feed sources without a release, e.g. before the feeds are loaded
+    let mut unresolved = Vec::new();
+    for source in &profile.images {
+        match source {
+            ImageSource::Feed { group, pattern } => {
+                let item = match newest_release(ctx.feeds, group, pattern) {
+                    Some(item) => item,
+                    None => {
+                        plan.warnings
+                            .push(format!("No release in the feeds matches {source}"));
+                        unresolved.push(pattern.as_str());
+                        continue;
+                    }
+                };
+                let to = image_dir.join(item.image_name());
+                wanted.push(to.clone());
+                if to.is_file() {
+                    continue;
+                }
+                let downloaded = ctx.download_dir.join(item.image_name());
+                if downloaded.is_file() {
+                    plan.steps.push(Step::Copy {
+                        from: downloaded,
+                        to,
+                    });
+                } else if let Some(source) = mirror::resolve(item, ctx.mirrors) {
+                    plan.steps.push(Step::Download {
+                        source,
+                        dir: ctx.download_dir.to_path_buf(),
+                        to,
+                    });
+                } else {
+                    plan.warnings.push(format!(
+                        "{} has no HTTP mirror, download it into {} with its torrent",
+                        item.image_name(),
+                        ctx.download_dir.display()
+                    ));
+                }
+            }
+            ImageSource::Local(path) => {
+                let name = match path.file_name() {
+                    Some(name) => name,
+                    None => {
+                        plan.warnings
+                            .push(format!("{} isn't a file", path.display()));
+                        continue;
+                    }
+                };
+                let to = image_dir.join(name);
+                wanted.push(to.clone());
+                let size = match fs::metadata(path) {
+                    Ok(metadata) => metadata.len(),
+                    Err(err) => {
+                        plan.warnings
+                            .push(format!("Can't read {}: {err}", path.display()));
+                        continue;
+                    }
+                };
+                if fs::metadata(&to).map_or(true, |metadata| metadata.len() != size) {
+                    plan.steps.push(Step::Copy {
+                        from: path.clone(),
+                        to,
+                    });
+                }
+            }
+        }
+    }
+    if profile.prune {
+        let images = drive
+            .images()
+            .with_context(|| format!("failed to list the images on {:?}", drive.root()))?;
+        // Whatever the unresolved sources would have kept is kept as is
+        let kept = |image: &Path| {
+            let name = image.file_name().and_then(|name| name.to_str());
+            wanted.iter().any(|path| path == image)
+                || name
+                    .is_some_and(|name| unresolved.iter().any(|pattern| glob_match(pattern, name)))
+        };
+        plan.steps.extend(
+            images
+                .into_iter()
+                .filter(|image| !kept(image))
+                .map(Step::Delete),
+        );
+    }
+
+    let ventoy_json = VentoyJson::load(drive.ventoy_json_path())?;
+    let mut theme_name = None;
+    if let Some(archive) = &profile.theme {
+        let name = theme::theme_name(archive)
+            .with_context(|| format!("unsupported theme archive {archive:?}"))?;
+        let installed = theme::installed_themes(drive)?
+            .into_iter()
+            .find(|theme| theme.name == name);
+        match installed {
+            Some(theme) => {
+                if ThemeSelection::load(&ventoy_json).files != [theme.ventoy_path] {
+                    theme_name = Some(name.to_string());
+                }
+            }
+            None => {
+                plan.steps.push(Step::InstallTheme(archive.clone()));
+                theme_name = Some(name.to_string());
+            }
+        }
+    }
+    let plugins: BTreeMap<_, _> = profile
+        .plugins
+        .iter()
+        .filter(|(name, value)| ventoy_json.plugin(name) != Some(value))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    if !plugins.is_empty() || theme_name.is_some() {
+        plan.steps.push(Step::Configure {
+            plugins,
+            theme: theme_name,
+        });
+    }
+    Ok(plan)
+}
+
+/// Carries out the steps of `plan` on `drive`, calling `progress` with the index of each
+/// step before it. Downloads are verified as in [`mirror::download_iso`], with signatures
+/// if there's a `keyring`. Returns the steps to do by hand.
+pub fn execute<F>(
+    plan: &Plan,
+    drive: &VentoyDrive,
+    downloads: &DownloadManager,
+    keyring: Option<&Keyring>,
+    cancel: &CancelToken,
+    mut progress: F,
+) -> anyhow::Result<Vec<Step>>
+where
+    F: FnMut(usize, &Step),
+{
+    let mut manual = Vec::new();
+    for (i, step) in plan.steps.iter().enumerate() {
+        cancel.check()?;
+        progress(i, step);
+        tracing::info!("{}", step);
+        match step {
+            Step::UpdateVentoy { .. } => manual.push(step.clone()),
+            Step::Download { source, dir, to } => {
+                let iso = mirror::download_iso(source, dir, downloads, keyring, cancel)?;
+                sync::copy_file(&iso.path, to, cancel, |_| ())?;
+            }
+            Step::Copy { from, to } => sync::copy_file(from, to, cancel, |_| ())?,
+            Step::Delete(path) => {
+                fs::remove_file(path).with_context(|| format!("failed to delete {path:?}"))?
+            }
+            Step::InstallTheme(archive) => {
+                theme::install_theme(drive, archive)?;
+            }
+            Step::Configure { plugins, theme } => {
+                let path = drive.ventoy_json_path();
+                let mut ventoy_json = VentoyJson::load(&path)?;
+                for (name, value) in plugins {
+                    ventoy_json.set_plugin(name, value.clone());
+                }
+                if let Some(name) = theme {
+                    let theme = theme::installed_themes(drive)?
+                        .into_iter()
+                        .find(|theme| &theme.name == name)
+                        .with_context(|| format!("theme `{name}` isn't installed"))?;
+                    let mut selection = ThemeSelection::load(&ventoy_json);
+                    selection.files = vec![theme.ventoy_path];
+                    selection.default_file = 0;
+                    selection.apply(&mut ventoy_json);
+                }
+                ventoy_json.save(&path)?;
+            }
+        }
+    }
+    Ok(manual)
+}
+
+/// The newest release of `group` whose image name matches `pattern`.
+fn newest_release<'a>(feeds: &'a [FeedsItem], group: &str, pattern: &str) -> Option<&'a FeedsItem> {
+    feeds
+        .iter()
+        .filter(|item| item.group == group && glob_match(pattern, item.image_name()))
+        .max_by(|a, b| compare_versions(a.version(), b.version()).then_with(|| a.date.cmp(&b.date)))
+}
+
+fn compare_versions(a: Option<&str>, b: Option<&str>) -> Ordering {
+    let numbers = |version: Option<&str>| -> Vec<u64> {
+        version
+            .unwrap_or_default()
+            .split('.')
+            .filter_map(|n| n.parse().ok())
+            .collect()
+    };
+    numbers(a).cmp(&numbers(b))
+}
+
+/// Whether `name` matches `pattern`, where `*` matches anything. An empty pattern matches
+/// everything.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    if pattern.is_empty() {
+        return true;
+    }
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match name.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<_> = parts.collect();
+    let (last, middle) = match parts.split_last() {
+        Some(split) => split,
+        // No `*` at all
+        None => return rest.is_empty(),
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use tempdir::TempDir;
+
+    fn feed(group: &str, name: &str, iso_url: Option<&str>) -> FeedsItem {
+        FeedsItem {
+            group: group.to_string(),
+            name: format!("{name}.torrent"),
+            iso_url: iso_url.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn patterns_and_sources() {
+        assert!(glob_match("", "ubuntu-22.10-desktop-amd64.iso"));
+        assert!(glob_match(
+            "*desktop-amd64.iso",
+            "ubuntu-22.10-desktop-amd64.iso"
+        ));
+        assert!(glob_match(
+            "ubuntu-*-desktop-*.iso",
+            "ubuntu-22.10-desktop-amd64.iso"
+        ));
+        assert!(!glob_match("*server*", "ubuntu-22.10-desktop-amd64.iso"));
+        assert!(!glob_match("ubuntu.iso", "ubuntu.iso.zsync"));
+
+        let source: ImageSource = "ubuntu: *desktop*".parse().unwrap();
+        assert_eq!(source.to_string(), "ubuntu: *desktop*");
+        assert_eq!(
+            "debian".parse(),
+            Ok(ImageSource::Feed {
+                group: "debian".to_string(),
+                pattern: String::new(),
+            })
+        );
+        for path in ["/srv/iso/tools.img", r"C:\ISO\tools.img", "tools.img"] {
+            assert_eq!(path.parse(), Ok(ImageSource::Local(path.into())));
+        }
+    }
+
+    #[test]
+    fn plan_and_execute() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let root = tmpdir.path().join("drive");
+        let downloads = tmpdir.path().join("downloads");
+        fs::create_dir_all(root.join("ISO")).unwrap();
+        fs::create_dir_all(root.join("ventoy")).unwrap();
+        fs::create_dir_all(&downloads).unwrap();
+        fs::write(root.join("ISO").join("old.iso"), "old").unwrap();
+        fs::write(
+            root.join("ISO").join("debian-11.6.0-amd64-netinst.iso"),
+            "debian",
+        )
+        .unwrap();
+        fs::write(downloads.join("ubuntu-22.10-desktop-amd64.iso"), "ubuntu").unwrap();
+        let local = tmpdir.path().join("tools.img");
+        fs::write(&local, "tools").unwrap();
+        let drive = VentoyDrive::new(&root);
+
+        let feeds = [
+            feed("ubuntu", "ubuntu-22.04.1-desktop-amd64.iso", None),
+            feed("ubuntu", "ubuntu-22.10-desktop-amd64.iso", None),
+            feed("ubuntu", "ubuntu-22.10-live-server-amd64.iso", None),
+            feed("debian", "debian-11.6.0-amd64-netinst.iso", None),
+            feed(
+                "fedora",
+                "Fedora-Workstation-Live-x86_64-37.iso",
+                Some("http://x/f.iso"),
+            ),
+        ];
+        let profile = Profile {
+            name: "lab".to_string(),
+            secure_boot: true,
+            image_dir: "ISO".to_string(),
+            images: vec![
+                ImageSource::Feed {
+                    group: "ubuntu".to_string(),
+                    pattern: "*desktop*".to_string(),
+                },
+                ImageSource::Feed {
+                    group: "debian".to_string(),
+                    pattern: String::new(),
+                },
+                ImageSource::Feed {
+                    group: "fedora".to_string(),
+                    pattern: "*Workstation*".to_string(),
+                },
+                ImageSource::Feed {
+                    group: "arch".to_string(),
+                    pattern: "archlinux-*".to_string(),
+                },
+                ImageSource::Local(local.clone()),
+            ],
+            prune: true,
+            plugins: BTreeMap::from([(
+                "control".to_string(),
+                json!([{ "VTOY_DEFAULT_MENU_MODE": "1" }]),
+            )]),
+            ..Default::default()
+        };
+        let mirrors = BTreeMap::new();
+        let ctx = PlanContext {
+            feeds: &feeds,
+            mirrors: &mirrors,
+            download_dir: &downloads,
+            latest_version: Some("1.0.86"),
+            installed_version: Some("1.0.80"),
+        };
+        let plan = plan(&profile, &drive, &ctx).unwrap();
+        let iso = |name: &str| root.join("ISO").join(name);
+        assert_eq!(
+            plan.steps[0].to_string(),
+            "Update Ventoy 1.0.80 to 1.0.86 with `Ventoy2Disk.sh -u -s`"
+        );
+        assert_eq!(
+            plan.steps[1],
+            Step::Copy {
+                from: downloads.join("ubuntu-22.10-desktop-amd64.iso"),
+                to: iso("ubuntu-22.10-desktop-amd64.iso"),
+            }
+        );
+        assert!(matches!(
+            &plan.steps[2],
+            Step::Download { source, .. } if source.url == "http://x/f.iso"
+        ));
+        assert_eq!(
+            plan.steps[3],
+            Step::Copy {
+                from: local.clone(),
+                to: iso("tools.img"),
+            }
+        );
+        assert_eq!(plan.steps[4], Step::Delete(iso("old.iso")));
+        assert!(
+            matches!(&plan.steps[5], Step::Configure { plugins, theme: None } if plugins.contains_key("control"))
+        );
+        assert_eq!(plan.steps.len(), 6);
+        assert_eq!(plan.warnings.len(), 1);
+
+        let mut plan = plan;
+        // Nothing to download from
+        plan.steps.remove(2);
+        let manual = execute(
+            &plan,
+            &drive,
+            &DownloadManager::default(),
+            None,
+            &CancelToken::new(),
+            |_, _| (),
+        )
+        .unwrap();
+        assert_eq!(manual.len(), 1);
+        assert_eq!(
+            fs::read_to_string(iso("ubuntu-22.10-desktop-amd64.iso")).unwrap(),
+            "ubuntu"
+        );
+        assert_eq!(fs::read_to_string(iso("tools.img")).unwrap(), "tools");
+        assert!(!iso("old.iso").exists());
+
+        let ctx = PlanContext {
+            installed_version: Some("1.0.86"),
+            ..ctx
+        };
+        let profile = Profile {
+            images: profile
+                .images
+                .into_iter()
+                .filter(
+                    |image| !matches!(image, ImageSource::Feed { group, .. } if group == "fedora"),
+                )
+                .collect(),
+            ..profile
+        };
+        let plan = super::plan(&profile, &drive, &ctx).unwrap();
+        assert_eq!(plan.steps, Vec::new());
+
+        // Still an update when the version can't be read, never an install
+        let ctx = PlanContext {
+            installed_version: None,
+            ..ctx
+        };
+        let plan = super::plan(&profile, &drive, &ctx).unwrap();
+        assert_eq!(
+            plan.steps[0].to_string(),
+            "Update Ventoy to 1.0.86 with `Ventoy2Disk.sh -u -s`"
+        );
+        assert_eq!(plan.steps.len(), 1);
+        assert!(plan.warnings[0].contains("VTOYEFI"));
+    }
+
+    #[test]
+    fn prune_keeps_unresolved_images() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let root = tmpdir.path().join("drive");
+        fs::create_dir_all(&root).unwrap();
+        for name in [
+            "debian-11.6.0-amd64-netinst.iso",
+            "archlinux-2023.01.01-x86_64.iso",
+            "old.img",
+        ] {
+            fs::write(root.join(name), name).unwrap();
+        }
+        let drive = VentoyDrive::new(&root);
+        let mut profile = Profile {
+            images: vec![
+                "debian".parse().unwrap(),
+                "arch: archlinux-*".parse().unwrap(),
+            ],
+            prune: true,
+            ..Default::default()
+        };
+        let mirrors = BTreeMap::new();
+        // The feeds aren't loaded yet
+        let ctx = PlanContext {
+            feeds: &[],
+            mirrors: &mirrors,
+            download_dir: tmpdir.path(),
+            latest_version: Some("1.0.86"),
+            installed_version: Some("1.0.86"),
+        };
+        let planned = plan(&profile, &drive, &ctx).unwrap();
+        assert_eq!(planned.steps, Vec::new());
+        assert_eq!(planned.warnings.len(), 2);
+
+        profile.images.remove(0);
+        let planned = plan(&profile, &drive, &ctx).unwrap();
+        assert_eq!(
+            planned.steps,
+            vec![
+                Step::Delete(root.join("debian-11.6.0-amd64-netinst.iso")),
+                Step::Delete(root.join("old.img")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! Secure boot support of a Ventoy drive, as seen from its `VTOYEFI` partition.
+//!
+//! Ventoy boots through a shim when installed with secure boot support, and its key
+//! has to be enrolled with MokManager on the first boot of every machine.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+
+/// Label of the small FAT partition Ventoy keeps its bootloaders on.
+pub const EFI_PARTITION_LABEL: &str = "VTOYEFI";
+/// Key shipped at the root of the `VTOYEFI` partition.
+pub const ENROLL_KEY_FILE: &str = "ENROLL_THIS_KEY_IN_MOKMANAGER.cer";
+
+/// What to do on the blue MokManager screen on the first boot.
+pub const ENROLL_STEPS: &[&str] = &[
+    "Boot from the drive, a \"Verification failed: (0x1A) Security Violation\" screen shows up. Press OK.",
+    "In \"Shim UEFI key management\" press any key and choose \"Enroll key from disk\".",
+    "Select the VTOYEFI volume, then the ENROLL_THIS_KEY_IN_MOKMANAGER.cer file.",
+    "Choose \"Continue\" and \"Yes\" to enroll the key, then \"Reboot\".",
+    "Boot from the drive again, Ventoy now starts with secure boot enabled.",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecureBootInfo {
+    /// Whether Ventoy was installed with the secure boot support option
+    pub enabled: bool,
+    pub key: Option<PathBuf>,
+    pub shim: Option<PathBuf>,
+    pub mok_manager: Option<PathBuf>,
+}
+
+/// Looks up `name` in `dir` ignoring case, as FAT file names aren't case sensitive.
+fn find_entry(dir: &Path, name: &str) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .and_then(|file_name| file_name.to_str())
+                .is_some_and(|file_name| file_name.eq_ignore_ascii_case(name))
+        })
+}
+
+/// Inspects the mount point of a `VTOYEFI` partition.
+pub fn inspect<P: AsRef<Path>>(efi_root: P) -> io::Result<SecureBootInfo> {
+    let efi_root = efi_root.as_ref();
+    let boot_dir = find_entry(efi_root, "EFI")
+        .and_then(|efi_dir| find_entry(&efi_dir, "BOOT"))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "{efi_root:?} is not a {EFI_PARTITION_LABEL} partition, EFI/BOOT is missing"
+                ),
+            )
+        })?;
+
+    let key = find_entry(efi_root, ENROLL_KEY_FILE);
+    let mok_manager =
+        find_entry(&boot_dir, "MokManager.efi").or_else(|| find_entry(&boot_dir, "mmx64.efi"));
+    // Without secure boot the real grub replaces the shim as BOOTX64.EFI
+    let real_grub = find_entry(&boot_dir, "grubx64_real.efi");
+    let shim = real_grub
+        .is_some()
+        .then(|| find_entry(&boot_dir, "BOOTX64.EFI"))
+        .flatten();
+    Ok(SecureBootInfo {
+        enabled: key.is_some() || (shim.is_some() && mok_manager.is_some()),
+        key,
+        shim,
+        mok_manager,
+    })
+}
+
+/// Guesses where the `VTOYEFI` partition is mounted, next to the Ventoy partition
+/// as desktop automounters do, e.g. `/media/user/Ventoy` and `/media/user/VTOYEFI`.
+pub fn guess_efi_partition<P: AsRef<Path>>(drive_root: P) -> Option<PathBuf> {
+    let parent = drive_root.as_ref().parent()?;
+    find_entry(parent, EFI_PARTITION_LABEL).filter(|path| path.is_dir())
+}
+
+/// Copies the enrollment key into `dest_dir`, e.g. to enroll it with
+/// `mokutil --import` or through the firmware setup instead.
+pub fn export_key<P: AsRef<Path>>(info: &SecureBootInfo, dest_dir: P) -> anyhow::Result<PathBuf> {
+    let Some(key) = &info.key else {
+        bail!(
+            "the drive has no {}, was Ventoy installed with secure boot support?",
+            ENROLL_KEY_FILE
+        );
+    };
+    let dest_dir = dest_dir.as_ref();
+    fs::create_dir_all(dest_dir)?;
+    let dest = dest_dir.join(ENROLL_KEY_FILE);
+    fs::copy(key, &dest).with_context(|| format!("failed to copy {key:?} to {dest:?}"))?;
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn efi_partition(root: &Path, files: &[&str]) {
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file.as_bytes()).unwrap();
+        }
+    }
+
+    #[test]
+    fn detect_secure_boot() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let efi_root = tmpdir.path().join("VTOYEFI");
+        efi_partition(
+            &efi_root,
+            &[
+                "EFI/BOOT/BOOTX64.EFI",
+                "EFI/BOOT/grubx64.efi",
+                "EFI/BOOT/grubx64_real.efi",
+                "EFI/BOOT/MokManager.efi",
+                ENROLL_KEY_FILE,
+            ],
+        );
+        let info = inspect(&efi_root).unwrap();
+        assert!(info.enabled);
+        assert_eq!(info.key, Some(efi_root.join(ENROLL_KEY_FILE)));
+        assert_eq!(info.shim, Some(efi_root.join("EFI/BOOT/BOOTX64.EFI")));
+
+        let dest = export_key(&info, tmpdir.path().join("export")).unwrap();
+        assert_eq!(fs::read(dest).unwrap(), ENROLL_KEY_FILE.as_bytes());
+        assert_eq!(
+            guess_efi_partition(tmpdir.path().join("Ventoy")),
+            Some(efi_root)
+        );
+    }
+
+    #[test]
+    fn detect_without_secure_boot() {
+        let tmpdir = TempDir::new("test").unwrap();
+        efi_partition(
+            tmpdir.path(),
+            &["efi/boot/bootx64.efi", "efi/boot/grubia32.efi"],
+        );
+        let info = inspect(tmpdir.path()).unwrap();
+        assert!(!info.enabled);
+        assert!(export_key(&info, tmpdir.path()).is_err());
+
+        assert!(inspect(tmpdir.path().join("efi")).is_err());
+    }
+}
@@ -0,0 +1,686 @@
+//! Mirroring a directory of images, e.g. on a NAS, onto a Ventoy drive.
+//!
+//! [`compare`] lists what a sync would do, which doubles as its dry run, and [`apply`]
+//! does it. FAT32 and exFAT store modification times coarsely, and sometimes in local
+//! time, so images of the same size whose times still differ are hashed before being
+//! copied again, see [`Filesystem::same_mtime`].
+
+use std::{
+    fmt, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    drive::{self, Mount, VentoyDrive},
+    error::{Error, Result},
+    integrity::{HashAlgo, VerifyCache},
+    task::CancelToken,
+    update,
+};
+
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Filesystem {
+    Fat32,
+    Exfat,
+    Ntfs,
+    #[default]
+    Other,
+}
+
+impl Filesystem {
+    /// From the type of a mount, e.g. `vfat`.
+    pub fn from_fstype(fstype: &str) -> Self {
+        match fstype.to_ascii_lowercase().as_str() {
+            "vfat" | "msdos" | "fat" | "fat32" => Self::Fat32,
+            "exfat" => Self::Exfat,
+            "ntfs" | "ntfs3" => Self::Ntfs,
+            _ => Self::Other,
+        }
+    }
+
+    /// Largest file it can store, `None` if there's no limit worth checking.
+    pub fn max_file_size(self) -> Option<u64> {
+        match self {
+            Self::Fat32 => Some(u32::MAX as u64),
+            _ => None,
+        }
+    }
+
+    /// Whether two modification times are the same as far as it can tell.
+    ///
+    /// FAT32 keeps them to 2 seconds and exFAT to 10 milliseconds. FAT32 also keeps them
+    /// in local time, as do some exFAT drivers, which shifts them by the UTC offset of
+    /// whichever machine wrote them. That's not guessed here, [`compare`] hashes the
+    /// images instead.
+    pub fn same_mtime(self, a: SystemTime, b: SystemTime) -> bool {
+        let diff = a.duration_since(b).unwrap_or_else(|err| err.duration());
+        let precision = match self {
+            Self::Fat32 => Duration::from_secs(2),
+            Self::Exfat => Duration::from_millis(10),
+            // Sources like NFS or SMB mounts don't all keep sub-second times
+            Self::Ntfs | Self::Other => Duration::from_secs(1),
+        };
+        diff <= precision
+    }
+}
+
+impl fmt::Display for Filesystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Fat32 => "FAT32",
+            Self::Exfat => "exFAT",
+            Self::Ntfs => "NTFS",
+            Self::Other => "unknown filesystem",
+        })
+    }
+}
+
+/// Filesystem the directory `path` is on, from `/proc/self/mounts`. Always
+/// [`Filesystem::Other`] where that's not available.
+#[cfg(not(windows))]
+pub fn detect_filesystem<P: AsRef<Path>>(path: P) -> Filesystem {
+    let mounts = drive::read_mounts("/proc/self/mounts").unwrap_or_default();
+    detect_filesystem_in(path, &mounts)
+}
+
+/// Filesystem the directory `path` is on, from the information of its volume.
+#[cfg(windows)]
+pub fn detect_filesystem<P: AsRef<Path>>(path: P) -> Filesystem {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::{
+        core::PCWSTR,
+        Win32::Storage::FileSystem::{GetVolumeInformationW, GetVolumePathNameW},
+    };
+
+    let wide: Vec<u16> = path
+        .as_ref()
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    // `D:\` for `D:\ISO`, or the mount point of a volume mounted into a directory
+    let mut volume = [0u16; 261];
+    let mut fstype = [0u16; 261];
+    let found = unsafe {
+        GetVolumePathNameW(PCWSTR::from_raw(wide.as_ptr()), &mut volume).as_bool()
+            && GetVolumeInformationW(
+                PCWSTR::from_raw(volume.as_ptr()),
+                None,
+                None,
+                None,
+                None,
+                Some(&mut fstype),
+            )
+            .as_bool()
+    };
+    if !found {
+        return Filesystem::Other;
+    }
+    let len = fstype.iter().position(|c| *c == 0).unwrap_or(fstype.len());
+    Filesystem::from_fstype(&String::from_utf16_lossy(&fstype[..len]))
+}
+
+pub fn detect_filesystem_in<P: AsRef<Path>>(path: P, mounts: &[Mount]) -> Filesystem {
+    let path = fs::canonicalize(path.as_ref()).unwrap_or_else(|_| path.as_ref().to_path_buf());
+    mounts
+        .iter()
+        .filter(|mount| path.starts_with(&mount.mount_point))
+        .max_by_key(|mount| mount.mount_point.len())
+        .map_or(Filesystem::Other, |mount| {
+            Filesystem::from_fstype(&mount.fstype)
+        })
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompareBy {
+    /// Hash only images of the same size but another modification time
+    #[default]
+    SizeAndTime,
+    /// Hash all images of the same size every time, catching changes that kept the
+    /// modification time
+    Hash,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncOptions {
+    /// Directory on the drive mirroring the source, the root if empty
+    pub dest_dir: String,
+    pub compare: CompareBy,
+    /// Delete the images in `dest_dir` that aren't in the source
+    pub delete_extraneous: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyReason {
+    New,
+    Size,
+    Modified,
+    Content,
+}
+
+impl fmt::Display for CopyReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::New => "new",
+            Self::Size => "size changed",
+            Self::Modified => "modified",
+            Self::Content => "content changed",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncAction {
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+        size: u64,
+        reason: CopyReason,
+    },
+    Delete(PathBuf),
+    /// Too large for the filesystem of the drive, left out
+    TooLarge {
+        path: PathBuf,
+        size: u64,
+        filesystem: Filesystem,
+    },
+}
+
+impl fmt::Display for SyncAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Copy {
+                from, size, reason, ..
+            } => write!(
+                f,
+                "Copy {} ({}, {})",
+                from.display(),
+                drive::human_size(*size),
+                reason
+            ),
+            Self::Delete(path) => write!(f, "Delete {}", path.display()),
+            Self::TooLarge {
+                path,
+                size,
+                filesystem,
+            } => write!(
+                f,
+                "Skip {}, {} is too large for {}",
+                path.display(),
+                drive::human_size(*size),
+                filesystem
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    pub filesystem: Filesystem,
+    pub actions: Vec<SyncAction>,
+    /// Images already the same on the drive
+    pub unchanged: usize,
+}
+
+impl SyncReport {
+    pub fn bytes_to_copy(&self) -> u64 {
+        self.actions
+            .iter()
+            .map(|action| match action {
+                SyncAction::Copy { size, .. } => *size,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Whether the drive is already in sync, apart from images too large for it.
+    pub fn is_synced(&self) -> bool {
+        self.actions
+            .iter()
+            .all(|action| matches!(action, SyncAction::TooLarge { .. }))
+    }
+}
+
+/// Compares the images in `source` with the ones in [`SyncOptions::dest_dir`] of `drive`,
+/// without changing anything. `cache` keeps the hashes of [`CompareBy::Hash`].
+///
+/// Staging files left behind by interrupted copies are always deleted, first thing.
+pub fn compare<P: AsRef<Path>>(
+    source: P,
+    drive: &VentoyDrive,
+    options: &SyncOptions,
+    filesystem: Filesystem,
+    cache: &VerifyCache,
+    cancel: &CancelToken,
+) -> Result<SyncReport> {
+    let source = source.as_ref();
+    let dest_root = drive.root().join(&options.dest_dir);
+    let mut report = SyncReport {
+        filesystem,
+        ..Default::default()
+    };
+    if dest_root.is_dir() {
+        let leftovers =
+            find_files(&dest_root, update::is_staging).map_err(|e| Error::io(&dest_root, e))?;
+        report
+            .actions
+            .extend(leftovers.into_iter().map(SyncAction::Delete));
+    }
+    let mut expected = Vec::new();
+    let images =
+        find_files(source, |path| drive::is_image(path)).map_err(|e| Error::io(source, e))?;
+    for from in images {
+        cancel.check()?;
+        let relative = from.strip_prefix(source).unwrap_or(&from);
+        let to = dest_root.join(relative);
+        expected.push(to.clone());
+        let metadata = fs::metadata(&from).map_err(|e| Error::io(&from, e))?;
+        let size = metadata.len();
+        if filesystem.max_file_size().is_some_and(|max| size > max) {
+            report.actions.push(SyncAction::TooLarge {
+                path: from,
+                size,
+                filesystem,
+            });
+            continue;
+        }
+
+        let reason = match fs::metadata(&to) {
+            Err(_) => Some(CopyReason::New),
+            Ok(dest) if dest.len() != size => Some(CopyReason::Size),
+            Ok(dest) => {
+                let hash = |path: &Path| cache.hash(path, HashAlgo::Sha256, cancel, |_, _| ());
+                match options.compare {
+                    CompareBy::SizeAndTime => {
+                        let modified = |metadata: &fs::Metadata, path: &Path| {
+                            metadata.modified().map_err(|e| Error::io(path, e))
+                        };
+                        let same_mtime = filesystem
+                            .same_mtime(modified(&metadata, &from)?, modified(&dest, &to)?);
+                        // Possibly only shifted by a time zone, see `same_mtime`
+                        (!same_mtime && hash(&from)? != hash(&to)?).then_some(CopyReason::Modified)
+                    }
+                    // The cache can't tell, it only knows files by their size and time
+                    CompareBy::Hash => {
+                        let rehash =
+                            |path: &Path| cache.rehash(path, HashAlgo::Sha256, cancel, |_, _| ());
+                        (rehash(&from)? != rehash(&to)?).then_some(CopyReason::Content)
+                    }
+                }
+            }
+        };
+        match reason {
+            Some(reason) => report.actions.push(SyncAction::Copy {
+                from,
+                to,
+                size,
+                reason,
+            }),
+            None => report.unchanged += 1,
+        }
+    }
+
+    if options.delete_extraneous {
+        let images = drive.images().map_err(|e| Error::io(drive.root(), e))?;
+        report.actions.extend(
+            images
+                .into_iter()
+                .filter(|image| image.starts_with(&dest_root) && !expected.contains(image))
+                .map(SyncAction::Delete),
+        );
+    }
+    Ok(report)
+}
+
+/// Carries out the actions of `report`, calling `progress` with the action being done,
+/// the bytes copied so far and the bytes to copy in total.
+pub fn apply<F>(report: &SyncReport, cancel: &CancelToken, mut progress: F) -> Result<()>
+where
+    F: FnMut(&SyncAction, u64, u64),
+{
+    let total = report.bytes_to_copy();
+    let mut copied = 0;
+    for action in &report.actions {
+        cancel.check()?;
+        progress(action, copied, total);
+        match action {
+            SyncAction::Copy { from, to, size, .. } => {
+                copy_file(from, to, cancel, |done| {
+                    progress(action, copied + done, total)
+                })?;
+                copied += size;
+            }
+            SyncAction::Delete(path) => fs::remove_file(path).map_err(|e| Error::io(path, e))?,
+            SyncAction::TooLarge { .. } => continue,
+        }
+        tracing::info!("{}", action);
+    }
+    Ok(())
+}
+
+/// Copies `from` to `to` through a staging file, so that a cancelled copy leaves nothing
+/// behind, and keeps its modification time. `progress` gets the bytes copied so far.
+pub(crate) fn copy_file<F>(
+    from: &Path,
+    to: &Path,
+    cancel: &CancelToken,
+    mut progress: F,
+) -> Result<()>
+where
+    F: FnMut(u64),
+{
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+    }
+    let staging = update::staging_path(to).map_err(|e| Error::io(to, e))?;
+    let copied = (|| {
+        let mut reader = fs::File::open(from).map_err(|e| Error::io(from, e))?;
+        let modified = reader
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| Error::io(from, e))?;
+        let mut writer = fs::File::create(&staging).map_err(|e| Error::io(&staging, e))?;
+        let mut buf = vec![0; CHUNK_SIZE];
+        let mut done = 0;
+        loop {
+            cancel.check()?;
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(Error::io(from, err)),
+            };
+            writer
+                .write_all(&buf[..n])
+                .map_err(|e| Error::io(&staging, e))?;
+            done += n as u64;
+            progress(done);
+        }
+        writer
+            .set_modified(modified)
+            .and_then(|_| writer.sync_all())
+            .map_err(|e| Error::io(&staging, e))?;
+        drop(writer);
+        fs::rename(&staging, to).map_err(|e| Error::io(to, e))
+    })();
+    if copied.is_err() {
+        let _ = fs::remove_file(&staging);
+    }
+    copied
+}
+
+/// Files under `dir` that `keep` picks, sorted. Symlinks to files are kept, symlinked
+/// directories aren't followed as they could loop.
+fn find_files(dir: &Path, keep: fn(&Path) -> bool) -> io::Result<Vec<PathBuf>> {
+    fn walk(dir: &Path, keep: fn(&Path) -> bool, found: &mut Vec<PathBuf>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                walk(&path, keep, found)?;
+            } else if path.is_file() && keep(&path) {
+                found.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut found = Vec::new();
+    walk(dir, keep, &mut found)?;
+    found.sort();
+    Ok(found)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn set_mtime(path: &Path, mtime: SystemTime) {
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    }
+
+    #[test]
+    fn filesystems() {
+        let now = SystemTime::now();
+        let secs = Duration::from_secs;
+        assert!(Filesystem::Fat32.same_mtime(now, now + secs(1)));
+        assert!(Filesystem::Fat32.same_mtime(now + secs(2), now));
+        assert!(!Filesystem::Fat32.same_mtime(now, now + secs(3)));
+        // Left to hashing, a real change can be a multiple of 15 minutes too
+        assert!(!Filesystem::Fat32.same_mtime(now + secs(3600), now));
+        assert!(Filesystem::Exfat.same_mtime(now, now + Duration::from_millis(10)));
+        assert!(!Filesystem::Exfat.same_mtime(now, now + secs(1)));
+        assert!(Filesystem::Other.same_mtime(now, now + secs(1)));
+
+        let mount = |mount_point: &str, fstype: &str| Mount {
+            source: String::new(),
+            mount_point: mount_point.to_string(),
+            fstype: fstype.to_string(),
+        };
+        let mounts = [
+            mount("/", "ext4"),
+            mount("/media/user/Ventoy", "exfat"),
+            mount("/media/user/VTOYEFI", "vfat"),
+        ];
+        assert_eq!(
+            detect_filesystem_in("/media/user/Ventoy/ISO", &mounts),
+            Filesystem::Exfat
+        );
+        assert_eq!(
+            detect_filesystem_in("/media/user/VTOYEFI", &mounts),
+            Filesystem::Fat32
+        );
+        assert_eq!(detect_filesystem_in("/home", &mounts), Filesystem::Other);
+        // As Windows names them
+        assert_eq!(Filesystem::from_fstype("FAT32"), Filesystem::Fat32);
+        assert_eq!(Filesystem::from_fstype("exFAT"), Filesystem::Exfat);
+        assert_eq!(Filesystem::from_fstype("NTFS"), Filesystem::Ntfs);
+    }
+
+    #[test]
+    fn compare_and_apply() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let source = tmpdir.path().join("nas");
+        let root = tmpdir.path().join("drive");
+        fs::create_dir_all(source.join("linux")).unwrap();
+        fs::create_dir_all(root.join("ISO").join("linux")).unwrap();
+        fs::create_dir_all(root.join("ventoy")).unwrap();
+        fs::write(source.join("tools.img"), "tools").unwrap();
+        fs::write(source.join("linux").join("debian.iso"), "debian 11.6").unwrap();
+        fs::write(source.join("notes.txt"), "not an image").unwrap();
+        fs::write(
+            root.join("ISO").join("linux").join("debian.iso"),
+            "debian 11",
+        )
+        .unwrap();
+        fs::write(root.join("ISO").join("old.iso"), "old").unwrap();
+        // Left behind by an interrupted copy
+        fs::write(root.join("ISO").join(".staging-new.iso"), "ne").unwrap();
+        fs::write(root.join("other.iso"), "outside of ISO").unwrap();
+        let drive = VentoyDrive::new(&root);
+        let cache = VerifyCache::default();
+        let cancel = CancelToken::new();
+        let mut options = SyncOptions {
+            dest_dir: "ISO".to_string(),
+            delete_extraneous: true,
+            ..Default::default()
+        };
+
+        let report = compare(
+            &source,
+            &drive,
+            &options,
+            Filesystem::Exfat,
+            &cache,
+            &cancel,
+        )
+        .unwrap();
+        let iso = |name: &str| root.join("ISO").join(name);
+        assert_eq!(
+            report.actions,
+            vec![
+                SyncAction::Delete(iso(".staging-new.iso")),
+                SyncAction::Copy {
+                    from: source.join("linux").join("debian.iso"),
+                    to: iso("linux/debian.iso"),
+                    size: 11,
+                    reason: CopyReason::Size,
+                },
+                SyncAction::Copy {
+                    from: source.join("tools.img"),
+                    to: iso("tools.img"),
+                    size: 5,
+                    reason: CopyReason::New,
+                },
+                SyncAction::Delete(iso("old.iso")),
+            ]
+        );
+        assert_eq!(report.bytes_to_copy(), 16);
+        // A dry run changes nothing
+        assert!(iso("old.iso").exists());
+
+        let mut copied = 0;
+        apply(&report, &cancel, |_, done, _| copied = done).unwrap();
+        assert_eq!(copied, 16);
+        assert_eq!(fs::read_to_string(iso("tools.img")).unwrap(), "tools");
+        assert!(!iso("old.iso").exists());
+        assert!(!iso(".staging-new.iso").exists());
+        assert!(root.join("other.iso").exists());
+        let report = compare(
+            &source,
+            &drive,
+            &options,
+            Filesystem::Exfat,
+            &cache,
+            &cancel,
+        )
+        .unwrap();
+        assert!(report.is_synced());
+        assert_eq!(report.unchanged, 2);
+        // Hashed and cached while still the same
+        options.compare = CompareBy::Hash;
+        let report = compare(
+            &source,
+            &drive,
+            &options,
+            Filesystem::Exfat,
+            &cache,
+            &cancel,
+        )
+        .unwrap();
+        assert!(report.is_synced());
+        options.compare = CompareBy::SizeAndTime;
+
+        // Same size and time, different content
+        let mtime = fs::metadata(iso("tools.img")).unwrap().modified().unwrap();
+        fs::write(iso("tools.img"), "TOOLS").unwrap();
+        set_mtime(&iso("tools.img"), mtime);
+        let report = compare(
+            &source,
+            &drive,
+            &options,
+            Filesystem::Exfat,
+            &cache,
+            &cancel,
+        )
+        .unwrap();
+        assert!(report.is_synced());
+        options.compare = CompareBy::Hash;
+        let report = compare(
+            &source,
+            &drive,
+            &options,
+            Filesystem::Exfat,
+            &cache,
+            &cancel,
+        )
+        .unwrap();
+        assert!(matches!(
+            report.actions[..],
+            [SyncAction::Copy {
+                reason: CopyReason::Content,
+                ..
+            }]
+        ));
+
+        options.compare = CompareBy::SizeAndTime;
+        set_mtime(&iso("tools.img"), mtime + Duration::from_secs(60));
+        let report = compare(
+            &source,
+            &drive,
+            &options,
+            Filesystem::Exfat,
+            &cache,
+            &cancel,
+        )
+        .unwrap();
+        assert!(matches!(
+            report.actions[..],
+            [SyncAction::Copy {
+                reason: CopyReason::Modified,
+                ..
+            }]
+        ));
+        // Only an hour off, as when written in local time
+        fs::write(iso("tools.img"), "tools").unwrap();
+        set_mtime(&iso("tools.img"), mtime + Duration::from_secs(3600));
+        let report = compare(
+            &source,
+            &drive,
+            &options,
+            Filesystem::Fat32,
+            &cache,
+            &cancel,
+        )
+        .unwrap();
+        assert!(report.is_synced());
+
+        // Symlinked directories aren't followed
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&source, source.join("linux").join("loop")).unwrap();
+            let report = compare(
+                &source,
+                &drive,
+                &options,
+                Filesystem::Exfat,
+                &cache,
+                &cancel,
+            )
+            .unwrap();
+            assert_eq!(report.unchanged, 2);
+            fs::remove_file(source.join("linux").join("loop")).unwrap();
+        }
+
+        // Sparse, doesn't take up the space
+        let large = fs::File::create(source.join("windows.iso")).unwrap();
+        large.set_len(u32::MAX as u64 + 1).unwrap();
+        let report = compare(
+            &source,
+            &drive,
+            &options,
+            Filesystem::Fat32,
+            &cache,
+            &cancel,
+        )
+        .unwrap();
+        assert!(report.actions.contains(&SyncAction::TooLarge {
+            path: source.join("windows.iso"),
+            size: u32::MAX as u64 + 1,
+            filesystem: Filesystem::Fat32,
+        }));
+    }
+}
@@ -0,0 +1,216 @@
+//! Background jobs that can be cancelled, and the list of the ones running.
+//!
+//! Downloads and extractions take a [`CancelToken`] and check it between chunks of work,
+//! stopping with [`Error::Cancelled`] after removing what they partially wrote.
+
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use super::error::{Error, Result};
+
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fails with [`Error::Cancelled`] once cancelled, to bail out with `?`.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+/// A reader failing every read once `token` is cancelled, stopping whatever reads from it.
+pub struct Cancellable<R> {
+    inner: R,
+    token: CancelToken,
+}
+
+impl<R> Cancellable<R> {
+    pub fn new(inner: R, token: CancelToken) -> Self {
+        Self { inner, token }
+    }
+}
+
+impl<R: Read> Read for Cancellable<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Not `Interrupted`, readers retry on that
+        if self.token.is_cancelled() {
+            return Err(io::Error::other("cancelled"));
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Seek> Seek for Cancellable<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+pub type TaskId = u64;
+
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub id: TaskId,
+    /// What it's doing, e.g. "Downloading ventoy-1.0.86-linux.tar.gz"
+    pub name: String,
+    pub started: Instant,
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Default)]
+struct Tasks {
+    next_id: TaskId,
+    running: Vec<(TaskInfo, CancelToken)>,
+}
+
+/// The running tasks, shared by the threads running them.
+#[derive(Debug, Clone, Default)]
+pub struct TaskManager {
+    tasks: Arc<Mutex<Tasks>>,
+}
+
+impl TaskManager {
+    /// Lists a new task until the returned [`Task`] is dropped, move it into whatever
+    /// runs the task.
+    pub fn start<S: Into<String>>(&self, name: S) -> Task {
+        let mut tasks = self.tasks.lock().unwrap();
+        let id = tasks.next_id;
+        tasks.next_id += 1;
+        let token = CancelToken::new();
+        let info = TaskInfo {
+            id,
+            name: name.into(),
+            started: Instant::now(),
+            cancelled: false,
+        };
+        tracing::debug!("Started task {}: {}", id, info.name);
+        tasks.running.push((info, token.clone()));
+        Task {
+            id,
+            token,
+            manager: self.clone(),
+        }
+    }
+
+    /// The running tasks, oldest first.
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        let tasks = self.tasks.lock().unwrap();
+        tasks
+            .running
+            .iter()
+            .map(|(info, token)| TaskInfo {
+                cancelled: token.is_cancelled(),
+                ..info.clone()
+            })
+            .collect()
+    }
+
+    /// Returns whether the task was still running.
+    pub fn cancel(&self, id: TaskId) -> bool {
+        let tasks = self.tasks.lock().unwrap();
+        match tasks.running.iter().find(|(info, _)| info.id == id) {
+            Some((info, token)) => {
+                tracing::info!("Cancelling task {}: {}", id, info.name);
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn cancel_all(&self) {
+        for (_, token) in &self.tasks.lock().unwrap().running {
+            token.cancel();
+        }
+    }
+}
+
+/// A running task, removed from its [`TaskManager`] once dropped.
+#[derive(Debug)]
+pub struct Task {
+    id: TaskId,
+    token: CancelToken,
+    manager: TaskManager,
+}
+
+impl Task {
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    pub fn token(&self) -> &CancelToken {
+        &self.token
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        let mut tasks = self.manager.tasks.lock().unwrap();
+        tasks.running.retain(|(info, _)| info.id != self.id);
+        tracing::debug!("Finished task {}", self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn list_and_cancel_tasks() {
+        let manager = TaskManager::default();
+        let download = manager.start("Downloading ventoy-1.0.86-linux.tar.gz");
+        let feeds = manager.start("Fetching release feeds");
+        let names = |manager: &TaskManager| {
+            manager
+                .tasks()
+                .into_iter()
+                .map(|task| (task.name, task.cancelled))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(&manager),
+            [
+                ("Downloading ventoy-1.0.86-linux.tar.gz".to_string(), false),
+                ("Fetching release feeds".to_string(), false)
+            ]
+        );
+
+        assert!(manager.cancel(download.id()));
+        assert!(download.token().is_cancelled());
+        assert!(matches!(download.token().check(), Err(Error::Cancelled)));
+        assert!(!feeds.token().is_cancelled());
+        let mut reader = Cancellable::new(&b"ventoy"[..], download.token().clone());
+        assert!(reader.read(&mut [0; 6]).is_err());
+
+        let id = download.id();
+        drop(download);
+        assert!(!manager.cancel(id));
+        assert_eq!(
+            names(&manager),
+            [("Fetching release feeds".to_string(), false)]
+        );
+        manager.cancel_all();
+        assert!(feeds.token().is_cancelled());
+    }
+}
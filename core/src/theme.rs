@@ -0,0 +1,637 @@
+//! GRUB themes for Ventoy's `theme` plugin: installing them from archives,
+//! selecting them in `ventoy.json` and laying out a rough preview of `theme.txt`.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context};
+use serde_json::{json, Map, Value};
+
+use super::{drive::VentoyDrive, update, utils, ventoy_json::VentoyJson};
+
+const ARCHIVE_EXTENSIONS: &[&str] = &[".tar.gz", ".tgz", ".zip"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstalledTheme {
+    /// Name of the directory holding `theme.txt`
+    pub name: String,
+    pub theme_txt: PathBuf,
+    /// Path of `theme.txt` as referred to in `ventoy.json`
+    pub ventoy_path: String,
+    /// Parsed from the name it's installed under, like `blur_1920x1080`, or else the
+    /// directory holding `theme.txt`. Used by `resolution_fit`
+    pub resolution: Option<(u32, u32)>,
+}
+
+/// Name a theme archive is installed under, e.g. `blur_1920x1080` for
+/// `blur_1920x1080.tar.gz`. `None` if it's not a supported archive.
+pub fn theme_name(archive: &Path) -> Option<&str> {
+    let file_name = archive.file_name()?.to_str()?;
+    ARCHIVE_EXTENSIONS.iter().find_map(|ext| {
+        let stem = file_name.get(..file_name.len().checked_sub(ext.len())?)?;
+        file_name[stem.len()..]
+            .eq_ignore_ascii_case(ext)
+            .then_some(stem)
+    })
+}
+
+/// Extracts a theme archive into `/ventoy/theme/<name>` on the drive.
+pub fn install_theme<P: AsRef<Path>>(drive: &VentoyDrive, archive: P) -> anyhow::Result<PathBuf> {
+    let archive = archive.as_ref();
+    let name = theme_name(archive).ok_or_else(|| {
+        anyhow!(
+            "unsupported theme archive {:?}, expected .tar.gz or .zip",
+            archive
+        )
+    })?;
+
+    let dest_dir = drive.ventoy_dir().join("theme").join(name);
+    if dest_dir.exists() {
+        bail!("a theme named `{}` is already installed", name);
+    }
+    fs::create_dir_all(&dest_dir)?;
+    let is_zip = archive
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+    let extracted = if is_zip {
+        update::extract_zip(archive, dest_dir.as_path())
+    } else {
+        update::extract_targz(archive, dest_dir.as_path())
+    };
+    match extracted.map(|_| utils::find_file(&dest_dir, "theme.txt")) {
+        Ok(Some(theme_txt)) => Ok(theme_txt),
+        Ok(None) => {
+            fs::remove_dir_all(&dest_dir)?;
+            bail!("{:?} doesn't contain a theme.txt", archive)
+        }
+        Err(err) => {
+            fs::remove_dir_all(&dest_dir)?;
+            Err(anyhow!(err).context(format!("failed to extract {archive:?}")))
+        }
+    }
+}
+
+/// Lists every `theme.txt` under `/ventoy/theme` on the drive.
+pub fn installed_themes(drive: &VentoyDrive) -> io::Result<Vec<InstalledTheme>> {
+    fn walk(dir: &Path, found: &mut Vec<PathBuf>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                walk(&path, found)?;
+            } else if path.file_name().is_some_and(|name| name == "theme.txt") {
+                found.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let theme_dir = drive.ventoy_dir().join("theme");
+    let mut found = Vec::new();
+    if theme_dir.is_dir() {
+        walk(&theme_dir, &mut found)?;
+    }
+    found.sort();
+    Ok(found
+        .into_iter()
+        .filter_map(|theme_txt| {
+            let name = theme_txt.parent()?.file_name()?.to_str()?.to_string();
+            let install_dir = theme_txt.strip_prefix(&theme_dir).ok()?.iter().next()?;
+            Some(InstalledTheme {
+                ventoy_path: drive.image_path(&theme_txt)?,
+                resolution: install_dir
+                    .to_str()
+                    .and_then(parse_resolution)
+                    .or_else(|| parse_resolution(&name)),
+                name,
+                theme_txt,
+            })
+        })
+        .collect())
+}
+
+fn parse_resolution(name: &str) -> Option<(u32, u32)> {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .find_map(|token| {
+            let (w, h) = token.split_once('x')?;
+            Some((w.parse().ok()?, h.parse().ok()?))
+        })
+}
+
+/// Theme related options of the `theme` plugin.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ThemeSelection {
+    /// `theme.txt` paths on the drive
+    pub files: Vec<String>,
+    /// 1-based index into `files`, `0` lets Ventoy pick one at random
+    pub default_file: usize,
+    /// Let Ventoy pick the theme closest to the screen resolution
+    pub resolution_fit: bool,
+    /// e.g. `1920x1080`
+    pub gfxmode: String,
+}
+
+impl ThemeSelection {
+    pub fn load(ventoy_json: &VentoyJson) -> Self {
+        let theme = match ventoy_json.plugin("theme") {
+            Some(Value::Object(theme)) => theme,
+            _ => return Self::default(),
+        };
+        let files = match &theme.get("file") {
+            Some(Value::String(file)) => vec![file.clone()],
+            Some(Value::Array(files)) => files
+                .iter()
+                .filter_map(|file| file.as_str().map(String::from))
+                .collect(),
+            _ => Vec::new(),
+        };
+        Self {
+            files,
+            default_file: theme
+                .get("default_file")
+                .and_then(|v| v.as_u64())
+                .unwrap_or_default() as usize,
+            resolution_fit: theme.get("resolution_fit").and_then(|v| v.as_u64()) == Some(1),
+            gfxmode: theme
+                .get("gfxmode")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+
+    /// Writes the selection into the `theme` plugin, keeping its other options.
+    pub fn apply(&self, ventoy_json: &mut VentoyJson) {
+        let mut theme = match ventoy_json.plugin("theme") {
+            Some(Value::Object(theme)) => theme.clone(),
+            _ => Map::new(),
+        };
+        for key in ["file", "default_file", "resolution_fit", "gfxmode"] {
+            theme.remove(key);
+        }
+        match self.files.as_slice() {
+            [] => (),
+            [file] => {
+                theme.insert("file".to_string(), json!(file));
+            }
+            files => {
+                theme.insert("file".to_string(), json!(files));
+                theme.insert("default_file".to_string(), json!(self.default_file));
+                if self.resolution_fit {
+                    theme.insert("resolution_fit".to_string(), json!(1));
+                }
+            }
+        }
+        if !self.gfxmode.is_empty() {
+            theme.insert("gfxmode".to_string(), json!(self.gfxmode));
+        }
+
+        if theme.is_empty() {
+            ventoy_json.remove_plugin("theme");
+        } else {
+            ventoy_json.set_plugin("theme", Value::Object(theme));
+        }
+    }
+}
+
+/// Parsed GRUB `theme.txt`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ThemeTxt {
+    /// Global properties such as `desktop-image`
+    pub properties: BTreeMap<String, String>,
+    pub components: Vec<Component>,
+}
+
+/// A `+ boot_menu { ... }` style block.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Component {
+    pub kind: String,
+    pub properties: BTreeMap<String, String>,
+    pub children: Vec<Component>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Plus,
+    Open,
+    Close,
+    Equals,
+    Colon,
+    Word(String),
+}
+
+fn tokenize(content: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = content.chars().peekable();
+    let mut line_start = true;
+    while let Some(&c) = chars.peek() {
+        match c {
+            // Only whole lines are comments, unquoted colors start with `#` too
+            '#' if line_start => while chars.next_if(|&c| c != '\n').is_some() {},
+            c if c.is_whitespace() => {
+                line_start |= c == '\n';
+                chars.next();
+                continue;
+            }
+            '{' | '}' | '=' | ':' => {
+                chars.next();
+                tokens.push(match c {
+                    '{' => Token::Open,
+                    '}' => Token::Close,
+                    '=' => Token::Equals,
+                    _ => Token::Colon,
+                });
+            }
+            '"' => {
+                chars.next();
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.extend(chars.next()),
+                        Some(c) => word.push(c),
+                        None => bail!("unterminated string in theme.txt"),
+                    }
+                }
+                tokens.push(Token::Word(word));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && !"{}=:\"".contains(c))
+                {
+                    word.push(c);
+                }
+                // `+` is only a token on its own, `50%+10` is a value
+                tokens.push(match word.as_str() {
+                    "+" => Token::Plus,
+                    _ => Token::Word(word),
+                });
+            }
+        }
+        line_start = false;
+    }
+    Ok(tokens)
+}
+
+impl ThemeTxt {
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        let mut tokens = tokenize(content)?.into_iter().peekable();
+        let mut theme = Self::default();
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::Plus => theme.components.push(parse_component(&mut tokens)?),
+                Token::Word(key) => {
+                    if tokens.next() != Some(Token::Colon) {
+                        bail!("expected `:` after `{}` in theme.txt", key);
+                    }
+                    match tokens.next() {
+                        Some(Token::Word(value)) => theme.properties.insert(key, value),
+                        _ => bail!("missing value for `{}` in theme.txt", key),
+                    };
+                }
+                token => bail!("unexpected {:?} in theme.txt", token),
+            }
+        }
+        Ok(theme)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        Self::parse(&fs::read_to_string(path)?).with_context(|| format!("failed to parse {path:?}"))
+    }
+}
+
+fn parse_component<I>(tokens: &mut std::iter::Peekable<I>) -> anyhow::Result<Component>
+where
+    I: Iterator<Item = Token>,
+{
+    let kind = match tokens.next() {
+        Some(Token::Word(kind)) => kind,
+        _ => bail!("expected component type after `+` in theme.txt"),
+    };
+    if tokens.next() != Some(Token::Open) {
+        bail!("expected `{{` after `+ {}` in theme.txt", kind);
+    }
+    let mut component = Component {
+        kind,
+        ..Default::default()
+    };
+    loop {
+        match tokens.next() {
+            Some(Token::Close) => return Ok(component),
+            Some(Token::Plus) => component.children.push(parse_component(tokens)?),
+            Some(Token::Word(key)) => {
+                if tokens.next() != Some(Token::Equals) {
+                    bail!("expected `=` after `{}` in theme.txt", key);
+                }
+                match tokens.next() {
+                    Some(Token::Word(value)) => component.properties.insert(key, value),
+                    _ => bail!("missing value for `{}` in theme.txt", key),
+                };
+            }
+            Some(token) => bail!("unexpected {:?} in `{}` component", token, component.kind),
+            None => bail!("unterminated `{}` component in theme.txt", component.kind),
+        }
+    }
+}
+
+/// Resolves GRUB's absolute/relative lengths like `120`, `30%` and `50%-100`.
+pub fn resolve_length(value: &str, total: f32) -> Option<f32> {
+    let value = value.trim();
+    let (percent, offset) = match value.find('%') {
+        Some(idx) => {
+            let percent: f32 = value[..idx].trim().parse().ok()?;
+            let offset = value[idx + 1..].replace(' ', "");
+            let offset: f32 = if offset.is_empty() {
+                0.
+            } else {
+                offset.trim_start_matches('+').parse().ok()?
+            };
+            (percent, offset)
+        }
+        None => (0., value.parse().ok()?),
+    };
+    Some(total * percent / 100. + offset)
+}
+
+/// Parses `#rrggbb`, `#rgb`, `r, g, b` and a few named colors.
+pub fn parse_color(value: &str) -> Option<[u8; 3]> {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        // Sliced by bytes below
+        if !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize, len: usize| {
+            u8::from_str_radix(&hex[i * len..(i + 1) * len], 16)
+                .ok()
+                .map(|c| if len == 1 { c * 17 } else { c })
+        };
+        return match hex.len() {
+            3 => Some([channel(0, 1)?, channel(1, 1)?, channel(2, 1)?]),
+            6 | 8 => Some([channel(0, 2)?, channel(1, 2)?, channel(2, 2)?]),
+            _ => None,
+        };
+    }
+    if value.contains(',') {
+        let rgb = value
+            .split(',')
+            .map(|c| c.trim().parse().ok())
+            .collect::<Option<Vec<u8>>>()?;
+        return (rgb.len() >= 3).then(|| [rgb[0], rgb[1], rgb[2]]);
+    }
+    match value.to_lowercase().as_str() {
+        "black" => Some([0, 0, 0]),
+        "white" => Some([255, 255, 255]),
+        "red" => Some([255, 0, 0]),
+        "green" => Some([0, 128, 0]),
+        "blue" => Some([0, 0, 255]),
+        "yellow" => Some([255, 255, 0]),
+        "cyan" => Some([0, 255, 255]),
+        "magenta" => Some([255, 0, 255]),
+        "gray" | "grey" => Some([128, 128, 128]),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThemePreview {
+    pub background: Option<PathBuf>,
+    pub background_color: [u8; 3],
+    pub elements: Vec<PreviewElement>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreviewElement {
+    pub kind: String,
+    /// `[x, y, width, height]` in pixels of the previewed resolution
+    pub rect: [f32; 4],
+    pub color: [u8; 3],
+    pub text: Option<String>,
+}
+
+impl ThemeTxt {
+    /// Lays out the components for a screen of `width` x `height` pixels.
+    pub fn preview(&self, theme_dir: &Path, width: f32, height: f32) -> ThemePreview {
+        fn layout(
+            component: &Component,
+            origin: [f32; 2],
+            size: [f32; 2],
+            elements: &mut Vec<PreviewElement>,
+        ) {
+            let prop = |key: &str| component.properties.get(key).map(|v| v.as_str());
+            let length = |key: &str, total: f32, default: f32| {
+                prop(key)
+                    .and_then(|v| resolve_length(v, total))
+                    .unwrap_or(default)
+            };
+            let rect = [
+                origin[0] + length("left", size[0], 0.),
+                origin[1] + length("top", size[1], 0.),
+                length("width", size[0], 0.),
+                length("height", size[1], 0.),
+            ];
+            let color_key = match component.kind.as_str() {
+                "boot_menu" => "item_color",
+                "progress_bar" => "fg_color",
+                _ => "color",
+            };
+            let text = match component.kind.as_str() {
+                "label" => prop("text").map(|text| {
+                    if text.starts_with("@TIMEOUT_NOTIFICATION") {
+                        "Booting in 5s".to_string()
+                    } else {
+                        text.to_string()
+                    }
+                }),
+                _ => None,
+            };
+            elements.push(PreviewElement {
+                kind: component.kind.clone(),
+                rect,
+                color: prop(color_key)
+                    .and_then(parse_color)
+                    .unwrap_or([204, 204, 204]),
+                text,
+            });
+            for child in &component.children {
+                layout(child, [rect[0], rect[1]], [rect[2], rect[3]], elements);
+            }
+        }
+
+        let mut elements = Vec::new();
+        for component in &self.components {
+            layout(component, [0., 0.], [width, height], &mut elements);
+        }
+        ThemePreview {
+            background: self
+                .properties
+                .get("desktop-image")
+                .map(|image| theme_dir.join(image))
+                .filter(|image| image.is_file()),
+            background_color: self
+                .properties
+                .get("desktop-color")
+                .and_then(|color| parse_color(color))
+                .unwrap_or([0, 0, 0]),
+            elements,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    const THEME_TXT: &str = r##"
+# Global properties
+desktop-image: "background.png"
+desktop-color: "#102030"
+title-text: ""
+
++ boot_menu {
+    left = 30%
+    top = 30%
+    width = 40%
+    height = 50%-20
+    item_color = "#ccc"
+    selected_item_color = "255, 255, 255"
+}
+
++ vbox {
+    left = 50%+10
+    top = 90%
+    + label { text = "@TIMEOUT_NOTIFICATION_LONG@" color = #fff }
+}
+"##;
+
+    #[test]
+    fn parse_theme_txt() {
+        let theme = ThemeTxt::parse(THEME_TXT).unwrap();
+        assert_eq!(theme.properties["desktop-image"], "background.png");
+        assert_eq!(theme.properties["title-text"], "");
+        assert_eq!(theme.components.len(), 2);
+        assert_eq!(theme.components[0].properties["height"], "50%-20");
+        assert_eq!(theme.components[1].children[0].kind, "label");
+        assert!(ThemeTxt::parse("+ boot_menu { left = 1").is_err());
+    }
+
+    #[test]
+    fn lengths_and_colors() {
+        assert_eq!(resolve_length("120", 1000.), Some(120.));
+        assert_eq!(resolve_length("30%", 1000.), Some(300.));
+        assert_eq!(resolve_length("50%-100", 1000.), Some(400.));
+        assert_eq!(resolve_length("50% + 10", 1000.), Some(510.));
+        assert_eq!(parse_color("#102030"), Some([16, 32, 48]));
+        assert_eq!(parse_color("#fff"), Some([255, 255, 255]));
+        assert_eq!(parse_color("0, 128, 255"), Some([0, 128, 255]));
+        assert_eq!(parse_color("nope"), None);
+        assert_eq!(parse_color("#ffé"), None);
+        assert_eq!(parse_color("#éé"), None);
+    }
+
+    #[test]
+    fn preview_layout() {
+        let tmpdir = TempDir::new("test").unwrap();
+        fs::write(tmpdir.path().join("background.png"), b"").unwrap();
+        let preview = ThemeTxt::parse(THEME_TXT)
+            .unwrap()
+            .preview(tmpdir.path(), 1000., 800.);
+        assert_eq!(
+            preview.background,
+            Some(tmpdir.path().join("background.png"))
+        );
+        assert_eq!(preview.background_color, [16, 32, 48]);
+        assert_eq!(preview.elements[0].rect, [300., 240., 400., 380.]);
+        assert_eq!(preview.elements[2].rect[..2], [510., 720.]);
+        assert_eq!(preview.elements[2].text.as_deref(), Some("Booting in 5s"));
+        assert_eq!(preview.elements[2].color, [255, 255, 255]);
+    }
+
+    #[test]
+    fn install_and_list() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let archive = tmpdir.path().join("blur_1920x1080.tar.gz");
+        {
+            let enc = flate2::write::GzEncoder::new(
+                fs::File::create(&archive).unwrap(),
+                flate2::Compression::default(),
+            );
+            let mut tar = tar::Builder::new(enc);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(THEME_TXT.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, "blur/theme.txt", THEME_TXT.as_bytes())
+                .unwrap();
+            tar.into_inner().unwrap().finish().unwrap();
+        }
+
+        let drive = VentoyDrive::new(tmpdir.path().join("drive"));
+        let theme_txt = install_theme(&drive, &archive).unwrap();
+        assert!(theme_txt.ends_with("ventoy/theme/blur_1920x1080/blur/theme.txt"));
+        assert!(install_theme(&drive, &archive).is_err());
+
+        let themes = installed_themes(&drive).unwrap();
+        assert_eq!(themes.len(), 1);
+        assert_eq!(themes[0].name, "blur");
+        assert_eq!(
+            themes[0].ventoy_path,
+            "/ventoy/theme/blur_1920x1080/blur/theme.txt"
+        );
+        // From the name it's installed under, not the `blur` directory
+        assert_eq!(themes[0].resolution, Some((1920, 1080)));
+    }
+
+    #[test]
+    fn theme_names() {
+        assert_eq!(
+            theme_name(Path::new("blur_1920x1080.tar.gz")),
+            Some("blur_1920x1080")
+        );
+        assert_eq!(theme_name(Path::new("Thème.ZIP")), Some("Thème"));
+        assert_eq!(theme_name(Path::new("é.tgz")), Some("é"));
+        // The Kelvin sign is 3 bytes but lowercases to a 1 byte `k`
+        assert_eq!(
+            theme_name(Path::new("\u{212a}\u{212a}.zip")),
+            Some("\u{212a}\u{212a}")
+        );
+        assert_eq!(theme_name(Path::new("é")), None);
+        assert_eq!(theme_name(Path::new("theme.txt")), None);
+    }
+
+    #[test]
+    fn apply_selection() {
+        let mut ventoy_json = VentoyJson::default();
+        ventoy_json.set_plugin(
+            "theme",
+            json!({ "file": "/a/theme.txt", "display_mode": "GUI" }),
+        );
+        let mut selection = ThemeSelection::load(&ventoy_json);
+        assert_eq!(selection.files, vec!["/a/theme.txt"]);
+
+        selection.files.push("/b/theme.txt".to_string());
+        selection.default_file = 2;
+        selection.resolution_fit = true;
+        selection.apply(&mut ventoy_json);
+        assert_eq!(
+            ventoy_json.plugin("theme"),
+            Some(&json!({
+                "display_mode": "GUI",
+                "file": ["/a/theme.txt", "/b/theme.txt"],
+                "default_file": 2,
+                "resolution_fit": 1
+            }))
+        );
+        assert_eq!(ThemeSelection::load(&ventoy_json), selection);
+
+        ThemeSelection::default().apply(&mut ventoy_json);
+        assert_eq!(
+            ventoy_json.plugin("theme"),
+            Some(&json!({ "display_mode": "GUI" }))
+        );
+    }
+}
@@ -0,0 +1,676 @@
+//! Backends for handing feed items straight to a running torrent client
+//! over its RPC/Web API, instead of relying on the OS magnet link handler.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TorrentClientKind {
+    #[default]
+    Transmission,
+    QBittorrent,
+    Deluge,
+}
+
+impl TorrentClientKind {
+    pub const ALL: [Self; 3] = [Self::Transmission, Self::QBittorrent, Self::Deluge];
+
+    pub const fn default_url(&self) -> &'static str {
+        match self {
+            Self::Transmission => "http://localhost:9091",
+            Self::QBittorrent => "http://localhost:8080",
+            Self::Deluge => "http://localhost:8112",
+        }
+    }
+}
+
+impl fmt::Display for TorrentClientKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Transmission => "Transmission",
+            Self::QBittorrent => "qBittorrent",
+            Self::Deluge => "Deluge",
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TorrentClientConfig {
+    pub kind: TorrentClientKind,
+    pub url: String,
+    pub username: String,
+    /// Only kept for the session, configs of older versions may still have it
+    #[serde(skip_serializing)]
+    pub password: String,
+    pub save_path: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TorrentStatus {
+    pub name: String,
+    /// Ranges from `0.0` to `1.0`.
+    pub progress: f32,
+    pub state: String,
+}
+
+pub trait TorrentClient {
+    /// Adds a magnet link to the client, returns the info-hash of the torrent.
+    fn add_magnet(&mut self, magnet: &str, save_path: &Path) -> anyhow::Result<String>;
+    fn status(&mut self, hash: &str) -> anyhow::Result<TorrentStatus>;
+}
+
+pub fn connect(config: &TorrentClientConfig) -> Box<dyn TorrentClient + Send> {
+    let url = if config.url.is_empty() {
+        config.kind.default_url().to_string()
+    } else {
+        config.url.trim_end_matches('/').to_string()
+    };
+    match config.kind {
+        TorrentClientKind::Transmission => Box::new(Transmission {
+            url,
+            username: config.username.clone(),
+            password: config.password.clone(),
+            session_id: None,
+        }),
+        TorrentClientKind::QBittorrent => Box::new(QBittorrent {
+            url,
+            username: config.username.clone(),
+            password: config.password.clone(),
+            cookie: None,
+        }),
+        TorrentClientKind::Deluge => Box::new(Deluge {
+            url,
+            password: config.password.clone(),
+            cookie: None,
+            request_id: 0,
+        }),
+    }
+}
+
+/// Returns the info-hash of a magnet link as lowercase hex.
+pub fn info_hash_from_magnet(magnet: &str) -> Option<String> {
+    let query = magnet.strip_prefix("magnet:?")?;
+    let hash = query
+        .split('&')
+        .find_map(|param| param.strip_prefix("xt=urn:btih:"))?;
+    match hash.len() {
+        40 if hash.chars().all(|c| c.is_ascii_hexdigit()) => Some(hash.to_lowercase()),
+        32 => base32_to_hex(hash),
+        _ => None,
+    }
+}
+
+fn base32_to_hex(s: &str) -> Option<String> {
+    let mut bits: u64 = 0;
+    let mut nbits = 0;
+    let mut out = String::with_capacity(40);
+    for c in s.chars() {
+        let val = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u64 - 'A' as u64,
+            c @ '2'..='7' => c as u64 - '2' as u64 + 26,
+            _ => return None,
+        };
+        bits = (bits << 5) | val;
+        nbits += 5;
+        if nbits >= 8 {
+            nbits -= 8;
+            out.push_str(&format!("{:02x}", (bits >> nbits) & 0xff));
+        }
+    }
+    Some(out)
+}
+
+fn form_encode(params: &[(&str, &str)]) -> String {
+    params
+        .iter()
+        .map(|(k, v)| format!("{}={}", url_encode(k), url_encode(v)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn url_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+/// A response of a client's API, whatever its status.
+struct Response {
+    url: String,
+    status: u16,
+    status_text: String,
+    /// Header names are lowercased.
+    headers: BTreeMap<String, String>,
+    bytes: Vec<u8>,
+}
+
+impl Response {
+    fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.bytes).ok()
+    }
+}
+
+/// Sends `request` with `body`, only failing if there's no response at all.
+fn fetch(request: ureq::Request, body: Option<&[u8]>) -> anyhow::Result<Response> {
+    let url = request.url().to_string();
+    let sent = match body {
+        Some(body) => request.send_bytes(body),
+        None => request.call(),
+    };
+    let resp = match sent {
+        Ok(resp) | Err(ureq::Error::Status(_, resp)) => resp,
+        Err(e) => bail!("request to {url} failed: {e}"),
+    };
+    let headers = resp
+        .headers_names()
+        .into_iter()
+        .filter_map(|name| {
+            let value = resp.header(&name)?.to_string();
+            Some((name.to_lowercase(), value))
+        })
+        .collect();
+    let status = resp.status();
+    let status_text = resp.status_text().to_string();
+    let mut bytes = Vec::new();
+    resp.into_reader()
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("failed to read the response of {url}"))?;
+    Ok(Response {
+        url,
+        status,
+        status_text,
+        headers,
+        bytes,
+    })
+}
+
+fn ensure_ok(resp: &Response) -> anyhow::Result<()> {
+    if !(200..300).contains(&resp.status) {
+        bail!("{} {} for {}", resp.status, resp.status_text, resp.url)
+    }
+    Ok(())
+}
+
+fn session_cookie(resp: &Response) -> Option<String> {
+    resp.headers
+        .get("set-cookie")
+        .and_then(|cookie| cookie.split(';').next())
+        .map(|cookie| cookie.trim().to_string())
+}
+
+pub struct Transmission {
+    url: String,
+    username: String,
+    password: String,
+    session_id: Option<String>,
+}
+
+impl Transmission {
+    const SESSION_HEADER: &'static str = "X-Transmission-Session-Id";
+
+    fn rpc(&mut self, method: &str, arguments: Value) -> anyhow::Result<Value> {
+        let body = json!({ "method": method, "arguments": arguments }).to_string();
+        // First request of a session is always answered with 409 along with the session id
+        for _ in 0..2 {
+            let mut request = ureq::post(&format!("{}/transmission/rpc", self.url))
+                .set("Content-Type", "application/json");
+            if let Some(id) = &self.session_id {
+                request = request.set(Self::SESSION_HEADER, id);
+            }
+            if !self.username.is_empty() {
+                let credentials = base64::engine::general_purpose::STANDARD
+                    .encode(format!("{}:{}", self.username, self.password));
+                request = request.set("Authorization", &format!("Basic {credentials}"));
+            }
+
+            let resp = fetch(request, Some(body.as_bytes()))?;
+            if resp.status == 409 {
+                self.session_id = resp
+                    .headers
+                    .get(&Self::SESSION_HEADER.to_lowercase())
+                    .cloned();
+                continue;
+            }
+            ensure_ok(&resp)?;
+            let mut resp: Value = serde_json::from_slice(&resp.bytes)
+                .context("transmission: invalid json in response")?;
+            return match resp["result"].as_str() {
+                Some("success") => Ok(resp["arguments"].take()),
+                Some(err) => Err(anyhow!("transmission: {err}")),
+                None => Err(anyhow!("transmission: malformed response")),
+            };
+        }
+        bail!("transmission: failed to negotiate a session id")
+    }
+}
+
+impl TorrentClient for Transmission {
+    fn add_magnet(&mut self, magnet: &str, save_path: &Path) -> anyhow::Result<String> {
+        let args = self.rpc(
+            "torrent-add",
+            json!({ "filename": magnet, "download-dir": save_path }),
+        )?;
+        let torrent = match &args["torrent-added"] {
+            Value::Null => &args["torrent-duplicate"],
+            torrent => torrent,
+        };
+        torrent["hashString"]
+            .as_str()
+            .map(|hash| hash.to_lowercase())
+            .ok_or_else(|| anyhow!("transmission: no torrent in response"))
+    }
+
+    fn status(&mut self, hash: &str) -> anyhow::Result<TorrentStatus> {
+        let args = self.rpc(
+            "torrent-get",
+            json!({ "ids": [hash], "fields": ["name", "percentDone", "status"] }),
+        )?;
+        let torrent = &args["torrents"][0];
+        if torrent.is_null() {
+            bail!("transmission: torrent {hash} not found");
+        }
+        let state = match torrent["status"].as_u64() {
+            Some(0) => "stopped",
+            Some(1 | 2) => "checking",
+            Some(3) => "queued",
+            Some(4) => "downloading",
+            Some(5 | 6) => "seeding",
+            _ => "unknown",
+        };
+        Ok(TorrentStatus {
+            name: torrent["name"].as_str().unwrap_or_default().to_string(),
+            progress: torrent["percentDone"].as_f64().unwrap_or_default() as f32,
+            state: state.to_string(),
+        })
+    }
+}
+
+pub struct QBittorrent {
+    url: String,
+    username: String,
+    password: String,
+    cookie: Option<String>,
+}
+
+impl QBittorrent {
+    fn login(&mut self) -> anyhow::Result<()> {
+        if self.cookie.is_some() {
+            return Ok(());
+        }
+        let resp = self.post(
+            "auth/login",
+            &[("username", &self.username), ("password", &self.password)],
+        )?;
+        if resp.text() != Some("Ok.") {
+            bail!("qbittorrent: authentication failed");
+        }
+        self.cookie =
+            Some(session_cookie(&resp).ok_or_else(|| anyhow!("qbittorrent: no session cookie"))?);
+        Ok(())
+    }
+
+    fn post(&self, endpoint: &str, form: &[(&str, &str)]) -> anyhow::Result<Response> {
+        let request = ureq::post(&format!("{}/api/v2/{endpoint}", self.url))
+            .set("Content-Type", "application/x-www-form-urlencoded");
+        self.send(request, Some(form_encode(form).as_bytes()))
+    }
+
+    fn send(&self, request: ureq::Request, body: Option<&[u8]>) -> anyhow::Result<Response> {
+        // Required by the CSRF protection of the Web UI
+        let mut request = request.set("Referer", &self.url);
+        if let Some(cookie) = &self.cookie {
+            request = request.set("Cookie", cookie);
+        }
+        let resp = fetch(request, body)?;
+        ensure_ok(&resp)?;
+        Ok(resp)
+    }
+}
+
+impl TorrentClient for QBittorrent {
+    fn add_magnet(&mut self, magnet: &str, save_path: &Path) -> anyhow::Result<String> {
+        let hash = info_hash_from_magnet(magnet)
+            .ok_or_else(|| anyhow!("qbittorrent: magnet link has no valid info-hash"))?;
+        self.login()?;
+        let resp = self.post(
+            "torrents/add",
+            &[("urls", magnet), ("savepath", &save_path.to_string_lossy())],
+        )?;
+        if resp.text() == Some("Fails.") {
+            bail!("qbittorrent: failed to add torrent");
+        }
+        Ok(hash)
+    }
+
+    fn status(&mut self, hash: &str) -> anyhow::Result<TorrentStatus> {
+        self.login()?;
+        let resp = self.send(
+            ureq::get(&format!(
+                "{}/api/v2/torrents/info?hashes={}",
+                self.url, hash
+            )),
+            None,
+        )?;
+        let torrents: Value =
+            serde_json::from_slice(&resp.bytes).context("qbittorrent: invalid json in response")?;
+        let torrent = &torrents[0];
+        if torrent.is_null() {
+            bail!("qbittorrent: torrent {hash} not found");
+        }
+        Ok(TorrentStatus {
+            name: torrent["name"].as_str().unwrap_or_default().to_string(),
+            progress: torrent["progress"].as_f64().unwrap_or_default() as f32,
+            state: torrent["state"].as_str().unwrap_or("unknown").to_string(),
+        })
+    }
+}
+
+pub struct Deluge {
+    url: String,
+    password: String,
+    cookie: Option<String>,
+    request_id: u64,
+}
+
+impl Deluge {
+    fn call(&mut self, method: &str, params: Value) -> anyhow::Result<Value> {
+        self.request_id += 1;
+        let body = json!({ "method": method, "params": params, "id": self.request_id });
+        let mut request =
+            ureq::post(&format!("{}/json", self.url)).set("Content-Type", "application/json");
+        if let Some(cookie) = &self.cookie {
+            request = request.set("Cookie", cookie);
+        }
+
+        let resp = fetch(request, Some(body.to_string().as_bytes()))?;
+        ensure_ok(&resp)?;
+        if let Some(cookie) = session_cookie(&resp) {
+            self.cookie = Some(cookie);
+        }
+        let mut resp: Value =
+            serde_json::from_slice(&resp.bytes).context("deluge: invalid json in response")?;
+        if !resp["error"].is_null() {
+            bail!(
+                "deluge: {}",
+                resp["error"]["message"].as_str().unwrap_or("unknown error")
+            );
+        }
+        Ok(resp["result"].take())
+    }
+
+    fn login(&mut self) -> anyhow::Result<()> {
+        if self.cookie.is_some() {
+            return Ok(());
+        }
+        if self.call("auth.login", json!([self.password]))? != Value::Bool(true) {
+            self.cookie = None;
+            bail!("deluge: authentication failed");
+        }
+        // Logged in but not connected yet, the next call has to log in and connect again
+        let connected = self.connect_daemon();
+        if connected.is_err() {
+            self.cookie = None;
+        }
+        connected
+    }
+
+    /// Attaches the web UI to its first daemon, if it isn't attached to one yet.
+    fn connect_daemon(&mut self) -> anyhow::Result<()> {
+        if self.call("web.connected", json!([]))? == Value::Bool(true) {
+            return Ok(());
+        }
+        let hosts = self.call("web.get_hosts", json!([]))?;
+        let host_id = hosts[0][0]
+            .as_str()
+            .ok_or_else(|| anyhow!("deluge: no daemon configured in the web UI"))?
+            .to_string();
+        self.call("web.connect", json!([host_id]))?;
+        Ok(())
+    }
+}
+
+impl TorrentClient for Deluge {
+    fn add_magnet(&mut self, magnet: &str, save_path: &Path) -> anyhow::Result<String> {
+        self.login()?;
+        let hash = self.call(
+            "core.add_torrent_magnet",
+            json!([magnet, { "download_location": save_path }]),
+        )?;
+        // Deluge returns null for torrents that were already added
+        match hash.as_str() {
+            Some(hash) => Ok(hash.to_lowercase()),
+            None => info_hash_from_magnet(magnet)
+                .ok_or_else(|| anyhow!("deluge: failed to add torrent")),
+        }
+    }
+
+    fn status(&mut self, hash: &str) -> anyhow::Result<TorrentStatus> {
+        self.login()?;
+        let torrent = self.call(
+            "core.get_torrent_status",
+            json!([hash, ["name", "progress", "state"]]),
+        )?;
+        if !matches!(torrent.as_object(), Some(obj) if !obj.is_empty()) {
+            bail!("deluge: torrent {hash} not found");
+        }
+        Ok(TorrentStatus {
+            name: torrent["name"].as_str().unwrap_or_default().to_string(),
+            progress: (torrent["progress"].as_f64().unwrap_or_default() / 100.) as f32,
+            state: torrent["state"]
+                .as_str()
+                .unwrap_or("unknown")
+                .to_lowercase(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{MockResponse, MockServer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const MAGNET: &str = "magnet:?xt=urn:btih:C12FE1C06BBA254A9DC9F519B335AA7C1367A88A&dn=some.iso";
+    const HASH: &str = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+
+    fn config(kind: TorrentClientKind, server: &MockServer) -> TorrentClientConfig {
+        TorrentClientConfig {
+            kind,
+            url: server.url.clone(),
+            username: "admin".to_string(),
+            password: "hunter2".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn magnet_info_hash() {
+        assert_eq!(info_hash_from_magnet(MAGNET).as_deref(), Some(HASH));
+        assert_eq!(
+            info_hash_from_magnet("magnet:?xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK")
+                .as_deref(),
+            Some(HASH)
+        );
+        assert_eq!(info_hash_from_magnet("https://example.com/a.torrent"), None);
+    }
+
+    #[test]
+    fn transmission_add_and_status() {
+        let server = MockServer::start(|req| {
+            if req
+                .headers
+                .get("x-transmission-session-id")
+                .map(|s| s.as_str())
+                != Some("abc")
+            {
+                return MockResponse::new(409, "").header("X-Transmission-Session-Id", "abc");
+            }
+            let body = req.json();
+            match body["method"].as_str().unwrap() {
+                "torrent-add" => {
+                    assert_eq!(body["arguments"]["filename"], MAGNET);
+                    assert_eq!(body["arguments"]["download-dir"], "/isos");
+                    MockResponse::json(json!({
+                        "result": "success",
+                        "arguments": { "torrent-added": { "id": 1, "hashString": HASH, "name": "some.iso" } }
+                    }))
+                }
+                "torrent-get" => MockResponse::json(json!({
+                    "result": "success",
+                    "arguments": { "torrents": [{ "name": "some.iso", "percentDone": 0.5, "status": 4 }] }
+                })),
+                _ => MockResponse::new(400, ""),
+            }
+        });
+
+        let mut client = connect(&config(TorrentClientKind::Transmission, &server));
+        assert_eq!(client.add_magnet(MAGNET, Path::new("/isos")).unwrap(), HASH);
+        let status = client.status(HASH).unwrap();
+        assert_eq!(status.progress, 0.5);
+        assert_eq!(status.state, "downloading");
+        assert!(server.requests()[1]
+            .headers
+            .get("authorization")
+            .unwrap()
+            .starts_with("Basic "));
+        // The RPC is POST only
+        assert!(server.requests().iter().all(|req| req.method == "POST"));
+    }
+
+    #[test]
+    fn qbittorrent_add_and_status() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/api/v2/auth/login" => {
+                assert_eq!(req.text(), "username=admin&password=hunter2");
+                MockResponse::new(200, "Ok.").header("Set-Cookie", "SID=xyz; HttpOnly; path=/")
+            }
+            _ if req.headers.get("cookie").map(|s| s.as_str()) != Some("SID=xyz") => {
+                MockResponse::new(403, "Forbidden")
+            }
+            "/api/v2/torrents/add" => {
+                assert!(req.text().contains("savepath=%2Fisos"));
+                MockResponse::new(200, "Ok.")
+            }
+            path if path.starts_with("/api/v2/torrents/info") => MockResponse::json(json!([
+                { "name": "some.iso", "progress": 1.0, "state": "uploading" }
+            ])),
+            _ => MockResponse::new(404, ""),
+        });
+
+        let mut client = connect(&config(TorrentClientKind::QBittorrent, &server));
+        assert_eq!(client.add_magnet(MAGNET, Path::new("/isos")).unwrap(), HASH);
+        let status = client.status(HASH).unwrap();
+        assert_eq!(status.progress, 1.0);
+        assert_eq!(status.state, "uploading");
+    }
+
+    #[test]
+    fn qbittorrent_bad_login() {
+        let server = MockServer::start(|_| MockResponse::new(200, "Fails."));
+        let mut client = connect(&config(TorrentClientKind::QBittorrent, &server));
+        assert!(client.add_magnet(MAGNET, Path::new("/isos")).is_err());
+    }
+
+    #[test]
+    fn deluge_add_and_status() {
+        let server = MockServer::start(|req| {
+            let body = req.json();
+            let result = match body["method"].as_str().unwrap() {
+                "auth.login" => {
+                    return MockResponse::json(
+                        json!({ "result": true, "error": null, "id": body["id"] }),
+                    )
+                    .header("Set-Cookie", "_session_id=s3cr3t; Path=/json")
+                }
+                "web.connected" => json!(true),
+                "core.add_torrent_magnet" => {
+                    assert_eq!(body["params"][1]["download_location"], "/isos");
+                    json!(HASH)
+                }
+                "core.get_torrent_status" => {
+                    json!({ "name": "some.iso", "progress": 25.0, "state": "Downloading" })
+                }
+                _ => {
+                    return MockResponse::json(
+                        json!({ "result": null, "error": { "message": "unknown method" } }),
+                    )
+                }
+            };
+            assert_eq!(req.headers.get("cookie").unwrap(), "_session_id=s3cr3t");
+            MockResponse::json(json!({ "result": result, "error": null, "id": body["id"] }))
+        });
+
+        let mut client = connect(&config(TorrentClientKind::Deluge, &server));
+        assert_eq!(client.add_magnet(MAGNET, Path::new("/isos")).unwrap(), HASH);
+        let status = client.status(HASH).unwrap();
+        assert_eq!(status.progress, 0.25);
+        assert_eq!(status.state, "downloading");
+        assert!(server.requests().iter().all(|req| req.method == "POST"));
+    }
+
+    #[test]
+    fn deluge_retry_daemon_connection() {
+        // No daemon until the second attempt
+        let attempts = AtomicUsize::new(0);
+        let server = MockServer::start(move |req| {
+            let body = req.json();
+            let result = match body["method"].as_str().unwrap() {
+                "auth.login" => {
+                    return MockResponse::json(
+                        json!({ "result": true, "error": null, "id": body["id"] }),
+                    )
+                    .header("Set-Cookie", "_session_id=s3cr3t; Path=/json")
+                }
+                "web.connected" => json!(false),
+                "web.get_hosts" => match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => json!([]),
+                    _ => json!([["c0ffee", "127.0.0.1", 58846, "Offline"]]),
+                },
+                "web.connect" => json!([]),
+                "core.add_torrent_magnet" => json!(HASH),
+                _ => unreachable!(),
+            };
+            MockResponse::json(json!({ "result": result, "error": null, "id": body["id"] }))
+        });
+
+        let mut client = connect(&config(TorrentClientKind::Deluge, &server));
+        assert!(client.add_magnet(MAGNET, Path::new("/isos")).is_err());
+        assert_eq!(client.add_magnet(MAGNET, Path::new("/isos")).unwrap(), HASH);
+        let methods = server
+            .requests()
+            .iter()
+            .map(|req| req.json()["method"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            methods
+                .iter()
+                .filter(|method| *method == "web.connect")
+                .count(),
+            1
+        );
+        assert_eq!(
+            methods
+                .iter()
+                .filter(|method| *method == "auth.login")
+                .count(),
+            2
+        );
+    }
+}
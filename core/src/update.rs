@@ -0,0 +1,606 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    downloads::{DownloadKind, DownloadManager, DownloadRequest},
+    error::{Error, Result},
+    extract,
+    package::{self, PkgKind, PkgTarget},
+    task::{CancelToken, Cancellable},
+};
+
+/// Latest Ventoy release, as returned by the GitHub API.
+pub const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/ventoy/Ventoy/releases/latest";
+
+/// A Ventoy release on GitHub, e.g. `v1.0.86`.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct Release {
+    pub tag_name: String,
+    pub assets: Vec<ReleaseAsset>,
+}
+
+/// A file attached to a [`Release`], see [`super::package`] for what they contain.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct ReleaseAsset {
+    pub name: String,
+    #[serde(rename = "browser_download_url")]
+    pub download_url: String,
+}
+
+/// Marks a fully extracted package directory. Directories without it were extracted in
+/// place by older versions, they're extracted again before being used.
+pub const COMPLETE_MARKER: &str = ".complete";
+/// Prefix of files and directories that are written before being renamed into place.
+const STAGING_PREFIX: &str = ".staging-";
+const PKG_DIR_PREFIX: &str = "ventoy-";
+
+/// Name of the directory a release package is extracted to, e.g. `ventoy-v1.0.86-linux`.
+pub fn pkg_dir_name(tag_name: &str, os: &str) -> String {
+    format!("{PKG_DIR_PREFIX}{tag_name}-{os}")
+}
+
+pub(crate) fn staging_path(dest: &Path) -> io::Result<PathBuf> {
+    let file_name = dest
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid path {dest:?}"),
+            )
+        })?;
+    Ok(dest.with_file_name(format!("{STAGING_PREFIX}{file_name}")))
+}
+
+/// Whether `path` is a file or directory being written by [`staging_path`] users, or a
+/// leftover of one that was interrupted.
+pub(crate) fn is_staging(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(STAGING_PREFIX))
+}
+
+/// Writes the file next to `dest` first, so that `dest` either doesn't exist or is complete.
+pub fn write_atomic<P: AsRef<Path>>(dest: P, bytes: &[u8]) -> io::Result<()> {
+    let dest = dest.as_ref();
+    let staging = staging_path(dest)?;
+    let mut file = fs::File::create(&staging)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&staging, dest)
+}
+
+pub fn is_complete<P: AsRef<Path>>(dir: P) -> bool {
+    dir.as_ref().join(COMPLETE_MARKER).is_file()
+}
+
+/// Runs `extract` on a staging directory next to `dest_dir` and renames it into place
+/// once it's marked complete, replacing an existing `dest_dir`.
+///
+/// Its own failures are marked as failures to write, see [`extract::split_write_error`].
+pub fn install_dir<P, F>(dest_dir: P, extract: F) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&Path) -> io::Result<()>,
+{
+    let dest_dir = dest_dir.as_ref();
+    let staging = staging_path(dest_dir)?;
+    let write_error = |e| extract::write_error(&staging, e);
+    if staging.exists() {
+        fs::remove_dir_all(&staging).map_err(write_error)?;
+    }
+    fs::create_dir_all(&staging).map_err(write_error)?;
+    let installed = extract(&staging)
+        .and_then(|_| fs::write(staging.join(COMPLETE_MARKER), b"").map_err(write_error))
+        .and_then(|_| {
+            if dest_dir.exists() {
+                fs::remove_dir_all(dest_dir).map_err(|e| extract::write_error(dest_dir, e))?;
+            }
+            fs::rename(&staging, dest_dir).map_err(|e| extract::write_error(dest_dir, e))
+        });
+    if installed.is_err() {
+        let _ = fs::remove_dir_all(&staging);
+    }
+    installed
+}
+
+/// Removes staging leftovers, returning what was removed.
+///
+/// Package directories are renamed into place once complete, so they're kept even
+/// without the [`COMPLETE_MARKER`], which older versions didn't write. One of those may
+/// still be what the user launches Ventoy2Disk from.
+pub fn clean_cache_dir<P: AsRef<Path>>(cache_dir: P) -> io::Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    let entries = match fs::read_dir(cache_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(removed),
+        Err(err) => return Err(err),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        if !name.starts_with(STAGING_PREFIX) {
+            continue;
+        }
+        if path.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+        removed.push(path);
+    }
+    removed.sort();
+    Ok(removed)
+}
+
+/// Fetches the latest Ventoy release, blocking until it's done or `cancel` is cancelled.
+pub fn fetch_latest_release(cancel: &CancelToken) -> Result<Release> {
+    get_json(LATEST_RELEASE_URL, "latest Ventoy release", cancel)
+}
+
+/// Downloads `url` to `dest_file`, blocking until it's done.
+///
+/// The body is streamed into a file next to `dest_file` that's renamed into place once
+/// complete, and removed if the download fails or `cancel` is cancelled midway.
+/// Queue downloads with [`DownloadManager`] instead to have them retried and throttled.
+pub fn download<P: AsRef<Path>>(url: &str, dest_file: P, cancel: &CancelToken) -> Result<()> {
+    download_with(url, dest_file, cancel, |_, _| ())
+}
+
+/// [`download`], calling `progress` with the bytes downloaded so far and the total, if
+/// known, after every chunk. Blocking in `progress` holds back the download.
+pub fn download_with<P, F>(
+    url: &str,
+    dest_file: P,
+    cancel: &CancelToken,
+    mut progress: F,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+    F: FnMut(u64, Option<u64>),
+{
+    let dest_file = dest_file.as_ref();
+    cancel.check()?;
+    let resp = get(url)?;
+
+    let total = resp
+        .header("Content-Length")
+        .and_then(|len| len.parse().ok());
+    let staging = staging_path(dest_file).map_err(|e| Error::io(dest_file, e))?;
+    let downloaded = copy_to_file(resp.into_reader(), &staging, cancel, |done| {
+        progress(done, total)
+    })
+    .and_then(|_| fs::rename(&staging, dest_file).map_err(|e| Error::io(dest_file, e)));
+    if downloaded.is_err() {
+        let _ = fs::remove_file(&staging);
+    }
+    downloaded
+}
+
+/// Sends a GET request to `url`, failing on error statuses.
+pub(crate) fn get(url: &str) -> Result<ureq::Response> {
+    ureq::get(url).call().map_err(|e| match e {
+        ureq::Error::Status(_, resp) => Error::http(&resp),
+        ureq::Error::Transport(e) => Error::Network(e.to_string()),
+    })
+}
+
+/// Copies `reader` into a new file at `path`, checking `cancel` between chunks.
+fn copy_to_file<R, F>(
+    mut reader: R,
+    path: &Path,
+    cancel: &CancelToken,
+    mut progress: F,
+) -> Result<()>
+where
+    R: Read,
+    F: FnMut(u64),
+{
+    let mut file = fs::File::create(path).map_err(|e| Error::io(path, e))?;
+    let mut buf = vec![0; 64 * 1024];
+    let mut done = 0;
+    loop {
+        cancel.check()?;
+        let read = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(Error::Network(err.to_string())),
+        };
+        file.write_all(&buf[..read])
+            .map_err(|e| Error::io(path, e))?;
+        done += read as u64;
+        progress(done);
+    }
+    file.sync_all().map_err(|e| Error::io(path, e))
+}
+
+/// Fetches and parses the JSON at `url`, `what` names it in the error, e.g. "release feeds".
+///
+/// The body is read through `cancel`, so cancelling stops the request midway instead of
+/// once the whole response is in.
+pub fn get_json<T: DeserializeOwned>(
+    url: &str,
+    what: &'static str,
+    cancel: &CancelToken,
+) -> Result<T> {
+    cancel.check()?;
+    let resp = get(url)?;
+    let url = resp.get_url().to_string();
+    let mut body = Vec::new();
+    Cancellable::new(resp.into_reader(), cancel.clone())
+        .read_to_end(&mut body)
+        .map_err(|e| match cancel.is_cancelled() {
+            true => Error::Cancelled,
+            false => Error::Network(e.to_string()),
+        })?;
+    serde_json::from_slice(&body).map_err(|e| Error::Json {
+        what,
+        url,
+        source: e.into(),
+    })
+}
+
+/// A downloaded package, kept around to skip downloading it again.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedPkg {
+    /// Tag of the release it's from
+    pub version: String,
+    pub path: PathBuf,
+}
+
+/// Downloads the package of `release` for `target` into `cache_dir` through `downloads`
+/// and extracts it next to it with [`install_dir`], returning where it was extracted to.
+///
+/// `cached` is reused if it's the same package, and extracted again only if the previous
+/// extraction didn't complete. Once `cancel` is cancelled the download and extraction
+/// stop, leaving only a previously cached package behind.
+pub fn install_pkg<P: AsRef<Path>>(
+    release: &Release,
+    target: PkgTarget,
+    cache_dir: P,
+    cached: Option<&CachedPkg>,
+    downloads: &DownloadManager,
+    cancel: &CancelToken,
+) -> Result<(PathBuf, CachedPkg)> {
+    let cache_dir = cache_dir.as_ref();
+    let asset = package::select_asset(&release.assets, target)?;
+    let pkg = CachedPkg {
+        version: release.tag_name.clone(),
+        path: cache_dir.join(&asset.name),
+    };
+    let pkg_dir = cache_dir.join(pkg_dir_name(&release.tag_name, &target.to_string()));
+
+    let reused = cached.filter(|cached| **cached == pkg && cached.path.is_file());
+    if reused.is_none() {
+        fs::create_dir_all(cache_dir).map_err(|e| Error::io(cache_dir, e))?;
+        let id = downloads.enqueue(DownloadRequest {
+            url: asset.download_url.clone(),
+            dest: pkg.path.clone(),
+            kind: DownloadKind::Package,
+        });
+        downloads.wait(id, cancel)?;
+    }
+    if reused.is_none() || !is_complete(&pkg_dir) {
+        let installed = install_dir(&pkg_dir, |staging_dir| {
+            let file = Cancellable::new(fs::File::open(&pkg.path)?, cancel.clone());
+            match target.kind {
+                PkgKind::Windows => extract::unpack_zip(file, staging_dir, Default::default()),
+                _ => extract::unpack_targz(file, staging_dir, Default::default()),
+            }
+        });
+        if let Err(err) = installed {
+            if !cancel.is_cancelled() {
+                // A full disk isn't fixed by downloading again
+                return Err(match extract::split_write_error(err) {
+                    Ok((path, err)) => Error::io(path, err),
+                    Err(err) => Error::extract(&pkg.path, err),
+                });
+            }
+            // Nothing would refer to a package downloaded just now
+            if reused.is_none() {
+                let _ = fs::remove_file(&pkg.path);
+            }
+            return Err(Error::Cancelled);
+        }
+    }
+    Ok((pkg_dir, pkg))
+}
+
+/// See [`extract::extract_targz`], with the default limits.
+pub fn extract_targz<P>(archive_path: P, dest_dir: P) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    extract::extract_targz(archive_path, dest_dir, Default::default())
+}
+
+/// See [`extract::extract_zip`], with the default limits.
+pub fn extract_zip<P>(archive_path: P, dest_dir: P) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    extract::extract_zip(archive_path, dest_dir, Default::default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        downloads::DownloadConfig,
+        task::TaskManager,
+        testing::{MockResponse, MockServer},
+    };
+    use tempdir::TempDir;
+
+    #[test]
+    fn install_dir_atomically() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let dest = tmpdir.path().join(pkg_dir_name("v1.0.86", "linux"));
+
+        let err = install_dir(&dest, |dir| {
+            fs::write(dir.join("VentoyGUI.x86_64"), b"elf")?;
+            Err(io::Error::other("crashed"))
+        });
+        assert!(err.is_err());
+        assert!(!dest.exists());
+        assert_eq!(fs::read_dir(tmpdir.path()).unwrap().count(), 0);
+
+        install_dir(&dest, |dir| fs::write(dir.join("VentoyGUI.x86_64"), b"elf")).unwrap();
+        assert!(is_complete(&dest));
+        install_dir(&dest, |dir| fs::write(dir.join("Ventoy2Disk.sh"), b"sh")).unwrap();
+        assert!(!dest.join("VentoyGUI.x86_64").exists());
+        assert!(dest.join("Ventoy2Disk.sh").exists());
+    }
+
+    #[test]
+    fn reinstall_cached_pkg() {
+        let tmpdir = TempDir::new("test").unwrap();
+        // Nothing listens on a port that was just freed, the cached package has to be used
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let release = Release {
+            tag_name: "v1.0.86".to_string(),
+            assets: vec![ReleaseAsset {
+                name: "ventoy-1.0.86-linux.tar.gz".to_string(),
+                download_url: format!("http://127.0.0.1:{port}/ventoy-1.0.86-linux.tar.gz"),
+            }],
+        };
+        let target = PkgTarget {
+            kind: PkgKind::Linux,
+            arch: package::Arch::X86_64,
+        };
+        let cached = CachedPkg {
+            version: release.tag_name.clone(),
+            path: tmpdir.path().join("ventoy-1.0.86-linux.tar.gz"),
+        };
+        let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+            fs::File::create(&cached.path).unwrap(),
+            flate2::Compression::default(),
+        ));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(3);
+        header.set_cksum();
+        tar.append_data(&mut header, "ventoy-1.0.86/VentoyGUI.x86_64", &b"elf"[..])
+            .unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+
+        let downloads = DownloadManager::new(
+            DownloadConfig {
+                max_retries: 0,
+                ..Default::default()
+            },
+            TaskManager::default(),
+        );
+        let cancel = CancelToken::new();
+        let (pkg_dir, pkg) = install_pkg(
+            &release,
+            target,
+            tmpdir.path(),
+            Some(&cached),
+            &downloads,
+            &cancel,
+        )
+        .unwrap();
+        assert_eq!(pkg, cached);
+        assert_eq!(pkg_dir, tmpdir.path().join("ventoy-v1.0.86-linux-x86_64"));
+        assert!(is_complete(&pkg_dir));
+        assert!(pkg_dir.join("ventoy-1.0.86/VentoyGUI.x86_64").is_file());
+
+        // A package of another release isn't reused
+        let older = CachedPkg {
+            version: "v1.0.85".to_string(),
+            ..cached.clone()
+        };
+        let err = install_pkg(
+            &release,
+            target,
+            tmpdir.path(),
+            Some(&older),
+            &downloads,
+            &cancel,
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::Network(_)), "{err:?}");
+
+        // Cancelled extractions leave the cached package and nothing else behind
+        fs::remove_dir_all(&pkg_dir).unwrap();
+        cancel.cancel();
+        let err = install_pkg(
+            &release,
+            target,
+            tmpdir.path(),
+            Some(&cached),
+            &downloads,
+            &cancel,
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::Cancelled), "{err:?}");
+        assert!(cached.path.is_file());
+        assert_eq!(fs::read_dir(tmpdir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn tell_write_errors_from_corrupt_pkgs() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let release = Release {
+            tag_name: "v1.0.86".to_string(),
+            assets: vec![ReleaseAsset {
+                name: "ventoy-1.0.86-linux.tar.gz".to_string(),
+                download_url: String::new(),
+            }],
+        };
+        let target = PkgTarget {
+            kind: PkgKind::Linux,
+            arch: package::Arch::X86_64,
+        };
+        let cached = CachedPkg {
+            version: release.tag_name.clone(),
+            path: tmpdir.path().join("ventoy-1.0.86-linux.tar.gz"),
+        };
+        let downloads = DownloadManager::new(Default::default(), TaskManager::default());
+        let install = || {
+            install_pkg(
+                &release,
+                target,
+                tmpdir.path(),
+                Some(&cached),
+                &downloads,
+                &CancelToken::new(),
+            )
+            .unwrap_err()
+        };
+
+        fs::write(&cached.path, b"not a tar.gz").unwrap();
+        let err = install();
+        assert!(matches!(err, Error::Extract { .. }), "{err:?}");
+
+        // `ventoy/tool` can't be both a file and a directory, as a write would fail
+        let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+            fs::File::create(&cached.path).unwrap(),
+            flate2::Compression::default(),
+        ));
+        for name in ["ventoy/tool", "ventoy/tool/x86_64"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(3);
+            header.set_cksum();
+            tar.append_data(&mut header, name, &b"elf"[..]).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+        let err = install();
+        assert!(
+            matches!(&err, Error::Io { path, .. } if path.ends_with("ventoy/tool")),
+            "{err:?}"
+        );
+        assert!(!err.is_retryable());
+    }
+
+    #[test]
+    fn download_to_file() {
+        let server = MockServer::start(|req| match req.path.as_str() {
+            "/ventoy-1.0.86-livecd.iso" => MockResponse::new(200, vec![7; 200 * 1024]),
+            _ => MockResponse::new(404, "Not Found"),
+        });
+        let tmpdir = TempDir::new("test").unwrap();
+        let dest = tmpdir.path().join("ventoy-1.0.86-livecd.iso");
+        let cancel = CancelToken::new();
+
+        let url = format!("{}/ventoy-1.0.86-livecd.iso", server.url);
+        download(&url, &dest, &cancel).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), vec![7; 200 * 1024]);
+
+        let missing = tmpdir.path().join("missing.iso");
+        let err = download(&format!("{}/missing.iso", server.url), &missing, &cancel).unwrap_err();
+        assert!(
+            matches!(err, Error::HttpStatus { status: 404, .. }),
+            "{err:?}"
+        );
+
+        // Cancelled midway, after the first chunk
+        fs::remove_file(&dest).unwrap();
+        let mut chunks = 0;
+        let err = download_with(&url, &dest, &cancel, |done, total| {
+            chunks += 1;
+            assert!(done < total.unwrap());
+            cancel.cancel();
+        })
+        .unwrap_err();
+        assert!(matches!(err, Error::Cancelled), "{err:?}");
+        assert_eq!(chunks, 1);
+        assert_eq!(fs::read_dir(tmpdir.path()).unwrap().count(), 0);
+
+        // Already cancelled, without a request
+        let err = download(&url, &dest, &cancel).unwrap_err();
+        assert!(matches!(err, Error::Cancelled), "{err:?}");
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn fetch_json() {
+        let release = r#"{"tag_name":"v1.0.86","assets":[]}"#;
+        let server = MockServer::start(move |req| match req.path.as_str() {
+            "/releases/latest" => MockResponse::new(200, release),
+            _ => MockResponse::new(200, "<html>"),
+        });
+        let cancel = CancelToken::new();
+
+        let url = format!("{}/releases/latest", server.url);
+        let fetched: Release = get_json(&url, "latest Ventoy release", &cancel).unwrap();
+        assert_eq!(fetched.tag_name, "v1.0.86");
+        let err = get_json::<Release>(&server.url, "latest Ventoy release", &cancel).unwrap_err();
+        assert!(matches!(err, Error::Json { .. }), "{err:?}");
+
+        cancel.cancel();
+        let err = get_json::<Release>(&url, "latest Ventoy release", &cancel).unwrap_err();
+        assert!(matches!(err, Error::Cancelled), "{err:?}");
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn clean_partial_installs() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let complete = tmpdir.path().join(pkg_dir_name("v1.0.86", "linux"));
+        install_dir(&complete, |_| Ok(())).unwrap();
+        // Extracted by a version without markers
+        let unmarked = tmpdir.path().join(pkg_dir_name("v1.0.85", "linux"));
+        fs::create_dir_all(&unmarked).unwrap();
+        let staging = staging_path(&tmpdir.path().join("pkg.tar.gz")).unwrap();
+        fs::write(&staging, b"half a pkg").unwrap();
+        let staging_dir = staging_path(&complete).unwrap();
+        fs::create_dir_all(&staging_dir).unwrap();
+        fs::write(tmpdir.path().join("cache.ron"), b"()").unwrap();
+
+        let mut staged = vec![staging, staging_dir];
+        staged.sort();
+        assert_eq!(clean_cache_dir(tmpdir.path()).unwrap(), staged);
+        assert!(complete.exists());
+        assert!(unmarked.exists());
+        assert!(tmpdir.path().join("cache.ron").exists());
+        assert!(clean_cache_dir(tmpdir.path().join("missing"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn github_release_api() {
+        let res = ureq::get("https://api.github.com/repos/ventoy/Ventoy/releases/latest")
+            .call()
+            .expect("failed to get a response");
+        dbg!(serde_json::from_str::<Release>(
+            &res.into_string()
+                .expect("failed to get response body as string")
+        )
+        .expect("failed to deserialize"));
+    }
+}
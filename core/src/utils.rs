@@ -7,13 +7,55 @@ use std::{
 use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+use super::{error, task::CancelToken, update};
+
+/// Linux distro releases, generated by `nozwock/ventoy-toybox-feed`.
+pub const RELEASE_FEEDS_URL: &str =
+    "https://github.com/nozwock/ventoy-toybox-feed/releases/download/feeds/releases.json";
+
+/// A linux distro release and its torrent.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct FeedsItem {
     pub group: String,
     pub name: String,
     pub torrent_url: String,
     pub magnet: String,
     pub date: String,
+    /// Direct download of the image from a mirror, see [`crate::mirror::resolve`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iso_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256sums_url: Option<String>,
+    /// Detached GPG signature of the `sha256sums_url` file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature_url: Option<String>,
+}
+
+impl FeedsItem {
+    /// File name of the image the torrent downloads.
+    pub fn image_name(&self) -> &str {
+        self.name.strip_suffix(".torrent").unwrap_or(&self.name)
+    }
+
+    /// Release version parsed from the image name, e.g. `22.10` for `ubuntu-22.10-desktop-amd64.iso`.
+    pub fn version(&self) -> Option<&str> {
+        self.image_name()
+            .split(['-', ' '])
+            .map(|token| {
+                token
+                    .trim_start_matches(['v', 'V'])
+                    .trim_end_matches(|c: char| !c.is_ascii_digit())
+            })
+            .find(|token| {
+                token.starts_with(|c: char| c.is_ascii_digit())
+                    && token.chars().all(|c| c.is_ascii_digit() || c == '.')
+            })
+    }
+}
+
+/// Fetches the release feeds, blocking until it's done or `cancel` is cancelled.
+pub fn fetch_release_feeds(cancel: &CancelToken) -> error::Result<Vec<FeedsItem>> {
+    update::get_json(RELEASE_FEEDS_URL, "release feeds", cancel)
 }
 
 pub fn find_file<P>(path: P, fname: &str) -> Option<PathBuf>
@@ -108,9 +150,29 @@ where
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::testing::feeds_item;
     use fs::File;
     use tempdir::TempDir;
 
+    #[test]
+    fn feeds_item_version() {
+        for (name, version) in [
+            ("ubuntu-22.10-desktop-amd64.iso.torrent", Some("22.10")),
+            (
+                "Fedora-Workstation-Live-x86_64-37-1.7.iso.torrent",
+                Some("37"),
+            ),
+            (
+                "archlinux-2022.11.01-x86_64.iso.torrent",
+                Some("2022.11.01"),
+            ),
+            ("debian-11.5.0-amd64-netinst.iso.torrent", Some("11.5.0")),
+            ("kali-linux-amd64.iso.torrent", None),
+        ] {
+            assert_eq!(feeds_item("", name).version(), version, "{name}");
+        }
+    }
+
     #[test]
     fn file_found() {
         let tmpdir = TempDir::new("test").unwrap();
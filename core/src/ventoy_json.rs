@@ -0,0 +1,275 @@
+//! Reading and merging plugin entries of a drive's `ventoy/ventoy.json`.
+//!
+//! Only the entries touched by the app are replaced, everything else in the file
+//! is kept as is.
+
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, bail, Context};
+use serde_json::{json, Map, Value};
+
+use super::utils::FeedsItem;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VentoyJson {
+    plugins: Map<String, Value>,
+}
+
+impl VentoyJson {
+    /// Loads `ventoy.json`, a missing file is treated as an empty config.
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)?;
+        if content.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        match serde_json::from_str(&content).with_context(|| format!("invalid json in {path:?}"))? {
+            Value::Object(plugins) => Ok(Self { plugins }),
+            _ => bail!("{:?} is not a json object", path),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut content = serde_json::to_string_pretty(&self.plugins)?;
+        content.push('\n');
+        Ok(fs::write(path, content)?)
+    }
+
+    pub fn plugin(&self, name: &str) -> Option<&Value> {
+        self.plugins.get(name)
+    }
+
+    pub fn set_plugin(&mut self, name: &str, value: Value) {
+        self.plugins.insert(name.to_string(), value);
+    }
+
+    pub fn remove_plugin(&mut self, name: &str) -> Option<Value> {
+        self.plugins.remove(name)
+    }
+
+    /// Entries of a list-style plugin such as `menu_alias`.
+    pub fn entries(&self, plugin: &str) -> &[Value] {
+        match self.plugins.get(plugin) {
+            Some(Value::Array(entries)) => entries,
+            _ => &[],
+        }
+    }
+
+    /// Inserts `entry` into a list-style plugin, replacing an existing entry
+    /// with the same value for `key` (e.g. the `image` an alias is for).
+    pub fn upsert(&mut self, plugin: &str, key: &str, entry: Value) -> anyhow::Result<()> {
+        let key_value = entry
+            .get(key)
+            .cloned()
+            .ok_or_else(|| anyhow!("entry for `{plugin}` is missing `{key}`"))?;
+        let entries = self
+            .plugins
+            .entry(plugin)
+            .or_insert_with(|| Value::Array(Vec::new()));
+        let entries = match entries {
+            Value::Array(entries) => entries,
+            _ => bail!("`{}` in ventoy.json is not a list", plugin),
+        };
+        match entries.iter_mut().find(|e| e.get(key) == Some(&key_value)) {
+            Some(existing) => *existing = entry,
+            None => entries.push(entry),
+        }
+        Ok(())
+    }
+
+    /// Adds `value` to `field` of the entry whose `key` equals `key_value`, the field
+    /// becomes a list once it holds more than one value (e.g. multiple `backend`s).
+    pub fn append(
+        &mut self,
+        plugin: &str,
+        key: &str,
+        key_value: &str,
+        field: &str,
+        value: &str,
+    ) -> anyhow::Result<()> {
+        let existing = match self.plugins.get_mut(plugin) {
+            Some(Value::Array(entries)) => entries.iter_mut().find(|e| e[key] == key_value),
+            _ => None,
+        };
+        // Keep other options of an existing entry, e.g. `autosel`
+        if let Some(Value::Object(entry)) = existing {
+            let values = match entry.remove(field) {
+                Some(Value::String(old)) if old != value => json!([old, value]),
+                Some(Value::Array(mut values)) => {
+                    if !values.iter().any(|v| v == value) {
+                        values.push(json!(value));
+                    }
+                    Value::Array(values)
+                }
+                _ => json!(value),
+            };
+            entry.insert(field.to_string(), values);
+            return Ok(());
+        }
+        self.upsert(plugin, key, json!({ key: key_value, field: value }))
+    }
+
+    /// Removes entries of a list-style plugin whose `key` equals `value`.
+    pub fn remove(&mut self, plugin: &str, key: &str, value: &str) {
+        if let Some(Value::Array(entries)) = self.plugins.get_mut(plugin) {
+            entries.retain(|e| e.get(key).and_then(|v| v.as_str()) != Some(value));
+        }
+    }
+
+    pub fn set_menu_alias(&mut self, image: &str, alias: &str) -> anyhow::Result<()> {
+        self.upsert(
+            "menu_alias",
+            "image",
+            json!({ "image": image, "alias": alias }),
+        )
+    }
+
+    /// `key` is matched by Ventoy against the image file names.
+    pub fn set_menu_class(&mut self, key: &str, class: &str) -> anyhow::Result<()> {
+        self.upsert("menu_class", "key", json!({ "key": key, "class": class }))
+    }
+}
+
+/// Friendly boot menu name for an image downloaded from the feed, e.g. `Ubuntu 22.10`.
+pub fn menu_alias(item: &FeedsItem) -> String {
+    let group = item.group.trim();
+    if group.is_empty() {
+        return item.image_name().to_string();
+    }
+    let mut alias = String::new();
+    let mut chars = group.chars();
+    if let Some(first) = chars.next() {
+        alias.extend(first.to_uppercase());
+        alias.push_str(chars.as_str());
+    }
+    if let Some(version) = item.version() {
+        alias.push(' ');
+        alias.push_str(version);
+    }
+    alias
+}
+
+/// Menu class (icon name used by GRUB themes) for an image downloaded from the feed.
+pub fn menu_class(item: &FeedsItem) -> String {
+    let class = item
+        .group
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+    match class.as_str() {
+        "arch" => "archlinux".to_string(),
+        "mint" => "linuxmint".to_string(),
+        "popos" => "pop-os".to_string(),
+        "redhat" | "rhel" => "rhel".to_string(),
+        _ => class,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::feeds_item;
+    use tempdir::TempDir;
+
+    #[test]
+    fn menu_entries_from_feed() {
+        let item = feeds_item("ubuntu", "ubuntu-22.10-desktop-amd64.iso.torrent");
+        assert_eq!(menu_alias(&item), "Ubuntu 22.10");
+        assert_eq!(menu_class(&item), "ubuntu");
+        assert_eq!(menu_class(&feeds_item("Linux Mint", "")), "linuxmint");
+        assert_eq!(menu_alias(&feeds_item("", "some.iso.torrent")), "some.iso");
+    }
+
+    #[test]
+    fn merge_with_existing() {
+        let tmpdir = TempDir::new("test").unwrap();
+        let path = tmpdir.path().join("ventoy/ventoy.json");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(
+            &path,
+            r#"{
+                "control": [{ "VTOY_DEFAULT_MENU_MODE": "1" }],
+                "menu_alias": [
+                    { "image": "/ISO/old.iso", "alias": "Old" },
+                    { "image": "/ISO/ubuntu.iso", "alias": "Stale" }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let mut config = VentoyJson::load(&path).unwrap();
+        config
+            .set_menu_alias("/ISO/ubuntu.iso", "Ubuntu 22.10")
+            .unwrap();
+        config.set_menu_class("ubuntu.iso", "ubuntu").unwrap();
+        config.save(&path).unwrap();
+
+        let config = VentoyJson::load(&path).unwrap();
+        assert_eq!(
+            config.plugin("control"),
+            Some(&json!([{ "VTOY_DEFAULT_MENU_MODE": "1" }]))
+        );
+        assert_eq!(
+            config.entries("menu_alias"),
+            &[
+                json!({ "image": "/ISO/old.iso", "alias": "Old" }),
+                json!({ "image": "/ISO/ubuntu.iso", "alias": "Ubuntu 22.10" }),
+            ]
+        );
+        assert_eq!(
+            config.entries("menu_class"),
+            &[json!({ "key": "ubuntu.iso", "class": "ubuntu" })]
+        );
+    }
+
+    #[test]
+    fn append_to_entry() {
+        let mut config = VentoyJson::default();
+        config
+            .append("auto_install", "image", "/a.iso", "template", "/a.ks")
+            .unwrap();
+        assert_eq!(
+            config.entries("auto_install"),
+            &[json!({ "image": "/a.iso", "template": "/a.ks" })]
+        );
+
+        config.set_plugin(
+            "auto_install",
+            json!([{ "image": "/a.iso", "template": "/a.ks", "autosel": 1 }]),
+        );
+        config
+            .append("auto_install", "image", "/a.iso", "template", "/b.ks")
+            .unwrap();
+        config
+            .append("auto_install", "image", "/a.iso", "template", "/b.ks")
+            .unwrap();
+        assert_eq!(
+            config.entries("auto_install"),
+            &[json!({ "image": "/a.iso", "autosel": 1, "template": ["/a.ks", "/b.ks"] })]
+        );
+    }
+
+    #[test]
+    fn missing_file_is_empty() {
+        let tmpdir = TempDir::new("test").unwrap();
+        assert_eq!(
+            VentoyJson::load(tmpdir.path().join("ventoy.json")).unwrap(),
+            VentoyJson::default()
+        );
+    }
+
+    #[test]
+    fn reject_non_list_plugin() {
+        let mut config = VentoyJson::default();
+        config.set_plugin("menu_alias", json!({}));
+        assert!(config.set_menu_alias("/a.iso", "A").is_err());
+    }
+}
@@ -0,0 +1,96 @@
+//! Installing a Ventoy update the way a frontend would, through the public API only.
+
+use std::fs;
+
+use tempdir::TempDir;
+use ventoy_toybox_core::{
+    error::Error,
+    package::{self, Arch, PkgKind, PkgTarget},
+    update::{self, Release},
+    utils::FeedsItem,
+};
+
+const RELEASE_JSON: &str = r#"{
+    "tag_name": "v1.0.86",
+    "name": "1.0.86 release",
+    "assets": [
+        {
+            "name": "ventoy-1.0.86-linux.tar.gz",
+            "browser_download_url": "https://github.com/ventoy/Ventoy/releases/download/v1.0.86/ventoy-1.0.86-linux.tar.gz",
+            "size": 17125063
+        },
+        {
+            "name": "ventoy-1.0.86-windows.zip",
+            "browser_download_url": "https://github.com/ventoy/Ventoy/releases/download/v1.0.86/ventoy-1.0.86-windows.zip",
+            "size": 16334270
+        }
+    ]
+}"#;
+
+#[test]
+fn install_linux_package() {
+    let release: Release = serde_json::from_str(RELEASE_JSON).unwrap();
+    let target = PkgTarget {
+        kind: PkgKind::Linux,
+        arch: Arch::X86_64,
+    };
+    let asset = package::select_asset(&release.assets, target).unwrap();
+    assert_eq!(asset.name, "ventoy-1.0.86-linux.tar.gz");
+
+    // Stands in for the downloaded package
+    let tmpdir = TempDir::new("test").unwrap();
+    let pkg_path = tmpdir.path().join(&asset.name);
+    let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+        fs::File::create(&pkg_path).unwrap(),
+        flate2::Compression::default(),
+    ));
+    let bin = b"\x7fELF";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bin.len() as u64);
+    header.set_mode(0o755);
+    header.set_cksum();
+    tar.append_data(
+        &mut header,
+        format!("ventoy-1.0.86/{}", target.bin_name()),
+        &bin[..],
+    )
+    .unwrap();
+    tar.into_inner().unwrap().finish().unwrap();
+
+    let dest = tmpdir
+        .path()
+        .join(update::pkg_dir_name(&release.tag_name, &target.to_string()));
+    update::install_dir(&dest, |staging| {
+        update::extract_targz(pkg_path.as_path(), staging)
+    })
+    .unwrap();
+    assert!(update::is_complete(&dest));
+    assert_eq!(
+        fs::read(dest.join("ventoy-1.0.86/VentoyGUI.x86_64")).unwrap(),
+        bin
+    );
+
+    let target = PkgTarget {
+        kind: PkgKind::LiveCd,
+        arch: Arch::X86_64,
+    };
+    let err = package::select_asset(&release.assets, target).unwrap_err();
+    assert!(matches!(err, Error::NoMatchingAsset { .. }));
+    assert!(!err.is_retryable());
+}
+
+#[test]
+fn parse_release_feeds() {
+    let feeds: Vec<FeedsItem> = serde_json::from_str(
+        r#"[{
+            "group": "ubuntu",
+            "name": "ubuntu-22.10-desktop-amd64.iso.torrent",
+            "torrent_url": "https://releases.ubuntu.com/22.10/ubuntu-22.10-desktop-amd64.iso.torrent",
+            "magnet": "magnet:?xt=urn:btih:99c82bb73505a3c0b453f9fa0e881d6e5a32a0c1",
+            "date": "2022-10-20"
+        }]"#,
+    )
+    .unwrap();
+    assert_eq!(feeds[0].image_name(), "ubuntu-22.10-desktop-amd64.iso");
+    assert_eq!(feeds[0].version(), Some("22.10"));
+}
@@ -1,2 +0,0 @@
-pub mod update;
-pub mod utils;
@@ -13,3 +13,34 @@ pub fn app_cache_dir() -> Option<PathBuf> {
 pub fn app_cache_path() -> PathBuf {
     app_cache_dir().unwrap_or_default().join("cache.ron")
 }
+
+/// Hashes of verified images, see `ventoy_toybox_core::integrity::VerifyCache`.
+pub fn app_verify_cache_path() -> PathBuf {
+    app_cache_dir().unwrap_or_default().join("verified.json")
+}
+
+pub fn app_log_dir() -> Option<PathBuf> {
+    Some(app_cache_dir()?.join("logs"))
+}
+
+pub fn app_config_dir() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join(APP_DIR))
+}
+
+pub fn app_config_path() -> PathBuf {
+    app_config_dir().unwrap_or_default().join("config.ron")
+}
+
+pub fn app_templates_dir() -> PathBuf {
+    app_config_dir().unwrap_or_default().join("templates")
+}
+
+/// Keys imported to verify the signatures of mirrored images with.
+pub fn app_keyring_dir() -> Option<PathBuf> {
+    Some(app_config_dir()?.join("keyrings"))
+}
+
+/// Provisioning profiles, stored as `<name>.ron`.
+pub fn app_profiles_dir() -> PathBuf {
+    app_config_dir().unwrap_or_default().join("profiles")
+}
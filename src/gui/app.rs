@@ -1,48 +1,297 @@
+mod auto_install;
+mod downloads;
+mod drive;
+mod flash;
+mod logs;
+mod persistence;
+mod profiles;
+mod secure_boot;
+mod settings;
+mod sync;
+mod themes;
+mod verify;
+
 use std::{
-    fs,
-    path::{Path, PathBuf},
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
-use crate::{
-    core::{update, utils, utils::FeedsItem},
-    defines,
-};
+use crate::defines;
 use eframe::egui::{self, RichText, ScrollArea};
 use poll_promise::Promise;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info};
+#[cfg(target_os = "linux")]
+use ventoy_toybox_core::elevate;
+use ventoy_toybox_core::{
+    auto_install::{is_secret, Template},
+    cache::AppCache,
+    compat::CompatDb,
+    downloads::{DownloadConfig, DownloadKind, DownloadManager, DownloadRequest},
+    drive::{BlockDevice, DeviceSafety},
+    error::{self, Error},
+    flash::Progress,
+    flow::{self, FeedsEvent, UpdateEvent},
+    integrity::{self, VerifyCache},
+    iso,
+    logs::LogBuffer,
+    mirror::{self, GroupMirror},
+    package::{self, PkgTarget},
+    persistence::PersistenceLabel,
+    provision::{self, Profile},
+    secure_boot::SecureBootInfo,
+    sync::{SyncOptions, SyncReport},
+    task::{CancelToken, TaskManager},
+    theme, torrent,
+    update::{self, CachedPkg},
+    utils::{self, FeedsItem},
+};
 
-use super::{PromptDialog, VentoyUpdateFrames};
+use super::PromptDialog;
+use drive::draw_compat_badge;
 
 #[derive(Default)]
 pub struct App {
     cache: AppCache,
+    config: AppConfig,
     page: AppPages,
-    frame: AppFrames,
+    update_flow: flow::UpdateFlow,
+    feeds_flow: flow::FeedsFlow,
     promise: AppPromises,
+    tasks: AppTasks,
+    downloads: DownloadManager,
     prompt: AppPromptDialogs,
 
     // filter ui states in release browse page
     filter_release_entry_box: String,
-    filter_group_by_combobox: Vec<String>,
     filter_group_by_combobox_idx: usize,
 
-    // cloned states of some promises
-    ventoy_update_dir: Option<Result<PathBuf, String>>,
+    // found in the extracted ventoy release pkg
     ventoy_update_bin: Option<PathBuf>,
-    is_groups_processed: bool
+
+    // bundled compatibility hints, possibly updated from `config.compat_db`
+    compat: CompatDb,
+    compat_status: Option<Result<String, String>>,
+
+    // images downloaded from mirrors, and their settings
+    iso_downloads: Vec<IsoDownload>,
+    mirrors: MirrorsForm,
+
+    // feed items sent to the torrent client
+    torrents: Vec<TrackedTorrent>,
+    torrents_polled_at: Option<Instant>,
+
+    // ventoy drive page
+    drive_images: Vec<DriveImage>,
+    iso_inspection: Option<IsoInspection>,
+    drive_status: Option<Result<String, String>>,
+    persistence: PersistenceForm,
+    integrity: IntegrityForm,
+    verify_cache: VerifyCache,
+    auto_install: AutoInstallForm,
+    theme: ThemeForm,
+    secure_boot: Option<Result<SecureBootInfo, String>>,
+    secure_boot_export_dir: String,
+    profiles: ProfilesForm,
+    sync: SyncForm,
+
+    // flash page
+    flash: FlashForm,
+    flash_job: Option<FlashJob>,
+    flash_status: Option<Result<String, String>>,
+
+    // logs page
+    logs: LogsView,
 }
 
 #[derive(Default, Serialize, Deserialize, Clone, Debug)]
-struct AppCache {
-    release_feeds: Vec<FeedsItem>,
-    ventoy_update_pkg: Option<ReleasePkg>
+#[serde(default)]
+struct AppConfig {
+    torrent_client_enabled: bool,
+    torrent_client: torrent::TorrentClientConfig,
+    ventoy_drive: PathBuf,
+    /// Ventoy package to download, `None` picks the one for the running system
+    ventoy_pkg_target: Option<PkgTarget>,
+    /// Mount point of the `VTOYEFI` partition, guessed from `ventoy_drive` if empty
+    ventoy_efi_partition: PathBuf,
+    /// Allows overwriting system disks, still behind the typed confirmation
+    expert_mode: bool,
+    /// Values of the template variables, except secret ones like passwords
+    auto_install_vars: BTreeMap<String, String>,
+    downloads: DownloadConfig,
+    /// Mirrors of the feeds groups, by group
+    mirrors: BTreeMap<String, GroupMirror>,
+    /// Require the checksums of mirrored images to be signed by a key in the keyring
+    verify_signatures: bool,
+    /// Local compatibility database updating the bundled one, none if empty
+    compat_db: PathBuf,
+    /// Directory of images mirrored onto the drive
+    sync_source: PathBuf,
+    sync: SyncOptions,
 }
 
-#[derive(Default, Serialize, Deserialize, Clone, Debug)]
-struct ReleasePkg {
-    version: String,
-    path: PathBuf
+struct IsoDownload {
+    source: mirror::IsoSource,
+    result: Promise<Result<mirror::VerifiedIso, String>>,
+}
+
+#[derive(Default)]
+struct MirrorsForm {
+    new_group: String,
+    key_path: String,
+    status: Option<Result<String, String>>,
+}
+
+struct TrackedTorrent {
+    name: String,
+    hash: Promise<Result<String, String>>,
+    status: Option<Result<torrent::TorrentStatus, String>>,
+}
+
+struct DriveImage {
+    path: PathBuf,
+    /// Path as referred to in `ventoy.json`
+    ventoy_path: String,
+    feed: Option<FeedsItem>,
+    /// Label, boot modes and distro read from an ISO image
+    info: Option<iso::IsoInfo>,
+    selected: bool,
+}
+
+/// ISOs of the drive being inspected in the background, read before the app can show
+/// their labels.
+struct IsoInspection {
+    /// Inspected ISOs not yet filled into `drive_images`
+    inspected: Arc<Mutex<Vec<(PathBuf, iso::IsoInfo)>>>,
+    token: CancelToken,
+    done: Promise<()>,
+}
+
+struct PersistenceForm {
+    image_idx: usize,
+    size_mib: u64,
+    label: PersistenceLabel,
+}
+
+impl Default for PersistenceForm {
+    fn default() -> Self {
+        Self {
+            image_idx: 0,
+            size_mib: 1024,
+            label: Default::default(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct IntegrityForm {
+    /// Checksums pasted in, by image path
+    pasted: BTreeMap<PathBuf, String>,
+    checks: BTreeMap<PathBuf, IntegrityCheck>,
+}
+
+struct IntegrityCheck {
+    /// Bytes hashed and the file size
+    progress: Arc<Mutex<(u64, u64)>>,
+    token: CancelToken,
+    result: Promise<Result<integrity::Verification, String>>,
+}
+
+#[derive(Default)]
+struct AutoInstallForm {
+    templates: Vec<Template>,
+    template_idx: usize,
+    image_idx: usize,
+    import_path: String,
+    /// Values of secret variables, kept out of the saved config
+    secrets: BTreeMap<String, String>,
+}
+
+#[derive(Default)]
+struct ThemeForm {
+    installed: Vec<theme::InstalledTheme>,
+    selection: theme::ThemeSelection,
+    archive_path: String,
+    preview_idx: Option<usize>,
+    preview: Option<Result<theme::ThemePreview, String>>,
+    background: Option<egui::TextureHandle>,
+}
+
+#[derive(Default)]
+struct ProfilesForm {
+    profiles: Vec<Profile>,
+    /// Profile being edited, a copy of one in `profiles` or a new one
+    editing: Profile,
+    /// Images of `editing`, a `group: pattern` or a path per line
+    images: String,
+    /// Plugins of `editing`, as a json object
+    plugins: String,
+    /// Mount points of the drives to apply it to, one per line
+    drives: String,
+    plans: Vec<(PathBuf, Result<provision::Plan, String>)>,
+    job: Option<ProvisionJob>,
+    status: Option<Result<String, String>>,
+}
+
+struct ProvisionJob {
+    /// Step being carried out
+    current: Arc<Mutex<String>>,
+    token: CancelToken,
+    /// Steps left to do by hand, by drive
+    result: Promise<Vec<(PathBuf, Result<Vec<provision::Step>, String>)>>,
+}
+
+#[derive(Default)]
+struct SyncForm {
+    comparing: Option<(CancelToken, Promise<Result<SyncReport, String>>)>,
+    /// Outcome of the last comparison, the dry run
+    report: Option<SyncReport>,
+    job: Option<SyncJob>,
+    status: Option<Result<String, String>>,
+}
+
+struct SyncJob {
+    /// Action being done, bytes copied and bytes to copy
+    progress: Arc<Mutex<(String, u64, u64)>>,
+    token: CancelToken,
+    result: Promise<Result<(), String>>,
+}
+
+#[derive(Default)]
+struct LogsView {
+    buffer: LogBuffer,
+    /// Least severe level shown, everything if `None`
+    level: Option<tracing::Level>,
+    search: String,
+    status: Option<Result<String, String>>,
+}
+
+#[derive(Default)]
+struct FlashForm {
+    /// Write a disk image instead of the Ventoy LiveCD
+    from_image: bool,
+    image_path: String,
+    livecd: Option<PathBuf>,
+    devices: Vec<BlockDevice>,
+    device_idx: usize,
+    /// Write to an image file instead of a device
+    to_file: bool,
+    file_path: String,
+    confirm: bool,
+    /// The drive picked when confirming, so refreshing the list can't swap what gets
+    /// overwritten
+    confirm_device: Option<BlockDevice>,
+    /// Device name typed in to confirm overwriting it
+    typed_name: String,
+    safety: Option<Result<DeviceSafety, String>>,
+}
+
+struct FlashJob {
+    target: String,
+    progress: Arc<Mutex<Progress>>,
+    result: Promise<Result<u64, String>>,
 }
 
 #[derive(Debug, PartialEq, Default)]
@@ -50,20 +299,37 @@ enum AppPages {
     #[default]
     VentoyUpdate,
     ReleaseBrowse,
+    Drive,
+    Flash,
+    Downloads,
+    Logs,
+    Settings,
 }
 
-#[derive(Default)]
-struct AppFrames {
-    ventoy_update: VentoyUpdateFrames,
-}
-
-type ReqPromise<T> = Promise<ehttp::Result<T>>;
+type ReqPromise<T> = Promise<error::Result<T>>;
 
 #[derive(Default)]
 struct AppPromises {
     release_feeds: Option<ReqPromise<Vec<FeedsItem>>>,
     ventoy_release_info: Option<ReqPromise<update::Release>>,
-    ventoy_update_pkg: Option<ReqPromise<(PathBuf, ReleasePkg)>>,
+    ventoy_update_pkg: Option<ReqPromise<(PathBuf, CachedPkg)>>,
+    torrent_status: Option<Promise<Vec<(String, Result<torrent::TorrentStatus, String>)>>>,
+    persistence: Option<Promise<Result<PathBuf, String>>>,
+    livecd: Option<Promise<Result<PathBuf, String>>>,
+    /// Ventoy2Disk running elevated, resolves once it's closed
+    #[cfg(target_os = "linux")]
+    ventoy_launch: Option<Promise<Result<(), String>>>,
+}
+
+/// Cancel tokens of the running [`AppPromises`], and the window listing all tasks.
+#[derive(Default)]
+struct AppTasks {
+    manager: TaskManager,
+    ventoy_release_info: Option<CancelToken>,
+    ventoy_update_pkg: Option<CancelToken>,
+    release_feeds: Option<CancelToken>,
+    livecd: Option<CancelToken>,
+    window_open: bool,
 }
 
 #[derive(Default)]
@@ -74,7 +340,7 @@ struct AppPromptDialogs {
 
 impl App {
     /// Called once before the first frame.
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, logs: LogBuffer) -> Self {
         // This is also where you can customized the look at feel of egui using
         // `cc.egui_ctx.set_visuals` and `cc.egui_ctx.set_fonts`.
 
@@ -85,22 +351,49 @@ impl App {
             return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
         }
 
+        // Remove whatever an interrupted download or extraction left behind
+        if let Some(cache_dir) = defines::app_cache_dir() {
+            match update::clean_cache_dir(&cache_dir) {
+                Ok(removed) if !removed.is_empty() => {
+                    info!("Removed partial files from the cache dir: {:#?}", removed)
+                }
+                Ok(_) => (),
+                Err(err) => error!("Failed to clean the cache dir {:?}: {}", cache_dir, err),
+            }
+        }
+
         // Setup app cache
         let mut cache: AppCache = confy::load_path(defines::app_cache_path()).unwrap_or_default();
-        let cached_pkg_path = cache.ventoy_update_pkg.unwrap_or_default();
-        cache.ventoy_update_pkg = if cached_pkg_path.path.is_file() {
-            Some(cached_pkg_path)
-        } else {
-            None
-        };
+        cache.drop_missing_pkg();
         debug!("Cached ventoy release pkg: {:#?}", cache.ventoy_update_pkg);
 
+        let mut config: AppConfig =
+            confy::load_path(defines::app_config_path()).unwrap_or_default();
+        // Saved by older versions
+        config.auto_install_vars.retain(|name, _| !is_secret(name));
+
         // Set custom font styles for the app
         configure_fonts(&cc.egui_ctx);
 
-        Self {
+        let release_feeds = cache.release_feeds.clone();
+        let tasks = AppTasks::default();
+        let downloads = DownloadManager::new(config.downloads.clone(), tasks.manager.clone());
+        for request in std::mem::take(&mut cache.downloads) {
+            downloads.enqueue(request);
+        }
+        let iso_downloads = std::mem::take(&mut cache.iso_downloads);
+        let mut app = Self {
             cache,
-            filter_group_by_combobox: vec!["all".to_string()],
+            config,
+            verify_cache: VerifyCache::load(defines::app_verify_cache_path()),
+            compat: CompatDb::bundled(),
+            tasks,
+            downloads,
+            logs: LogsView {
+                buffer: logs,
+                level: Some(tracing::Level::INFO),
+                ..Default::default()
+            },
             prompt: AppPromptDialogs {
                 ventoy_launch_err: PromptDialog {
                     title: "Error occurred!".to_string(),
@@ -116,33 +409,252 @@ impl App {
                 },
             },
             ..Default::default()
+        };
+        app.handle_update_event(&cc.egui_ctx, UpdateEvent::Check);
+        app.handle_feeds_event(&cc.egui_ctx, FeedsEvent::Load(release_feeds));
+        app.reload_compat_db();
+        app.reload_profiles();
+        for source in iso_downloads {
+            app.download_iso(&cc.egui_ctx, source);
+        }
+        app
+    }
+
+    fn handle_update_event(&mut self, ctx: &egui::Context, event: UpdateEvent) {
+        let effect = match self.update_flow.handle(event) {
+            Some(effect) => effect,
+            None => return,
+        };
+        match effect {
+            flow::UpdateEffect::FetchRelease => {
+                let ctx = ctx.clone();
+                let task = self.tasks.manager.start("Checking for Ventoy updates");
+                self.tasks.ventoy_release_info = Some(task.token().clone());
+                self.promise.ventoy_release_info =
+                    Some(Promise::spawn_thread("ventoy_release_info", move || {
+                        let ventoy_release = update::fetch_latest_release(task.token());
+                        match &ventoy_release {
+                            Ok(release) => {
+                                debug!("Fetching ventoy releases information: {:#?}", release)
+                            }
+                            Err(err) => {
+                                error!("Error fetching ventoy releases information: {}", err)
+                            }
+                        }
+                        drop(task);
+                        ctx.request_repaint();
+                        ventoy_release
+                    }));
+            }
+            flow::UpdateEffect::CancelFetch => {
+                if let Some(token) = &self.tasks.ventoy_release_info {
+                    token.cancel();
+                }
+            }
+            flow::UpdateEffect::InstallPkg { release, target } => {
+                info!("Installing the ventoy release pkg for {}", target);
+                self.ventoy_update_bin = None;
+                let cache_dir =
+                    defines::app_cache_dir().expect("expect to have a os-wide cache dir");
+                let cached_pkg = self.cache.ventoy_update_pkg.clone();
+                let downloads = self.downloads.clone();
+                let ctx = ctx.clone();
+                let task = self.tasks.manager.start(format!(
+                    "Installing Ventoy {} for {}",
+                    release.tag_name, target
+                ));
+                self.tasks.ventoy_update_pkg = Some(task.token().clone());
+                self.promise.ventoy_update_pkg =
+                    Some(Promise::spawn_thread("ventoy_update_pkg", move || {
+                        let pkg_status = update::install_pkg(
+                            &release,
+                            target,
+                            cache_dir,
+                            cached_pkg.as_ref(),
+                            &downloads,
+                            task.token(),
+                        );
+                        match &pkg_status {
+                            Ok(pkg) => debug!("Fetching ventoy release pkg: {:#?}", pkg),
+                            Err(Error::Cancelled) => info!("Cancelled fetching ventoy release pkg"),
+                            Err(err) => error!("Error fetching ventoy release pkg: {}", err),
+                        }
+                        drop(task);
+                        ctx.request_repaint();
+                        pkg_status
+                    }));
+            }
+            flow::UpdateEffect::CachePkg(pkg) => self.cache.ventoy_update_pkg = Some(pkg),
+            flow::UpdateEffect::CancelInstall => {
+                if let Some(token) = &self.tasks.ventoy_update_pkg {
+                    token.cancel();
+                }
+            }
         }
     }
 
-    fn draw_release_cards(&self, ui: &mut egui::Ui) {
-        let group_name = &self.filter_group_by_combobox[self.filter_group_by_combobox_idx];
+    fn handle_feeds_event(&mut self, ctx: &egui::Context, event: FeedsEvent) {
+        match self.feeds_flow.handle(event) {
+            Some(flow::FeedsEffect::FetchFeeds) => {
+                let ctx = ctx.clone();
+                let task = self.tasks.manager.start("Fetching release feeds");
+                self.tasks.release_feeds = Some(task.token().clone());
+                self.promise.release_feeds =
+                    Some(Promise::spawn_thread("release_feeds", move || {
+                        let release_feeds = utils::fetch_release_feeds(task.token());
+                        if let Err(err) = &release_feeds {
+                            error!("Error fetching release feeds: {}", err);
+                        }
+                        drop(task);
+                        ctx.request_repaint();
+                        release_feeds
+                    }));
+            }
+            Some(flow::FeedsEffect::CancelFetch) => {
+                if let Some(token) = &self.tasks.release_feeds {
+                    token.cancel();
+                }
+            }
+            Some(flow::FeedsEffect::CacheFeeds) => {
+                self.cache.release_feeds = self.feeds_flow.feeds().to_vec();
+                self.filter_group_by_combobox_idx = 0;
+            }
+            None => (),
+        }
+    }
+
+    /// Feeds the results of the finished requests back into the flows.
+    fn poll_flows(&mut self, ctx: &egui::Context) {
+        if let Some(promise) = self.promise.ventoy_release_info.take() {
+            match promise.try_take() {
+                Ok(fetched) => self.handle_update_event(ctx, UpdateEvent::ReleaseFetched(fetched)),
+                Err(promise) => self.promise.ventoy_release_info = Some(promise),
+            }
+        }
+        if let Some(promise) = self.promise.ventoy_update_pkg.take() {
+            match promise.try_take() {
+                Ok(installed) => self.handle_update_event(ctx, UpdateEvent::Installed(installed)),
+                Err(promise) => self.promise.ventoy_update_pkg = Some(promise),
+            }
+        }
+        if let Some(promise) = self.promise.release_feeds.take() {
+            match promise.try_take() {
+                Ok(fetched) => self.handle_feeds_event(ctx, FeedsEvent::Fetched(fetched)),
+                Err(promise) => self.promise.release_feeds = Some(promise),
+            }
+        }
+    }
+
+    fn draw_release_cards(&mut self, ui: &mut egui::Ui) {
+        let (feeds, groups) = match self.feeds_flow.state() {
+            flow::FeedsState::Loaded { feeds, groups } => (feeds, groups),
+            _ => return,
+        };
+        let mut send_to_client = None;
+        let mut download_torrent = None;
+        let mut download_iso = None;
+        let group_name = groups
+            .get(self.filter_group_by_combobox_idx)
+            .map_or("all", |group| group.as_str());
         let entry_text = &self.filter_release_entry_box;
-        for item in &self.cache.release_feeds {
-            if (group_name == "all" || group_name == &item.group)
+        for item in feeds {
+            if (group_name == "all" || group_name == item.group)
                 && (entry_text.is_empty() || item.name.contains(entry_text.as_str()))
             {
                 const PADDING: f32 = 3.;
                 ui.add_space(PADDING);
                 ui.horizontal(|ui| {
                     ui.label(&item.name);
+                    draw_compat_badge(ui, self.compat.for_feed(item));
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::RIGHT), |ui| {
                         ui.style_mut().visuals.hyperlink_color =
                             egui::Color32::from_rgb(0, 255, 255);
                         ui.hyperlink_to("Magnet Link ⤴", &item.magnet);
                         ui.style_mut().visuals.hyperlink_color =
                             egui::Color32::from_rgb(236, 135, 10);
-                        ui.hyperlink_to("Torrent ⤴", &item.torrent_url)
+                        ui.hyperlink_to("Torrent ⤴", &item.torrent_url);
+                        if ui
+                            .small_button("⮋")
+                            .on_hover_text("Queue the .torrent file in Downloads")
+                            .clicked()
+                        {
+                            download_torrent = Some(item.clone());
+                        }
+                        if let Some(source) = mirror::resolve(item, &self.config.mirrors) {
+                            if ui
+                                .small_button("⬇ HTTP")
+                                .on_hover_text(format!("Download and verify {}", source.url))
+                                .clicked()
+                            {
+                                download_iso = Some(source);
+                            }
+                        }
+                        if self.config.torrent_client_enabled
+                            && ui
+                                .button("⮋ Send")
+                                .on_hover_text(format!(
+                                    "Add to {}",
+                                    self.config.torrent_client.kind
+                                ))
+                                .clicked()
+                        {
+                            send_to_client = Some(item.clone());
+                        }
                     });
                 });
                 ui.add_space(PADDING);
                 ui.separator();
             }
         }
+        if let Some(item) = send_to_client {
+            self.send_to_torrent_client(ui.ctx(), item);
+        }
+        if let Some(item) = download_torrent {
+            let dest_dir = dirs::download_dir()
+                .or_else(defines::app_cache_dir)
+                .unwrap_or_default();
+            self.downloads.enqueue(DownloadRequest {
+                url: item.torrent_url,
+                dest: dest_dir.join(item.name),
+                kind: DownloadKind::Torrent,
+            });
+        }
+        if let Some(source) = download_iso {
+            self.download_iso(ui.ctx(), source);
+        }
+    }
+
+    fn draw_tasks_window(&mut self, ctx: &egui::Context) {
+        let tasks = self.tasks.manager.tasks();
+        if tasks.is_empty() {
+            self.tasks.window_open = false;
+            return;
+        }
+        let manager = self.tasks.manager.clone();
+        egui::Window::new("Running Tasks")
+            .open(&mut self.tasks.window_open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("tasks-grid")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for task in &tasks {
+                            ui.label(&task.name);
+                            ui.label(format!("{}s", task.started.elapsed().as_secs()));
+                            if ui
+                                .add_enabled(!task.cancelled, egui::Button::new("✖ Cancel"))
+                                .clicked()
+                            {
+                                manager.cancel(task.id);
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        // Keep the elapsed times ticking
+        ctx.request_repaint_after(Duration::from_secs(1));
     }
 
     fn draw_topbar(&mut self, ui: &mut egui::Ui) {
@@ -153,7 +665,22 @@ impl App {
                 AppPages::ReleaseBrowse,
                 "🔍 Browse OS Releases",
             );
+            ui.selectable_value(&mut self.page, AppPages::Drive, "🖴 Ventoy Drive");
+            if ui
+                .selectable_value(&mut self.page, AppPages::Flash, "💾 Flash")
+                .clicked()
+            {
+                self.refresh_block_devices();
+            }
+            ui.selectable_value(&mut self.page, AppPages::Downloads, "⮋ Downloads");
+            ui.selectable_value(&mut self.page, AppPages::Logs, "📜 Logs");
+            ui.selectable_value(&mut self.page, AppPages::Settings, "⚙ Settings");
             ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                let running = self.tasks.manager.tasks().len();
+                if running > 0 {
+                    ui.toggle_value(&mut self.tasks.window_open, format!("⏳ {running}"))
+                        .on_hover_text("Running tasks");
+                }
                 egui::warn_if_debug_build(ui);
                 if !cfg!(debug_assertions) {
                     ui.label(
@@ -176,112 +703,71 @@ impl eframe::App for App {
     }
 
     fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // Mirror downloads are started again as a whole, to verify what they download
+        self.cache.iso_downloads = self
+            .iso_downloads
+            .iter()
+            .filter(|download| download.result.ready().is_none())
+            .map(|download| download.source.clone())
+            .collect();
+        let iso_files: Vec<String> = self
+            .cache
+            .iso_downloads
+            .iter()
+            .flat_map(|source| {
+                [
+                    source.name.clone(),
+                    format!("{}.sha256sums", source.name),
+                    format!("{}.sha256sums.sig", source.name),
+                ]
+            })
+            .collect();
+        // Packages are downloaded again by the update flow when needed
+        self.cache.downloads = self
+            .downloads
+            .pending()
+            .into_iter()
+            .filter(|request| request.kind != DownloadKind::Package)
+            .filter(|request| {
+                request.kind != DownloadKind::Iso
+                    || !request
+                        .dest
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| iso_files.iter().any(|file| file == name))
+            })
+            .collect();
+        // Stop the downloads, so that they clean up after themselves
+        self.tasks.manager.cancel_all();
+
         // Store cache on exit
+        let verify_cache_path = defines::app_verify_cache_path();
+        debug!(
+            "Storing verified hashes in {:?}: {:?}",
+            verify_cache_path,
+            self.verify_cache.save(&verify_cache_path)
+        );
         let cache_path = defines::app_cache_path();
-        debug!("Storing cache in {:?}: {:?}", cache_path, confy::store_path(&cache_path, &self.cache));
+        debug!(
+            "Storing cache in {:?}: {:?}",
+            cache_path,
+            confy::store_path(&cache_path, &self.cache)
+        );
+        let config_path = defines::app_config_path();
+        debug!(
+            "Storing config in {:?}: {:?}",
+            config_path,
+            confy::store_path(&config_path, &self.config)
+        );
     }
 
     /// Called each time the UI needs repainting, which may be many times per second.
     /// Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`, `Window` or `Area`.
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
-        // I hate the way this is being done...but ok T~T
-
-        // ! why's rust analyzer dead for this chunk of code. What's going on with it?
-        let release_feeds_promise = self.promise.release_feeds.get_or_insert_with(|| {
-            let ctx = ctx.clone();
-            let (sender, promise) = Promise::new();
-            // Use cache if exists
-            if !self.cache.release_feeds.is_empty() {
-                sender.send(Ok(self.cache.release_feeds.clone()));
-                ctx.request_repaint();
-            }
-            else {
-                let request = ehttp::Request::get(
-                    "https://github.com/nozwock/ventoy-toybox-feed/releases/download/feeds/releases.json"
-                );
-                ehttp::fetch(request, move |response| {
-                    let release_feeds  = response.and_then(|response| {
-                        // ! had to manually format this long line...why's fmt not working?????
-                        serde_json::from_str::<Vec<FeedsItem>>(response.text().ok_or(format!(
-                            "{} {}: failed to get valid utf8 text from response\n{}",
-                            response.status, response.status_text, response.url
-                        ))?)
-                        .map_err(|e| e.to_string())
-                    });
-                    if let Err(err) = &release_feeds {
-                        error!("Error fetching release feeds: {}", err);
-                    }
-                    sender.send(release_feeds);
-                    ctx.request_repaint();
-                });
-            };
-            promise
-        });
-
-        let ventoy_release_info_promise =
-            self.promise.ventoy_release_info.get_or_insert_with(|| {
-                let ctx = ctx.clone();
-                let (sender, promise) = Promise::new();
-                let request = ehttp::Request::get(
-                    "https://api.github.com/repos/ventoy/Ventoy/releases/latest",
-                );
-                ehttp::fetch(request, move |response| {
-                    let ventoy_release = response.and_then(|response| {
-                        serde_json::from_str::<update::Release>(response.text().ok_or(format!(
-                            "{} {}: failed to get valid utf8 text from response\n{}",
-                            response.status, response.status_text, response.url
-                        ))?)
-                        .map_err(|e| e.to_string())
-                    });
-                    match &ventoy_release {
-                        Ok(release) => debug!("Fetching ventoy releases information: {:#?}", release),
-                        Err(err) => error!("Error fetching ventoy releases information: {}", err)
-                    }
-                    sender.send(ventoy_release);
-                    ctx.request_repaint();
-                });
-                promise
-            });
-
-        // * had to do this at the start...bcz I can't figure out how to solve that multiple borrow issue atm ;-[
-        let release_feeds_status = match release_feeds_promise.ready() {
-            None => None,
-            Some(Err(err)) => Some(Err(err.clone())),
-            Some(Ok(feeds)) => {
-                // * this branch will continue only once and i.e. on the first frame
-                if !self.is_groups_processed {
-                    self.cache.release_feeds = feeds.clone();
-                    let mut group_duplicates = Vec::new();
-                    let mut groups = self
-                        .cache
-                        .release_feeds
-                        .iter()
-                        .map(|group| group.group.clone())
-                        // filtering out duplicate groups
-                        .filter(|group| {
-                            for dup in &group_duplicates {
-                                if dup == group {
-                                    return false;
-                                }
-                            }
-                            group_duplicates.push(group.clone());
-                            true
-                        })
-                        .collect::<Vec<_>>();
-                    // 'all' the default group
-                    groups.insert(0, "all".to_string());
-                    self.filter_group_by_combobox = groups;
-                    self.is_groups_processed = true;
-                }
-                Some(Ok(()))
-            }
-        };
-
-        let ventoy_release_info = match ventoy_release_info_promise.ready() {
-            None => None,
-            Some(Err(err)) => Some(Err(err.clone())),
-            Some(Ok(release)) => Some(Ok(release.clone())),
-        };
+        self.poll_flows(ctx);
+        self.poll_iso_inspection();
+        self.draw_tasks_window(ctx);
+        self.poll_tracked_torrents(ctx);
 
         // ------------------------------
         // * App View from here
@@ -295,8 +781,8 @@ impl eframe::App for App {
         egui::CentralPanel::default().show(ctx, |ui| {
             self.draw_topbar(ui);
             match self.page {
-                AppPages::VentoyUpdate => match ventoy_release_info {
-                    None => {
+                AppPages::VentoyUpdate => match self.update_flow.state().clone() {
+                    flow::UpdateState::Idle | flow::UpdateState::CheckingRelease => {
                         ui.vertical_centered_justified(|ui| {
                             ui.add_space((ui.available_height()) / 2. - 54.);
                             ui.label(
@@ -308,295 +794,215 @@ impl eframe::App for App {
                             ui.add(egui::Spinner::new().size(32.));
                         });
                     }
-                    Some(Err(err)) => {
-                        ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
-                            ui.label(RichText::new("Error occurred!").strong().italics());
-                            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
-                                if ui.button("🔃").clicked() {
-                                self.promise.ventoy_release_info = None;
-                                }
-                            });
-                        });
+                    flow::UpdateState::ReleaseFailed(err) => {
+                        ui.label(RichText::new("Error occurred!").strong().italics());
                         ui.separator();
-                        ui.label(RichText::new(err).color(egui::Color32::LIGHT_RED));
+                        if draw_error(ui, &err) {
+                            self.handle_update_event(ctx, UpdateEvent::Retry);
+                        }
                     }
-                    Some(Ok(release)) => match self.frame.ventoy_update {
-                        VentoyUpdateFrames::FoundRelease => {
-                            let mut release_msg = egui::text::LayoutJob::default();
-                            release_msg.append(
-                                "Found latest release ",
-                                0.,
-                                egui::TextFormat {
-                                    font_id: eframe::epaint::FontId {
-                                        size: 22.,
-                                        ..Default::default()
-                                    },
+                    flow::UpdateState::FoundRelease(release) => {
+                        let mut release_msg = egui::text::LayoutJob::default();
+                        release_msg.append(
+                            "Found latest release ",
+                            0.,
+                            egui::TextFormat {
+                                font_id: eframe::epaint::FontId {
+                                    size: 22.,
                                     ..Default::default()
                                 },
-                            );
-                            release_msg.append(
-                                release.tag_name.as_str(),
-                                0.,
-                                egui::TextFormat {
-                                    font_id: eframe::epaint::FontId {
-                                        size: 26.,
-                                        ..Default::default()
-                                    },
-                                    color: egui::Color32::from_rgb(255, 255, 15),
+                                ..Default::default()
+                            },
+                        );
+                        release_msg.append(
+                            release.tag_name.as_str(),
+                            0.,
+                            egui::TextFormat {
+                                font_id: eframe::epaint::FontId {
+                                    size: 26.,
                                     ..Default::default()
                                 },
-                            );
+                                color: egui::Color32::from_rgb(255, 255, 15),
+                                ..Default::default()
+                            },
+                        );
 
-                            ui.vertical_centered(|ui| {
-                                ui.add_space(ui.available_height() / 2. - 50.);
-                                ui.label(release_msg);
-                                ui.add_space(8.);
-                                if ui.button(RichText::new("⮋ Download").size(32.)).clicked() {
-                                    self.frame.ventoy_update = VentoyUpdateFrames::Downloading;
-                                    info!("Entered `{:?}` frame", self.frame.ventoy_update);
-                                }
-                            });
+                        ui.vertical_centered(|ui| {
+                            ui.add_space(ui.available_height() / 2. - 50.);
+                            ui.label(release_msg);
+                            ui.add_space(8.);
+                            if ui.button(RichText::new("⮋ Download").size(32.)).clicked() {
+                                let target = self.config.ventoy_pkg_target.or_else(PkgTarget::native);
+                                self.handle_update_event(ctx, UpdateEvent::Download(target));
+                            }
+                            ui.add_space(8.);
+                            draw_pkg_target_combobox(ui, &mut self.config.ventoy_pkg_target);
+                        });
+                    }
+                    flow::UpdateState::Downloading { .. } => {
+                        let cancelling = self
+                            .tasks
+                            .ventoy_update_pkg
+                            .as_ref()
+                            .is_some_and(|token| token.is_cancelled());
+                        ui.vertical_centered(|ui| {
+                            ui.add_space(ui.available_height() / 2. - 62.);
+                            ui.heading(if cancelling { "Cancelling..." } else { "Downloading..." });
+                            ui.add_space(8.);
+                            ui.add(egui::Spinner::new().size(32.));
+                            ui.add_space(8.);
+                            if ui.add_enabled(!cancelling, egui::Button::new("✖ Cancel")).clicked() {
+                                self.handle_update_event(ctx, UpdateEvent::Cancel);
+                            }
+                        });
+                    }
+                    flow::UpdateState::Done { pkg_dir, target, .. } => {
+                        if self.ventoy_update_bin.is_none() {
+                            self.ventoy_update_bin = utils::find_file(pkg_dir.as_path(), &target.bin_name());
+                            debug!("Path to ventoy release pkg binary: {:?}", self.ventoy_update_bin);
                         }
-                        VentoyUpdateFrames::Downloading => {
 
-                            fn extract_pkg<P: AsRef<Path>>(pkg_path: P, dest_dir: P) -> Result<(), String> {
+                        let runs_here = target.runs_here();
+                        ui.vertical_centered(|ui| {
+                            ui.add_space(ui.available_height() / 2. - 80.);
+                            ui.heading(
+                                RichText::new("All Done!")
+                                    .size(42.)
+                                    .color(egui::Color32::from_rgb(83, 157, 235)),
+                            );
+                            ui.add_space(16.);
+                            if ui
+                                .button(RichText::new("📂 Open Folder").size(32.))
+                                .clicked()
+                            {
+                                utils::open_in_explorer(
+                                    self
+                                        .ventoy_update_bin
+                                        .as_ref()
+                                        .unwrap()
+                                        .parent()
+                                        .unwrap(),
+                                )
+                                .unwrap();
+                            }
+                            ui.add_space(8.);
+                            if !runs_here {
+                                ui.label(
+                                    RichText::new(format!("⚠ The {target} package doesn't run on this system"))
+                                        .color(egui::Color32::YELLOW),
+                                );
+                            }
+                            if ui
+                                .add_enabled(
+                                    runs_here,
+                                    egui::Button::new(RichText::new("🗖 Launch Ventoy2Disk").size(32.)),
+                                )
+                                .clicked()
+                            {
+                                let ventoy_bin_path = self
+                                    .ventoy_update_bin
+                                    .as_ref()
+                                    .unwrap();
                                 #[cfg(windows)]
                                 {
-                                    update::extract_zip(
-                                        pkg_path,
-                                        dest_dir,
-                                    ).map_err(|e| e.to_string())?;
+                                    match utils::runas_admin(ventoy_bin_path) {
+                                        Ok(_) => {
+                                            self.prompt.ventoy_launch_info.visible = true;
+                                            self.prompt.ventoy_launch_err.visible = false;
+                                        }
+                                        Err(e) => {
+                                            self.prompt.ventoy_launch_err.visible = true;
+                                            self.prompt.ventoy_launch_err.text = e.to_string();
+                                        }
+                                    };
                                 }
                                 #[cfg(target_os = "linux")]
                                 {
-                                    update::extract_targz(
-                                        pkg_path,
-                                        dest_dir,
-                                    ).map_err(|e| e.to_string())?;
-                                }
-                                Ok(())
-                            }
-
-                            let ventoy_update_pkg_promise =
-                                self.promise.ventoy_update_pkg.get_or_insert_with(|| {
-                                    let ctx = ctx.clone();
-                                    let (sender, promise) = Promise::new();
-                                    
-                                    let native_os = std::env::consts::OS;
-                                    
-                                    // Use cache if exists
-                                    let cached_pkg = self.cache.ventoy_update_pkg.as_ref();
-                                    if cached_pkg.is_some() && release.tag_name.contains(&cached_pkg.unwrap().version) {
-                                            let cached_pkg = cached_pkg.unwrap();
-                                            let ventoy_bin_dir = cached_pkg.path.parent().unwrap()
-                                                .join(format!("ventoy-{}-{}", release.tag_name, native_os));
-                                            debug!("Path to ventoy release pkg binary: {:?}", ventoy_bin_dir);
-                                            fs::create_dir_all(&ventoy_bin_dir).unwrap();
-                                            let send = extract_pkg(cached_pkg.path.as_path(), ventoy_bin_dir.as_path())
-                                                .map(|_| (ventoy_bin_dir, cached_pkg.clone()));
-                                            sender.send(send);
-                                            ctx.request_repaint();
-                                    } else {
-                                        let mut pkg_idx: Option<usize> = None;
-
-                                        for (idx, asset) in release.assets.iter().enumerate() {
-                                            if asset.name.to_lowercase().contains(native_os) {
-                                                pkg_idx = Some(idx);
-                                                break;
-                                            }
-                                        }
-
-                                        match pkg_idx {
-                                            Some(idx) => {
-                                                let pkg_name =
-                                                    release.assets[idx].name.to_string();
-                                                let request = ehttp::Request::get(
-                                                    release
-                                                        .assets[idx]
-                                                        .download_url
-                                                        .clone(),
-                                                );
-
-                                                // ! rust fmt dead here aswell ???
-                                                let mut pkg_path =
-                                                    crate::defines::app_cache_dir().expect("expect to have a os-wide cache dir");
-                                                let mut ventoy_bin_dir = pkg_path.clone();
-                                                pkg_path.push(pkg_name);
-
-                                                ventoy_bin_dir.push(format!(
-                                                    "ventoy-{}-{}",
-                                                    release.tag_name, native_os
-                                                ));
-                                                debug!("Path to ventoy release pkg binary: {:?}", ventoy_bin_dir);
-                                                fs::create_dir_all(&ventoy_bin_dir).unwrap();
-
-
-                                                ehttp::fetch(request, move |response| {
-                                                    let pkg_status = response.and_then(|response| {
-                                                        // ! wow...what bootiphul code...rustfmt...aarghaagaaargh
-                                                        match update::write_resp_to_file(response, &pkg_path)
-                                                        {
-                                                            Ok(_) => {
-                                                                extract_pkg(&pkg_path, &ventoy_bin_dir)?;
-                                                                Ok((ventoy_bin_dir, ReleasePkg { version: release.tag_name, path: pkg_path}))
-                                                            },
-                                                            Err(e) => Err(e.to_string()),
-                                                        }
-                                                    });
-                                                    match &pkg_status {
-                                                        Ok(pkg) => debug!("Fetching ventoy release pkg: {:#?}", pkg),
-                                                        Err(err) => error!("Error fetching ventoy release pkg: {}", err),
-                                                    }
-                                                    sender.send(pkg_status);
+                                    match elevate::Elevator::detect() {
+                                        Ok(elevator) => {
+                                            let ventoy_bin_path = ventoy_bin_path.clone();
+                                            let ctx = ctx.clone();
+                                            self.prompt.ventoy_launch_info.visible = true;
+                                            self.promise.ventoy_launch =
+                                                Some(Promise::spawn_thread("ventoy_launch", move || {
+                                                    let launched = elevator
+                                                        .run::<&str>(&ventoy_bin_path, &[])
+                                                        .map(|_| ())
+                                                        .map_err(|e| match e {
+                                                            elevate::ElevateError::Denied(_) => format!(
+                                                                "Ventoy2Disk needs root privileges to \
+                                                                write to drives, {e}"
+                                                            ),
+                                                            e => format!("Ventoy2Disk failed: {e}"),
+                                                        });
                                                     ctx.request_repaint();
-                                                });
-                                            }
-                                            None => {
-                                                sender.send(Err(
-                                                    "failed to find correct pkg for native os"
-                                                        .to_string(),
-                                                ));
-                                                ctx.request_repaint();
-                                            }
+                                                    launched
+                                                }));
+                                        }
+                                        Err(e) => {
+                                            self.prompt.ventoy_launch_err.visible = true;
+                                            self.prompt.ventoy_launch_err.text = e.to_string();
                                         }
                                     }
-                                    promise
-                                });
-
-                            ui.vertical_centered(|ui| {
-                                ui.add_space(ui.available_height() / 2. - 46.);
-                                ui.heading("Downloading...");
-                                ui.add_space(8.);
-                                ui.add(egui::Spinner::new().size(32.));
-                            });
-
-                            match ventoy_update_pkg_promise.ready() {
-                                None => (),
-                                Some(Err(err)) => {
-                                    self.ventoy_update_dir = Some(Err(err.to_string()));
-                                    self.frame.ventoy_update = VentoyUpdateFrames::Failed;
-                                    info!("Entered `{:?}` frame", self.frame.ventoy_update);
                                 }
-                                Some(Ok(pkg)) => {
-                                    self.ventoy_update_dir = Some(Ok(pkg.0.clone()));
-
-                                    // setup cache
-                                    self.cache.ventoy_update_pkg = Some(ReleasePkg { version: pkg.1.version.clone(), path: pkg.1.path.clone() });
-
-                                    self.frame.ventoy_update = VentoyUpdateFrames::Done;
-                                    info!("Entered `{:?}` frame", self.frame.ventoy_update);
+                            }
+                            #[cfg(target_os = "linux")]
+                            if let Some(promise) = &self.promise.ventoy_launch {
+                                if let Some(launched) = promise.ready() {
+                                    if let Err(e) = launched {
+                                        error!("{}", e);
+                                        self.prompt.ventoy_launch_info.visible = false;
+                                        self.prompt.ventoy_launch_err.visible = true;
+                                        self.prompt.ventoy_launch_err.text = e.clone();
+                                    }
+                                    self.promise.ventoy_launch = None;
                                 }
                             }
-                        }
-                        VentoyUpdateFrames::Done => {
-                            if self.ventoy_update_bin.is_none() {
-                                self.ventoy_update_bin = utils::find_file(
-                                    self.ventoy_update_dir
-                                        .as_ref()
-                                        .expect("pkg must exist if reached `Done` frame arm, i.e. bin must also exist")
-                                        .as_ref()
-                                        .expect("err case will only exist in `Failed` frame arm")
-                                        .as_path(),
-                                    update::ventoy_bin_name(),
-                                );
-                                debug!("Path to ventoy release pkg binary: {:?}", self.ventoy_update_bin);
+                            ui.add_space(8.);
+                            if ui
+                                .button(RichText::new("🔑 Secure Boot Setup").size(32.))
+                                .on_hover_text("Check the installed drive and enroll its key with MokManager")
+                                .clicked()
+                            {
+                                self.page = AppPages::Drive;
+                                self.scan_drive(ui.ctx());
                             }
+                        });
 
-                            ui.vertical_centered(|ui| {
-                                ui.add_space(ui.available_height() / 2. - 80.);
-                                ui.heading(
-                                    RichText::new("All Done!")
-                                        .size(42.)
-                                        .color(egui::Color32::from_rgb(83, 157, 235)),
-                                );
-                                ui.add_space(16.);
-                                if ui
-                                    .button(RichText::new("📂 Open Folder").size(32.))
-                                    .clicked()
-                                {
-                                    utils::open_in_explorer(
-                                        self
-                                            .ventoy_update_bin
-                                            .as_ref()
-                                            .unwrap()
-                                            .parent()
-                                            .unwrap(),
-                                    )
-                                    .unwrap();
-                                }
-                                ui.add_space(8.);
-                                if ui
-                                    .button(RichText::new("🗖 Launch Ventoy2Disk").size(32.))
-                                    .clicked()
-                                {
-                                    let ventoy_bin_path = self
-                                        .ventoy_update_bin
-                                        .as_ref()
-                                        .unwrap();
-                                    #[cfg(windows)]
-                                    {
-                                        match utils::runas_admin(ventoy_bin_path) {
-                                            Ok(_) => {
-                                                self.prompt.ventoy_launch_info.visible = true;
-                                                self.prompt.ventoy_launch_err.visible = false;
-                                            }
-                                            Err(e) => {
-                                                self.prompt.ventoy_launch_err.visible = true;
-                                                self.prompt.ventoy_launch_err.text = e.to_string();
-                                            }
-                                        };
-                                    }
-                                    #[cfg(target_os = "linux")]
-                                    {
-                                        match std::process::Command::new(ventoy_bin_path).spawn()
-                                        {
-                                            Ok(_) => self.prompt.ventoy_launch_info.visible = true,
-                                            Err(e) => {
-                                                self.prompt.ventoy_launch_err.visible = true;
-                                                self.prompt.ventoy_launch_err.text = e.to_string();
-                                            }
-                                        }
-                                    }
+                        draw_prompt_dialog(
+                            ctx,
+                            &mut self.prompt.ventoy_launch_err,
+                            egui::Color32::LIGHT_RED,
+                        ); // error dialog
+                        draw_prompt_dialog(
+                            ctx,
+                            &mut self.prompt.ventoy_launch_info,
+                            egui::Color32::WHITE,
+                        );
+                    }
+                    flow::UpdateState::Failed { error: err, .. } => {
+                        ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
+                            ui.label(RichText::new("Error occurred!").strong().italics());
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                                if ui.button("⮨ Back").clicked() {
+                                    self.handle_update_event(ctx, UpdateEvent::Back);
                                 }
                             });
-
-                            draw_prompt_dialog(
-                                ctx,
-                                &mut self.prompt.ventoy_launch_err,
-                                egui::Color32::LIGHT_RED,
-                            ); // error dialog
-                            draw_prompt_dialog(
-                                ctx,
-                                &mut self.prompt.ventoy_launch_info,
-                                egui::Color32::WHITE,
-                            );
-                        }
-                        VentoyUpdateFrames::Failed => {
-                            ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
-                                ui.label(RichText::new("Error occurred!").strong().italics());
-                                ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
-                                    if ui.button("🔃").clicked() {
-                                        self.promise.ventoy_update_pkg = None;
-                                        self.frame.ventoy_update = VentoyUpdateFrames::Downloading;
-                                        info!("Entered `{:?}` frame", self.frame.ventoy_update);
-                                    }
-                                });
-                            });
-                            ui.separator();
-                            // ! why why rustfmt why
-                            ui.label(RichText::new(self.ventoy_update_dir.as_ref().unwrap().as_ref().unwrap_err()).color(egui::Color32::LIGHT_RED));
+                        });
+                        ui.separator();
+                        if draw_error(ui, &err) {
+                            self.handle_update_event(ctx, UpdateEvent::Retry);
                         }
-                    },
+                    }
                 },
                 AppPages::ReleaseBrowse => {
                     ui.horizontal(|ui| {
                         ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
-                            let filter_enabled: bool = match release_feeds_status {
-                                Some(Ok(_)) => true,
-                                Some(Err(_)) => false,
-                                None => false,
+                            let groups = match self.feeds_flow.state() {
+                                flow::FeedsState::Loaded { groups, .. } => groups.clone(),
+                                _ => Vec::new(),
                             };
-                            ui.add_enabled_ui(filter_enabled, |ui| {
+                            ui.add_enabled_ui(!groups.is_empty(), |ui| {
                                 ui.collapsing(" 📃 Filter", |ui| {
                                     ui.horizontal(|ui| {
                                         ui.label("By name:");
@@ -616,8 +1022,8 @@ impl eframe::App for App {
                                             .show_index(
                                                 ui,
                                                 &mut self.filter_group_by_combobox_idx,
-                                                self.filter_group_by_combobox.len(),
-                                                |idx| self.filter_group_by_combobox[idx].clone(),
+                                                groups.len(),
+                                                |idx| groups[idx].clone(),
                                             );
                                     });
                                 });
@@ -625,29 +1031,49 @@ impl eframe::App for App {
                         });
                         ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
                             if ui.button("🔃").clicked() {
-                                self.promise.release_feeds = None;
-                                self.cache.release_feeds = Default::default();
-                                self.is_groups_processed = false;
+                                self.handle_feeds_event(ctx, FeedsEvent::Refresh);
                             }
                         });
                     });
                     ui.separator();
 
+                    if !self.torrents.is_empty() {
+                        self.draw_tracked_torrents(ui);
+                        ui.separator();
+                    }
+
                     ScrollArea::vertical()
                         .auto_shrink([false; 2])
-                        .show(ui, |ui| match release_feeds_status {
-                            Some(Ok(_)) => self.draw_release_cards(ui),
-                            Some(Err(err)) => {
-                                ui.label(RichText::new(err).color(egui::Color32::LIGHT_RED));
+                        .show(ui, |ui| match self.feeds_flow.state() {
+                            flow::FeedsState::Loaded { .. } => self.draw_release_cards(ui),
+                            flow::FeedsState::Failed(err) => {
+                                let err = err.clone();
+                                if draw_error(ui, &err) {
+                                    self.handle_feeds_event(ctx, FeedsEvent::Retry);
+                                }
                             }
-                            None => {
+                            flow::FeedsState::Idle | flow::FeedsState::Loading => {
                                 ui.vertical_centered(|ui| {
-                                    ui.add_space(ui.available_height() / 2.0 - 36.0);
+                                    ui.add_space(ui.available_height() / 2.0 - 48.0);
                                     ui.add(egui::Spinner::new().size(36.));
+                                    ui.add_space(8.);
+                                    let cancelling = self
+                                        .tasks
+                                        .release_feeds
+                                        .as_ref()
+                                        .is_some_and(|token| token.is_cancelled());
+                                    if ui.add_enabled(!cancelling, egui::Button::new("✖ Cancel")).clicked() {
+                                        self.handle_feeds_event(ctx, FeedsEvent::Cancel);
+                                    }
                                 });
                             }
                         });
                 }
+                AppPages::Drive => self.draw_drive(ui),
+                AppPages::Flash => self.draw_flash(ui),
+                AppPages::Downloads => self.draw_downloads(ui),
+                AppPages::Logs => self.draw_logs(ui),
+                AppPages::Settings => self.draw_settings(ui),
             }
         });
 
@@ -698,6 +1124,43 @@ fn draw_release_footer(ctx: &egui::Context) {
     });
 }
 
+/// Manual override of the Ventoy package to download, for other architectures.
+fn draw_pkg_target_combobox(ui: &mut egui::Ui, pkg_target: &mut Option<PkgTarget>) {
+    let text = |target: Option<PkgTarget>| match target {
+        Some(target) => target.to_string(),
+        None => match PkgTarget::native() {
+            Some(native) => format!("auto ({native})"),
+            None => "auto".to_string(),
+        },
+    };
+    ui.horizontal(|ui| {
+        ui.label("Package:");
+        egui::ComboBox::from_id_source("ventoy-pkg-target-combobox")
+            .selected_text(text(*pkg_target))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(pkg_target, None, text(None));
+                for kind in [package::PkgKind::Linux, package::PkgKind::Windows] {
+                    for arch in package::Arch::ALL {
+                        if kind == package::PkgKind::Windows && arch == package::Arch::Mips64el {
+                            continue;
+                        }
+                        let target = Some(PkgTarget { kind, arch });
+                        ui.selectable_value(pkg_target, target, text(target));
+                    }
+                }
+            });
+    });
+}
+
+/// Shows `err` with what to do about it, returns whether retrying was asked for.
+fn draw_error(ui: &mut egui::Ui, err: &Error) -> bool {
+    ui.label(RichText::new(err.to_string()).color(egui::Color32::LIGHT_RED));
+    ui.add_space(4.);
+    ui.label(RichText::new(err.suggestion()).italics());
+    ui.add_space(4.);
+    err.is_retryable() && ui.button("🔃 Retry").clicked()
+}
+
 fn draw_prompt_dialog(ctx: &egui::Context, prompt: &mut PromptDialog, text_color: egui::Color32) {
     if prompt.visible {
         egui::Window::new(&prompt.title)
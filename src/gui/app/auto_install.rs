@@ -0,0 +1,138 @@
+use crate::defines;
+use eframe::egui;
+use tracing::info;
+use ventoy_toybox_core::{
+    auto_install::{self, TemplateLibrary},
+    drive::VentoyDrive,
+};
+
+use super::{drive::draw_image_combobox, App};
+
+impl App {
+    fn reload_templates(&mut self) {
+        match TemplateLibrary::new(defines::app_templates_dir()).templates() {
+            Ok(templates) => self.auto_install.templates = templates,
+            Err(err) => {
+                self.drive_status = Some(Err(format!("Failed to load templates: {err}")));
+            }
+        }
+        if self.auto_install.template_idx >= self.auto_install.templates.len() {
+            self.auto_install.template_idx = 0;
+        }
+    }
+
+    pub(super) fn draw_auto_install(&mut self, ui: &mut egui::Ui) {
+        if self.auto_install.templates.is_empty() {
+            self.reload_templates();
+        }
+        ui.label("Renders an unattended install template onto the drive and links it to an image.");
+        ui.add_space(4.);
+
+        let library = TemplateLibrary::new(defines::app_templates_dir());
+        let form = &mut self.auto_install;
+        let mut reload = false;
+        egui::Grid::new("auto-install-grid")
+            .num_columns(2)
+            .spacing([12., 6.])
+            .show(ui, |ui| {
+                ui.label("Image:");
+                draw_image_combobox(
+                    ui,
+                    "auto-install-image-combobox",
+                    &self.drive_images,
+                    &mut form.image_idx,
+                );
+                ui.end_row();
+
+                ui.label("Template:");
+                ui.horizontal(|ui| {
+                    let templates = &form.templates;
+                    egui::ComboBox::from_id_source("auto-install-template-combobox")
+                        .width(240.)
+                        .show_index(ui, &mut form.template_idx, templates.len(), |i| {
+                            format!("{} ({})", templates[i].name, templates[i].kind)
+                        });
+                    if let Some(template) = form.templates.get(form.template_idx) {
+                        if ui
+                            .add_enabled(!template.builtin, egui::Button::new("🗑"))
+                            .on_hover_text("Remove from library")
+                            .clicked()
+                        {
+                            if let Err(err) = library.remove(template) {
+                                self.drive_status = Some(Err(err.to_string()));
+                            }
+                            reload = true;
+                        }
+                    }
+                });
+                ui.end_row();
+
+                if let Some(template) = form.templates.get(form.template_idx) {
+                    for var in template.variables() {
+                        ui.label(format!("{var}:"));
+                        if auto_install::is_secret(var) {
+                            ui.add(
+                                egui::TextEdit::singleline(
+                                    form.secrets.entry(var.to_string()).or_default(),
+                                )
+                                .password(true),
+                            )
+                            .on_hover_text("Not saved, it has to be entered again after a restart");
+                        } else {
+                            ui.text_edit_singleline(
+                                self.config
+                                    .auto_install_vars
+                                    .entry(var.to_string())
+                                    .or_default(),
+                            );
+                        }
+                        ui.end_row();
+                    }
+                }
+
+                ui.label("Import:");
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut form.import_path)
+                            .hint_text("path to a template file"),
+                    );
+                    if ui.button("📥").on_hover_text("Add to library").clicked() {
+                        self.drive_status = Some(
+                            library
+                                .import(form.import_path.trim())
+                                .map(|template| format!("Imported template `{}`", template.name))
+                                .map_err(|e| format!("Failed to import template: {e}")),
+                        );
+                        reload = true;
+                    }
+                });
+                ui.end_row();
+            });
+        if reload {
+            self.reload_templates();
+        }
+        ui.add_space(4.);
+
+        if ui.button("💾 Install").clicked() {
+            let form = &self.auto_install;
+            if let Some(template) = form.templates.get(form.template_idx) {
+                let drive = VentoyDrive::new(&self.config.ventoy_drive);
+                let mut vars = self.config.auto_install_vars.clone();
+                vars.extend(form.secrets.clone());
+                self.drive_status = Some(
+                    auto_install::install(
+                        &drive,
+                        &self.drive_images[form.image_idx].path,
+                        template,
+                        &vars,
+                    )
+                    .map(|script| {
+                        info!("Installed auto install script {:?}", script);
+                        format!("Installed auto install script {script:?}")
+                    })
+                    .map_err(|e| format!("Failed to install template: {e}")),
+                );
+            }
+        }
+    }
+}
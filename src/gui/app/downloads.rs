@@ -0,0 +1,324 @@
+use std::time::{Duration, Instant};
+
+use crate::defines;
+use eframe::egui::{self, RichText, ScrollArea};
+use poll_promise::Promise;
+use tracing::{error, info};
+use ventoy_toybox_core::{
+    downloads::{DownloadKind, DownloadStatus},
+    mirror::{self, Keyring},
+    torrent,
+    utils::{self, FeedsItem},
+};
+
+use super::{App, IsoDownload, TrackedTorrent};
+
+impl App {
+    pub(super) fn keyring(&self) -> Keyring {
+        Keyring {
+            dirs: defines::app_keyring_dir().into_iter().collect(),
+        }
+    }
+
+    pub(super) fn download_iso(&mut self, ctx: &egui::Context, source: mirror::IsoSource) {
+        info!("Downloading {} from {}", source.name, source.url);
+        let ctx = ctx.clone();
+        let task = self
+            .tasks
+            .manager
+            .start(format!("Downloading {}", source.name));
+        let downloads = self.downloads.clone();
+        let keyring = self.config.verify_signatures.then(|| self.keyring());
+        self.iso_downloads.push(IsoDownload {
+            source: source.clone(),
+            result: Promise::spawn_thread("iso-download", move || {
+                let dest_dir = dirs::download_dir()
+                    .or_else(defines::app_cache_dir)
+                    .unwrap_or_default();
+                let verified = mirror::download_iso(
+                    &source,
+                    dest_dir,
+                    &downloads,
+                    keyring.as_ref(),
+                    task.token(),
+                )
+                .map_err(|e| format!("{e}\n{}", e.suggestion()));
+                drop(task);
+                ctx.request_repaint();
+                verified
+            }),
+        });
+    }
+
+    pub(super) fn draw_tracked_torrents(&self, ui: &mut egui::Ui) {
+        ui.collapsing(
+            format!(
+                " 🔗 Sent to {} ({})",
+                self.config.torrent_client.kind,
+                self.torrents.len()
+            ),
+            |ui| {
+                for tracked in &self.torrents {
+                    ui.horizontal(|ui| {
+                        ui.label(&tracked.name);
+                        ui.with_layout(
+                            egui::Layout::right_to_left(egui::Align::Center),
+                            |ui| match (tracked.hash.ready(), &tracked.status) {
+                                (None, _) => {
+                                    ui.add(egui::Spinner::new());
+                                }
+                                (Some(Err(err)), _) | (_, Some(Err(err))) => {
+                                    ui.label(RichText::new(err).color(egui::Color32::LIGHT_RED));
+                                }
+                                (Some(Ok(_)), None) => {
+                                    ui.label("Waiting for status...");
+                                }
+                                (Some(Ok(_)), Some(Ok(status))) => {
+                                    ui.add(
+                                        egui::ProgressBar::new(status.progress)
+                                            .desired_width(160.)
+                                            .text(format!(
+                                                "{:.0}% {}",
+                                                status.progress * 100.,
+                                                status.state
+                                            )),
+                                    );
+                                }
+                            },
+                        );
+                    });
+                }
+            },
+        );
+    }
+
+    pub(super) fn send_to_torrent_client(&mut self, ctx: &egui::Context, item: FeedsItem) {
+        info!(
+            "Sending `{}` to {}",
+            item.name, self.config.torrent_client.kind
+        );
+        let ctx = ctx.clone();
+        let config = self.config.torrent_client.clone();
+        self.torrents.push(TrackedTorrent {
+            name: item.name,
+            hash: Promise::spawn_thread("torrent-add", move || {
+                let hash = torrent::connect(&config)
+                    .add_magnet(&item.magnet, &config.save_path)
+                    .map_err(|e| e.to_string());
+                if let Err(err) = &hash {
+                    error!("Error sending torrent to {}: {}", config.kind, err);
+                }
+                ctx.request_repaint();
+                hash
+            }),
+            status: None,
+        });
+    }
+
+    /// Refreshes the progress of the torrents sent to the torrent client every few seconds.
+    pub(super) fn poll_tracked_torrents(&mut self, ctx: &egui::Context) {
+        const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+        if let Some(promise) = &self.promise.torrent_status {
+            let statuses = match promise.ready() {
+                Some(statuses) => statuses,
+                None => return,
+            };
+            for (hash, status) in statuses {
+                for tracked in &mut self.torrents {
+                    if matches!(tracked.hash.ready(), Some(Ok(h)) if h == hash) {
+                        tracked.status = Some(status.clone());
+                    }
+                }
+            }
+            self.promise.torrent_status = None;
+            self.torrents_polled_at = Some(Instant::now());
+        }
+
+        let hashes = self
+            .torrents
+            .iter()
+            .filter_map(|tracked| match tracked.hash.ready() {
+                Some(Ok(hash)) => Some(hash.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if hashes.is_empty() {
+            return;
+        }
+        if let Some(polled_at) = self.torrents_polled_at {
+            if polled_at.elapsed() < POLL_INTERVAL {
+                ctx.request_repaint_after(POLL_INTERVAL - polled_at.elapsed());
+                return;
+            }
+        }
+
+        let ctx = ctx.clone();
+        let config = self.config.torrent_client.clone();
+        self.promise.torrent_status = Some(Promise::spawn_thread("torrent-status", move || {
+            let mut client = torrent::connect(&config);
+            let statuses = hashes
+                .into_iter()
+                .map(|hash| {
+                    let status = client.status(&hash).map_err(|e| e.to_string());
+                    (hash, status)
+                })
+                .collect();
+            ctx.request_repaint();
+            statuses
+        }));
+    }
+
+    pub(super) fn draw_downloads(&mut self, ui: &mut egui::Ui) {
+        let downloads = self.downloads.downloads();
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("Downloads").strong());
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                if ui
+                    .add_enabled(
+                        downloads
+                            .iter()
+                            .any(|download| download.status.is_finished()),
+                        egui::Button::new("Clear Finished"),
+                    )
+                    .clicked()
+                {
+                    self.downloads.clear_finished();
+                }
+            });
+        });
+        ui.separator();
+        if downloads.is_empty() && self.iso_downloads.is_empty() {
+            ui.label("Nothing downloaded yet.");
+            return;
+        }
+
+        ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                if !self.iso_downloads.is_empty() {
+                    self.draw_iso_downloads(ui);
+                    ui.separator();
+                }
+                for download in &downloads {
+                    ui.horizontal(|ui| {
+                        let kind = match download.request.kind {
+                            DownloadKind::Package => "Package",
+                            DownloadKind::Torrent => "Torrent",
+                            DownloadKind::Iso => "ISO",
+                        };
+                        ui.label(RichText::new(kind).weak());
+                        ui.label(download.request.name())
+                            .on_hover_text(&download.request.url);
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if download.status.is_finished() {
+                                if ui.button("📂").on_hover_text("Open Folder").clicked() {
+                                    if let Some(dir) = download.request.dest.parent() {
+                                        if let Err(err) = utils::open_in_explorer(dir) {
+                                            error!("Failed to open {:?}: {}", dir, err);
+                                        }
+                                    }
+                                }
+                            } else if ui.button("✖ Cancel").clicked() {
+                                self.downloads.cancel(download.id);
+                            }
+                            match &download.status {
+                                DownloadStatus::Queued => {
+                                    ui.label("Queued");
+                                }
+                                DownloadStatus::Running { done, total } => {
+                                    let mib = |bytes: u64| bytes as f32 / (1024. * 1024.);
+                                    let text = match total {
+                                        Some(total) => {
+                                            format!("{:.1} / {:.1} MiB", mib(*done), mib(*total))
+                                        }
+                                        None => format!("{:.1} MiB", mib(*done)),
+                                    };
+                                    let fraction = total
+                                        .filter(|total| *total > 0)
+                                        .map_or(0., |total| *done as f32 / total as f32);
+                                    ui.add(
+                                        egui::ProgressBar::new(fraction)
+                                            .text(text)
+                                            .desired_width(220.),
+                                    );
+                                }
+                                DownloadStatus::Retrying { attempt, error } => {
+                                    ui.label(
+                                        RichText::new(format!("Retrying ({attempt})"))
+                                            .color(egui::Color32::YELLOW),
+                                    )
+                                    .on_hover_text(error.to_string());
+                                }
+                                DownloadStatus::Done => {
+                                    ui.label(
+                                        RichText::new("Done").color(egui::Color32::LIGHT_GREEN),
+                                    );
+                                }
+                                DownloadStatus::Failed(err) => {
+                                    ui.label(
+                                        RichText::new("Failed").color(egui::Color32::LIGHT_RED),
+                                    )
+                                    .on_hover_text(format!("{err}\n{}", err.suggestion()));
+                                }
+                                DownloadStatus::Cancelled => {
+                                    ui.label(RichText::new("Cancelled").weak());
+                                }
+                            }
+                        });
+                    });
+                    ui.separator();
+                }
+            });
+        if !downloads
+            .iter()
+            .all(|download| download.status.is_finished())
+        {
+            ui.ctx().request_repaint_after(Duration::from_millis(250));
+        }
+    }
+
+    fn draw_iso_downloads(&mut self, ui: &mut egui::Ui) {
+        ui.label(RichText::new("Verified Images").strong());
+        let mut remove = None;
+        for (idx, download) in self.iso_downloads.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(&download.source.name);
+                ui.with_layout(
+                    egui::Layout::right_to_left(egui::Align::Center),
+                    |ui| match download.result.ready() {
+                        None => {
+                            ui.add(egui::Spinner::new());
+                            ui.label("Downloading and verifying...");
+                        }
+                        Some(result) => {
+                            if ui.small_button("✖").on_hover_text("Dismiss").clicked() {
+                                remove = Some(idx);
+                            }
+                            match result {
+                                Ok(verified) => {
+                                    let text = match (verified.checksum, verified.signature) {
+                                        (true, true) => "✔ Checksum and signature verified",
+                                        (true, false) => "✔ Checksum verified",
+                                        _ => "⚠ Not verified, no checksums known",
+                                    };
+                                    ui.label(RichText::new(text).color(egui::Color32::LIGHT_GREEN))
+                                        .on_hover_text(verified.path.display().to_string());
+                                }
+                                Err(err) => {
+                                    ui.label(
+                                        RichText::new("Failed").color(egui::Color32::LIGHT_RED),
+                                    )
+                                    .on_hover_text(err);
+                                }
+                            }
+                        }
+                    },
+                );
+            });
+        }
+        if let Some(idx) = remove {
+            self.iso_downloads.remove(idx);
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::sync::{Arc, Mutex};
+
+use eframe::egui::{self, RichText, ScrollArea};
+use poll_promise::Promise;
+use tracing::{debug, info};
+use ventoy_toybox_core::{
+    compat::{CompatEntry, CompatStatus},
+    drive::VentoyDrive,
+    iso,
+    task::CancelToken,
+    ventoy_json::{self, VentoyJson},
+};
+
+use super::{App, DriveImage, IsoInspection};
+
+impl App {
+    pub(super) fn scan_drive(&mut self, ctx: &egui::Context) {
+        let drive = VentoyDrive::new(&self.config.ventoy_drive);
+        if let Some(inspection) = self.iso_inspection.take() {
+            inspection.token.cancel();
+        }
+        match drive.images() {
+            Ok(images) => {
+                self.drive_images = images
+                    .into_iter()
+                    .filter_map(|path| {
+                        let ventoy_path = drive.image_path(&path)?;
+                        let file_name = path.file_name()?.to_str()?;
+                        let feed = self
+                            .cache
+                            .release_feeds
+                            .iter()
+                            .find(|item| item.image_name() == file_name)
+                            .cloned();
+                        Some(DriveImage {
+                            path,
+                            ventoy_path,
+                            selected: feed.is_some(),
+                            feed,
+                            info: None,
+                        })
+                    })
+                    .collect();
+                debug!(
+                    "Found {} images on {:?}",
+                    self.drive_images.len(),
+                    drive.root()
+                );
+                self.inspect_isos(ctx);
+                self.drive_status = None;
+                self.persistence.image_idx = 0;
+                self.auto_install.image_idx = 0;
+                self.reload_themes();
+            }
+            Err(err) => {
+                self.drive_images.clear();
+                self.drive_status =
+                    Some(Err(format!("Failed to scan {:?}: {}", drive.root(), err)));
+            }
+        }
+        self.inspect_secure_boot();
+    }
+
+    /// Reads the ISOs among `drive_images` on another thread, opening each of them can
+    /// take a while on slow drives.
+    fn inspect_isos(&mut self, ctx: &egui::Context) {
+        let isos = self
+            .drive_images
+            .iter()
+            .map(|image| image.path.clone())
+            .filter(|path| {
+                path.extension()
+                    .map_or(false, |ext| ext.eq_ignore_ascii_case("iso"))
+            })
+            .collect::<Vec<_>>();
+        let inspected = Arc::new(Mutex::new(Vec::new()));
+        let token = CancelToken::new();
+        let ctx = ctx.clone();
+        self.iso_inspection = Some(IsoInspection {
+            inspected: Arc::clone(&inspected),
+            token: token.clone(),
+            done: Promise::spawn_thread("iso_inspection", move || {
+                for path in isos {
+                    if token.is_cancelled() {
+                        return;
+                    }
+                    match iso::inspect(&path) {
+                        Ok(info) => inspected.lock().unwrap().push((path, info)),
+                        Err(err) => debug!("Failed to inspect {:?}: {}", path, err),
+                    }
+                    ctx.request_repaint();
+                }
+            }),
+        });
+    }
+
+    /// Fills in what's been inspected so far.
+    pub(super) fn poll_iso_inspection(&mut self) {
+        let Some(inspection) = &self.iso_inspection else {
+            return;
+        };
+        for (path, info) in inspection.inspected.lock().unwrap().drain(..) {
+            if let Some(image) = self
+                .drive_images
+                .iter_mut()
+                .find(|image| image.path == path)
+            {
+                image.info = Some(info);
+            }
+        }
+        if inspection.done.ready().is_some() {
+            self.iso_inspection = None;
+        }
+    }
+
+    pub(super) fn draw_drive(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Ventoy drive:");
+            let mut drive_root = self.config.ventoy_drive.to_string_lossy().to_string();
+            if ui
+                .add(
+                    egui::TextEdit::singleline(&mut drive_root)
+                        .hint_text("mount point of the Ventoy partition"),
+                )
+                .changed()
+            {
+                self.config.ventoy_drive = drive_root.into();
+            }
+            if ui.button("🔃").clicked() {
+                self.scan_drive(ui.ctx());
+            }
+        });
+        ui.separator();
+
+        match &self.drive_status {
+            Some(Ok(msg)) => {
+                ui.label(RichText::new(msg).color(egui::Color32::LIGHT_GREEN));
+            }
+            Some(Err(err)) => {
+                ui.label(RichText::new(err).color(egui::Color32::LIGHT_RED));
+            }
+            None => (),
+        }
+
+        ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                // sections that work on the images of the drive
+                if self.drive_images.is_empty() {
+                    ui.label("No images found, scan a Ventoy drive to list its images.");
+                } else {
+                    egui::CollapsingHeader::new(RichText::new("Boot menu entries").strong())
+                        .default_open(true)
+                        .show(ui, |ui| self.draw_menu_entries(ui));
+                    egui::CollapsingHeader::new(RichText::new("Integrity").strong())
+                        .show(ui, |ui| self.draw_integrity(ui));
+                    egui::CollapsingHeader::new(RichText::new("Persistence").strong())
+                        .show(ui, |ui| self.draw_persistence(ui));
+                    egui::CollapsingHeader::new(RichText::new("Auto Install").strong())
+                        .show(ui, |ui| self.draw_auto_install(ui));
+                }
+                egui::CollapsingHeader::new(RichText::new("Theme").strong())
+                    .show(ui, |ui| self.draw_theme(ui));
+                egui::CollapsingHeader::new(RichText::new("Secure Boot").strong())
+                    .default_open(self.drive_images.is_empty())
+                    .show(ui, |ui| self.draw_secure_boot(ui));
+                egui::CollapsingHeader::new(RichText::new("Profiles").strong())
+                    .show(ui, |ui| self.draw_profiles(ui));
+                egui::CollapsingHeader::new(RichText::new("Sync").strong())
+                    .show(ui, |ui| self.draw_sync(ui));
+            });
+    }
+
+    fn draw_menu_entries(&mut self, ui: &mut egui::Ui) {
+        for image in &mut self.drive_images {
+            let compat = match (&image.feed, &image.info) {
+                (Some(item), _) => self.compat.for_feed(item),
+                (None, Some(info)) => self.compat.for_iso(info),
+                (None, None) => None,
+            };
+            ui.horizontal(|ui| match &image.feed {
+                Some(item) => {
+                    ui.checkbox(&mut image.selected, image.ventoy_path.as_str());
+                    draw_compat_badge(ui, compat);
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        draw_iso_warnings(ui, image.info.as_ref());
+                        ui.label(
+                            RichText::new(format!(
+                                "{} [{}]",
+                                ventoy_json::menu_alias(item),
+                                ventoy_json::menu_class(item)
+                            ))
+                            .color(egui::Color32::LIGHT_GREEN),
+                        );
+                    });
+                }
+                None => {
+                    ui.add_enabled(
+                        false,
+                        egui::Checkbox::new(&mut image.selected, image.ventoy_path.as_str()),
+                    )
+                    .on_disabled_hover_text("Not found in the release feeds");
+                    draw_compat_badge(ui, compat);
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        draw_iso_warnings(ui, image.info.as_ref());
+                        if let Some(name) = image.info.as_ref().and_then(|info| info.name()) {
+                            ui.label(RichText::new(name).weak());
+                        }
+                    });
+                }
+            });
+        }
+        ui.add_space(4.);
+        let any_selected = self.drive_images.iter().any(|image| image.selected);
+        if ui
+            .add_enabled(any_selected, egui::Button::new("📝 Write to ventoy.json"))
+            .on_hover_text("Sets `menu_alias` and `menu_class` of the selected images")
+            .clicked()
+        {
+            let drive = VentoyDrive::new(&self.config.ventoy_drive);
+            self.drive_status = Some(
+                write_menu_entries(&drive, &self.drive_images)
+                    .map(|count| {
+                        format!(
+                            "Wrote menu entries of {} image(s) to {:?}",
+                            count,
+                            drive.ventoy_json_path()
+                        )
+                    })
+                    .map_err(|e| e.to_string()),
+            );
+        }
+    }
+}
+
+/// Badge of how well the image boots with Ventoy, nothing if it's not in the database.
+pub(super) fn draw_compat_badge(ui: &mut egui::Ui, entry: Option<&CompatEntry>) {
+    let Some(entry) = entry else {
+        return;
+    };
+    let color = match entry.status {
+        CompatStatus::Tested => egui::Color32::LIGHT_GREEN,
+        CompatStatus::Grub2 | CompatStatus::Memdisk => egui::Color32::YELLOW,
+        CompatStatus::KnownIssue => egui::Color32::LIGHT_RED,
+    };
+    let mut hint = match &entry.note {
+        Some(note) => format!("{note}\n{}", entry.status.hint()),
+        None => entry.status.hint().to_string(),
+    };
+    if let Some(source) = &entry.source {
+        hint.push_str(&format!("\nSource: {source}"));
+    }
+    ui.label(RichText::new(entry.status.to_string()).small().color(color))
+        .on_hover_text(hint);
+}
+
+/// A warning sign listing what may keep an ISO from booting, nothing if it boots either way.
+fn draw_iso_warnings(ui: &mut egui::Ui, info: Option<&iso::IsoInfo>) {
+    let Some(info) = info else {
+        return;
+    };
+    let warnings = info.warnings();
+    if !warnings.is_empty() {
+        ui.label(RichText::new("⚠").color(egui::Color32::YELLOW))
+            .on_hover_text(warnings.join("\n"));
+    }
+}
+
+fn write_menu_entries(drive: &VentoyDrive, images: &[DriveImage]) -> anyhow::Result<usize> {
+    let mut ventoy_json = VentoyJson::load(drive.ventoy_json_path())?;
+    let mut count = 0;
+    for image in images.iter().filter(|image| image.selected) {
+        if let Some(item) = &image.feed {
+            ventoy_json.set_menu_alias(&image.ventoy_path, &ventoy_json::menu_alias(item))?;
+            ventoy_json.set_menu_class(item.image_name(), &ventoy_json::menu_class(item))?;
+            count += 1;
+        }
+    }
+    ventoy_json.save(drive.ventoy_json_path())?;
+    info!(
+        "Wrote menu entries of {} images to {:?}",
+        count,
+        drive.ventoy_json_path()
+    );
+    Ok(count)
+}
+
+pub(super) fn draw_image_combobox(
+    ui: &mut egui::Ui,
+    id: &str,
+    images: &[DriveImage],
+    idx: &mut usize,
+) {
+    egui::ComboBox::from_id_source(id)
+        .width(320.)
+        .show_index(ui, idx, images.len(), |i| images[i].ventoy_path.clone());
+}
@@ -0,0 +1,416 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use crate::defines;
+use eframe::egui::{self, RichText};
+use poll_promise::Promise;
+#[cfg(target_os = "linux")]
+use tracing::error;
+use tracing::info;
+#[cfg(target_os = "linux")]
+use ventoy_toybox_core::elevate;
+use ventoy_toybox_core::{
+    downloads::{DownloadKind, DownloadRequest},
+    drive, flash,
+    package::{self, PkgTarget},
+    update,
+};
+
+use super::{App, FlashJob};
+
+impl App {
+    pub(super) fn refresh_block_devices(&mut self) {
+        match drive::block_devices() {
+            Ok(devices) => self.flash.devices = devices,
+            Err(err) => {
+                self.flash.devices.clear();
+                self.flash_status = Some(Err(format!("Failed to list drives: {err}")));
+            }
+        }
+        if self.flash.device_idx >= self.flash.devices.len() {
+            self.flash.device_idx = 0;
+        }
+    }
+
+    fn download_livecd(&mut self, ctx: &egui::Context, release: update::Release) {
+        let ctx = ctx.clone();
+        let task = self.tasks.manager.start("Downloading the Ventoy LiveCD");
+        self.tasks.livecd = Some(task.token().clone());
+        let downloads = self.downloads.clone();
+        self.promise.livecd = Some(Promise::spawn_thread("livecd", move || {
+            let target = PkgTarget {
+                kind: package::PkgKind::LiveCd,
+                arch: package::Arch::native().unwrap_or(package::Arch::X86_64),
+            };
+            let downloaded = (|| {
+                let asset = package::select_asset(&release.assets, target)?;
+                let cache_dir = defines::app_cache_dir()
+                    .ok_or_else(|| anyhow::anyhow!("no cache dir to download to"))?;
+                fs::create_dir_all(&cache_dir)?;
+                // downloads are written atomically, an existing file is complete
+                let iso = cache_dir.join(&asset.name);
+                if !iso.is_file() {
+                    let id = downloads.enqueue(DownloadRequest {
+                        url: asset.download_url.clone(),
+                        dest: iso.clone(),
+                        kind: DownloadKind::Iso,
+                    });
+                    downloads.wait(id, task.token())?;
+                }
+                Ok::<_, anyhow::Error>(iso)
+            })()
+            .map_err(|e| format!("Failed to download the LiveCD: {e:#}"));
+            drop(task);
+            ctx.request_repaint();
+            downloaded
+        }));
+    }
+
+    pub(super) fn draw_flash(&mut self, ui: &mut egui::Ui) {
+        match &self.flash_status {
+            Some(Ok(msg)) => {
+                ui.label(RichText::new(msg).color(egui::Color32::LIGHT_GREEN));
+            }
+            Some(Err(err)) => {
+                ui.label(RichText::new(err).color(egui::Color32::LIGHT_RED));
+            }
+            None => (),
+        }
+
+        ui.label(RichText::new("Source").strong());
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.flash.from_image, false, "Ventoy LiveCD");
+            ui.radio_value(&mut self.flash.from_image, true, "Disk image");
+        });
+        if self.flash.from_image {
+            ui.label(
+                "For images that don't boot through Ventoy, e.g. Raspberry Pi or ChromeOS Flex. \
+                 .gz and .xz compressed images are decompressed while writing.",
+            );
+            ui.add(
+                egui::TextEdit::singleline(&mut self.flash.image_path)
+                    .hint_text("path to an .img, .img.gz, .img.xz or .iso file"),
+            );
+        } else {
+            ui.label(
+                "Boots into a minimal system that installs Ventoy, for machines without a desktop.",
+            );
+        }
+        ui.add_space(4.);
+        if let Some(promise) = &self.promise.livecd {
+            if let Some(downloaded) = promise.ready() {
+                match downloaded {
+                    Ok(iso) => self.flash.livecd = Some(iso.clone()),
+                    Err(err) => self.flash_status = Some(Err(err.clone())),
+                }
+                self.promise.livecd = None;
+            }
+        }
+        if !self.flash.from_image {
+            ui.horizontal(|ui| {
+                let release = self.update_flow.state().release().cloned();
+                let downloading = self.promise.livecd.is_some();
+                if ui
+                    .add_enabled(
+                        release.is_some() && !downloading,
+                        egui::Button::new("⮋ Download LiveCD"),
+                    )
+                    .on_disabled_hover_text("Waiting for the latest Ventoy release")
+                    .clicked()
+                {
+                    self.download_livecd(ui.ctx(), release.unwrap());
+                }
+                if downloading {
+                    ui.add(egui::Spinner::new());
+                    if let Some(token) = &self.tasks.livecd {
+                        if ui
+                            .add_enabled(!token.is_cancelled(), egui::Button::new("✖ Cancel"))
+                            .clicked()
+                        {
+                            token.cancel();
+                        }
+                    }
+                }
+                if let Some(iso) = &self.flash.livecd {
+                    ui.label(iso.to_string_lossy());
+                }
+            });
+        }
+        ui.separator();
+
+        ui.label(RichText::new("Target").strong());
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.flash.to_file, false, "Drive");
+            ui.radio_value(&mut self.flash.to_file, true, "Image file");
+        });
+        if self.flash.to_file {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.flash.file_path)
+                    .hint_text("path of the image file to write"),
+            );
+        } else {
+            ui.horizontal(|ui| {
+                let devices = &self.flash.devices;
+                egui::ComboBox::from_id_source("flash-device-combobox")
+                    .width(360.)
+                    .show_index(ui, &mut self.flash.device_idx, devices.len(), |i| {
+                        let removable = if devices[i].removable { "⏏ " } else { "" };
+                        format!("{}{}", removable, devices[i].description())
+                    });
+                if ui.button("🔃").clicked() {
+                    self.refresh_block_devices();
+                }
+            });
+        }
+        ui.add_space(4.);
+
+        let target = if self.flash.to_file {
+            Some(self.flash.file_path.trim().to_string()).filter(|path| !path.is_empty())
+        } else {
+            self.flash
+                .devices
+                .get(self.flash.device_idx)
+                .map(|device| device.path.to_string_lossy().to_string())
+        };
+        let source = if self.flash.from_image {
+            Some(PathBuf::from(self.flash.image_path.trim()))
+                .filter(|path| flash::is_flashable(path))
+        } else {
+            self.flash.livecd.clone()
+        };
+        let ready = source.is_some() && target.is_some() && self.flash_job.is_none();
+        if ui
+            .add_enabled(ready, egui::Button::new("💾 Write"))
+            .on_disabled_hover_text(format!(
+                "Pick a target and one of {}",
+                flash::IMAGE_EXTENSIONS.join(", ")
+            ))
+            .clicked()
+        {
+            self.flash.confirm = true;
+            self.flash.typed_name.clear();
+            self.flash.confirm_device = if self.flash.to_file {
+                None
+            } else {
+                self.flash.devices.get(self.flash.device_idx).cloned()
+            };
+            self.flash.safety = self.flash.confirm_device.as_ref().map(|device| {
+                drive::assess_device(device)
+                    .map_err(|e| format!("Failed to check what's mounted: {e}"))
+            });
+        }
+
+        // Close the confirmation once what it was about is gone, e.g. the image path was
+        // cleared or the target switched to a drive while it was open
+        let missing = if self.flash.to_file {
+            target.is_none()
+        } else {
+            self.flash.confirm_device.is_none()
+        };
+        if self.flash.confirm && (source.is_none() || missing) {
+            self.flash.confirm = false;
+        }
+        if self.flash.confirm {
+            let (description, target) = match &self.flash.confirm_device {
+                Some(device) if !self.flash.to_file => (
+                    device.description(),
+                    Some(device.path.to_string_lossy().to_string()),
+                ),
+                _ => (target.clone().unwrap_or_default(), target),
+            };
+            let mut open = true;
+            egui::Window::new("Erase and write?")
+                .collapsible(false)
+                .resizable(false)
+                .open(&mut open)
+                .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+                .show(ui.ctx(), |ui| {
+                    ui.label("Everything on");
+                    ui.label(
+                        RichText::new(&description)
+                            .strong()
+                            .color(egui::Color32::YELLOW),
+                    );
+                    ui.label("will be overwritten. This can't be undone.");
+                    ui.add_space(8.);
+                    let allowed = if self.flash.to_file {
+                        let path = target.clone().unwrap_or_default();
+                        if drive::is_device_node(&path) {
+                            Err(format!("{path} is a device, pick it as a drive instead"))
+                        } else {
+                            Ok(())
+                        }
+                    } else {
+                        let device = self.flash.confirm_device.as_ref().unwrap();
+                        match &self.flash.safety {
+                            Some(Ok(safety)) => {
+                                for reason in &safety.system {
+                                    ui.label(
+                                        RichText::new(format!("⛔ {reason}"))
+                                            .color(egui::Color32::LIGHT_RED),
+                                    );
+                                }
+                                for warning in &safety.warnings {
+                                    ui.label(
+                                        RichText::new(format!("⚠ {warning}"))
+                                            .color(egui::Color32::YELLOW),
+                                    );
+                                }
+                                ui.add_space(4.);
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("Type {} to confirm:", device.name));
+                                    ui.text_edit_singleline(&mut self.flash.typed_name);
+                                });
+                                drive::check_overwrite(
+                                    device,
+                                    safety,
+                                    &self.flash.typed_name,
+                                    self.config.expert_mode,
+                                )
+                                .map_err(|e| e.to_string())
+                            }
+                            Some(Err(err)) => Err(err.clone()),
+                            None => Err("The drive wasn't checked".to_string()),
+                        }
+                    };
+                    ui.add_space(8.);
+                    ui.horizontal(|ui| {
+                        if ui.button("Cancel").clicked() {
+                            self.flash.confirm = false;
+                        }
+                        let mut button = ui.add_enabled(
+                            allowed.is_ok(),
+                            egui::Button::new(
+                                RichText::new("Erase and write").color(egui::Color32::LIGHT_RED),
+                            ),
+                        );
+                        if let Err(err) = &allowed {
+                            button = button.on_disabled_hover_text(err.as_str());
+                        }
+                        if button.clicked() {
+                            self.flash.confirm = false;
+                            let kind = if self.flash.to_file {
+                                flash::TargetKind::File
+                            } else {
+                                flash::TargetKind::Device
+                            };
+                            let device = self
+                                .flash
+                                .confirm_device
+                                .clone()
+                                .filter(|_| kind == flash::TargetKind::Device);
+                            self.start_flash(
+                                ui.ctx(),
+                                source.clone().unwrap(),
+                                description.clone(),
+                                target.clone().unwrap().into(),
+                                device,
+                                kind,
+                            );
+                        }
+                    });
+                });
+            if !open {
+                self.flash.confirm = false;
+            }
+        }
+
+        if let Some(job) = &self.flash_job {
+            let progress = *job.progress.lock().unwrap();
+            let stage = match progress.stage {
+                flash::Stage::Writing => "Writing",
+                flash::Stage::Verifying => "Verifying",
+            };
+            ui.add_space(4.);
+            ui.add(
+                egui::ProgressBar::new(progress.fraction().unwrap_or_default())
+                    .show_percentage()
+                    .text(format!(
+                        "{} {}: {}",
+                        stage,
+                        job.target,
+                        drive::human_size(progress.done)
+                    )),
+            );
+            if let Some(result) = job.result.ready() {
+                self.flash_status = Some(match result {
+                    Ok(written) => {
+                        info!("Wrote and verified {} bytes to {}", written, job.target);
+                        Ok(format!(
+                            "Wrote and verified {} to {}",
+                            drive::human_size(*written),
+                            job.target
+                        ))
+                    }
+                    Err(err) => Err(err.clone()),
+                });
+                self.flash_job = None;
+            }
+        }
+    }
+
+    fn start_flash(
+        &mut self,
+        ctx: &egui::Context,
+        image: PathBuf,
+        description: String,
+        target: PathBuf,
+        device: Option<drive::BlockDevice>,
+        kind: flash::TargetKind,
+    ) {
+        info!("Writing {:?} to {:?}", image, target);
+        let progress = Arc::new(Mutex::new(flash::Progress::default()));
+        let job_progress = Arc::clone(&progress);
+        let ctx = ctx.clone();
+        self.flash_status = None;
+        self.flash_job = Some(FlashJob {
+            target: description,
+            progress,
+            result: Promise::spawn_thread("flash", move || {
+                let written = (|| {
+                    // Nothing may be left mounted from the drive while it's overwritten
+                    if let Some(device) = &device {
+                        drive::release_device(device).map_err(|e| format!("{e:#}"))?;
+                    }
+                    // Only the device node's permissions are elevated, not the writing itself
+                    #[cfg(target_os = "linux")]
+                    let mut access = None;
+                    #[cfg(target_os = "linux")]
+                    if drive::is_device_node(&target)
+                        && matches!(
+                            fs::OpenOptions::new().write(true).open(&target),
+                            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied
+                        )
+                    {
+                        info!("Asking for write access to {:?}", target);
+                        access = Some(
+                            elevate::Elevator::detect()
+                                .and_then(|elevator| elevator.grant_access(&target))
+                                .map_err(|e| {
+                                    format!("Failed to get write access to {target:?}: {e}")
+                                })?,
+                        );
+                    }
+                    let written = flash::write_image(&image, &target, kind, |progress| {
+                        *job_progress.lock().unwrap() = progress;
+                        ctx.request_repaint();
+                    })
+                    .map_err(|e| format!("Failed to write {image:?}: {e:#}"));
+                    #[cfg(target_os = "linux")]
+                    if let Some(access) = access {
+                        info!("Revoking the write access to {:?}", target);
+                        if let Err(e) = access.revoke() {
+                            error!("Failed to revoke the write access to {:?}: {}", target, e);
+                        }
+                    }
+                    written
+                })();
+                ctx.request_repaint();
+                written
+            }),
+        });
+    }
+}
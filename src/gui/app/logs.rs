@@ -0,0 +1,110 @@
+use std::fs;
+
+use crate::defines;
+use eframe::egui::{self, RichText, ScrollArea};
+use tracing::info;
+use ventoy_toybox_core::{
+    logs::{self, LogBuffer},
+    utils,
+};
+
+use super::App;
+
+impl App {
+    pub(super) fn draw_logs(&mut self, ui: &mut egui::Ui) {
+        let logs = &mut self.logs;
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("log-level-combobox")
+                .selected_text(
+                    logs.level
+                        .map_or("ALL".to_string(), |level| level.to_string()),
+                )
+                .show_ui(ui, |ui| {
+                    for level in [
+                        tracing::Level::ERROR,
+                        tracing::Level::WARN,
+                        tracing::Level::INFO,
+                        tracing::Level::DEBUG,
+                    ] {
+                        ui.selectable_value(&mut logs.level, Some(level), level.to_string());
+                    }
+                    ui.selectable_value(&mut logs.level, None, "ALL");
+                });
+            ui.add(egui::TextEdit::singleline(&mut logs.search).hint_text("🔍 Search"));
+            if ui.button("🗑 Clear").clicked() {
+                logs.buffer.clear();
+            }
+            if ui
+                .button("📦 Create Diagnostics Bundle")
+                .on_hover_text(
+                    "Zips the logs, settings without passwords and cache listing for bug reports",
+                )
+                .clicked()
+            {
+                logs.status = Some(create_diagnostics_bundle(&logs.buffer));
+            }
+        });
+        match &logs.status {
+            Some(Ok(msg)) => {
+                ui.label(RichText::new(msg).color(egui::Color32::LIGHT_GREEN));
+            }
+            Some(Err(err)) => {
+                ui.label(RichText::new(err).color(egui::Color32::LIGHT_RED));
+            }
+            None => (),
+        }
+        ui.separator();
+
+        let search = logs.search.to_lowercase();
+        let records = logs
+            .buffer
+            .records(logs.level.unwrap_or(tracing::Level::TRACE))
+            .into_iter()
+            .filter(|record| {
+                search.is_empty() || record.to_string().to_lowercase().contains(&search)
+            })
+            .collect::<Vec<_>>();
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        ScrollArea::both()
+            .auto_shrink([false; 2])
+            .stick_to_bottom(true)
+            .show_rows(ui, row_height, records.len(), |ui, range| {
+                for record in &records[range] {
+                    let color = match record.level {
+                        tracing::Level::ERROR => egui::Color32::LIGHT_RED,
+                        tracing::Level::WARN => egui::Color32::YELLOW,
+                        tracing::Level::INFO => ui.visuals().text_color(),
+                        _ => egui::Color32::GRAY,
+                    };
+                    ui.add(
+                        egui::Label::new(
+                            RichText::new(record.to_string()).monospace().color(color),
+                        )
+                        .wrap(false),
+                    );
+                }
+            });
+    }
+}
+
+fn create_diagnostics_bundle(buffer: &LogBuffer) -> Result<String, String> {
+    let cache_dir = defines::app_cache_dir().ok_or("No cache dir to create the bundle in")?;
+    fs::create_dir_all(&cache_dir).map_err(|e| e.to_string())?;
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let dest = cache_dir.join(format!("diagnostics-{secs}.zip"));
+    let sources = logs::DiagnosticsSources {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        log_dir: defines::app_log_dir(),
+        config_path: Some(defines::app_config_path()),
+        cache_path: Some(defines::app_cache_path()),
+        cache_dir: Some(cache_dir.clone()),
+    };
+    logs::create_diagnostics_bundle(&dest, &sources, &buffer.records(tracing::Level::TRACE))
+        .map_err(|e| format!("Failed to create the diagnostics bundle: {e:#}"))?;
+    info!("Created diagnostics bundle {:?}", dest);
+    _ = utils::open_in_explorer(&cache_dir);
+    Ok(format!("Created {}", dest.to_string_lossy()))
+}
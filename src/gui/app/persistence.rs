@@ -0,0 +1,86 @@
+use eframe::egui;
+use poll_promise::Promise;
+use tracing::info;
+use ventoy_toybox_core::{drive::VentoyDrive, persistence};
+
+use super::{drive::draw_image_combobox, App};
+
+impl App {
+    pub(super) fn draw_persistence(&mut self, ui: &mut egui::Ui) {
+        ui.label("Creates a persistence image on the drive and registers it for an image.");
+        ui.add_space(4.);
+        egui::Grid::new("persistence-grid")
+            .num_columns(2)
+            .spacing([12., 6.])
+            .show(ui, |ui| {
+                ui.label("Image:");
+                draw_image_combobox(
+                    ui,
+                    "persistence-image-combobox",
+                    &self.drive_images,
+                    &mut self.persistence.image_idx,
+                );
+                ui.end_row();
+
+                ui.label("Size:");
+                ui.add(
+                    egui::DragValue::new(&mut self.persistence.size_mib)
+                        .clamp_range(1..=4095)
+                        .suffix(" MiB"),
+                )
+                .on_hover_text("FAT32 drives can't hold files of 4 GiB or more");
+                ui.end_row();
+
+                ui.label("Label:");
+                egui::ComboBox::from_id_source("persistence-label-combobox")
+                    .selected_text(self.persistence.label.to_string())
+                    .show_ui(ui, |ui| {
+                        for label in persistence::PersistenceLabel::ALL {
+                            ui.selectable_value(
+                                &mut self.persistence.label,
+                                label,
+                                label.to_string(),
+                            );
+                        }
+                    });
+                ui.end_row();
+            });
+        ui.add_space(4.);
+
+        let creating = self.promise.persistence.is_some();
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!creating, egui::Button::new("➕ Create"))
+                .clicked()
+            {
+                let ctx = ui.ctx().clone();
+                let drive = VentoyDrive::new(&self.config.ventoy_drive);
+                let iso = self.drive_images[self.persistence.image_idx].path.clone();
+                let size = self.persistence.size_mib * 1024 * 1024;
+                let label = self.persistence.label;
+                self.promise.persistence = Some(Promise::spawn_thread("persistence", move || {
+                    let backend = persistence::create_for_image(&drive, &iso, size, label)
+                        .map_err(|e| e.to_string());
+                    ctx.request_repaint();
+                    backend
+                }));
+            }
+            if creating {
+                ui.add(egui::Spinner::new());
+            }
+        });
+
+        if let Some(promise) = &self.promise.persistence {
+            if let Some(backend) = promise.ready() {
+                self.drive_status = Some(match backend {
+                    Ok(path) => {
+                        info!("Created persistence image {:?}", path);
+                        Ok(format!("Created persistence image {path:?}"))
+                    }
+                    Err(err) => Err(format!("Failed to create persistence image: {err}")),
+                });
+                self.promise.persistence = None;
+            }
+        }
+    }
+}
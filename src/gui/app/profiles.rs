@@ -0,0 +1,424 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::defines;
+use eframe::egui::{self, RichText};
+use poll_promise::Promise;
+use tracing::{error, info};
+use ventoy_toybox_core::{
+    drive::{self, VentoyDrive},
+    provision::{self, ImageSource, Profile},
+};
+
+use super::{App, ProvisionJob};
+
+impl App {
+    pub(super) fn reload_profiles(&mut self) {
+        match load_profiles(&defines::app_profiles_dir()) {
+            Ok(profiles) => self.profiles.profiles = profiles,
+            Err(err) => {
+                self.profiles.status = Some(Err(format!("Failed to load profiles: {err}")));
+            }
+        }
+    }
+
+    fn edit_profile(&mut self, profile: Profile) {
+        let form = &mut self.profiles;
+        form.images = profile
+            .images
+            .iter()
+            .map(|image| image.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        form.plugins = if profile.plugins.is_empty() {
+            String::new()
+        } else {
+            serde_json::to_string_pretty(&profile.plugins).unwrap_or_default()
+        };
+        form.editing = profile;
+        form.plans.clear();
+    }
+
+    /// The profile being edited, with its images and plugins parsed.
+    fn edited_profile(&self) -> Result<Profile, String> {
+        let form = &self.profiles;
+        let mut profile = form.editing.clone();
+        profile.name = profile.name.trim().to_string();
+        if profile.name.is_empty() || profile.name.contains(['/', '\\', '.']) {
+            return Err("Name the profile, without slashes or dots".to_string());
+        }
+        profile.images = form
+            .images
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                line.parse::<ImageSource>()
+                    .unwrap_or_else(|never| match never {})
+            })
+            .collect();
+        profile.plugins = if form.plugins.trim().is_empty() {
+            BTreeMap::new()
+        } else {
+            serde_json::from_str(&form.plugins)
+                .map_err(|e| format!("The plugins aren't a json object: {e}"))?
+        };
+        Ok(profile)
+    }
+
+    fn save_profile(&mut self) {
+        let saved = self.edited_profile().and_then(|profile| {
+            let path = defines::app_profiles_dir().join(format!("{}.ron", profile.name));
+            confy::store_path(&path, &profile).map_err(|e| e.to_string())?;
+            info!("Saved profile {:?}", path);
+            Ok(profile.name)
+        });
+        self.profiles.status = Some(saved.map(|name| format!("Saved profile `{name}`")));
+        self.reload_profiles();
+    }
+
+    fn delete_profile(&mut self) {
+        let name = self.profiles.editing.name.trim().to_string();
+        let path = defines::app_profiles_dir().join(format!("{name}.ron"));
+        self.profiles.status = Some(
+            fs::remove_file(&path)
+                .map(|_| format!("Deleted profile `{name}`"))
+                .map_err(|e| format!("Failed to delete {path:?}: {e}")),
+        );
+        self.edit_profile(Profile::default());
+        self.reload_profiles();
+    }
+
+    /// Mount points the profile is applied to, the current drive if none are listed.
+    fn profile_drives(&self) -> Vec<PathBuf> {
+        let drives: Vec<PathBuf> = self
+            .profiles
+            .drives
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(PathBuf::from)
+            .collect();
+        if drives.is_empty() {
+            vec![self.config.ventoy_drive.clone()]
+        } else {
+            drives
+        }
+    }
+
+    fn plan_profile(&mut self) {
+        let profile = match self.edited_profile() {
+            Ok(profile) => profile,
+            Err(err) => {
+                self.profiles.status = Some(Err(err));
+                return;
+            }
+        };
+        let download_dir = dirs::download_dir()
+            .or_else(defines::app_cache_dir)
+            .unwrap_or_default();
+        let latest_version = self
+            .update_flow
+            .state()
+            .release()
+            .map(|release| release.tag_name.trim_start_matches('v').to_string());
+        self.profiles.plans = self
+            .profile_drives()
+            .into_iter()
+            .map(|root| {
+                let installed_version =
+                    drive::efi_partition(&root).and_then(drive::installed_version);
+                let ctx = provision::PlanContext {
+                    feeds: &self.cache.release_feeds,
+                    mirrors: &self.config.mirrors,
+                    download_dir: &download_dir,
+                    latest_version: latest_version.as_deref(),
+                    installed_version: installed_version.as_deref(),
+                };
+                let plan = provision::plan(&profile, &VentoyDrive::new(&root), &ctx)
+                    .map_err(|e| format!("{e:#}"));
+                (root, plan)
+            })
+            .collect();
+        self.profiles.status = None;
+    }
+
+    fn apply_profile(&mut self, ctx: &egui::Context) {
+        let plans: Vec<_> = self
+            .profiles
+            .plans
+            .iter()
+            .filter_map(|(root, plan)| Some((root.clone(), plan.as_ref().ok()?.clone())))
+            .collect();
+        let ctx = ctx.clone();
+        let task = self
+            .tasks
+            .manager
+            .start(format!("Applying profile {}", self.profiles.editing.name));
+        let token = task.token().clone();
+        let downloads = self.downloads.clone();
+        let keyring = self.config.verify_signatures.then(|| self.keyring());
+        let current = Arc::new(Mutex::new(String::new()));
+        let thread_current = current.clone();
+        let result = Promise::spawn_thread("provision", move || {
+            let mut results = Vec::new();
+            for (root, plan) in plans {
+                let drive = VentoyDrive::new(&root);
+                let applied = provision::execute(
+                    &plan,
+                    &drive,
+                    &downloads,
+                    keyring.as_ref(),
+                    task.token(),
+                    |i, step| {
+                        *thread_current.lock().unwrap() = format!(
+                            "{}: {}/{} {}",
+                            root.display(),
+                            i + 1,
+                            plan.steps.len(),
+                            step
+                        );
+                        ctx.request_repaint();
+                    },
+                )
+                .map_err(|e| format!("{e:#}"));
+                if let Err(err) = &applied {
+                    error!("Failed to apply the profile to {:?}: {}", root, err);
+                }
+                results.push((root, applied));
+                if task.token().is_cancelled() {
+                    break;
+                }
+            }
+            drop(task);
+            ctx.request_repaint();
+            results
+        });
+        self.profiles.plans.clear();
+        self.profiles.job = Some(ProvisionJob {
+            current,
+            token,
+            result,
+        });
+    }
+
+    pub(super) fn draw_profiles(&mut self, ui: &mut egui::Ui) {
+        ui.label("Describes a drive to build over and over, planned against each drive before it's applied.");
+        ui.add_space(4.);
+        let mut edit = None;
+        ui.horizontal(|ui| {
+            ui.label("Profile:");
+            egui::ComboBox::from_id_source("profile-combobox")
+                .selected_text(if self.profiles.editing.name.is_empty() {
+                    "New profile"
+                } else {
+                    self.profiles.editing.name.as_str()
+                })
+                .show_ui(ui, |ui| {
+                    if ui.selectable_label(false, "New profile").clicked() {
+                        edit = Some(Profile::default());
+                    }
+                    for profile in &self.profiles.profiles {
+                        if ui
+                            .selectable_label(
+                                profile.name == self.profiles.editing.name,
+                                profile.name.as_str(),
+                            )
+                            .clicked()
+                        {
+                            edit = Some(profile.clone());
+                        }
+                    }
+                });
+            if ui.button("💾 Save").clicked() {
+                self.save_profile();
+            }
+            let saved = self
+                .profiles
+                .profiles
+                .iter()
+                .any(|profile| profile.name == self.profiles.editing.name);
+            if ui
+                .add_enabled(saved, egui::Button::new("🗑 Delete"))
+                .clicked()
+            {
+                self.delete_profile();
+            }
+        });
+        if let Some(profile) = edit {
+            self.edit_profile(profile);
+        }
+
+        let form = &mut self.profiles;
+        let profile = &mut form.editing;
+        egui::Grid::new("profile-grid")
+            .num_columns(2)
+            .spacing([12., 6.])
+            .show(ui, |ui| {
+                ui.label("Name:");
+                ui.text_edit_singleline(&mut profile.name);
+                ui.end_row();
+
+                ui.label("Ventoy version:");
+                ui.add(egui::TextEdit::singleline(&mut profile.ventoy_version).hint_text("latest if empty"));
+                ui.end_row();
+
+                ui.label("Secure Boot:");
+                ui.checkbox(&mut profile.secure_boot, "")
+                    .on_hover_text("Update Ventoy with `-s`, the partitions are kept as they are");
+                ui.end_row();
+
+                ui.label("Image directory:");
+                ui.add(egui::TextEdit::singleline(&mut profile.image_dir).hint_text("drive root if empty"));
+                ui.end_row();
+
+                ui.label("Images:");
+                ui.vertical(|ui| {
+                    ui.add(
+                        egui::TextEdit::multiline(&mut form.images)
+                            .desired_rows(3)
+                            .hint_text("ubuntu: *desktop-amd64.iso\n/path/to/local.iso"),
+                    )
+                    .on_hover_text("A feeds group and a pattern of image names, the newest match is used, or a local image");
+                    ui.checkbox(&mut profile.prune, "Delete the other images on the drive");
+                });
+                ui.end_row();
+
+                ui.label("Plugins:");
+                ui.add(
+                    egui::TextEdit::multiline(&mut form.plugins)
+                        .code_editor()
+                        .desired_rows(3)
+                        .hint_text(r#"{ "control": [{ "VTOY_DEFAULT_MENU_MODE": "1" }] }"#),
+                )
+                .on_hover_text("ventoy.json plugins, replacing the ones of the same name on the drive");
+                ui.end_row();
+
+                ui.label("Theme:");
+                let mut theme = profile
+                    .theme
+                    .as_ref()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_default();
+                if ui
+                    .add(egui::TextEdit::singleline(&mut theme).hint_text("path to a .tar.gz or .zip theme"))
+                    .changed()
+                {
+                    profile.theme = (!theme.trim().is_empty()).then(|| theme.trim().into());
+                }
+                ui.end_row();
+
+                ui.label("Drives:");
+                ui.add(
+                    egui::TextEdit::multiline(&mut form.drives)
+                        .desired_rows(2)
+                        .hint_text("mount points, one per line, the drive above if empty"),
+                );
+                ui.end_row();
+            });
+        ui.add_space(4.);
+
+        if let Some(job) = &self.profiles.job {
+            match job.result.ready() {
+                None => {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::Spinner::new());
+                        ui.label(job.current.lock().unwrap().as_str());
+                        if ui.small_button("✖").on_hover_text("Cancel").clicked() {
+                            job.token.cancel();
+                        }
+                    });
+                    return;
+                }
+                Some(results) => {
+                    for (root, result) in results {
+                        match result {
+                            Ok(manual) => {
+                                ui.label(
+                                    RichText::new(format!("✔ Applied to {}", root.display()))
+                                        .color(egui::Color32::LIGHT_GREEN),
+                                );
+                                for step in manual {
+                                    ui.label(format!("    ⚠ Left to do: {step}"));
+                                }
+                            }
+                            Err(err) => {
+                                ui.label(
+                                    RichText::new(format!("Failed on {}: {}", root.display(), err))
+                                        .color(egui::Color32::LIGHT_RED),
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut apply = false;
+        ui.horizontal(|ui| {
+            if ui.button("📋 Plan").clicked() {
+                self.plan_profile();
+                self.profiles.job = None;
+            }
+            let ready = self.profiles.plans.iter().any(|(_, plan)| plan.is_ok());
+            apply = ui
+                .add_enabled(ready, egui::Button::new("▶ Apply"))
+                .clicked();
+        });
+        match &self.profiles.status {
+            Some(Ok(msg)) => {
+                ui.label(RichText::new(msg).color(egui::Color32::LIGHT_GREEN));
+            }
+            Some(Err(err)) => {
+                ui.label(RichText::new(err).color(egui::Color32::LIGHT_RED));
+            }
+            None => (),
+        }
+        for (root, plan) in &self.profiles.plans {
+            ui.label(RichText::new(root.display().to_string()).strong());
+            match plan {
+                Ok(plan) if plan.steps.is_empty() => {
+                    ui.label(RichText::new("Up to date").weak());
+                }
+                Ok(plan) => {
+                    for step in &plan.steps {
+                        ui.label(format!("• {step}"));
+                    }
+                }
+                Err(err) => {
+                    ui.label(RichText::new(err).color(egui::Color32::LIGHT_RED));
+                }
+            }
+            if let Ok(plan) = plan {
+                for warning in &plan.warnings {
+                    ui.label(RichText::new(format!("⚠ {warning}")).color(egui::Color32::YELLOW));
+                }
+            }
+        }
+        if apply {
+            self.apply_profile(ui.ctx());
+        }
+    }
+}
+
+/// Profiles in `dir`, the ones failing to load are skipped.
+fn load_profiles(dir: &Path) -> anyhow::Result<Vec<Profile>> {
+    let mut profiles = Vec::new();
+    if !dir.is_dir() {
+        return Ok(profiles);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().map_or(false, |ext| ext == "ron") {
+            match confy::load_path::<Profile>(&path) {
+                Ok(profile) => profiles.push(profile),
+                Err(err) => error!("Failed to load profile {:?}: {}", path, err),
+            }
+        }
+    }
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(profiles)
+}
@@ -0,0 +1,103 @@
+use eframe::egui::{self, RichText};
+use tracing::{debug, info};
+use ventoy_toybox_core::secure_boot;
+
+use super::App;
+
+impl App {
+    pub(super) fn inspect_secure_boot(&mut self) {
+        if self.config.ventoy_efi_partition.as_os_str().is_empty() {
+            if let Some(efi_root) = secure_boot::guess_efi_partition(&self.config.ventoy_drive) {
+                debug!("Guessed VTOYEFI partition at {:?}", efi_root);
+                self.config.ventoy_efi_partition = efi_root;
+            }
+        }
+        self.secure_boot = Some(
+            secure_boot::inspect(&self.config.ventoy_efi_partition).map_err(|e| e.to_string()),
+        );
+    }
+
+    pub(super) fn draw_secure_boot(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(format!("{} partition:", secure_boot::EFI_PARTITION_LABEL));
+            let mut efi_root = self
+                .config
+                .ventoy_efi_partition
+                .to_string_lossy()
+                .to_string();
+            if ui
+                .add(
+                    egui::TextEdit::singleline(&mut efi_root)
+                        .hint_text("mount point of the VTOYEFI partition"),
+                )
+                .changed()
+            {
+                self.config.ventoy_efi_partition = efi_root.into();
+            }
+            if ui.button("🔃").clicked() {
+                self.inspect_secure_boot();
+            }
+        });
+        ui.add_space(4.);
+
+        let info = match &self.secure_boot {
+            Some(Ok(info)) => info,
+            Some(Err(err)) => {
+                ui.label(RichText::new(err).color(egui::Color32::LIGHT_RED));
+                return;
+            }
+            None => {
+                ui.label("Scan a Ventoy drive to check its secure boot support.");
+                return;
+            }
+        };
+        if !info.enabled {
+            ui.label(
+                RichText::new("Ventoy was installed without secure boot support.")
+                    .color(egui::Color32::YELLOW),
+            );
+            ui.label(
+                "Either disable secure boot in the firmware setup, or reinstall Ventoy with \
+                 `Option > Secure Boot Support` checked in Ventoy2Disk.",
+            );
+            return;
+        }
+
+        ui.label(
+            RichText::new("Ventoy was installed with secure boot support.")
+                .color(egui::Color32::LIGHT_GREEN),
+        );
+        ui.label("Its key has to be enrolled once on every machine with secure boot enabled:");
+        for (i, step) in secure_boot::ENROLL_STEPS.iter().enumerate() {
+            ui.label(format!("{}. {}", i + 1, step));
+        }
+        ui.add_space(4.);
+
+        if self.secure_boot_export_dir.is_empty() {
+            self.secure_boot_export_dir = dirs::download_dir()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+        }
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.secure_boot_export_dir)
+                    .hint_text("directory to export the key to"),
+            );
+            if ui
+                .button("🔑 Export key")
+                .on_hover_text("e.g. to enroll it with `mokutil --import` or the firmware setup")
+                .clicked()
+            {
+                self.drive_status = Some(
+                    secure_boot::export_key(info, self.secure_boot_export_dir.trim())
+                        .map(|key| {
+                            info!("Exported secure boot key to {:?}", key);
+                            format!("Exported secure boot key to {key:?}")
+                        })
+                        .map_err(|e| format!("Failed to export key: {e:#}")),
+                );
+            }
+        });
+    }
+}
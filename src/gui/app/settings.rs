@@ -0,0 +1,313 @@
+use crate::defines;
+use eframe::egui::{self, RichText};
+use tracing::{error, info};
+use ventoy_toybox_core::{
+    compat::CompatDb,
+    mirror::{GroupMirror, Keyring},
+    torrent,
+};
+
+use super::App;
+
+impl App {
+    pub(super) fn reload_compat_db(&mut self) {
+        if self.config.compat_db.as_os_str().is_empty() {
+            self.compat = CompatDb::bundled();
+            self.compat_status = None;
+            return;
+        }
+        match CompatDb::load_over_bundled(&self.config.compat_db) {
+            Ok(db) => {
+                info!(
+                    "Loaded compatibility hints from {:?}",
+                    self.config.compat_db
+                );
+                self.compat_status = Some(Ok(format!(
+                    "Loaded {} entries, updated {}",
+                    db.entries.len(),
+                    db.updated.as_deref().unwrap_or("at an unknown date")
+                )));
+                self.compat = db;
+            }
+            Err(err) => {
+                error!("Failed to load compatibility hints: {:#}", err);
+                self.compat = CompatDb::bundled();
+                self.compat_status = Some(Err(format!("{err:#}, using the bundled hints")));
+            }
+        }
+    }
+
+    fn draw_compat_settings(&mut self, ui: &mut egui::Ui) {
+        ui.label(RichText::new("Compatibility Hints").strong());
+        ui.add_space(4.);
+        ui.horizontal(|ui| {
+            ui.label("Local database:");
+            let mut path = self.config.compat_db.to_string_lossy().to_string();
+            if ui
+                .add(
+                    egui::TextEdit::singleline(&mut path)
+                        .hint_text("compat.json, bundled hints if empty"),
+                )
+                .on_hover_text(
+                    "Its entries replace the bundled ones of the same distros. The bundled \
+                    hints only list images Ventoy tested, known issues have to come from here",
+                )
+                .changed()
+            {
+                self.config.compat_db = path.trim().into();
+            }
+            if ui.button("🔃 Reload").clicked() {
+                self.reload_compat_db();
+            }
+        });
+        match &self.compat_status {
+            Some(Ok(msg)) => {
+                ui.label(RichText::new(msg).color(egui::Color32::LIGHT_GREEN));
+            }
+            Some(Err(err)) => {
+                ui.label(RichText::new(err).color(egui::Color32::LIGHT_RED));
+            }
+            None => {
+                ui.label(
+                    RichText::new(format!(
+                        "Bundled hints, updated {}",
+                        self.compat
+                            .updated
+                            .as_deref()
+                            .unwrap_or("at an unknown date")
+                    ))
+                    .weak(),
+                );
+            }
+        }
+    }
+
+    fn draw_mirrors_settings(&mut self, ui: &mut egui::Ui) {
+        ui.label(RichText::new("Mirrors").strong());
+        ui.add_space(4.);
+        ui.label(
+            RichText::new(
+                "Image directory per feeds group, {version} is replaced by the release version",
+            )
+            .weak(),
+        );
+        let mut remove = None;
+        egui::Grid::new("mirrors-grid")
+            .num_columns(5)
+            .spacing([12., 6.])
+            .show(ui, |ui| {
+                ui.label("Group");
+                ui.label("URL");
+                ui.label("Checksums");
+                ui.label("Signature");
+                ui.end_row();
+                for (group, mirror) in self.config.mirrors.iter_mut() {
+                    ui.label(group);
+                    ui.add(
+                        egui::TextEdit::singleline(&mut mirror.url)
+                            .hint_text("https://releases.ubuntu.com/{version}"),
+                    );
+                    ui.add(egui::TextEdit::singleline(&mut mirror.sums_file).desired_width(100.));
+                    ui.add(
+                        egui::TextEdit::singleline(&mut mirror.signature_file)
+                            .hint_text("SHA256SUMS.gpg")
+                            .desired_width(120.),
+                    );
+                    if ui.small_button("✖").clicked() {
+                        remove = Some(group.clone());
+                    }
+                    ui.end_row();
+                }
+            });
+        if let Some(group) = remove {
+            self.config.mirrors.remove(&group);
+        }
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.mirrors.new_group)
+                    .hint_text("Group, e.g. ubuntu"),
+            );
+            let group = self.mirrors.new_group.trim().to_string();
+            if ui
+                .add_enabled(
+                    !group.is_empty() && !self.config.mirrors.contains_key(&group),
+                    egui::Button::new("Add Mirror"),
+                )
+                .clicked()
+            {
+                self.config.mirrors.insert(group, GroupMirror::default());
+                self.mirrors.new_group.clear();
+            }
+        });
+        ui.add_space(4.);
+
+        ui.checkbox(
+            &mut self.config.verify_signatures,
+            "Verify the signatures of checksums against the keyring",
+        );
+        let keys = self.keyring().keys();
+        ui.label(format!(
+            "{} key(s) in the keyring, none ship with the app",
+            keys.len()
+        ))
+        .on_hover_text(
+            keys.iter()
+                .map(|key| key.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.mirrors.key_path)
+                    .hint_text("Path to a distro's signing key (.asc or .gpg)"),
+            );
+            if ui
+                .add_enabled(
+                    !self.mirrors.key_path.trim().is_empty(),
+                    egui::Button::new("Import Key"),
+                )
+                .clicked()
+            {
+                let imported = defines::app_keyring_dir()
+                    .ok_or_else(|| anyhow::anyhow!("no config dir to import keys into"))
+                    .and_then(|dir| Keyring::import(dir, self.mirrors.key_path.trim()));
+                self.mirrors.status = Some(match imported {
+                    Ok(key) => {
+                        self.mirrors.key_path.clear();
+                        Ok(format!("Imported {}", key.display()))
+                    }
+                    Err(err) => Err(format!("Failed to import the key: {err:#}")),
+                });
+            }
+        });
+        match &self.mirrors.status {
+            Some(Ok(msg)) => {
+                ui.label(RichText::new(msg).color(egui::Color32::LIGHT_GREEN));
+            }
+            Some(Err(err)) => {
+                ui.label(RichText::new(err).color(egui::Color32::LIGHT_RED));
+            }
+            None => {}
+        }
+    }
+
+    pub(super) fn draw_settings(&mut self, ui: &mut egui::Ui) {
+        ui.label(RichText::new("Drive Safety").strong());
+        ui.add_space(4.);
+        ui.checkbox(
+            &mut self.config.expert_mode,
+            "Expert mode, allow overwriting disks the system runs from",
+        )
+        .on_hover_text("The device name still has to be typed in to confirm");
+        ui.separator();
+
+        ui.label(RichText::new("Downloads").strong());
+        ui.add_space(4.);
+        let downloads = &mut self.config.downloads;
+        let mut changed = false;
+        egui::Grid::new("downloads-grid")
+            .num_columns(2)
+            .spacing([12., 6.])
+            .show(ui, |ui| {
+                ui.label("Concurrent downloads:");
+                changed |= ui
+                    .add(egui::DragValue::new(&mut downloads.max_concurrent).clamp_range(1..=8))
+                    .changed();
+                ui.end_row();
+
+                ui.label("Bandwidth limit:");
+                ui.horizontal(|ui| {
+                    let mut limited = downloads.bandwidth_limit.is_some();
+                    if ui.checkbox(&mut limited, "").changed() {
+                        downloads.bandwidth_limit = limited.then_some(1024 * 1024);
+                        changed = true;
+                    }
+                    let mut kib = downloads.bandwidth_limit.unwrap_or(1024 * 1024) / 1024;
+                    if ui
+                        .add_enabled(
+                            limited,
+                            egui::DragValue::new(&mut kib)
+                                .clamp_range(16..=1024 * 1024)
+                                .suffix(" KiB/s"),
+                        )
+                        .changed()
+                    {
+                        downloads.bandwidth_limit = Some(kib * 1024);
+                        changed = true;
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Retries:");
+                changed |= ui
+                    .add(egui::DragValue::new(&mut downloads.max_retries).clamp_range(0..=10))
+                    .on_hover_text("Failed downloads are retried after 1s, 2s, 4s and so on")
+                    .changed();
+                ui.end_row();
+            });
+        if changed {
+            self.downloads.set_config(self.config.downloads.clone());
+        }
+        ui.separator();
+
+        self.draw_mirrors_settings(ui);
+        ui.separator();
+
+        self.draw_compat_settings(ui);
+        ui.separator();
+
+        ui.label(RichText::new("Torrent Client").strong());
+        ui.add_space(4.);
+        ui.checkbox(
+            &mut self.config.torrent_client_enabled,
+            "Send releases directly to a running torrent client",
+        );
+        ui.add_space(4.);
+        ui.add_enabled_ui(self.config.torrent_client_enabled, |ui| {
+            let client = &mut self.config.torrent_client;
+            egui::Grid::new("torrent-client-grid")
+                .num_columns(2)
+                .spacing([12., 6.])
+                .show(ui, |ui| {
+                    ui.label("Client:");
+                    egui::ComboBox::from_id_source("torrent-client-combobox")
+                        .selected_text(client.kind.to_string())
+                        .show_ui(ui, |ui| {
+                            for kind in torrent::TorrentClientKind::ALL {
+                                ui.selectable_value(&mut client.kind, kind, kind.to_string());
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("URL:");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut client.url)
+                            .hint_text(client.kind.default_url()),
+                    );
+                    ui.end_row();
+
+                    if client.kind != torrent::TorrentClientKind::Deluge {
+                        ui.label("Username:");
+                        ui.text_edit_singleline(&mut client.username);
+                        ui.end_row();
+                    }
+
+                    ui.label("Password:");
+                    ui.add(egui::TextEdit::singleline(&mut client.password).password(true))
+                        .on_hover_text("Not saved, it has to be entered again after a restart");
+                    ui.end_row();
+
+                    ui.label("Save path:");
+                    let mut save_path = client.save_path.to_string_lossy().to_string();
+                    if ui
+                        .text_edit_singleline(&mut save_path)
+                        .on_hover_text("Download directory as seen by the torrent client")
+                        .changed()
+                    {
+                        client.save_path = save_path.into();
+                    }
+                    ui.end_row();
+                });
+        });
+    }
+}
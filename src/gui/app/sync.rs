@@ -0,0 +1,226 @@
+use std::sync::{Arc, Mutex};
+
+use eframe::egui::{self, RichText};
+use poll_promise::Promise;
+use ventoy_toybox_core::{
+    drive::{self, VentoyDrive},
+    sync::{self, SyncReport},
+};
+
+use super::{App, SyncJob};
+
+impl App {
+    fn compare_sync(&mut self, ctx: &egui::Context) {
+        let ctx = ctx.clone();
+        let source = self.config.sync_source.clone();
+        let drive = VentoyDrive::new(&self.config.ventoy_drive);
+        let options = self.config.sync.clone();
+        let cache = self.verify_cache.clone();
+        let task = self
+            .tasks
+            .manager
+            .start(format!("Comparing {} with the drive", source.display()));
+        let token = task.token().clone();
+        self.sync.report = None;
+        self.sync.status = None;
+        self.sync.comparing = Some((
+            token,
+            Promise::spawn_thread("sync-compare", move || {
+                let filesystem = sync::detect_filesystem(drive.root());
+                let report =
+                    sync::compare(&source, &drive, &options, filesystem, &cache, task.token())
+                        .map_err(|e| format!("{e}\n{}", e.suggestion()));
+                drop(task);
+                ctx.request_repaint();
+                report
+            }),
+        ));
+    }
+
+    fn start_sync(&mut self, ctx: &egui::Context, report: SyncReport) {
+        let ctx = ctx.clone();
+        let task = self.tasks.manager.start("Syncing images onto the drive");
+        let token = task.token().clone();
+        let progress = Arc::new(Mutex::new((String::new(), 0, report.bytes_to_copy())));
+        let thread_progress = progress.clone();
+        let result = Promise::spawn_thread("sync", move || {
+            let synced = sync::apply(&report, task.token(), |action, done, total| {
+                *thread_progress.lock().unwrap() = (action.to_string(), done, total);
+                ctx.request_repaint();
+            })
+            .map_err(|e| format!("{e}\n{}", e.suggestion()));
+            drop(task);
+            ctx.request_repaint();
+            synced
+        });
+        self.sync.job = Some(SyncJob {
+            progress,
+            token,
+            result,
+        });
+    }
+
+    pub(super) fn draw_sync(&mut self, ui: &mut egui::Ui) {
+        ui.label("Mirrors a directory of images, e.g. on a NAS, onto the drive, copying only what changed.");
+        ui.add_space(4.);
+        let options = &mut self.config.sync;
+        egui::Grid::new("sync-grid")
+            .num_columns(2)
+            .spacing([12., 6.])
+            .show(ui, |ui| {
+                ui.label("Source:");
+                let mut source = self.config.sync_source.to_string_lossy().to_string();
+                if ui
+                    .add(egui::TextEdit::singleline(&mut source).hint_text("directory of images"))
+                    .changed()
+                {
+                    self.config.sync_source = source.trim().into();
+                }
+                ui.end_row();
+
+                ui.label("Drive directory:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut options.dest_dir)
+                        .hint_text("drive root if empty"),
+                );
+                ui.end_row();
+
+                ui.label("Compare by:");
+                ui.horizontal(|ui| {
+                    ui.radio_value(
+                        &mut options.compare,
+                        sync::CompareBy::SizeAndTime,
+                        "Size and time",
+                    );
+                    ui.radio_value(&mut options.compare, sync::CompareBy::Hash, "Hash")
+                        .on_hover_text("Hashes images of the same size on every comparison, slow");
+                });
+                ui.end_row();
+
+                ui.label("");
+                ui.checkbox(
+                    &mut options.delete_extraneous,
+                    "Delete images on the drive that aren't in the source",
+                );
+                ui.end_row();
+            });
+        ui.add_space(4.);
+
+        if let Some((token, promise)) = &self.sync.comparing {
+            match promise.ready() {
+                None => {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::Spinner::new());
+                        ui.label("Comparing...");
+                        if ui.small_button("✖").on_hover_text("Cancel").clicked() {
+                            token.cancel();
+                        }
+                    });
+                    return;
+                }
+                Some(Ok(report)) => self.sync.report = Some(report.clone()),
+                Some(Err(err)) => self.sync.status = Some(Err(err.clone())),
+            }
+            self.sync.comparing = None;
+        }
+        if let Some(job) = &self.sync.job {
+            match job.result.ready() {
+                None => {
+                    let (action, done, total) = job.progress.lock().unwrap().clone();
+                    let fraction = if total > 0 {
+                        done as f32 / total as f32
+                    } else {
+                        0.
+                    };
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::ProgressBar::new(fraction)
+                                .desired_width(240.)
+                                .text(format!(
+                                    "{} / {}",
+                                    drive::human_size(done),
+                                    drive::human_size(total)
+                                )),
+                        );
+                        if ui.small_button("✖").on_hover_text("Cancel").clicked() {
+                            job.token.cancel();
+                        }
+                    });
+                    ui.label(RichText::new(action).weak());
+                    return;
+                }
+                Some(result) => {
+                    self.sync.status = Some(
+                        result
+                            .clone()
+                            .map(|_| "Synced the images onto the drive".to_string()),
+                    );
+                    self.sync.job = None;
+                    self.scan_drive(ui.ctx());
+                }
+            }
+        }
+
+        let mut start = None;
+        ui.horizontal(|ui| {
+            let ready = !self.config.sync_source.as_os_str().is_empty();
+            if ui
+                .add_enabled(ready, egui::Button::new("🔍 Compare"))
+                .on_hover_text("A dry run, lists what syncing would do without changing anything")
+                .clicked()
+            {
+                self.compare_sync(ui.ctx());
+            }
+            let report = self
+                .sync
+                .report
+                .as_ref()
+                .filter(|report| !report.is_synced());
+            if ui
+                .add_enabled(report.is_some(), egui::Button::new("⟳ Sync"))
+                .clicked()
+            {
+                start = report.cloned();
+            }
+        });
+        match &self.sync.status {
+            Some(Ok(msg)) => {
+                ui.label(RichText::new(msg).color(egui::Color32::LIGHT_GREEN));
+            }
+            Some(Err(err)) => {
+                ui.label(RichText::new(err).color(egui::Color32::LIGHT_RED));
+            }
+            None => (),
+        }
+        if let Some(report) = &self.sync.report {
+            let deletes = report
+                .actions
+                .iter()
+                .filter(|action| matches!(action, sync::SyncAction::Delete(_)))
+                .count();
+            ui.label(format!(
+                "{} drive: {} to copy, {} to delete, {} unchanged",
+                report.filesystem,
+                drive::human_size(report.bytes_to_copy()),
+                deletes,
+                report.unchanged
+            ));
+            for action in &report.actions {
+                match action {
+                    sync::SyncAction::TooLarge { .. } => {
+                        ui.label(RichText::new(format!("⚠ {action}")).color(egui::Color32::YELLOW))
+                            .on_hover_text("FAT32 can't store files of 4 GiB or more, reinstall Ventoy with exFAT");
+                    }
+                    _ => {
+                        ui.label(format!("• {action}"));
+                    }
+                }
+            }
+        }
+        if let Some(report) = start {
+            self.sync.report = None;
+            self.sync.status = None;
+            self.start_sync(ui.ctx(), report);
+        }
+    }
+}
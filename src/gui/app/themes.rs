@@ -0,0 +1,264 @@
+use std::path::Path;
+
+use eframe::egui::{self, RichText};
+#[cfg(feature = "theme-preview")]
+use tracing::error;
+use tracing::info;
+use ventoy_toybox_core::{drive::VentoyDrive, theme, ventoy_json::VentoyJson};
+
+use super::App;
+
+impl App {
+    pub(super) fn reload_themes(&mut self) {
+        let drive = VentoyDrive::new(&self.config.ventoy_drive);
+        self.theme.installed = theme::installed_themes(&drive).unwrap_or_else(|err| {
+            self.drive_status = Some(Err(format!("Failed to list themes: {err}")));
+            Vec::new()
+        });
+        self.theme.selection = match VentoyJson::load(drive.ventoy_json_path()) {
+            Ok(ventoy_json) => theme::ThemeSelection::load(&ventoy_json),
+            Err(err) => {
+                self.drive_status = Some(Err(err.to_string()));
+                Default::default()
+            }
+        };
+        self.theme.preview_idx = None;
+        self.theme.preview = None;
+        self.theme.background = None;
+    }
+
+    #[cfg_attr(not(feature = "theme-preview"), allow(unused_variables))]
+    fn load_theme_preview(&mut self, ctx: &egui::Context, idx: usize) {
+        let installed = &self.theme.installed[idx];
+        let (width, height) = installed.resolution.unwrap_or((1920, 1080));
+        let theme_dir = installed.theme_txt.parent().unwrap_or(Path::new(""));
+        let preview = theme::ThemeTxt::load(&installed.theme_txt)
+            .map(|theme_txt| theme_txt.preview(theme_dir, width as f32, height as f32))
+            .map_err(|e| format!("{e:#}"));
+
+        self.theme.background = None;
+        #[cfg(feature = "theme-preview")]
+        if let Ok(theme::ThemePreview {
+            background: Some(background),
+            ..
+        }) = &preview
+        {
+            match image::open(background) {
+                Ok(image) => {
+                    let image = image.to_rgba8();
+                    let size = [image.width() as usize, image.height() as usize];
+                    let image = egui::ColorImage::from_rgba_unmultiplied(size, image.as_raw());
+                    self.theme.background = Some(ctx.load_texture(
+                        "theme-background",
+                        image,
+                        egui::TextureOptions::LINEAR,
+                    ));
+                }
+                Err(err) => error!("Failed to load theme background {:?}: {}", background, err),
+            }
+        }
+        self.theme.preview_idx = Some(idx);
+        self.theme.preview = Some(preview);
+    }
+
+    pub(super) fn draw_theme(&mut self, ui: &mut egui::Ui) {
+        ui.label("Installs GRUB themes onto the drive and selects them in the `theme` plugin.");
+        ui.add_space(4.);
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.theme.archive_path)
+                    .hint_text("path to a .tar.gz or .zip theme"),
+            );
+            if ui.button("📥 Install").clicked() {
+                let drive = VentoyDrive::new(&self.config.ventoy_drive);
+                let installed = theme::install_theme(&drive, self.theme.archive_path.trim());
+                self.reload_themes();
+                self.drive_status = Some(
+                    installed
+                        .map(|theme_txt| {
+                            info!("Installed theme {:?}", theme_txt);
+                            format!("Installed theme {theme_txt:?}")
+                        })
+                        .map_err(|e| format!("Failed to install theme: {e:#}")),
+                );
+            }
+        });
+        ui.add_space(4.);
+
+        if self.theme.installed.is_empty() {
+            ui.label("No themes installed in /ventoy/theme.");
+            return;
+        }
+        let mut preview_idx = None;
+        let selection = &mut self.theme.selection;
+        egui::Grid::new("theme-grid")
+            .num_columns(4)
+            .spacing([12., 6.])
+            .show(ui, |ui| {
+                for (i, installed) in self.theme.installed.iter().enumerate() {
+                    let position = selection
+                        .files
+                        .iter()
+                        .position(|file| *file == installed.ventoy_path);
+                    let mut enabled = position.is_some();
+                    if ui
+                        .checkbox(&mut enabled, installed.name.as_str())
+                        .on_hover_text(installed.ventoy_path.as_str())
+                        .changed()
+                    {
+                        match position {
+                            Some(position) => {
+                                selection.files.remove(position);
+                                selection.default_file = 0;
+                            }
+                            None => selection.files.push(installed.ventoy_path.clone()),
+                        }
+                    }
+                    ui.label(match installed.resolution {
+                        Some((w, h)) => format!("{w}x{h}"),
+                        None => "any".to_string(),
+                    });
+                    match position {
+                        Some(position) if selection.files.len() > 1 => {
+                            ui.radio_value(&mut selection.default_file, position + 1, "Default");
+                        }
+                        _ => {
+                            ui.label("");
+                        }
+                    }
+                    if ui
+                        .selectable_label(self.theme.preview_idx == Some(i), "👁 Preview")
+                        .clicked()
+                    {
+                        preview_idx = Some(i);
+                    }
+                    ui.end_row();
+                }
+            });
+        ui.add_space(4.);
+
+        ui.add_enabled_ui(selection.files.len() > 1, |ui| {
+            ui.radio_value(
+                &mut selection.default_file,
+                0,
+                "Pick a random theme on boot",
+            );
+            ui.checkbox(
+                &mut selection.resolution_fit,
+                "Pick the theme closest to the screen resolution",
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("gfxmode:");
+            ui.add(
+                egui::TextEdit::singleline(&mut selection.gfxmode)
+                    .desired_width(120.)
+                    .hint_text("e.g. 1920x1080"),
+            );
+        });
+        ui.add_space(4.);
+        if ui.button("📝 Write to ventoy.json").clicked() {
+            let drive = VentoyDrive::new(&self.config.ventoy_drive);
+            let written = VentoyJson::load(drive.ventoy_json_path()).and_then(|mut ventoy_json| {
+                selection.apply(&mut ventoy_json);
+                ventoy_json.save(drive.ventoy_json_path())
+            });
+            self.drive_status = Some(
+                written
+                    .map(|_| format!("Wrote theme selection to {:?}", drive.ventoy_json_path()))
+                    .map_err(|e| e.to_string()),
+            );
+        }
+
+        if let Some(idx) = preview_idx {
+            self.load_theme_preview(ui.ctx(), idx);
+        }
+        match &self.theme.preview {
+            Some(Ok(preview)) => {
+                ui.add_space(8.);
+                let resolution = self
+                    .theme
+                    .preview_idx
+                    .and_then(|idx| self.theme.installed[idx].resolution)
+                    .unwrap_or((1920, 1080));
+                draw_theme_preview(ui, preview, resolution, self.theme.background.as_ref());
+            }
+            Some(Err(err)) => {
+                ui.label(RichText::new(err).color(egui::Color32::LIGHT_RED));
+            }
+            None => (),
+        }
+    }
+}
+
+/// Roughly paints a GRUB theme, components are drawn as outlines with sample text.
+fn draw_theme_preview(
+    ui: &mut egui::Ui,
+    preview: &theme::ThemePreview,
+    resolution: (u32, u32),
+    background: Option<&egui::TextureHandle>,
+) {
+    let width = ui.available_width().min(640.);
+    let scale = width / resolution.0 as f32;
+    let (rect, _) = ui.allocate_exact_size(
+        egui::vec2(width, resolution.1 as f32 * scale),
+        egui::Sense::hover(),
+    );
+    let painter = ui.painter_at(rect);
+    let [r, g, b] = preview.background_color;
+    painter.rect_filled(rect, 0., egui::Color32::from_rgb(r, g, b));
+    if let Some(texture) = background {
+        painter.image(
+            texture.id(),
+            rect,
+            egui::Rect::from_min_max(egui::pos2(0., 0.), egui::pos2(1., 1.)),
+            egui::Color32::WHITE,
+        );
+    }
+
+    let font = egui::FontId::proportional(14. * scale.max(0.5));
+    for element in &preview.elements {
+        let [x, y, w, h] = element.rect;
+        let element_rect = egui::Rect::from_min_size(
+            rect.min + egui::vec2(x, y) * scale,
+            egui::vec2(w, h) * scale,
+        );
+        let [r, g, b] = element.color;
+        let color = egui::Color32::from_rgb(r, g, b);
+        match element.kind.as_str() {
+            "boot_menu" => {
+                painter.rect_stroke(element_rect, 2., egui::Stroke::new(1., color));
+                for (i, entry) in ["Ubuntu 22.10", "Fedora 37", "Windows 11"]
+                    .iter()
+                    .enumerate()
+                {
+                    painter.text(
+                        element_rect.min + egui::vec2(8., 8. + i as f32 * font.size * 1.6),
+                        egui::Align2::LEFT_TOP,
+                        entry,
+                        font.clone(),
+                        color,
+                    );
+                }
+            }
+            "progress_bar" => {
+                painter.rect_filled(element_rect, 2., color.linear_multiply(0.6));
+            }
+            _ => match &element.text {
+                Some(text) => {
+                    painter.text(
+                        element_rect.min,
+                        egui::Align2::LEFT_TOP,
+                        text,
+                        font.clone(),
+                        color,
+                    );
+                }
+                None if w > 0. && h > 0. => {
+                    painter.rect_stroke(element_rect, 0., egui::Stroke::new(1., color));
+                }
+                None => (),
+            },
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use eframe::egui::{self, RichText};
+use poll_promise::Promise;
+use ventoy_toybox_core::{drive, integrity, mirror};
+
+use super::{App, IntegrityCheck};
+
+impl App {
+    pub(super) fn draw_integrity(&mut self, ui: &mut egui::Ui) {
+        ui.label(
+            RichText::new("Checked against a pasted checksum, a checksum file next to the image, or the checksums of its feed")
+                .weak(),
+        );
+        ui.add_space(4.);
+        let mut verify = None;
+        egui::Grid::new("integrity-grid")
+            .num_columns(3)
+            .spacing([12., 6.])
+            .show(ui, |ui| {
+                for image in &self.drive_images {
+                    ui.label(&image.ventoy_path);
+                    let pasted = self.integrity.pasted.entry(image.path.clone()).or_default();
+                    ui.add(
+                        egui::TextEdit::singleline(pasted)
+                            .hint_text("sha256, sha1 or md5")
+                            .desired_width(180.),
+                    );
+                    ui.horizontal(|ui| match self.integrity.checks.get(&image.path) {
+                        Some(check) if check.result.ready().is_none() => {
+                            let (done, total) = *check.progress.lock().unwrap();
+                            let fraction = if total > 0 {
+                                done as f32 / total as f32
+                            } else {
+                                0.
+                            };
+                            ui.add(egui::ProgressBar::new(fraction).desired_width(160.).text(
+                                format!(
+                                    "{} / {}",
+                                    drive::human_size(done),
+                                    drive::human_size(total)
+                                ),
+                            ));
+                            if ui.small_button("✖").on_hover_text("Cancel").clicked() {
+                                check.token.cancel();
+                            }
+                            ui.ctx().request_repaint_after(Duration::from_millis(250));
+                        }
+                        check => {
+                            if ui.button("✔ Verify").clicked() {
+                                verify = Some(image.path.clone());
+                            }
+                            match check.and_then(|check| check.result.ready()) {
+                                Some(Ok(verification)) if verification.is_match() => {
+                                    ui.label(RichText::new("OK").color(egui::Color32::LIGHT_GREEN))
+                                        .on_hover_text(format!(
+                                            "{} matches {}{}",
+                                            verification.expected.algo,
+                                            verification.expected.source,
+                                            if verification.cached {
+                                                ", hashed earlier"
+                                            } else {
+                                                ""
+                                            }
+                                        ));
+                                }
+                                Some(Ok(verification)) => {
+                                    ui.label(
+                                        RichText::new("Corrupt").color(egui::Color32::LIGHT_RED),
+                                    )
+                                    .on_hover_text(format!(
+                                        "{} is {} but {} expects {}",
+                                        verification.expected.algo,
+                                        verification.actual,
+                                        verification.expected.source,
+                                        verification.expected.hash
+                                    ));
+                                }
+                                Some(Err(err)) => {
+                                    ui.label(
+                                        RichText::new("Failed").color(egui::Color32::LIGHT_RED),
+                                    )
+                                    .on_hover_text(err);
+                                }
+                                // Outcome of an earlier run
+                                None => match self
+                                    .verify_cache
+                                    .get(&image.path)
+                                    .and_then(|record| record.verified)
+                                {
+                                    Some(true) => {
+                                        ui.label(RichText::new("OK").weak())
+                                            .on_hover_text("Verified earlier, unchanged since");
+                                    }
+                                    Some(false) => {
+                                        ui.label(
+                                            RichText::new("Corrupt")
+                                                .color(egui::Color32::LIGHT_RED),
+                                        )
+                                        .on_hover_text("Didn't match its checksum earlier");
+                                    }
+                                    None => (),
+                                },
+                            }
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        if let Some(path) = verify {
+            self.verify_image(ui.ctx(), path);
+        }
+    }
+
+    fn verify_image(&mut self, ctx: &egui::Context, path: PathBuf) {
+        let Some(image) = self.drive_images.iter().find(|image| image.path == path) else {
+            return;
+        };
+        let name = image
+            .path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let pasted = self
+            .integrity
+            .pasted
+            .get(&path)
+            .cloned()
+            .unwrap_or_default();
+        let sums_url = image
+            .feed
+            .as_ref()
+            .and_then(|item| mirror::resolve(item, &self.config.mirrors))
+            .and_then(|source| source.sums_url);
+        let ctx = ctx.clone();
+        let task = self.tasks.manager.start(format!("Verifying {name}"));
+        let token = task.token().clone();
+        let progress = Arc::new(Mutex::new((0, 0)));
+        let cache = self.verify_cache.clone();
+        let thread_progress = progress.clone();
+        let image_path = path.clone();
+        let result = Promise::spawn_thread("verify", move || {
+            let path = image_path;
+            let verified = (|| {
+                let expected = if !pasted.trim().is_empty() {
+                    integrity::Expected::pasted(&pasted).ok_or_else(|| {
+                        "The pasted value isn't a sha256, sha1 or md5 hash".to_string()
+                    })?
+                } else if let Some(expected) = integrity::sibling_checksum(&path) {
+                    expected
+                } else if let Some(url) = sums_url {
+                    integrity::fetch_checksum(&url, &name)
+                        .map_err(|e| format!("{e}\n{}", e.suggestion()))?
+                } else {
+                    return Err("No checksum known for it, paste one to verify against".to_string());
+                };
+                integrity::verify(&path, expected, &cache, task.token(), |done, total| {
+                    *thread_progress.lock().unwrap() = (done, total);
+                    ctx.request_repaint();
+                })
+                .map_err(|e| format!("{e}\n{}", e.suggestion()))
+            })();
+            drop(task);
+            ctx.request_repaint();
+            verified
+        });
+        self.integrity.checks.insert(
+            path,
+            IntegrityCheck {
+                progress,
+                token,
+                result,
+            },
+        );
+    }
+}
@@ -6,12 +6,3 @@ struct PromptDialog {
     title: String,
     text: String,
 }
-
-#[derive(Default, PartialEq, Debug)]
-enum VentoyUpdateFrames {
-    #[default]
-    FoundRelease,
-    Downloading,
-    Done,
-    Failed,
-}
@@ -1,13 +1,28 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 // https://github.com/emilk/egui/pull/1008
-mod core;
 mod defines;
 mod gui;
 use crate::gui::app;
 use eframe::epaint::vec2;
+use tracing_subscriber::{filter::LevelFilter, prelude::*, EnvFilter};
+use ventoy_toybox_core::logs::{LogBuffer, LogLayer, RotatingFile};
 
 fn main() {
-    tracing_subscriber::fmt::init();
+    let logs = LogBuffer::default();
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer().with_filter(
+                // RUST_LOG, info and above by default
+                EnvFilter::builder()
+                    .with_default_directive(LevelFilter::INFO.into())
+                    .from_env_lossy(),
+            ),
+        )
+        .with(
+            LogLayer::new(logs.clone(), defines::app_log_dir().map(RotatingFile::new))
+                .with_filter(LevelFilter::DEBUG),
+        )
+        .init();
 
     let native_options = eframe::NativeOptions {
         initial_window_size: Some(vec2(700.0, 500.0)),
@@ -19,7 +34,7 @@ fn main() {
     eframe::run_native(
         crate::defines::APP_NAME,
         native_options,
-        Box::new(|cc| Box::new(app::App::new(cc))),
+        Box::new(|cc| Box::new(app::App::new(cc, logs))),
     );
 }
 